@@ -0,0 +1,29 @@
+//Lex and parse arbitrary bytes, which must give an AST or an error, without panicking, exiting or
+//overflowing the stack. Run with `cargo fuzz run parse`.
+//The interpreter is not a library yet, so the lexer and parser modules are included directly.
+#![no_main]
+#![allow(dead_code)]
+
+#[macro_use]
+extern crate num_derive;
+
+#[path = "../../src/diagnostics.rs"]
+mod diagnostics;
+#[path = "../../src/errors.rs"]
+mod errors;
+#[path = "../../src/fileinfo.rs"]
+mod fileinfo;
+#[path = "../../src/lexer/mod.rs"]
+mod lexer;
+#[path = "../../src/parser/mod.rs"]
+mod parser;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let info = fileinfo::FileInfo {
+        data,
+        name: String::from("fuzz"),
+    };
+    let _ = parser::parse_safe(&info);
+});
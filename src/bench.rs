@@ -0,0 +1,204 @@
+//Time the programs of a directory and print a table of their times, for `merlin bench-suite`
+
+use crate::errors::ExitCode;
+use crate::{format_time, RunOptions};
+use colored::Colorize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+//The directory of the programs, if none is given
+pub const BENCH_DIR: &str = "benches";
+//How many samples of each program are taken, if --time does not say
+const BENCH_SAMPLES: i32 = 3;
+
+//The best and mean time, standard deviation and runs of a program, or why it was not timed
+type Times = Result<(f64, f64, f64, usize), ExitCode>;
+
+//How a program is timed, from its path and source, the number of samples and the flags of the suite
+pub type Timer = fn(&Path, &str, i32, &RunOptions) -> Times;
+
+//A row of the table: the name of a program and its times, or why it was not timed
+struct Row {
+    name: String,
+    times: Times,
+}
+
+fn print_table(rows: &[Row]) {
+    let width = rows
+        .iter()
+        .map(|row| row.name.len())
+        .chain(std::iter::once("Benchmark".len()))
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{}",
+        format!(
+            "{:<width$}  {:>12}  {:>12}  {:>12}  {:>8}",
+            "Benchmark", "Best", "Mean", "Std dev", "Runs"
+        )
+        .bold()
+    );
+    for row in rows {
+        let (best, mean, std_dev, runs) = match row.times {
+            Ok(times) => times,
+            Err(code) => {
+                let reason = match code {
+                    ExitCode::Syntax => String::from("did not compile"),
+                    ExitCode::Exit(status) => format!("exited with {status}"),
+                    _ => String::from("errored"),
+                };
+                println!("{:<width$}  {}", row.name, reason.red());
+                continue;
+            }
+        };
+        println!(
+            "{:<width$}  {}  {:>12}  {:>12}  {:>8}",
+            row.name,
+            format!("{:>12}", format_time(best)).green(),
+            format_time(mean),
+            format_time(std_dev),
+            runs
+        );
+    }
+}
+
+//Time a program in this process, in a VM of its own
+#[cfg(test)]
+pub fn time_in_process(file: &Path, source: &str, n_exec: i32, options: &RunOptions) -> Times {
+    use crate::{stats, time_source};
+    time_source(source, &file.display().to_string(), n_exec, options).map(|timings| {
+        (
+            timings.times.iter().copied().fold(f64::MAX, f64::min),
+            stats::mean(&timings.times),
+            stats::std_dev(&timings.times),
+            timings.iterations,
+        )
+    })
+}
+
+//The flags of `merlin -t n --json` that time a program like the suite does
+pub fn child_args(n_exec: i32, options: &RunOptions) -> Vec<String> {
+    let mut args = vec![
+        String::from("-t"),
+        n_exec.to_string(),
+        String::from("--json"),
+        String::from("--color=never"),
+        format!("--log-level={}", options.log_level.name()),
+        format!("--int-overflow={}", options.int_overflow.name()),
+        format!("--repr-limit={}", options.repr_limit.unwrap_or(0)),
+    ];
+    if let Some(step_limit) = options.step_limit {
+        args.push(format!("--step-limit={step_limit}"));
+    }
+    if let Some(int_cache) = options.int_cache {
+        args.push(format!("--int-cache={int_cache}"));
+    }
+    if let Some(cache_dir) = &options.cache_dir {
+        args.push(format!("--cache-dir={}", cache_dir.display()));
+    }
+    for (set, flag) in [
+        (options.optimize, "--optimize"),
+        (options.deterministic, "--deterministic"),
+        (options.strict, "--strict"),
+        (options.warn_unused, "--warn=unused"),
+    ] {
+        if set {
+            args.push(String::from(flag));
+        }
+    }
+    args
+}
+
+//The number after a key of the JSON object that `merlin -t n --json` prints
+pub fn json_number(json: &str, key: &str) -> Option<f64> {
+    let rest = &json[json.find(&format!("\"{key}\": "))? + key.len() + 4..];
+    rest[..rest.find([',', '}'])?].parse().ok()
+}
+
+//Time a program in a merlin process of its own, with the flags of the suite. The runs of a program
+//do not free all of the objects that they allocate, so the memory of each program is returned when
+//its process exits, instead of adding up over the suite.
+pub fn time_in_child(file: &Path, _source: &str, n_exec: i32, options: &RunOptions) -> Times {
+    match std::env::current_exe() {
+        Ok(exe) => time_with(&exe, file, n_exec, options),
+        Err(_) => Err(ExitCode::Usage),
+    }
+}
+
+//Time a program in a process of the merlin executable at exe
+pub fn time_with(exe: &Path, file: &Path, n_exec: i32, options: &RunOptions) -> Times {
+    let output = Command::new(exe)
+        .args(child_args(n_exec, options))
+        .arg(file)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let Ok(output) = output else {
+        return Err(ExitCode::Usage);
+    };
+    match output.status.code() {
+        Some(0) => (),
+        Some(2) => return Err(ExitCode::Usage),
+        Some(3) => return Err(ExitCode::Syntax),
+        //Killed by a signal, like when it runs out of memory
+        Some(4) | None => return Err(ExitCode::Runtime),
+        Some(status) => return Err(ExitCode::Exit(status)),
+    }
+    //The program may print before its times
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = stdout
+        .lines()
+        .rev()
+        .find(|line| line.starts_with("{\"best_ns\""))
+        .ok_or(ExitCode::Runtime)?;
+    match ["best_ns", "mean_ns", "std_dev_ns", "iterations"].map(|key| json_number(json, key)) {
+        [Some(best), Some(mean), Some(std_dev), Some(runs)] => {
+            Ok((best, mean, std_dev, runs as usize))
+        }
+        _ => Err(ExitCode::Runtime),
+    }
+}
+
+//Time each .me file in dir, in the order of their names, with the timer, and print their times.
+//Each program runs in a VM of its own, so that one does not warm up the caches of the next.
+pub fn bench_suite(dir: &Path, options: &RunOptions, timer: Timer) -> ExitCode {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            println!(
+                "Directory '{}' is unable to be opened or read.",
+                dir.display()
+            );
+            return ExitCode::Usage;
+        }
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "me"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let n_exec = options.time.unwrap_or(BENCH_SAMPLES);
+    let mut rows = Vec::new();
+    for file in files {
+        let source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(_) => {
+                println!("File '{}' is unable to be opened or read.", file.display());
+                return ExitCode::Usage;
+            }
+        };
+        let name = file
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let times = timer(&file, &source, n_exec, options);
+        rows.push(Row { name, times });
+    }
+    print_table(&rows);
+
+    if rows.iter().all(|row| row.times.is_ok()) {
+        ExitCode::Success
+    } else {
+        ExitCode::Runtime
+    }
+}
@@ -0,0 +1,895 @@
+//Native functions available in every scope
+
+use crate::rc::Trc;
+use crate::{
+    errors::{Exit, ExitCode},
+    interpreter::VM,
+    objects::{
+        boolobject, builtinobject, dictobject,
+        exceptionobject::{
+            argumentexc_from_str, methodnotdefinedexc_from_str, typemismatchexc_from_str,
+            valueexc_from_str,
+        },
+        intobject, listobject, mhash, moduleobject, object_id, shape, stringobject, superobject,
+        typeobject, BuiltinFn, IdMap, MethodType, MethodValue, Object, ObjectInternals, RawObject,
+    },
+    parser::Position,
+    unwrap_fast,
+};
+use colored::{ColoredString, Colorize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Check the number of arguments passed to a builtin
+fn check_args<'a>(
+    selfv: &Object<'a>,
+    args: &[Object<'a>],
+    n: usize,
+) -> MethodValue<(), Object<'a>> {
+    if args.len() != n {
+        let exc = argumentexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected {} argument(s), got {}", n, args.len()),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(())
+}
+
+fn check_list<'a>(selfv: &Object<'a>, list: &Object<'a>) -> MethodValue<(), Object<'a>> {
+    if !is_type_exact!(list, unwrap_fast!(selfv.vm.types.listtp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'list', got '{}'", list.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(())
+}
+
+//Call a callable object through its call slot
+fn call_object<'a>(callable: &Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    if callable.tp.call.is_none() {
+        let exc = methodnotdefinedexc_from_str(
+            callable.vm.clone(),
+            &format!(
+                "Method 'call' is not defined for '{}' type",
+                callable.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    unwrap_fast!(callable.tp.call)(callable.clone(), args)
+}
+
+//map(fn, list)
+fn builtin_map<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 2);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let fun = &args[0];
+    let list = &args[1];
+    let res = check_list(&selfv, list);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+
+    let mut out = Vec::new();
+    for item in unsafe { list.internals.arr() }.iter() {
+        let res = call_object(fun, std::slice::from_ref(item));
+        if res.is_error() {
+            return res;
+        }
+        out.push(unwrap_fast!(res));
+    }
+    MethodValue::Some(listobject::list_from(selfv.vm.clone(), out))
+}
+
+//filter(fn, list)
+fn builtin_filter<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 2);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let fun = &args[0];
+    let list = &args[1];
+    let res = check_list(&selfv, list);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+
+    let mut out = Vec::new();
+    for item in unsafe { list.internals.arr() }.iter() {
+        let res = call_object(fun, std::slice::from_ref(item));
+        if res.is_error() {
+            return res;
+        }
+        let keep = unwrap_fast!(res);
+        if !is_type_exact!(&keep, unwrap_fast!(selfv.vm.types.booltp.as_ref()).clone()) {
+            let exc = typemismatchexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Function passed to 'filter' returned '{}', expected 'bool'",
+                    keep.tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+        if unsafe { keep.internals.bool() } {
+            out.push(item.clone());
+        }
+    }
+    MethodValue::Some(listobject::list_from(selfv.vm.clone(), out))
+}
+
+//reduce(fn, list, init)
+fn builtin_reduce<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 3);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let fun = &args[0];
+    let list = &args[1];
+    let res = check_list(&selfv, list);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+
+    let mut acc = args[2].clone();
+    for item in unsafe { list.internals.arr() }.iter() {
+        let res = call_object(fun, &[acc, item.clone()]);
+        if res.is_error() {
+            return res;
+        }
+        acc = unwrap_fast!(res);
+    }
+    MethodValue::Some(acc)
+}
+
+//type(x)
+fn builtin_type<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let object = &args[0];
+    MethodValue::Some(typeobject::type_from(selfv.vm.clone(), object.tp.clone()))
+}
+
+//isinstance(x, T)
+fn builtin_isinstance<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 2);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let object = &args[0];
+    let tp = &args[1];
+    if !is_type_exact!(tp, unwrap_fast!(selfv.vm.types.typetp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'type', got '{}'", tp.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        object.tp.is_subtype(unsafe { tp.internals.typ() }.typeid),
+    ))
+}
+
+//super(cls, obj)
+fn builtin_super<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 2);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let cls = &args[0];
+    let object = &args[1];
+    if !is_type_exact!(cls, unwrap_fast!(selfv.vm.types.typetp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'type', got '{}'", cls.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    //obj may be an instance of cls, or cls or one of its subclasses itself
+    let typeid = unsafe { cls.internals.typ() }.typeid;
+    let is_class = is_type_exact!(object, unwrap_fast!(selfv.vm.types.typetp.as_ref()).clone())
+        && unsafe { object.internals.typ() }.is_subtype(typeid);
+    if !object.tp.is_subtype(typeid) && !is_class {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Expected an instance of '{}', got '{}'",
+                unsafe { cls.internals.typ() }.typename,
+                object.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(superobject::super_from(
+        selfv.vm.clone(),
+        cls.clone(),
+        object.clone(),
+    ))
+}
+
+//id(x)
+fn builtin_id<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        object_id(&args[0]) as isize,
+    ))
+}
+
+//is_main()
+fn builtin_is_main<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 0);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), selfv.vm.is_main))
+}
+
+//breakpoint()
+fn builtin_breakpoint<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 0);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    //Restricted code may not do IO, so it is not paused either
+    #[cfg(feature = "debugger")]
+    if !selfv.vm.restricted {
+        crate::interpreter::debugger::debug(&selfv.vm);
+    }
+    MethodValue::Some(none_from!(selfv.vm))
+}
+
+//A dict of variables by name
+fn variables_dict<'a>(vm: Trc<VM<'a>>, variables: Vec<(String, Object<'a>)>) -> Object<'a> {
+    let mut map = mhash::HashMap::new();
+    for (name, value) in variables {
+        map.insert(stringobject::string_from(vm.clone(), name), value);
+    }
+    dictobject::dict_from(vm, map)
+}
+
+//locals()
+fn builtin_locals<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 0);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(variables_dict(selfv.vm.clone(), VM::locals(&selfv.vm)))
+}
+
+//globals()
+fn builtin_globals<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 0);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(variables_dict(selfv.vm.clone(), VM::globals(&selfv.vm)))
+}
+
+//sizeof(x)
+fn builtin_sizeof<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        RawObject::object_sizeof(&args[0]) as isize,
+    ))
+}
+
+//len(x)
+fn builtin_len<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let object = &args[0];
+    let Some(len) = object.tp.len else {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Object of type '{}' has no len", object.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    };
+    let res = len(object.clone());
+    if res.is_error() {
+        return res;
+    }
+    //The len method of a class may return anything
+    let res = unwrap_fast!(res);
+    if res.as_int().is_none() {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Expected 'len' of '{}' to return 'int', got '{}'",
+                object.tp.typename, res.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(res)
+}
+
+//dir(x)
+//The names of the attributes of an object and of the types it inherits from, sorted. A class lists
+//its own methods and those of its bases.
+fn builtin_dir<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let object = &args[0];
+    let mro = match object.as_type() {
+        Some(tp) => tp.mro(),
+        None => object.tp.mro(),
+    };
+    let mut names: std::collections::BTreeSet<String> =
+        shape::attr_names(object).into_iter().collect();
+    let dicts = object
+        .dict
+        .iter()
+        .chain(mro.iter().filter_map(|tp| tp.dict.as_ref()));
+    for dict in dicts {
+        for (key, _) in unsafe { dict.internals.map() } {
+            if let Some(name) = key.as_str() {
+                names.insert(name.to_string());
+            }
+        }
+    }
+
+    let names = names
+        .into_iter()
+        .map(|name| stringobject::string_from(selfv.vm.clone(), name))
+        .collect();
+    MethodValue::Some(listobject::list_from(selfv.vm.clone(), names))
+}
+
+//The value of an int or float argument as a float, for the float predicates
+fn float_arg<'a>(selfv: &Object<'a>, args: &[Object<'a>]) -> MethodValue<f64, Object<'a>> {
+    let res = check_args(selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let value = &args[0];
+    if is_type_exact!(value, unwrap_fast!(selfv.vm.types.floattp.as_ref()).clone()) {
+        return MethodValue::Some(unsafe { value.internals.float() });
+    }
+    if is_type_exact!(value, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+        return MethodValue::Some(unsafe { value.internals.int() } as f64);
+    }
+    let exc = typemismatchexc_from_str(
+        selfv.vm.clone(),
+        &format!("Expected 'int' or 'float', got '{}'", value.tp.typename),
+        Position::default(),
+        Position::default(),
+    );
+    MethodValue::Error(exc)
+}
+
+//isnan(x)
+fn builtin_isnan<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let value = float_arg(&selfv, args);
+    if value.is_error() {
+        return MethodValue::Error(value.unwrap_err());
+    }
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        value.unwrap().is_nan(),
+    ))
+}
+
+//isinf(x)
+fn builtin_isinf<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let value = float_arg(&selfv, args);
+    if value.is_error() {
+        return MethodValue::Error(value.unwrap_err());
+    }
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        value.unwrap().is_infinite(),
+    ))
+}
+
+//copy(x)
+fn builtin_copy<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    RawObject::object_copy(args[0].clone())
+}
+
+//deepcopy(x)
+fn builtin_deepcopy<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    RawObject::object_deepcopy(args[0].clone(), &mut IdMap::new())
+}
+
+//eval(source, restricted=false)
+fn builtin_eval<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let n_args = args.len();
+    if !(1..=2).contains(&n_args) {
+        let exc = argumentexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 1 or 2 argument(s), got {}", n_args),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let source = &args[0];
+    if !is_type_exact!(source, unwrap_fast!(selfv.vm.types.strtp.as_ref())) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'str', got '{}'", source.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let restricted = match args.get(1) {
+        Some(restricted) => {
+            if !is_type_exact!(restricted, unwrap_fast!(selfv.vm.types.booltp.as_ref())) {
+                let exc = typemismatchexc_from_str(
+                    selfv.vm.clone(),
+                    &format!("Expected 'bool', got '{}'", restricted.tp.typename),
+                    Position::default(),
+                    Position::default(),
+                );
+                return MethodValue::Error(exc);
+            }
+            unsafe { restricted.internals.bool() }
+        }
+        None => false,
+    };
+    MethodValue::Some(VM::eval(
+        selfv.vm.clone(),
+        unsafe { source.internals.str() },
+        restricted,
+    ))
+}
+
+//Whether the first value is less than the second. Values that cannot be ordered raise a
+//TypeMismatchExc naming both of them.
+fn sort_lt<'a>(a: &Object<'a>, b: &Object<'a>) -> MethodValue<bool, Object<'a>> {
+    let res = RawObject::object_lt_safe(a.clone(), b.clone());
+    if !res.is_error() {
+        return res;
+    }
+    let exc = res.unwrap_err();
+    if !is_type_exact!(&exc, unwrap_fast!(a.vm.types.tpmisexctp.as_ref()).clone())
+        && !is_type_exact!(
+            &exc,
+            unwrap_fast!(a.vm.types.mthntfndexctp.as_ref()).clone()
+        )
+    {
+        return MethodValue::Error(exc);
+    }
+    let repr = |object: &Object<'a>| match RawObject::object_repr_safe(object.clone()) {
+        MethodValue::Some(repr) => format!("{} ('{}')", repr, object.tp.typename),
+        MethodValue::Error(_) => format!("'{}'", object.tp.typename),
+    };
+    let exc = typemismatchexc_from_str(
+        a.vm.clone(),
+        &format!("Cannot order {} and {}", repr(a), repr(b)),
+        Position::default(),
+        Position::default(),
+    );
+    MethodValue::Error(exc)
+}
+
+//Sort items by their keys with a merge sort, which keeps items with equal keys in order and only
+//needs lt
+fn sort_items<'a>(
+    items: &[Object<'a>],
+    keyfn: Option<&Object<'a>>,
+) -> MethodValue<Vec<Object<'a>>, Object<'a>> {
+    let mut keys = Vec::with_capacity(items.len());
+    for item in items {
+        match keyfn {
+            Some(keyfn) => {
+                let res = call_object(keyfn, std::slice::from_ref(item));
+                if res.is_error() {
+                    return MethodValue::Error(res.unwrap_err());
+                }
+                keys.push(unwrap_fast!(res));
+            }
+            None => keys.push(item.clone()),
+        }
+    }
+
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    let mut merged = Vec::with_capacity(order.len());
+    let mut width = 1;
+    while width < order.len() {
+        merged.clear();
+        for start in (0..order.len()).step_by(2 * width) {
+            let mid = (start + width).min(order.len());
+            let end = (start + 2 * width).min(order.len());
+            let (mut left, mut right) = (start, mid);
+            while left < mid && right < end {
+                //The right item is only taken first if it is strictly less, so the sort is stable
+                let res = sort_lt(&keys[order[right]], &keys[order[left]]);
+                if res.is_error() {
+                    return MethodValue::Error(res.unwrap_err());
+                }
+                if unwrap_fast!(res) {
+                    merged.push(order[right]);
+                    right += 1;
+                } else {
+                    merged.push(order[left]);
+                    left += 1;
+                }
+            }
+            merged.extend_from_slice(&order[left..mid]);
+            merged.extend_from_slice(&order[right..end]);
+        }
+        std::mem::swap(&mut order, &mut merged);
+        width *= 2;
+    }
+    MethodValue::Some(order.into_iter().map(|i| items[i].clone()).collect())
+}
+
+//The list and key function passed to sort or sorted
+fn sort_args<'a>(
+    selfv: &Object<'a>,
+    args: &[Object<'a>],
+) -> MethodValue<(Object<'a>, Option<Object<'a>>), Object<'a>> {
+    let n_args = args.len();
+    if !(1..=2).contains(&n_args) {
+        let exc = argumentexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 1 or 2 argument(s), got {}", n_args),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let list = &args[0];
+    let res = check_list(selfv, list);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some((list.clone(), args.get(1).cloned()))
+}
+
+//sort(list, keyfn): sort the list in place, by the results of keyfn if it is passed
+fn builtin_sort<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = sort_args(&selfv, args);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let (mut list, keyfn) = unwrap_fast!(res);
+    let res = sort_items(unsafe { list.internals.arr() }, keyfn.as_ref());
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    list.internals = ObjectInternals::new_arr(unwrap_fast!(res));
+    MethodValue::Some(none_from!(selfv.vm.clone()))
+}
+
+//sorted(list, keyfn): a sorted copy of the list, by the results of keyfn if it is passed
+fn builtin_sorted<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = sort_args(&selfv, args);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let (list, keyfn) = unwrap_fast!(res);
+    let res = sort_items(unsafe { list.internals.arr() }, keyfn.as_ref());
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    MethodValue::Some(listobject::list_from(selfv.vm.clone(), unwrap_fast!(res)))
+}
+
+//env.get(name): the value of an environment variable, or none if it is not set
+fn env_get<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let name = &args[0];
+    if !is_type_exact!(name, unwrap_fast!(selfv.vm.types.strtp.as_ref())) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'str', got '{}'", name.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    match std::env::var(unsafe { name.internals.str() }.as_str()) {
+        Ok(value) => MethodValue::Some(stringobject::string_from(selfv.vm.clone(), value)),
+        Err(_) => MethodValue::Some(none_from!(selfv.vm.clone())),
+    }
+}
+
+//env.args(): the command line arguments after the script name
+fn env_args<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 0);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let args = selfv
+        .vm
+        .args
+        .iter()
+        .map(|arg| stringobject::string_from(selfv.vm.clone(), arg.clone()))
+        .collect();
+    MethodValue::Some(listobject::list_from(selfv.vm.clone(), args))
+}
+
+//env.exit(code): stop the program with the exit code
+fn env_exit<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_args(&selfv, args, 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let code = &args[0];
+    if !is_type_exact!(code, unwrap_fast!(selfv.vm.types.inttp.as_ref())) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'int', got '{}'", code.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    //The status of a process is a byte, so a larger code would exit with another status
+    match u8::try_from(unsafe { code.internals.int() }) {
+        Ok(code) => {
+            selfv.vm.print_stats();
+            Exit::raise(ExitCode::Exit(code as i32))
+        }
+        Err(_) => {
+            let exc = valueexc_from_str(
+                selfv.vm.clone(),
+                "Exit code out of range, expected 0 to 255",
+                Position::default(),
+                Position::default(),
+            );
+            MethodValue::Error(exc)
+        }
+    }
+}
+
+//The env module, for scripts that behave like command line tools
+fn env_module(vm: Trc<VM<'_>>) -> Object<'_> {
+    let members: [(&str, BuiltinFn); 3] =
+        [("get", env_get), ("args", env_args), ("exit", env_exit)];
+    let mut map = mhash::HashMap::new();
+    for (name, fun) in members {
+        let builtin = builtinobject::io_builtin_from(vm.clone(), name.to_string(), fun);
+        map.insert(
+            stringobject::string_from(vm.clone(), name.to_string()),
+            builtin,
+        );
+    }
+    moduleobject::module_from(
+        vm.clone(),
+        String::from("env"),
+        dictobject::dict_from(vm.clone(), map),
+    )
+}
+
+//The levels of log messages, from the least to the most severe
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        match name.to_lowercase().as_str() {
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+
+    fn label(&self) -> ColoredString {
+        match self {
+            LogLevel::Debug => "DEBUG".blue(),
+            LogLevel::Info => "INFO".green(),
+            LogLevel::Warn => "WARN".yellow(),
+            LogLevel::Error => "ERROR".red().bold(),
+        }
+    }
+}
+
+//The UTC time of day, as hh:mm:ss.mmm
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_millis()
+    )
+}
+
+//Print the arguments separated by spaces to stderr, if the level is at least the minimum level.
+//The timestamp is left out with --deterministic, so that runs print the same output.
+fn log<'a>(selfv: Object<'a>, args: &[Object<'a>], level: LogLevel) -> MethodType<'a> {
+    if level < selfv.vm.log_level {
+        return MethodValue::Some(none_from!(selfv.vm.clone()));
+    }
+    let mut parts = Vec::new();
+    for arg in args.iter() {
+        match RawObject::object_str_safe(arg.clone()) {
+            MethodValue::Some(part) => parts.push(part),
+            MethodValue::Error(exc) => return MethodValue::Error(exc),
+        }
+    }
+    if selfv.vm.deterministic {
+        eprintln!("{:<5} {}", level.label(), parts.join(" "));
+    } else {
+        eprintln!(
+            "{} {:<5} {}",
+            timestamp().dimmed(),
+            level.label(),
+            parts.join(" ")
+        );
+    }
+    MethodValue::Some(none_from!(selfv.vm.clone()))
+}
+
+//log.debug(*args), log.info(*args), log.warn(*args), log.error(*args)
+fn log_debug<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    log(selfv, args, LogLevel::Debug)
+}
+
+fn log_info<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    log(selfv, args, LogLevel::Info)
+}
+
+fn log_warn<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    log(selfv, args, LogLevel::Warn)
+}
+
+fn log_error<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    log(selfv, args, LogLevel::Error)
+}
+
+//The log module, for diagnostics that can be filtered by level
+fn log_module(vm: Trc<VM<'_>>) -> Object<'_> {
+    let members: [(&str, BuiltinFn); 4] = [
+        ("debug", log_debug),
+        ("info", log_info),
+        ("warn", log_warn),
+        ("error", log_error),
+    ];
+    let mut map = mhash::HashMap::new();
+    for (name, fun) in members {
+        let builtin = builtinobject::io_builtin_from(vm.clone(), name.to_string(), fun);
+        map.insert(
+            stringobject::string_from(vm.clone(), name.to_string()),
+            builtin,
+        );
+    }
+    moduleobject::module_from(
+        vm.clone(),
+        String::from("log"),
+        dictobject::dict_from(vm.clone(), map),
+    )
+}
+
+pub fn init_builtins(mut vm: Trc<VM<'_>>) {
+    let builtins: [(&str, BuiltinFn); 21] = [
+        ("map", builtin_map),
+        ("filter", builtin_filter),
+        ("reduce", builtin_reduce),
+        ("type", builtin_type),
+        ("isinstance", builtin_isinstance),
+        ("super", builtin_super),
+        ("id", builtin_id),
+        ("is_main", builtin_is_main),
+        ("breakpoint", builtin_breakpoint),
+        ("locals", builtin_locals),
+        ("globals", builtin_globals),
+        ("sizeof", builtin_sizeof),
+        ("len", builtin_len),
+        ("dir", builtin_dir),
+        ("isnan", builtin_isnan),
+        ("isinf", builtin_isinf),
+        ("copy", builtin_copy),
+        ("deepcopy", builtin_deepcopy),
+        ("eval", builtin_eval),
+        ("sort", builtin_sort),
+        ("sorted", builtin_sorted),
+    ];
+    for (name, fun) in builtins {
+        let builtin = builtinobject::builtin_from(vm.clone(), name.to_string(), fun);
+        vm.builtins.insert(name.to_string(), builtin);
+    }
+
+    let env = env_module(vm.clone());
+    vm.builtins.insert(String::from("env"), env);
+    let log = log_module(vm.clone());
+    vm.builtins.insert(String::from("log"), log);
+
+    //Builtin types, so that scripts can dispatch on the type of a value
+    let types = [
+        unwrap_fast!(vm.types.inttp.as_ref()).clone(),
+        unwrap_fast!(vm.types.bigtp.as_ref()).clone(),
+        unwrap_fast!(vm.types.floattp.as_ref()).clone(),
+        unwrap_fast!(vm.types.strtp.as_ref()).clone(),
+        unwrap_fast!(vm.types.listtp.as_ref()).clone(),
+        unwrap_fast!(vm.types.dicttp.as_ref()).clone(),
+        unwrap_fast!(vm.types.booltp.as_ref()).clone(),
+        //Exception types, so that errors can be told apart
+        unwrap_fast!(vm.types.exctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.nameexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.overflwexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.mthntfndexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.tpmisexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.keyntfndexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.valueexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.divzeroexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.attrexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.unhashableexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.indexexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.argumentexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.ioexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.notimplexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.permissionexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.timeoutexctp.as_ref()).clone(),
+        unwrap_fast!(vm.types.memoryexctp.as_ref()).clone(),
+    ];
+    for tp in types {
+        let name = tp.typename.clone();
+        let tp = typeobject::type_from(vm.clone(), tp);
+        vm.builtins.insert(name, tp);
+    }
+}
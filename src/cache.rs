@@ -0,0 +1,100 @@
+//Compiled programs stored on disk, so that running a program again skips parsing and compiling it.
+//A cache file holds a header, the warnings that compiling printed, the serialized bytecode and a
+//checksum of the warnings and bytecode. It is only used if the header matches, meaning the same
+//version of merlin, the same optimization setting and the same source, and if the checksum matches,
+//so that a file that was damaged on disk is compiled again instead of run.
+
+use crate::rc::Trc;
+use crate::{
+    compiler::{serialize, Bytecode},
+    diagnostics::Warning,
+    fileinfo::FileInfo,
+    interpreter::VM,
+};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"MERLINBC";
+//Changed whenever the format written by serialize changes
+const FORMAT_VERSION: u32 = 7;
+
+//64-bit FNV-1a, which is stable across builds unlike the std hasher
+fn hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+//The cache file of a program. The hash of the full path keeps files with the same name apart.
+fn cache_path(dir: &Path, file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let full = path.canonicalize().unwrap_or(path.to_path_buf());
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    dir.join(format!(
+        "{}-{:016x}.mbc",
+        stem,
+        hash(full.to_string_lossy().as_bytes())
+    ))
+}
+
+fn header(source: &[u8], optimize: bool) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    header.extend_from_slice(&(version.len() as u64).to_le_bytes());
+    header.extend_from_slice(version);
+    header.push(optimize as u8);
+    header.extend_from_slice(&(source.len() as u64).to_le_bytes());
+    header.extend_from_slice(&hash(source).to_le_bytes());
+    header
+}
+
+//The cached bytecode of a program and the warnings that compiling it printed, if the cache has it
+//for this source
+pub fn load<'a>(
+    dir: &Path,
+    info: &'a FileInfo<'a>,
+    optimize: bool,
+    vm: Trc<VM<'a>>,
+) -> Option<(Trc<Bytecode<'a>>, Vec<Warning>)> {
+    let data = std::fs::read(cache_path(dir, &info.name)).ok()?;
+    let data = data.strip_prefix(header(info.data, optimize).as_slice())?;
+    let (data, checksum) = data.split_at(data.len().checked_sub(8)?);
+    if hash(data).to_le_bytes() != checksum {
+        return None;
+    }
+    let (warnings, body) = serialize::deserialize_warnings(data, info, vm.clone())?;
+    Some((serialize::deserialize(body, info, vm)?, warnings))
+}
+
+//Store the bytecode of a program. Failing to write the cache only makes the next run slower, so
+//errors are ignored.
+pub fn store<'a>(
+    dir: &Path,
+    file: &str,
+    source: &[u8],
+    optimize: bool,
+    bytecode: &Bytecode<'a>,
+    warnings: &[Warning],
+    vm: &VM<'a>,
+) {
+    let Some(body) = serialize::serialize(bytecode, vm) else {
+        return;
+    };
+    let mut contents = serialize::serialize_warnings(warnings, vm);
+    contents.extend(body);
+    let mut data = header(source, optimize);
+    data.extend_from_slice(&contents);
+    data.extend_from_slice(&hash(&contents).to_le_bytes());
+    if std::fs::create_dir_all(dir).is_err() {
+        return;
+    }
+    //Written to a temporary file first, so that a run that reads the cache never sees half a file
+    let path = cache_path(dir, file);
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    if std::fs::write(&tmp, data).is_err() || std::fs::rename(&tmp, &path).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
@@ -0,0 +1,111 @@
+//The positions of the instructions of a body, stored compactly. Each entry is encoded as
+//variable-length integers, with the line relative to the entry before and the end columns and
+//lines relative to the start, so most entries take a few bytes. Entries are decoded when an error
+//needs them, starting from the closest checkpoint.
+
+use crate::parser::Position;
+
+//How many entries there are between checkpoints
+const CHECKPOINT_INTERVAL: usize = 32;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineTable {
+    data: Vec<u8>,
+    checkpoints: Vec<(usize, usize)>, //Offset in data and line before every CHECKPOINT_INTERVAL entries
+    len: usize,
+}
+
+fn push_unsigned(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+//Signed values are zigzag encoded, so that small negative values are small too
+fn push_signed(data: &mut Vec<u8>, value: isize) {
+    push_unsigned(data, ((value << 1) ^ (value >> (isize::BITS - 1))) as usize);
+}
+
+fn read_unsigned(data: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*offset];
+        *offset += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+fn read_signed(data: &[u8], offset: &mut usize) -> isize {
+    let value = read_unsigned(data, offset);
+    ((value >> 1) as isize) ^ -((value & 1) as isize)
+}
+
+//Add a signed delta to an unsigned position
+fn add_delta(base: usize, delta: isize) -> usize {
+    base.wrapping_add_signed(delta)
+}
+
+impl LineTable {
+    pub fn new(positions: &[(Position, Position)]) -> LineTable {
+        let mut table = LineTable::default();
+        let mut line = 0;
+        for (start, end) in positions {
+            if table.len % CHECKPOINT_INTERVAL == 0 {
+                table.checkpoints.push((table.data.len(), line));
+            }
+            push_signed(&mut table.data, start.line as isize - line as isize);
+            push_unsigned(&mut table.data, start.startcol);
+            push_signed(
+                &mut table.data,
+                start.endcol as isize - start.startcol as isize,
+            );
+            push_signed(&mut table.data, end.line as isize - start.line as isize);
+            push_signed(
+                &mut table.data,
+                end.startcol as isize - start.startcol as isize,
+            );
+            push_signed(&mut table.data, end.endcol as isize - end.startcol as isize);
+            line = start.line;
+            table.len += 1;
+        }
+        table
+    }
+
+    //The start and end of the instruction at an index
+    pub fn get(&self, idx: usize) -> Option<(Position, Position)> {
+        if idx >= self.len {
+            return None;
+        }
+        let (mut offset, mut line) = self.checkpoints[idx / CHECKPOINT_INTERVAL];
+        let data = &self.data;
+        let mut res = None;
+        for _ in 0..=idx % CHECKPOINT_INTERVAL {
+            line = add_delta(line, read_signed(data, &mut offset));
+            let startcol = read_unsigned(data, &mut offset);
+            let endcol = add_delta(startcol, read_signed(data, &mut offset));
+            let end_line = add_delta(line, read_signed(data, &mut offset));
+            let end_startcol = add_delta(startcol, read_signed(data, &mut offset));
+            let end_endcol = add_delta(end_startcol, read_signed(data, &mut offset));
+            res = Some((
+                Position {
+                    startcol,
+                    endcol,
+                    line,
+                },
+                Position {
+                    startcol: end_startcol,
+                    endcol: end_endcol,
+                    line: end_line,
+                },
+            ));
+        }
+        res
+    }
+}
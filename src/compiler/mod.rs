@@ -1,38 +1,59 @@
 //Generate bytecode from AST
+
+pub mod linetable;
+pub mod resolver;
+pub mod serialize;
+
 use crate::objects::{exceptionobject, RawObject};
+use crate::rc::Trc;
 use crate::{
-    errors::{raise_error, ErrorType},
+    errors::{print_warning, raise_error, ErrorType, OrRaise, WarningType},
     fileinfo::FileInfo,
-    interpreter::VM,
-    objects::{codeobject, intobject, listobject, stringobject, Object},
+    interpreter::{quicken::Feedback, Namespace, VM},
+    objects::{codeobject, floatobject, intobject, listobject, stringobject, Object},
     parser::{
         self,
         nodes::{NodeType, OpType},
         Position,
     },
+    unwrap_fast,
 };
-use colored::Colorize;
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use itertools::{izip, Itertools};
+use linetable::LineTable;
+use std::cell::Cell;
 use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use trc::Trc;
+use std::ops::Deref;
 
 pub struct Compiler<'a> {
     instructions: Vec<CompilerInstruction<'a>>,
     consts: Vec<Object<'a>>,
-    names: HashMap<String, i32>,
+    attr_names: Vec<String>, //Names of the attributes that the body loads
+    names: Names,            //Shared with the bodies nested in this one, which may load them
     info: &'a FileInfo<'a>,
     vm: Trc<VM<'a>>,
     positions: Vec<(Position, Position)>,
+    bodies: Vec<Trc<Bytecode<'a>>>, //The functions, classes and comprehensions defined in the body
     register_index: i32,
     register_max: i32,
 
-    undef_index: i32,
-    undef_names: HashMap<i32, String>,
+    enclosing: Vec<Names>, //Names of enclosing scopes, outermost first
+    outer_names: IndexMap<(usize, usize), String>,
+    is_class_body: bool,
+    with_depth: usize, //Number of with statements around the current statement
+    optimize: bool,    //Inline calls to small functions (-O)
+    named_consts: IndexMap<String, Object<'a>>, //Names declared with const, loaded as constants
+    n_variables: i32,  //Number of variables, including the ones of names that are out of scope
+    blocks: Vec<Vec<(String, Option<i32>)>>, //Names declared with let in each block, and the variables they shadow
+    out_of_scope: IndexMap<i32, String>, //Variables of names declared with let in a block that ended
+    statement: Position, //The start of the statement being compiled, for diagnostics
+    qualname: Vec<String>, //The classes and functions that the body is in, for qualified names
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum CompilerInstruction<'a> {
     BinaryAdd {
         a: CompilerRegister,
@@ -52,23 +73,123 @@ pub enum CompilerInstruction<'a> {
         result: CompilerRegister,
         i: usize,
     },
+    //Specialized for ints by the compiler: guarded, so they fall back to the slot for other types
+    BinaryAddInt {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinarySubInt {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryMulInt {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
     BinaryDiv {
         a: CompilerRegister,
         b: CompilerRegister,
         result: CompilerRegister,
         i: usize,
     },
+    BinaryEq {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryNe {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryLt {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryGt {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryLe {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryGe {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    //Whether a is in b, from the contains method of b
+    BinaryContains {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryBitAnd {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryBitOr {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryBitXor {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryLShift {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
+    BinaryRShift {
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
     CopyRegister {
         from: CompilerRegister,
         to: CompilerRegister,
         i: usize,
     },
+    //A copy from a register that is not read again, so the value is moved instead of cloned
+    MoveRegister {
+        from: CompilerRegister,
+        to: CompilerRegister,
+        i: usize,
+    },
     MakeFunction {
         nameidx: usize,
         argsidx: usize,
         codeidx: usize,
+        bodyidx: usize, //In Bytecode::bodies, with the positions of the body
         out: CompilerRegister,
-    }, //All are in consts
+        is_abstract: bool,
+    }, //All but bodyidx are in consts
     Call {
         callableregister: CompilerRegister,
         result: CompilerRegister,
@@ -79,11 +200,54 @@ pub enum CompilerInstruction<'a> {
         register: CompilerRegister,
         i: usize,
     },
+    //Superinstructions, which do the work of two instructions that often follow each other with
+    //one dispatch (see Compiler::fuse_instructions)
+    //An AttrLoad followed by a Call of the attribute
+    CallAttr {
+        left: CompilerRegister,
+        nameidx: usize, //Index into Code::attr_names
+        result: CompilerRegister,
+        arg_registers: Vec<RegisterContext>,
+        i: usize,
+    },
+    //A CopyRegister or MoveRegister followed by a Return of the register it stored to
+    StoreReturn {
+        from: CompilerRegister,
+        to: CompilerRegister,
+        i: usize,
+    },
+    //Skip the next n instructions if the register holds false
+    SkipIfFalse {
+        register: CompilerRegister,
+        n: usize,
+        i: usize,
+    },
+    EnterContext {
+        register: CompilerRegister,
+        out: CompilerRegister,
+        i: usize,
+    },
+    ExitContext {
+        i: usize,
+    },
+    Comprehension {
+        iterable: CompilerRegister,
+        codeidx: usize, //In consts
+        bodyidx: usize, //In Bytecode::bodies
+        is_dict: bool,
+        result: CompilerRegister,
+        i: usize,
+    },
     UnaryNeg {
         a: CompilerRegister,
         result: CompilerRegister,
         i: usize,
     },
+    UnaryInvert {
+        a: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    },
     BuildList {
         result: CompilerRegister,
         value_registers: Vec<CompilerRegister>,
@@ -95,25 +259,53 @@ pub enum CompilerInstruction<'a> {
         value_registers: Vec<CompilerRegister>,
         i: usize,
     },
+    //The dict of a class, from the variables that its body bound when it ended
+    BuildClassDict {
+        result: CompilerRegister,
+        names: Vec<usize>,     //Indices into Code::attr_names
+        variables: Vec<usize>, //The variable of each name
+        i: usize,
+    },
     MakeClass {
         name: String,
-        methods: HashMap<i32, String>,
+        bases: Vec<CompilerRegister>,
         out: CompilerRegister,
-        bytecode: Trc<Bytecode<'a>>,
+        code: Trc<Code<'a>>,
+        bodyidx: usize, //In Bytecode::bodies
+        i: usize,
     },
     AttrLoad {
         left: CompilerRegister,
-        attridx: CompilerRegister,
+        nameidx: usize, //Index into Code::attr_names
         result: CompilerRegister,
         i: usize,
     },
+    AttrStore {
+        left: CompilerRegister,
+        nameidx: usize, //Index into Code::attr_names
+        value: CompilerRegister,
+        i: usize,
+    },
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CompilerRegister {
     R(usize),
     V(usize),
     C(usize),
+    E(usize, usize), //Enclosing scope (level, outermost first), variable index
+}
+
+impl CompilerRegister {
+    //Register indices are i32 while compiling, and never negative, as registers are released in
+    //the reverse order that they are taken
+    fn register(index: i32) -> CompilerRegister {
+        CompilerRegister::R(usize::try_from(index).expect("Register index is negative"))
+    }
+
+    fn variable(index: i32) -> CompilerRegister {
+        CompilerRegister::V(usize::try_from(index).expect("Variable index is negative"))
+    }
 }
 
 impl From<CompilerRegister> for usize {
@@ -122,38 +314,517 @@ impl From<CompilerRegister> for usize {
             CompilerRegister::V(v) => v,
             CompilerRegister::R(v) => v,
             CompilerRegister::C(v) => v,
+            CompilerRegister::E(_, v) => v,
         }
     }
 }
-#[derive(Clone, PartialEq, Eq)]
-pub struct Bytecode<'a> {
+impl<'a> CompilerInstruction<'a> {
+    //The registers that an instruction reads or writes
+    pub fn registers_mut(&mut self) -> Vec<&mut CompilerRegister> {
+        match self {
+            CompilerInstruction::BinaryAdd { a, b, result, .. }
+            | CompilerInstruction::BinarySub { a, b, result, .. }
+            | CompilerInstruction::BinaryMul { a, b, result, .. }
+            | CompilerInstruction::BinaryAddInt { a, b, result, .. }
+            | CompilerInstruction::BinarySubInt { a, b, result, .. }
+            | CompilerInstruction::BinaryMulInt { a, b, result, .. }
+            | CompilerInstruction::BinaryDiv { a, b, result, .. }
+            | CompilerInstruction::BinaryEq { a, b, result, .. }
+            | CompilerInstruction::BinaryNe { a, b, result, .. }
+            | CompilerInstruction::BinaryLt { a, b, result, .. }
+            | CompilerInstruction::BinaryGt { a, b, result, .. }
+            | CompilerInstruction::BinaryLe { a, b, result, .. }
+            | CompilerInstruction::BinaryGe { a, b, result, .. }
+            | CompilerInstruction::BinaryContains { a, b, result, .. }
+            | CompilerInstruction::BinaryBitAnd { a, b, result, .. }
+            | CompilerInstruction::BinaryBitOr { a, b, result, .. }
+            | CompilerInstruction::BinaryBitXor { a, b, result, .. }
+            | CompilerInstruction::BinaryLShift { a, b, result, .. }
+            | CompilerInstruction::BinaryRShift { a, b, result, .. } => vec![a, b, result],
+            CompilerInstruction::CopyRegister { from, to, .. }
+            | CompilerInstruction::MoveRegister { from, to, .. } => vec![from, to],
+            CompilerInstruction::MakeFunction { out, .. } => vec![out],
+            CompilerInstruction::Call {
+                callableregister,
+                result,
+                arg_registers,
+                ..
+            } => {
+                let mut registers = vec![callableregister, result];
+                registers.extend(arg_registers.iter_mut().map(|arg| &mut arg.value));
+                registers
+            }
+            CompilerInstruction::CallAttr {
+                left,
+                result,
+                arg_registers,
+                ..
+            } => {
+                let mut registers = vec![left, result];
+                registers.extend(arg_registers.iter_mut().map(|arg| &mut arg.value));
+                registers
+            }
+            CompilerInstruction::StoreReturn { from, to, .. } => vec![from, to],
+            CompilerInstruction::Return { register, .. }
+            | CompilerInstruction::SkipIfFalse { register, .. } => vec![register],
+            CompilerInstruction::EnterContext { register, out, .. } => vec![register, out],
+            CompilerInstruction::ExitContext { .. } => Vec::new(),
+            CompilerInstruction::Comprehension {
+                iterable, result, ..
+            } => vec![iterable, result],
+            CompilerInstruction::UnaryNeg { a, result, .. }
+            | CompilerInstruction::UnaryInvert { a, result, .. } => vec![a, result],
+            CompilerInstruction::BuildList {
+                result,
+                value_registers,
+                ..
+            } => {
+                let mut registers = vec![result];
+                registers.extend(value_registers.iter_mut());
+                registers
+            }
+            CompilerInstruction::BuildDict {
+                result,
+                key_registers,
+                value_registers,
+                ..
+            } => {
+                let mut registers = vec![result];
+                registers.extend(key_registers.iter_mut());
+                registers.extend(value_registers.iter_mut());
+                registers
+            }
+            CompilerInstruction::BuildClassDict { result, .. } => vec![result],
+            CompilerInstruction::MakeClass { bases, out, .. } => {
+                let mut registers = vec![out];
+                registers.extend(bases.iter_mut());
+                registers
+            }
+            CompilerInstruction::AttrLoad { left, result, .. } => vec![left, result],
+            CompilerInstruction::AttrStore { left, value, .. } => vec![left, value],
+        }
+    }
+
+    //The registers that an instruction reads, which are the ones that it uses other than the one
+    //it writes, unless it also reads that
+    pub fn read_registers(&self) -> Vec<CompilerRegister> {
+        let mut registers = self
+            .clone()
+            .registers_mut()
+            .into_iter()
+            .map(|register| *register)
+            .collect_vec();
+        if let Some(written) = self.written_register() {
+            if let Some(idx) = registers.iter().position(|register| *register == written) {
+                registers.remove(idx);
+            }
+        }
+        registers
+    }
+}
+
+impl<'a> CompilerInstruction<'a> {
+    //The name of the opcode, for --count-instructions and --trace
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompilerInstruction::BinaryAdd { .. } => "BinaryAdd",
+            CompilerInstruction::BinarySub { .. } => "BinarySub",
+            CompilerInstruction::BinaryMul { .. } => "BinaryMul",
+            CompilerInstruction::BinaryAddInt { .. } => "BinaryAddInt",
+            CompilerInstruction::BinarySubInt { .. } => "BinarySubInt",
+            CompilerInstruction::BinaryMulInt { .. } => "BinaryMulInt",
+            CompilerInstruction::BinaryDiv { .. } => "BinaryDiv",
+            CompilerInstruction::BinaryEq { .. } => "BinaryEq",
+            CompilerInstruction::BinaryNe { .. } => "BinaryNe",
+            CompilerInstruction::BinaryLt { .. } => "BinaryLt",
+            CompilerInstruction::BinaryGt { .. } => "BinaryGt",
+            CompilerInstruction::BinaryLe { .. } => "BinaryLe",
+            CompilerInstruction::BinaryGe { .. } => "BinaryGe",
+            CompilerInstruction::BinaryContains { .. } => "BinaryContains",
+            CompilerInstruction::BinaryBitAnd { .. } => "BinaryBitAnd",
+            CompilerInstruction::BinaryBitOr { .. } => "BinaryBitOr",
+            CompilerInstruction::BinaryBitXor { .. } => "BinaryBitXor",
+            CompilerInstruction::BinaryLShift { .. } => "BinaryLShift",
+            CompilerInstruction::BinaryRShift { .. } => "BinaryRShift",
+            CompilerInstruction::CopyRegister { .. } => "CopyRegister",
+            CompilerInstruction::MoveRegister { .. } => "MoveRegister",
+            CompilerInstruction::MakeFunction { .. } => "MakeFunction",
+            CompilerInstruction::Call { .. } => "Call",
+            CompilerInstruction::Return { .. } => "Return",
+            CompilerInstruction::CallAttr { .. } => "CallAttr",
+            CompilerInstruction::StoreReturn { .. } => "StoreReturn",
+            CompilerInstruction::SkipIfFalse { .. } => "SkipIfFalse",
+            CompilerInstruction::EnterContext { .. } => "EnterContext",
+            CompilerInstruction::ExitContext { .. } => "ExitContext",
+            CompilerInstruction::Comprehension { .. } => "Comprehension",
+            CompilerInstruction::UnaryNeg { .. } => "UnaryNeg",
+            CompilerInstruction::UnaryInvert { .. } => "UnaryInvert",
+            CompilerInstruction::BuildList { .. } => "BuildList",
+            CompilerInstruction::BuildDict { .. } => "BuildDict",
+            CompilerInstruction::BuildClassDict { .. } => "BuildClassDict",
+            CompilerInstruction::MakeClass { .. } => "MakeClass",
+            CompilerInstruction::AttrLoad { .. } => "AttrLoad",
+            CompilerInstruction::AttrStore { .. } => "AttrStore",
+        }
+    }
+
+    //The register that an instruction stores its result in
+    pub fn written_register(&self) -> Option<CompilerRegister> {
+        match self {
+            CompilerInstruction::BinaryAdd { result, .. }
+            | CompilerInstruction::BinarySub { result, .. }
+            | CompilerInstruction::BinaryMul { result, .. }
+            | CompilerInstruction::BinaryAddInt { result, .. }
+            | CompilerInstruction::BinarySubInt { result, .. }
+            | CompilerInstruction::BinaryMulInt { result, .. }
+            | CompilerInstruction::BinaryDiv { result, .. }
+            | CompilerInstruction::BinaryEq { result, .. }
+            | CompilerInstruction::BinaryNe { result, .. }
+            | CompilerInstruction::BinaryLt { result, .. }
+            | CompilerInstruction::BinaryGt { result, .. }
+            | CompilerInstruction::BinaryLe { result, .. }
+            | CompilerInstruction::BinaryGe { result, .. }
+            | CompilerInstruction::BinaryContains { result, .. }
+            | CompilerInstruction::BinaryBitAnd { result, .. }
+            | CompilerInstruction::BinaryBitOr { result, .. }
+            | CompilerInstruction::BinaryBitXor { result, .. }
+            | CompilerInstruction::BinaryLShift { result, .. }
+            | CompilerInstruction::BinaryRShift { result, .. }
+            | CompilerInstruction::Call { result, .. }
+            | CompilerInstruction::CallAttr { result, .. }
+            | CompilerInstruction::Comprehension { result, .. }
+            | CompilerInstruction::UnaryNeg { result, .. }
+            | CompilerInstruction::UnaryInvert { result, .. }
+            | CompilerInstruction::BuildList { result, .. }
+            | CompilerInstruction::BuildDict { result, .. }
+            | CompilerInstruction::BuildClassDict { result, .. }
+            | CompilerInstruction::AttrLoad { result, .. } => Some(*result),
+            CompilerInstruction::CopyRegister { to, .. }
+            | CompilerInstruction::MoveRegister { to, .. }
+            | CompilerInstruction::StoreReturn { to, .. } => Some(*to),
+            CompilerInstruction::MakeFunction { out, .. }
+            | CompilerInstruction::EnterContext { out, .. }
+            | CompilerInstruction::MakeClass { out, .. } => Some(*out),
+            CompilerInstruction::Return { .. }
+            | CompilerInstruction::SkipIfFalse { .. }
+            | CompilerInstruction::ExitContext { .. }
+            | CompilerInstruction::AttrStore { .. } => None,
+        }
+    }
+
+    //The index of the instruction's entry in the positions
+    pub fn index_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            CompilerInstruction::BinaryAdd { i, .. }
+            | CompilerInstruction::BinarySub { i, .. }
+            | CompilerInstruction::BinaryMul { i, .. }
+            | CompilerInstruction::BinaryAddInt { i, .. }
+            | CompilerInstruction::BinarySubInt { i, .. }
+            | CompilerInstruction::BinaryMulInt { i, .. }
+            | CompilerInstruction::BinaryDiv { i, .. }
+            | CompilerInstruction::BinaryEq { i, .. }
+            | CompilerInstruction::BinaryNe { i, .. }
+            | CompilerInstruction::BinaryLt { i, .. }
+            | CompilerInstruction::BinaryGt { i, .. }
+            | CompilerInstruction::BinaryLe { i, .. }
+            | CompilerInstruction::BinaryGe { i, .. }
+            | CompilerInstruction::BinaryContains { i, .. }
+            | CompilerInstruction::BinaryBitAnd { i, .. }
+            | CompilerInstruction::BinaryBitOr { i, .. }
+            | CompilerInstruction::BinaryBitXor { i, .. }
+            | CompilerInstruction::BinaryLShift { i, .. }
+            | CompilerInstruction::BinaryRShift { i, .. }
+            | CompilerInstruction::CopyRegister { i, .. }
+            | CompilerInstruction::MoveRegister { i, .. }
+            | CompilerInstruction::Call { i, .. }
+            | CompilerInstruction::CallAttr { i, .. }
+            | CompilerInstruction::Return { i, .. }
+            | CompilerInstruction::StoreReturn { i, .. }
+            | CompilerInstruction::SkipIfFalse { i, .. }
+            | CompilerInstruction::EnterContext { i, .. }
+            | CompilerInstruction::ExitContext { i }
+            | CompilerInstruction::Comprehension { i, .. }
+            | CompilerInstruction::UnaryNeg { i, .. }
+            | CompilerInstruction::UnaryInvert { i, .. }
+            | CompilerInstruction::BuildList { i, .. }
+            | CompilerInstruction::BuildDict { i, .. }
+            | CompilerInstruction::BuildClassDict { i, .. }
+            | CompilerInstruction::MakeClass { i, .. }
+            | CompilerInstruction::AttrLoad { i, .. }
+            | CompilerInstruction::AttrStore { i, .. } => Some(i),
+            CompilerInstruction::MakeFunction { .. } => None,
+        }
+    }
+
+    //Whether the instruction may be copied into another function's bytecode: it does not refer
+    //to the consts or contexts of its own function other than through registers
+    fn is_inlinable(&self) -> bool {
+        !matches!(
+            self,
+            CompilerInstruction::MakeFunction { .. }
+                | CompilerInstruction::EnterContext { .. }
+                | CompilerInstruction::ExitContext { .. }
+                | CompilerInstruction::Comprehension { .. }
+                | CompilerInstruction::BuildClassDict { .. }
+                | CompilerInstruction::MakeClass { .. }
+        )
+    }
+}
+
+//The names of the variables of a scope, by name. A scope shares them with the bodies nested in it
+//instead of copying them, so that a file with many functions stores each scope's names once.
+pub type Names = Trc<IndexMap<String, i32>>;
+
+//What a body runs: its instructions, consts and names. Bodies that run the same way share it, as a
+//code object, and each one keeps where it was compiled from in its Bytecode.
+#[derive(Clone)]
+pub struct Code<'a> {
     pub instructions: Vec<CompilerInstruction<'a>>,
     pub consts: Vec<Object<'a>>,
-    pub names: HashMap<i32, String>,
-    pub positions: Vec<(Position, Position)>,
+    pub attr_names: Vec<String>, //Attribute names, interned by the VM
+    pub names: IndexMap<i32, String>,
+    pub scope_names: Names, //The names that are bound when the body ends
+    pub outer_names: IndexMap<(usize, usize), String>,
+    pub named_consts: IndexMap<String, Object<'a>>, //Constants that the code may load, for eval
     pub n_registers: i32,
     pub n_variables: i32,
+    pub feedback: Vec<Cell<Feedback>>, //What each instruction has run with, for quickening
+    #[cfg(feature = "jit")]
+    pub jit: Cell<crate::interpreter::jit::JitState>, //Calls so far, or the native code of the body
     _marker: PhantomData<&'a ()>,
 }
 
-impl Debug for Bytecode<'_> {
+//A body as it runs: its code, and where it was compiled from, for errors and eval
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bytecode<'a> {
+    pub code: Trc<Code<'a>>,
+    pub positions: LineTable,
+    pub info: &'a FileInfo<'a>, //The file that the body was compiled from, for errors
+    pub enclosing_names: Vec<Names>, //The scope_names of the enclosing bodies, for eval
+    pub bodies: Vec<Trc<Bytecode<'a>>>, //The functions, classes and comprehensions defined in it, by bodyidx
+}
+
+impl<'a> Deref for Bytecode<'a> {
+    type Target = Code<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.code
+    }
+}
+
+//Two bodies are equal when they run the same way: the same instructions, names and equal consts.
+//Where they came from is not compared, so identical functions share one code object, and each
+//keeps its own positions and scopes in its Bytecode.
+impl PartialEq for Code<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions == other.instructions
+            && self.consts.len() == other.consts.len()
+            && izip!(&self.consts, &other.consts).all(|(a, b)| const_eq(a, b))
+            && self.attr_names == other.attr_names
+            && self.names == other.names
+            && self.scope_names == other.scope_names
+            && self.outer_names == other.outer_names
+            && self.named_consts.len() == other.named_consts.len()
+            && self
+                .named_consts
+                .iter()
+                .all(|(name, a)| other.named_consts.get(name).is_some_and(|b| const_eq(a, b)))
+            && self.n_registers == other.n_registers
+            && self.n_variables == other.n_variables
+    }
+}
+
+impl Eq for Code<'_> {}
+
+//Only the instructions and consts are hashed, which is enough to tell most bodies apart
+impl Hash for Code<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.instructions.hash(state);
+        for object in &self.consts {
+            object.tp.typeid.hash(state);
+            if let Some(hash_fn) = object.tp.hash_fn {
+                let res = hash_fn(object.clone());
+                if !res.is_error() {
+                    res.unwrap().as_int().hash(state);
+                }
+            }
+        }
+    }
+}
+
+//Consts are equal when they are of the same type and its eq slot says so. The eq slots of the
+//builtin types return false for other types, but a const may be of any type.
+fn const_eq<'a>(a: &Object<'a>, b: &Object<'a>) -> bool {
+    if Trc::ptr_eq(a, b) {
+        return true;
+    }
+    if a.tp.typeid != b.tp.typeid {
+        return false;
+    }
+    match a.tp.eq {
+        Some(eq) => {
+            let res = eq(a.clone(), b.clone());
+            !res.is_error() && res.unwrap().as_bool() == Some(true)
+        }
+        None => false,
+    }
+}
+
+impl Debug for Code<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.instructions)
     }
 }
 
+//A constant of a body, as listed by Code::const_table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConstEntry {
+    pub idx: usize,
+    pub depth: usize, //How many code objects the constant is nested in
+    pub typename: String,
+    pub repr: String,
+}
+
+impl Code<'_> {
+    //The constant pool of the body, with the constants of each code object after it, one level
+    //deeper
+    pub fn const_table(&self) -> Vec<ConstEntry> {
+        let mut table = Vec::new();
+        self.add_const_entries(0, &mut table);
+        table
+    }
+
+    fn add_const_entries(&self, depth: usize, table: &mut Vec<ConstEntry>) {
+        for (idx, object) in self.consts.iter().enumerate() {
+            table.push(ConstEntry {
+                idx,
+                depth,
+                typename: object.tp.typename.clone(),
+                repr: RawObject::object_repr(object),
+            });
+            if let Some(code) = object.as_code() {
+                code.add_const_entries(depth + 1, table);
+            }
+        }
+    }
+}
+
+impl Bytecode<'_> {
+    //A readable listing of the body: its register and variable counts, the names of its variables,
+    //its constants and its instructions with their indices and source lines. The bodies defined in
+    //it follow, indented.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.write_dump(&mut out, 0);
+        out
+    }
+
+    fn write_dump(&self, out: &mut String, depth: usize) {
+        use std::fmt::Write;
+
+        let indent = "    ".repeat(depth);
+        let _ = writeln!(
+            out,
+            "{}registers: {}, variables: {}",
+            indent, self.n_registers, self.n_variables
+        );
+
+        let _ = writeln!(out, "{}names:", indent);
+        for (idx, name) in self.names.iter().sorted() {
+            let _ = writeln!(
+                out,
+                "{}    {:?} {}",
+                indent,
+                CompilerRegister::V(*idx as usize),
+                name
+            );
+        }
+        for ((level, idx), name) in self.outer_names.iter().sorted() {
+            let _ = writeln!(
+                out,
+                "{}    {:?} {}",
+                indent,
+                CompilerRegister::E(*level, *idx),
+                name
+            );
+        }
+
+        let _ = writeln!(out, "{}constants:", indent);
+        for (idx, object) in self.consts.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{}    {:?} {} {}",
+                indent,
+                CompilerRegister::C(idx),
+                object.tp.typename,
+                RawObject::object_repr(object)
+            );
+        }
+
+        let _ = writeln!(out, "{}instructions:", indent);
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            let line = match self.positions.get(idx) {
+                Some((start, _)) => (start.line + 1).to_string(),
+                None => String::from("?"),
+            };
+            //The names of the variables that the instruction uses
+            let names = instruction
+                .clone()
+                .registers_mut()
+                .into_iter()
+                .filter_map(|register| match *register {
+                    CompilerRegister::V(v) => self.names.get(&(v as i32)),
+                    CompilerRegister::E(level, v) => self.outer_names.get(&(level, v)),
+                    _ => None,
+                })
+                .unique()
+                .join(", ");
+            let _ = write!(
+                out,
+                "{}    {:>4} line {:<4} {:?}",
+                indent, idx, line, instruction
+            );
+            if names.is_empty() {
+                let _ = writeln!(out);
+            } else {
+                let _ = writeln!(out, "  ; {}", names);
+            }
+        }
+
+        for (idx, body) in self.bodies.iter().enumerate() {
+            let _ = writeln!(out, "{}body {}:", indent, idx);
+            body.write_dump(out, depth + 1);
+        }
+    }
+}
+
 type Node = parser::nodes::Node;
 
+//The file of code that is compiled at runtime, until compile_expr is given its source
+static EVAL_INFO: FileInfo<'static> = FileInfo {
+    data: b"",
+    name: String::new(),
+};
+
+//The longest function body that -O inlines
+const INLINE_MAX_INSTRUCTIONS: usize = 8;
+
+//The most registers that a body may use at once
+const MAX_REGISTERS: i32 = u16::MAX as i32;
+
 macro_rules! increment_reg_num {
     ($this:ident) => {
         $this.register_index += 1;
+        if $this.register_index > MAX_REGISTERS {
+            $this.raise_too_many_registers();
+        }
         if $this.register_index > $this.register_max {
             $this.register_max = $this.register_index;
         };
     };
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RegisterContext {
     pub value: CompilerRegister,
     left: Option<CompilerRegister>,
@@ -166,40 +837,815 @@ pub struct RegisterContext {
 }
 
 impl<'a> Compiler<'a> {
-    pub fn new(info: &'a FileInfo<'a>, vm: Trc<VM<'a>>) -> Compiler<'a> {
+    pub fn new(info: &'a FileInfo<'a>, vm: Trc<VM<'a>>, optimize: bool) -> Compiler<'a> {
         Compiler {
             instructions: Vec::new(),
             consts: Vec::new(),
-            names: HashMap::new(),
+            attr_names: Vec::new(),
+            names: Names::default(),
             info,
             vm,
             positions: Vec::new(),
+            bodies: Vec::new(),
             register_index: 0,
             register_max: 0,
-            undef_index: 0,
-            undef_names: HashMap::new(),
+            enclosing: Vec::new(),
+            outer_names: IndexMap::new(),
+            is_class_body: false,
+            with_depth: 0,
+            optimize,
+            named_consts: IndexMap::new(),
+            n_variables: 0,
+            blocks: Vec::new(),
+            out_of_scope: IndexMap::new(),
+            statement: Position::default(),
+            qualname: Vec::new(),
+        }
+    }
+
+    //Create a compiler for top-level code that runs in a namespace, so that it uses the variables
+    //that earlier code defined there
+    #[allow(dead_code)]
+    pub fn new_in_namespace(
+        info: &'a FileInfo<'a>,
+        vm: Trc<VM<'a>>,
+        optimize: bool,
+        namespace: &Namespace<'a>,
+    ) -> Compiler<'a> {
+        let mut compiler = Compiler::new(info, vm, optimize);
+        compiler.names = Trc::new(namespace.names().clone());
+        compiler.n_variables = namespace.names().len().max(namespace.variables().len()) as i32;
+        compiler
+    }
+
+    //Create a compiler for code that is evaluated at runtime in the scope of a running body, so
+    //that it may load the names that the body may load. See compile_expr.
+    pub fn new_in_scope(vm: Trc<VM<'a>>, scope: &Bytecode<'a>) -> Compiler<'a> {
+        let mut compiler = Compiler::new(&EVAL_INFO, vm, false);
+        compiler.names = Trc::new((*scope.scope_names).clone());
+        compiler.n_variables = scope.n_variables;
+        compiler.enclosing = scope.enclosing_names.clone();
+        compiler.named_consts = scope.named_consts.clone();
+        compiler
+    }
+
+    //Compile source code that holds a single expression into bytecode that returns its value. The
+    //VM owns the source, as the bytecode and the objects that it makes may live as long as the VM.
+    pub fn compile_expr(&mut self, source: &str) -> Trc<Bytecode<'a>> {
+        let info = VM::own_source(self.vm.clone(), source, "<eval>");
+        self.info = info;
+
+        let ast = crate::parse(info, &self.vm.diagnostics);
+        let expr = match ast.as_slice() {
+            [expr] if Self::is_expr(expr) => expr,
+            _ => raise_error(
+                "Expected a single expression",
+                ErrorType::ExpectedExpression,
+                &ast.get(1)
+                    .or(ast.first())
+                    .map_or(Position::default(), |node| node.start),
+                info,
+                &self.vm.diagnostics,
+            ),
+        };
+
+        self.statement = expr.start;
+        let ctx = self.compile_expr_values(expr);
+        let register = ctx.value;
+        self.compile_expr_operation(expr, ctx);
+        self.instructions.push(CompilerInstruction::Return {
+            register,
+            i: self.instructions.len(),
+        });
+        self.positions.push((expr.start, expr.end));
+
+        self.generate_bytecode(&Vec::new())
+    }
+
+    //Compile a class body, which returns the dict of the names that it bound, in the order that
+    //they were defined
+    fn generate_class_body(
+        &mut self,
+        ast: &Vec<Node>,
+        position: (Position, Position),
+    ) -> Trc<Bytecode<'a>> {
+        for node in ast {
+            self.compile_statement(node);
+        }
+
+        let mut bound = self
+            .names
+            .iter()
+            .map(|(name, idx)| (*idx, name.clone()))
+            .collect_vec();
+        bound.sort();
+        let (variables, names) = bound
+            .into_iter()
+            .map(|(idx, name)| {
+                (
+                    usize::try_from(idx).expect("Variable index is negative"),
+                    self.add_attr_name(&name),
+                )
+            })
+            .unzip();
+
+        let result = CompilerRegister::register(self.register_index);
+        self.instructions.push(CompilerInstruction::BuildClassDict {
+            result,
+            names,
+            variables,
+            i: self.instructions.len(),
+        });
+        self.positions.push(position);
+        self.instructions.push(CompilerInstruction::Return {
+            register: result,
+            i: self.instructions.len(),
+        });
+        self.positions.push(position);
+
+        self.generate_bytecode(&Vec::new())
+    }
+
+    //Whether a node is an expression, which produces a value and defines no names
+    fn is_expr(expr: &Node) -> bool {
+        !matches!(
+            expr.tp,
+            NodeType::StoreNode
+                | NodeType::AttrStore
+                | NodeType::Return
+                | NodeType::Function
+                | NodeType::Class
+                | NodeType::With
+                | NodeType::Const
+                | NodeType::Let
+        )
+    }
+
+    //Whether an expression only computes a value from constants and variables, without calls or
+    //stores, so that a statement of it does nothing
+    fn is_pure(expr: &Node) -> bool {
+        let data = expr.data.get_data();
+        match expr.tp {
+            NodeType::Decimal | NodeType::String | NodeType::Identifier => true,
+            NodeType::Binary => ["left", "right"].iter().all(|child| {
+                data.nodes
+                    .get(*child)
+                    .is_some_and(|node| Self::is_pure(node))
+            }),
+            NodeType::Unary => data
+                .nodes
+                .get("expr")
+                .is_some_and(|node| Self::is_pure(node)),
+            NodeType::Compare => data
+                .nodearr
+                .is_some_and(|nodes| nodes.iter().all(Self::is_pure)),
+            _ => false,
+        }
+    }
+
+    //Create a compiler for a nested body (function or class) which may load the names of this one
+    fn new_nested(&self, is_class_body: bool) -> Compiler<'a> {
+        let mut compiler = Compiler::new(self.info, self.vm.clone(), self.optimize);
+        compiler.enclosing = self.enclosing.clone();
+        if !self.is_class_body {
+            compiler.enclosing.push(self.names.clone());
+        }
+        compiler.is_class_body = is_class_body;
+        compiler.named_consts = self.named_consts.clone();
+        compiler.qualname = self.qualname.clone();
+        compiler
+    }
+
+    fn raise_too_many_registers(&self) -> ! {
+        raise_error(
+            &format!(
+                "Statement needs more than {MAX_REGISTERS} registers, split it into smaller ones"
+            ),
+            ErrorType::TooManyRegisters,
+            &self.statement,
+            self.info,
+            &self.vm.diagnostics,
+        );
+    }
+
+    fn release_registers(&mut self, n: i32) {
+        self.register_index = self
+            .register_index
+            .checked_sub(n)
+            .filter(|index| *index >= 0)
+            .expect("Released more registers than were taken");
+    }
+
+    //Names declared with const may not be bound again, in this scope or in a nested one
+    fn check_not_const(&self, name: &String, pos: &Position) {
+        if self.named_consts.contains_key(name) {
+            raise_error(
+                &format!("Cannot assign to constant '{name}'"),
+                ErrorType::ConstantReassignment,
+                pos,
+                self.info,
+                &self.vm.diagnostics,
+            );
+        }
+    }
+
+    //Get the index of a name in this scope, adding it if it is not yet defined
+    fn define_name(&mut self, name: String) -> i32 {
+        if let Some(idx) = self.names.get(&name) {
+            return *idx;
+        }
+        let idx = self.n_variables;
+        self.n_variables += 1;
+        self.names.insert(name, idx);
+        idx
+    }
+
+    //Bind a name declared with let. In a block it gets a new variable, which shadows the one that
+    //the name had until the end of the block.
+    fn declare_name(&mut self, name: String) -> i32 {
+        let Some(block) = self.blocks.last_mut() else {
+            return self.define_name(name);
+        };
+        let idx = self.n_variables;
+        self.n_variables += 1;
+        block.push((name.clone(), self.names.insert(name, idx)));
+        idx
+    }
+
+    //Unbind the names declared with let in a block that ended
+    fn end_block(&mut self) {
+        let block = self.blocks.pop().expect("No block");
+        for (name, shadowed) in block.into_iter().rev() {
+            let idx = match shadowed {
+                Some(shadowed) => self.names.insert(name.clone(), shadowed),
+                None => self.names.shift_remove(&name),
+            };
+            self.out_of_scope
+                .insert(idx.expect("Declared name is not bound"), name);
+        }
+    }
+
+    //Resolve a name that is not local by walking the enclosing scopes, innermost first
+    fn resolve_enclosing(&mut self, name: &String) -> Option<CompilerRegister> {
+        for (level, names) in self.enclosing.iter().enumerate().rev() {
+            if let Some(idx) = names.get(name) {
+                let idx = usize::try_from(*idx).expect("Variable index is negative");
+                self.outer_names.insert((level, idx), name.clone());
+                return Some(CompilerRegister::E(level, idx));
+            }
         }
+        None
+    }
+
+    //Resolve a name that is loaded: local first, then the enclosing scopes, then the builtins
+    fn resolve_name(&mut self, name: &String, start: Position, end: Position) -> CompilerRegister {
+        if let Some(v) = self.names.get(name) {
+            return CompilerRegister::variable(*v);
+        }
+        if let Some(object) = self.named_consts.get(name).cloned() {
+            return CompilerRegister::C(self.add_const(&object));
+        }
+        if let Some(register) = self.resolve_enclosing(name) {
+            return register;
+        }
+        if let Some(builtin) = self.vm.builtins.get(name) {
+            let builtin = builtin.clone();
+            let mut idx = usize::MAX;
+            for (i, var) in self.consts.iter().enumerate() {
+                if unsafe {
+                    (var.tp.eq.unwrap())(var.clone(), builtin.clone())
+                        .unwrap()
+                        .internals
+                        .bool()
+                } {
+                    idx = i;
+                    break;
+                }
+            }
+            if idx == usize::MAX {
+                self.consts.push(builtin);
+                idx = self.consts.len() - 1;
+            }
+            return CompilerRegister::C(idx);
+        }
+        let exc = exceptionobject::nameexc_from_str(
+            self.vm.clone(),
+            &format!("Name '{}' not defined", name),
+            start,
+            end,
+        );
+        self.raise_exc_pos(exc, start, end);
+    }
+
+    //Compile the body of a list or dict comprehension. It runs in its own scope, where the loop
+    //variable is the only argument, and returns the element (or a [key, value] list).
+    fn compile_comprehension(&mut self, expr: &Node) -> Trc<Bytecode<'a>> {
+        let data = expr.data.get_data();
+        let mut compiler = self.new_nested(false);
+        compiler.statement = expr.start;
+        compiler.define_name(
+            expr.raw("name")
+                .or_raise(self.info, &self.vm.diagnostics)
+                .clone(),
+        );
+
+        let mut nodes = Vec::new();
+        if let Some(key) = data.nodes.get("key") {
+            nodes.push(*key);
+        }
+        nodes.push(
+            expr.child("value")
+                .or_raise(self.info, &self.vm.diagnostics),
+        );
+
+        let old = compiler.register_index;
+        increment_reg_num!(compiler);
+        let ctxs = nodes
+            .iter()
+            .map(|node| compiler.compile_expr_values(node))
+            .collect_vec();
+        let registers = ctxs.iter().map(|ctx| ctx.value).collect_vec();
+        for (node, ctx) in izip!(&nodes, ctxs) {
+            compiler.compile_expr_operation(node, ctx);
+        }
+
+        let result = if expr.tp == NodeType::DictComp {
+            compiler.instructions.push(CompilerInstruction::BuildList {
+                result: CompilerRegister::register(old),
+                value_registers: registers,
+                i: compiler.instructions.len(),
+            });
+            compiler.positions.push((expr.start, expr.end));
+            CompilerRegister::register(old)
+        } else {
+            registers[0]
+        };
+        compiler.instructions.push(CompilerInstruction::Return {
+            register: result,
+            i: compiler.instructions.len(),
+        });
+        compiler.positions.push((expr.start, expr.end));
+
+        compiler.generate_bytecode(&Vec::new())
     }
 
     pub fn generate_bytecode(&mut self, ast: &Vec<Node>) -> Trc<Bytecode<'a>> {
         for head_node in ast {
             self.compile_statement(head_node);
         }
-        Trc::new(Bytecode {
+        if self.optimize {
+            self.inline_calls();
+        }
+        self.eliminate_dead_code();
+        self.move_dead_copies();
+        self.fuse_instructions();
+        let code = Code {
             instructions: self.instructions.clone(),
             consts: self.consts.clone(),
-            names: self.names.iter().map(|(k, v)| (*v, k.clone())).collect(),
-            positions: self.positions.clone(),
+            attr_names: self.attr_names.clone(),
+            names: self
+                .names
+                .iter()
+                .map(|(k, v)| (*v, k.clone()))
+                .chain(self.out_of_scope.clone())
+                .sorted_by_key(|(idx, _)| *idx)
+                .collect(),
+            scope_names: self.names.clone(),
+            outer_names: self.outer_names.clone(),
+            named_consts: self.named_consts.clone(),
             n_registers: self.register_max,
-            n_variables: self.names.len() as i32,
+            n_variables: self.n_variables,
+            feedback: vec![Cell::default(); self.instructions.len()],
+            #[cfg(feature = "jit")]
+            jit: Cell::default(),
             _marker: PhantomData,
+        };
+        Trc::new(Bytecode {
+            code: Trc::new(code),
+            positions: LineTable::new(&self.positions),
+            info: self.info,
+            enclosing_names: self.enclosing.clone(),
+            bodies: self.bodies.clone(),
         })
     }
 
+    //Replace the calls to small functions that were defined in this scope with their bodies.
+    //Calls are only replaced while the variable still holds the function, so the result is the same.
+    fn inline_calls(&mut self) {
+        let instructions = std::mem::take(&mut self.instructions);
+        let positions = std::mem::take(&mut self.positions);
+        //The inlined bodies all use the registers after the ones of this scope, as they do not
+        //overlap
+        let base = self.register_max as usize;
+
+        //Functions by the register they were made in and by the variable that holds them, as
+        //(codeidx, number of arguments)
+        let mut made: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut known: HashMap<usize, (usize, usize)> = HashMap::new();
+        //Where each instruction moved to, so that the skips over inlined calls can be resized
+        let mut moved = Vec::with_capacity(instructions.len() + 1);
+        let mut skips = Vec::new();
+        for (mut instruction, position) in instructions.into_iter().zip(positions) {
+            moved.push(self.instructions.len());
+            if matches!(instruction, CompilerInstruction::SkipIfFalse { .. }) {
+                skips.push((self.instructions.len(), moved.len() - 1));
+            }
+            let inlined = match &instruction {
+                CompilerInstruction::Call {
+                    callableregister: CompilerRegister::V(var),
+                    result,
+                    arg_registers,
+                    ..
+                } => known.get(var).and_then(|(codeidx, n_args)| {
+                    let args = arg_registers.iter().map(|arg| arg.value).collect_vec();
+                    self.inline_body(*codeidx, *n_args, &args, *result, base)
+                }),
+                _ => None,
+            };
+
+            let written = instruction.written_register();
+            match &instruction {
+                CompilerInstruction::MakeFunction {
+                    argsidx,
+                    codeidx,
+                    out: CompilerRegister::R(out),
+                    is_abstract: false,
+                    ..
+                } => {
+                    if let Some(args) = self.consts[*argsidx].as_list() {
+                        made.insert(*out, (*codeidx, args.len()));
+                    }
+                }
+                CompilerInstruction::CopyRegister {
+                    from: CompilerRegister::R(from),
+                    to: CompilerRegister::V(to),
+                    ..
+                } if made.contains_key(from) => {
+                    known.insert(*to, made[from]);
+                }
+                _ => match written {
+                    Some(CompilerRegister::R(v)) => {
+                        made.remove(&v);
+                    }
+                    Some(CompilerRegister::V(v)) => {
+                        known.remove(&v);
+                    }
+                    _ => {}
+                },
+            }
+
+            match inlined {
+                Some(body) => {
+                    for mut instruction in body {
+                        if let Some(i) = instruction.index_mut() {
+                            *i = self.instructions.len();
+                        }
+                        self.instructions.push(instruction);
+                        self.positions.push(position);
+                    }
+                }
+                None => {
+                    if let Some(i) = instruction.index_mut() {
+                        *i = self.instructions.len();
+                    }
+                    self.instructions.push(instruction);
+                    self.positions.push(position);
+                }
+            }
+        }
+        moved.push(self.instructions.len());
+
+        for (new, old) in skips {
+            if let CompilerInstruction::SkipIfFalse { n, .. } = &mut self.instructions[new] {
+                *n = moved[old + 1 + *n] - new - 1;
+            }
+        }
+    }
+
+    //The instructions of a function's body with its registers moved after base, for a call with
+    //the args registers that stores the return value in result. None if it can not be inlined.
+    fn inline_body(
+        &mut self,
+        codeidx: usize,
+        n_args: usize,
+        args: &[CompilerRegister],
+        result: CompilerRegister,
+        base: usize,
+    ) -> Option<Vec<CompilerInstruction<'a>>> {
+        let bytecode = self.consts[codeidx].as_code()?.clone();
+        //Only functions whose variables are their arguments are inlined, so the variables can
+        //live in registers. They refer to no enclosing scope, so they can not be recursive.
+        if args.len() != n_args
+            || bytecode.n_variables as usize != n_args
+            || bytecode.instructions.len() > INLINE_MAX_INSTRUCTIONS
+            || !matches!(
+                bytecode.instructions.last(),
+                Some(CompilerInstruction::Return { .. } | CompilerInstruction::StoreReturn { .. })
+            )
+        {
+            return None;
+        }
+        let mut body = bytecode.instructions.clone();
+        for instruction in &mut body {
+            if !instruction.is_inlinable()
+                || instruction
+                    .registers_mut()
+                    .iter()
+                    .any(|register| matches!(register, CompilerRegister::E(_, _)))
+            {
+                return None;
+            }
+        }
+
+        let n_registers = bytecode.n_registers as usize;
+        //The body is not inlined if its registers do not fit in the frame of this scope
+        if base + n_registers + n_args > MAX_REGISTERS as usize {
+            return None;
+        }
+        let mut res = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            res.push(CompilerInstruction::CopyRegister {
+                from: *arg,
+                to: CompilerRegister::R(base + n_registers + i),
+                i: 0,
+            });
+        }
+        for mut instruction in body {
+            for register in instruction.registers_mut() {
+                *register = match *register {
+                    CompilerRegister::R(v) => CompilerRegister::R(base + v),
+                    CompilerRegister::V(v) => CompilerRegister::R(base + n_registers + v),
+                    CompilerRegister::C(v) => {
+                        CompilerRegister::C(self.add_const(&bytecode.consts[v]))
+                    }
+                    CompilerRegister::E(_, _) => unreachable!(),
+                };
+            }
+            if let CompilerInstruction::AttrLoad { nameidx, .. }
+            | CompilerInstruction::AttrStore { nameidx, .. }
+            | CompilerInstruction::CallAttr { nameidx, .. } = &mut instruction
+            {
+                *nameidx = self.add_attr_name(&bytecode.attr_names[*nameidx]);
+            }
+            match instruction {
+                CompilerInstruction::Return { register, .. } => {
+                    res.push(CompilerInstruction::CopyRegister {
+                        from: register,
+                        to: result,
+                        i: 0,
+                    })
+                }
+                CompilerInstruction::StoreReturn { from, to, .. } => {
+                    res.push(CompilerInstruction::CopyRegister { from, to, i: 0 });
+                    res.push(CompilerInstruction::CopyRegister {
+                        from: to,
+                        to: result,
+                        i: 0,
+                    });
+                }
+                instruction => res.push(instruction),
+            }
+        }
+
+        self.register_max = self.register_max.max((base + n_registers + n_args) as i32);
+        Some(res)
+    }
+
+    //Get the index of a const, adding it if this scope does not have it. Code objects are compared
+    //by what they run, so bodies that run the same way share one.
+    fn add_const(&mut self, object: &Object<'a>) -> usize {
+        let same = |c: &Object<'a>| {
+            Trc::ptr_eq(c, object)
+                || c.as_code()
+                    .zip(object.as_code())
+                    .is_some_and(|(a, b)| a == b)
+        };
+        match self.consts.iter().position(same) {
+            Some(idx) => idx,
+            None => {
+                self.consts.push(object.clone());
+                self.consts.len() - 1
+            }
+        }
+    }
+
+    //Add a body defined in this one, and return the indices of its code in the consts and of the
+    //body in the bodies. A body that runs the same way as one before it shares its code.
+    fn add_body(&mut self, mut bytecode: Trc<Bytecode<'a>>) -> (usize, usize) {
+        let codeidx = self.add_const(&codeobject::code_from(
+            self.vm.clone(),
+            bytecode.code.clone(),
+        ));
+        bytecode.code = unwrap_fast!(self.consts[codeidx].as_code()).clone();
+        self.bodies.push(bytecode);
+        (codeidx, self.bodies.len() - 1)
+    }
+
+    //Get the index of an attribute name, adding it if this scope does not have it. The VM interns
+    //the name, so loading the attribute does not allocate a string.
+    fn add_attr_name(&mut self, name: &str) -> usize {
+        match self.attr_names.iter().position(|n| n == name) {
+            Some(idx) => idx,
+            None => {
+                VM::intern(self.vm.clone(), name);
+                self.attr_names.push(name.to_string());
+                self.attr_names.len() - 1
+            }
+        }
+    }
+
+    //There are no jumps, and skips stay within an expression, so the instructions after the first
+    //return can not be reached. Remove them, and the registers that only they used.
+    fn eliminate_dead_code(&mut self) {
+        let end = match self
+            .instructions
+            .iter()
+            .position(|instruction| matches!(instruction, CompilerInstruction::Return { .. }))
+        {
+            Some(pos) => pos + 1,
+            None => return,
+        };
+        self.instructions.truncate(end);
+        self.positions.truncate(end);
+        //The bodies are added with the instructions that make them, so the removed ones are last
+        let n_bodies = self
+            .instructions
+            .iter()
+            .filter(|instruction| {
+                matches!(
+                    instruction,
+                    CompilerInstruction::MakeFunction { .. }
+                        | CompilerInstruction::Comprehension { .. }
+                        | CompilerInstruction::MakeClass { .. }
+                )
+            })
+            .count();
+        self.bodies.truncate(n_bodies);
+
+        let mut n_registers = 0;
+        for instruction in &mut self.instructions {
+            for register in instruction.registers_mut() {
+                if let CompilerRegister::R(v) = register {
+                    n_registers = n_registers.max(*v + 1);
+                }
+            }
+        }
+        self.register_max = i32::try_from(n_registers).expect("Too many registers");
+    }
+
+    //Turn the copies from registers that are not read again into moves, so that the value is not
+    //cloned and the register does not keep it alive
+    fn move_dead_copies(&mut self) {
+        for idx in 0..self.instructions.len() {
+            if let CompilerInstruction::CopyRegister {
+                from: from @ CompilerRegister::R(_),
+                to,
+                i,
+            } = self.instructions[idx]
+            {
+                if from != to && !Self::is_read_after(&self.instructions, idx, from) {
+                    self.instructions[idx] = CompilerInstruction::MoveRegister { from, to, i };
+                }
+            }
+        }
+    }
+
+    //Whether an instruction after idx may read the value that the register holds after it. Skips
+    //only go forward, so the value is replaced at the first write that can not be skipped over.
+    fn is_read_after(
+        instructions: &[CompilerInstruction<'a>],
+        idx: usize,
+        register: CompilerRegister,
+    ) -> bool {
+        let mut skipped_until = idx;
+        for (j, instruction) in instructions.iter().enumerate().skip(idx + 1) {
+            if instruction.read_registers().contains(&register) {
+                return true;
+            }
+            if instruction.written_register() == Some(register) && j > skipped_until {
+                return false;
+            }
+            if let CompilerInstruction::SkipIfFalse { n, .. } = instruction {
+                skipped_until = skipped_until.max(j + n);
+            }
+        }
+        false
+    }
+
+    //Replace the pairs of instructions that --count-instructions shows to be the most common with
+    //superinstructions, which take one dispatch instead of two. Consts are operands of every
+    //instruction, so there is no LoadConst to fuse.
+    fn fuse_instructions(&mut self) {
+        let instructions = std::mem::take(&mut self.instructions);
+        let positions = std::mem::take(&mut self.positions);
+        //A pair is not fused if a skip lands between its instructions
+        let targets = instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(j, instruction)| match instruction {
+                CompilerInstruction::SkipIfFalse { n, .. } => Some(j + n + 1),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        //Where each instruction moved to, so that the skips can be resized
+        let mut moved = Vec::with_capacity(instructions.len() + 1);
+        let mut skips = Vec::new();
+        let mut idx = 0;
+        while idx < instructions.len() {
+            moved.push(self.instructions.len());
+            let fused = match instructions.get(idx + 1) {
+                Some(next) if !targets.contains(&(idx + 1)) => Self::fuse(&instructions, idx, next),
+                _ => None,
+            };
+            let (mut instruction, position) = match fused {
+                Some((instruction, position)) => {
+                    moved.push(self.instructions.len());
+                    idx += 2;
+                    (instruction, positions[position])
+                }
+                None => {
+                    if matches!(instructions[idx], CompilerInstruction::SkipIfFalse { .. }) {
+                        skips.push((self.instructions.len(), idx));
+                    }
+                    idx += 1;
+                    (instructions[idx - 1].clone(), positions[idx - 1])
+                }
+            };
+            if let Some(i) = instruction.index_mut() {
+                *i = self.instructions.len();
+            }
+            self.instructions.push(instruction);
+            self.positions.push(position);
+        }
+        moved.push(self.instructions.len());
+
+        for (new, old) in skips {
+            if let CompilerInstruction::SkipIfFalse { n, .. } = &mut self.instructions[new] {
+                *n = moved[old + 1 + *n] - new - 1;
+            }
+        }
+    }
+
+    //The superinstruction for the instruction at idx and the next one, if there is one, and the
+    //index of the position that its errors are reported at
+    fn fuse(
+        instructions: &[CompilerInstruction<'a>],
+        idx: usize,
+        next: &CompilerInstruction<'a>,
+    ) -> Option<(CompilerInstruction<'a>, usize)> {
+        match (&instructions[idx], next) {
+            (
+                CompilerInstruction::AttrLoad {
+                    left,
+                    nameidx,
+                    result: attr,
+                    ..
+                },
+                CompilerInstruction::Call {
+                    callableregister,
+                    result,
+                    arg_registers,
+                    i,
+                },
+            ) if callableregister == attr
+                && matches!(attr, CompilerRegister::R(_))
+                && !arg_registers.iter().any(|arg| arg.value == *attr)
+                && (result == attr || !Self::is_read_after(instructions, idx + 1, *attr)) =>
+            {
+                Some((
+                    CompilerInstruction::CallAttr {
+                        left: *left,
+                        nameidx: *nameidx,
+                        result: *result,
+                        arg_registers: arg_registers.clone(),
+                        i: *i,
+                    },
+                    idx + 1,
+                ))
+            }
+            (
+                CompilerInstruction::CopyRegister { from, to, i }
+                | CompilerInstruction::MoveRegister { from, to, i },
+                CompilerInstruction::Return { register, .. },
+            ) if register == to => Some((
+                CompilerInstruction::StoreReturn {
+                    from: *from,
+                    to: *to,
+                    i: *i,
+                },
+                idx,
+            )),
+            _ => None,
+        }
+    }
+
     fn compile_statement(&mut self, expr: &Node) {
+        self.statement = expr.start;
         match expr.tp {
             NodeType::Decimal
             | NodeType::Binary
+            | NodeType::Compare
             | NodeType::Identifier
             | NodeType::StoreNode
             | NodeType::Call
@@ -208,67 +1654,171 @@ impl<'a> Compiler<'a> {
             | NodeType::String
             | NodeType::List
             | NodeType::Dict
-            | NodeType::AttrLoad => {
+            | NodeType::ListComp
+            | NodeType::DictComp
+            | NodeType::AttrLoad
+            | NodeType::AttrStore => {
+                if Self::is_pure(expr) {
+                    print_warning(
+                        "The value of this expression is never used, assign it or remove it",
+                        WarningType::UnusedValue,
+                        &expr.start,
+                        &expr.end,
+                        self.info,
+                        &self.vm.diagnostics,
+                    );
+                }
                 let ctx = self.compile_expr_values(expr);
                 self.compile_expr_operation(expr, ctx);
             }
             NodeType::Class => {
                 let mut registers = 0;
                 let name = expr
-                    .data
-                    .get_data()
-                    .raw
-                    .get("name")
-                    .expect("Node.raw.name not found")
+                    .raw("name")
+                    .or_raise(self.info, &self.vm.diagnostics)
                     .clone();
 
-                let mut compiler = Compiler::new(self.info, self.vm.clone());
-                let bytecode = compiler.generate_bytecode(
-                    expr.data
-                        .get_data()
-                        .nodearr
-                        .expect("Node.nodearr is not present"),
+                self.check_not_const(&name, &expr.start);
+
+                let mut bases = Vec::new();
+                for base in expr.args().or_raise(self.info, &self.vm.diagnostics) {
+                    bases.push(self.resolve_name(&base, expr.start, expr.end));
+                }
+
+                //Define the name first, so that methods can refer to their class
+                let idx = self.define_name(name.clone());
+                let mut compiler = self.new_nested(true);
+                compiler.qualname.push(name.clone());
+                let bytecode = compiler.generate_class_body(
+                    expr.nodearr().or_raise(self.info, &self.vm.diagnostics),
+                    (expr.start, expr.end),
                 );
+                let code = bytecode.code.clone();
+                self.bodies.push(bytecode);
 
                 self.instructions.push(CompilerInstruction::MakeClass {
                     name: name.clone(),
-                    methods: bytecode.names.clone(),
-                    out: CompilerRegister::R(self.register_index.try_into().unwrap()),
-                    bytecode,
+                    bases,
+                    out: CompilerRegister::register(self.register_index),
+                    code,
+                    bodyidx: self.bodies.len() - 1,
+                    i: self.instructions.len(),
                 });
                 increment_reg_num!(self);
                 registers += 1;
 
                 self.positions.push((expr.start, expr.end));
 
-                self.names.insert(name, self.names.len() as i32);
                 self.instructions.push(CompilerInstruction::CopyRegister {
-                    from: CompilerRegister::R((self.register_index - 1).try_into().unwrap()),
-                    to: CompilerRegister::V(self.names.len() - 1),
+                    from: CompilerRegister::register(self.register_index - 1),
+                    to: CompilerRegister::variable(idx),
+                    i: self.instructions.len(),
+                });
+                self.positions.push((expr.start, expr.end));
+
+                self.release_registers(registers);
+            }
+            NodeType::Const => {
+                let name = &expr.raw("name").or_raise(self.info, &self.vm.diagnostics);
+                let value = expr.child("expr").or_raise(self.info, &self.vm.diagnostics);
+                self.check_not_const(name, &expr.start);
+                if self.names.contains_key(name) {
+                    raise_error(
+                        &format!(
+                            "Cannot declare '{name}' as a constant, as it is already a variable"
+                        ),
+                        ErrorType::ConstantReassignment,
+                        &expr.start,
+                        self.info,
+                        &self.vm.diagnostics,
+                    );
+                }
+                if !matches!(value.tp, NodeType::Decimal | NodeType::String) {
+                    raise_error(
+                        "Constant value must be a number or string literal",
+                        ErrorType::ConstantNotLiteral,
+                        &value.start,
+                        self.info,
+                        &self.vm.diagnostics,
+                    );
+                }
+
+                //Literals are loaded from the consts, so no instructions are needed
+                let old = self.register_index;
+                let ctx = self.compile_expr_values(value);
+                self.register_index = old;
+                let object = self.consts[usize::from(ctx.value)].clone();
+                self.named_consts.insert(name.clone(), object);
+            }
+            NodeType::Let => {
+                let name = &expr.raw("name").or_raise(self.info, &self.vm.diagnostics);
+                let value = expr.child("expr").or_raise(self.info, &self.vm.diagnostics);
+                self.check_not_const(name, &expr.start);
+
+                let old = self.register_index;
+                let ctx = self.compile_expr_values(value);
+                let register = ctx.value;
+                self.compile_expr_operation(value, ctx);
+                self.register_index = old;
+
+                let idx = self.declare_name(name.clone());
+                self.instructions.push(CompilerInstruction::CopyRegister {
+                    from: register,
+                    to: CompilerRegister::variable(idx),
+                    i: self.instructions.len(),
+                });
+                self.positions.push((expr.start, expr.end));
+            }
+            NodeType::With => {
+                let manager = expr.child("expr").or_raise(self.info, &self.vm.diagnostics);
+                let ctx = self.compile_expr_values(manager);
+                let register = ctx.value;
+                self.compile_expr_operation(manager, ctx);
+
+                let idx = self.define_name(
+                    expr.raw("name")
+                        .or_raise(self.info, &self.vm.diagnostics)
+                        .clone(),
+                );
+                self.instructions.push(CompilerInstruction::EnterContext {
+                    register,
+                    out: CompilerRegister::variable(idx),
                     i: self.instructions.len(),
                 });
                 self.positions.push((expr.start, expr.end));
 
-                self.register_index -= registers;
+                self.with_depth += 1;
+                self.blocks.push(Vec::new());
+                for node in expr.nodearr().or_raise(self.info, &self.vm.diagnostics) {
+                    self.compile_statement(node);
+                }
+                self.end_block();
+                self.with_depth -= 1;
+
+                self.instructions.push(CompilerInstruction::ExitContext {
+                    i: self.instructions.len(),
+                });
+                self.positions.push((expr.start, expr.end));
             }
             NodeType::Function => {
                 let mut registers = 0;
                 let name_str = expr
-                    .data
-                    .get_data()
-                    .raw
-                    .get("name")
-                    .expect("Node.raw.name not found")
+                    .raw("name")
+                    .or_raise(self.info, &self.vm.diagnostics)
                     .clone();
+                self.check_not_const(&name_str, &expr.start);
 
-                let name = stringobject::string_from(self.vm.clone(), name_str.clone());
+                //Functions are named by their qualified name, as in Class.method
+                let mut qualname = self.qualname.clone();
+                qualname.push(name_str.clone());
+                let name = stringobject::string_from(self.vm.clone(), qualname.join("."));
                 let mut nameidx = usize::MAX;
                 for (i, var) in self.consts.iter().enumerate() {
                     if unsafe {
                         (var.tp.eq.unwrap())(var.clone(), name.clone())
                             .unwrap()
                             .internals
-                            .bool
+                            .bool()
                     } {
                         nameidx = i;
                         break;
@@ -279,13 +1829,11 @@ impl<'a> Compiler<'a> {
                     nameidx = self.consts.len() - 1;
                 }
 
-                let mut names = HashMap::new();
+                let mut names = IndexMap::new();
                 let mut args = Vec::new();
                 for (i, arg) in expr
-                    .data
-                    .get_data()
-                    .args
-                    .expect("Node.args is not present")
+                    .args()
+                    .or_raise(self.info, &self.vm.diagnostics)
                     .iter()
                     .enumerate()
                 {
@@ -301,7 +1849,7 @@ impl<'a> Compiler<'a> {
                         (var.tp.eq.unwrap())(var.clone(), args.clone())
                             .unwrap()
                             .internals
-                            .bool
+                            .bool()
                     } {
                         argsidx = i;
                         break;
@@ -312,52 +1860,39 @@ impl<'a> Compiler<'a> {
                     argsidx = self.consts.len() - 1;
                 }
 
-                let mut compiler = Compiler::new(self.info, self.vm.clone());
-                compiler.names = names;
-                let bytecode = compiler.generate_bytecode(
-                    expr.data
-                        .get_data()
-                        .nodearr
-                        .expect("Node.nodearr is not present"),
-                );
+                //Define the name first so that the body may refer to the function itself
+                let idx = self.define_name(name_str);
 
-                let code = codeobject::code_from(self.vm.clone(), bytecode);
-                let mut codeidx = usize::MAX;
-                for (i, var) in self.consts.iter().enumerate() {
-                    if unsafe {
-                        (var.tp.eq.unwrap())(var.clone(), code.clone())
-                            .unwrap()
-                            .internals
-                            .bool
-                    } {
-                        codeidx = i;
-                        break;
-                    }
-                }
-                if codeidx == usize::MAX {
-                    self.consts.push(code);
-                    codeidx = self.consts.len() - 1;
-                }
+                let mut compiler = self.new_nested(false);
+                compiler.qualname = qualname;
+                compiler.n_variables = names.len() as i32;
+                compiler.names = Trc::new(names);
+                let bytecode = compiler
+                    .generate_bytecode(expr.nodearr().or_raise(self.info, &self.vm.diagnostics));
+
+                let (codeidx, bodyidx) = self.add_body(bytecode);
 
                 self.instructions.push(CompilerInstruction::MakeFunction {
                     nameidx,
                     argsidx,
                     codeidx,
-                    out: CompilerRegister::R(self.register_index.try_into().unwrap()),
+                    bodyidx,
+                    out: CompilerRegister::register(self.register_index),
+                    is_abstract: expr.data.get_data().raw.get("abstract")
+                        == Some(&String::from("true")),
                 });
                 increment_reg_num!(self);
                 registers += 1;
 
                 self.positions.push((expr.start, expr.end));
 
-                self.names.insert(name_str, self.names.len() as i32);
                 self.instructions.push(CompilerInstruction::CopyRegister {
-                    from: CompilerRegister::R((self.register_index - 1).try_into().unwrap()),
-                    to: CompilerRegister::V(self.names.len() - 1),
+                    from: CompilerRegister::register(self.register_index - 1),
+                    to: CompilerRegister::variable(idx),
                     i: self.instructions.len(),
                 });
                 self.positions.push((expr.start, expr.end));
-                self.register_index -= registers;
+                self.release_registers(registers);
             }
         }
     }
@@ -369,58 +1904,55 @@ impl<'a> Compiler<'a> {
                 unimplemented!()
             }
         };
-        let location: String = format!(
-            "{}:{}:{}",
-            self.info.name,
-            start.line + 1,
-            start.startcol + 1
-        );
-        println!("{}", header.red().bold());
-        println!("{}", location.red());
-        let lines = Vec::from_iter(self.info.data.split(|num| *num as char == '\n'));
-
-        let snippet: String = format!(
-            "{}",
-            String::from_utf8(
-                lines
-                    .get(start.line)
-                    .expect("Line index out of range")
-                    .to_vec()
-            )
-            .expect("utf8 conversion failed")
-            .blue()
-        );
-        let mut arrows: String = String::new();
-        for idx in 0..snippet.len() {
-            if idx >= start.startcol && idx < end.endcol {
-                arrows += "^";
-            } else {
-                arrows += " ";
-            }
-        }
-        let linestr = (start.line + 1).to_string().blue().bold();
-        println!("{} | {}", linestr, snippet);
-        println!("{} | {}", " ".repeat(linestr.len()), arrows.green());
+        self.vm.diagnostics.report(&header, self.info, &start, &end);
 
         //Should this happen??
         VM::terminate(self.vm.clone());
     }
 
+    //Equal literals share a const, so constant keys of a dict literal that load the same const are
+    //duplicates. Only the value of the last one is kept, which is likely a mistake.
+    fn warn_duplicate_keys(&self, expr: &Node, keys: &[RegisterContext]) {
+        let mapping = expr.mapping().or_raise(self.info, &self.vm.diagnostics);
+        for (n, key) in keys.iter().enumerate() {
+            if !matches!(key.value, CompilerRegister::C(_)) {
+                continue;
+            }
+            if let Some(first) = keys[..n].iter().position(|k| k.value == key.value) {
+                let (first, _) = &mapping[first];
+                let (key, _) = &mapping[n];
+                print_warning(
+                    &format!(
+                        "Key repeats the key at line {}, column {}, only the last value is kept",
+                        first.start.line + 1,
+                        first.start.startcol + 1
+                    ),
+                    WarningType::DuplicateKey,
+                    &key.start,
+                    &key.end,
+                    self.info,
+                    &self.vm.diagnostics,
+                );
+            }
+        }
+    }
+
     //Compile the values of the node - load them all.
-    //Only increment the register_idx if new data is being added.
-    //That is - the node is atomic and does not need any other nodes.
+    //Increment the register_idx if new data is being added: that is - the node is atomic,
+    //or it produces a result register that must not be shared with any of its operands.
     fn compile_expr_values(&mut self, expr: &Node) -> RegisterContext {
         match expr.tp {
             NodeType::Decimal => {
-                let int = intobject::int_from_str(
-                    self.vm.clone(),
-                    expr.data
-                        .get_data()
-                        .raw
-                        .get("value")
-                        .expect("Node.raw.value not found")
-                        .to_string(),
-                );
+                //Underscores only separate digits, and a fraction or exponent makes a float
+                let value = expr
+                    .raw("value")
+                    .or_raise(self.info, &self.vm.diagnostics)
+                    .replace('_', "");
+                let int = if value.contains(['.', 'e', 'E']) {
+                    floatobject::float_from_str(self.vm.clone(), value)
+                } else {
+                    intobject::int_from_str(self.vm.clone(), value)
+                };
 
                 maybe_handle_exception_pos!(self, int, expr.start, expr.end);
                 let mut idx = usize::MAX;
@@ -429,7 +1961,7 @@ impl<'a> Compiler<'a> {
                         (var.tp.eq.unwrap())(var.clone(), int.unwrap())
                             .unwrap()
                             .internals
-                            .bool
+                            .bool()
                     } {
                         idx = i;
                         break;
@@ -455,45 +1987,35 @@ impl<'a> Compiler<'a> {
             }
             NodeType::Binary => {
                 let old = self.register_index;
+                increment_reg_num!(self);
 
                 let left = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("left")
-                        .expect("Node.nodes.left not found"),
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
                 );
                 let right = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("right")
-                        .expect("Node.nodes.right not found"),
+                    expr.child("right")
+                        .or_raise(self.info, &self.vm.diagnostics),
                 );
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: Some(left.value),
                     leftctx: Some(Box::new(left)),
                     right: Some(right.value),
                     rightctx: Some(Box::new(right)),
                     args: None,
                     mapping: None,
-                    registers: 0,
+                    registers: 1,
                 }
             }
             NodeType::StoreNode => {
                 let old = self.register_index;
                 let expr = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                 );
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: Some(expr.value),
                     leftctx: Some(Box::new(expr)),
                     right: None,
@@ -504,48 +2026,15 @@ impl<'a> Compiler<'a> {
                 }
             }
             NodeType::Identifier => {
-                let var = self.names.get_key_value(
-                    expr.data
-                        .get_data()
-                        .raw
-                        .get("name")
-                        .expect("Node.raw.name not found"),
-                );
+                let name = expr
+                    .raw("name")
+                    .or_raise(self.info, &self.vm.diagnostics)
+                    .clone();
 
-                if var.is_none() {
-                    let name = expr
-                        .data
-                        .get_data()
-                        .raw
-                        .get("name")
-                        .expect("Node.raw.name not found")
-                        .clone();
-                    let exc = exceptionobject::nameexc_from_str(
-                        self.vm.clone(),
-                        &format!("Name '{}' not defined", name),
-                        expr.start,
-                        expr.end,
-                    );
-                    self.raise_exc_pos(exc, expr.start, expr.end);
-                }
+                let value = self.resolve_name(&name, expr.start, expr.end);
 
                 RegisterContext {
-                    value: CompilerRegister::V(match var {
-                        Some(v) => (*v.1).try_into().unwrap(),
-                        None => {
-                            self.undef_index -= 1;
-                            self.undef_names.insert(
-                                self.undef_index,
-                                expr.data
-                                    .get_data()
-                                    .raw
-                                    .get("name")
-                                    .expect("Node.raw.name not found")
-                                    .clone(),
-                            );
-                            self.undef_index.try_into().unwrap()
-                        }
-                    }),
+                    value,
                     left: None,
                     leftctx: None,
                     right: None,
@@ -556,44 +2045,31 @@ impl<'a> Compiler<'a> {
                 }
             }
             NodeType::Call => {
-                let name = *expr
-                    .data
-                    .get_data()
-                    .nodes
-                    .get("name")
-                    .expect("Node.nodes.name not found");
+                let name = expr.child("name").or_raise(self.info, &self.vm.diagnostics);
                 let old = self.register_index;
+                increment_reg_num!(self);
                 let callable = self.compile_expr_values(name);
 
                 let mut args = Vec::new();
-                for arg in expr
-                    .data
-                    .get_data()
-                    .nodearr
-                    .expect("Node.nodearr is not present")
-                {
+                for arg in expr.nodearr().or_raise(self.info, &self.vm.diagnostics) {
                     let arg = self.compile_expr_values(arg);
                     args.push(arg);
                 }
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: Some(callable.value),
                     leftctx: Some(Box::new(callable)),
                     right: None,
                     rightctx: None,
                     args: Some(args),
                     mapping: None,
-                    registers: 0,
+                    registers: 1,
                 }
             }
             NodeType::Return => {
                 let var = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                 );
 
                 RegisterContext {
@@ -609,33 +2085,27 @@ impl<'a> Compiler<'a> {
             }
             NodeType::Unary => {
                 let old = self.register_index;
+                increment_reg_num!(self);
                 let var = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                 );
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: Some(var.value),
                     leftctx: Some(Box::new(var)),
                     right: None,
                     rightctx: None,
                     args: None,
                     mapping: None,
-                    registers: 0,
+                    registers: 1,
                 }
             }
             NodeType::String => {
                 let str = stringobject::string_from(
                     self.vm.clone(),
-                    expr.data
-                        .get_data()
-                        .raw
-                        .get("value")
-                        .expect("Node.raw.value not found")
+                    expr.raw("value")
+                        .or_raise(self.info, &self.vm.diagnostics)
                         .to_string(),
                 );
 
@@ -645,7 +2115,7 @@ impl<'a> Compiler<'a> {
                         (var.tp.eq.unwrap())(var.clone(), str.clone())
                             .unwrap()
                             .internals
-                            .bool
+                            .bool()
                     } {
                         idx = i;
                         break;
@@ -671,91 +2141,180 @@ impl<'a> Compiler<'a> {
             }
             NodeType::List => {
                 let old = self.register_index;
+                increment_reg_num!(self);
                 let mut args = Vec::new();
-                for arg in expr
-                    .data
-                    .get_data()
-                    .nodearr
-                    .expect("Node.nodearr is not present")
-                {
+                for arg in expr.nodearr().or_raise(self.info, &self.vm.diagnostics) {
                     let arg = self.compile_expr_values(arg);
                     args.push(arg);
                 }
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: None,
                     leftctx: None,
                     right: None,
                     rightctx: None,
                     args: Some(args),
                     mapping: None,
-                    registers: 0,
+                    registers: 1,
+                }
+            }
+            NodeType::Compare => {
+                let old = self.register_index;
+                increment_reg_num!(self);
+                let mut args = Vec::new();
+                for arg in expr.nodearr().or_raise(self.info, &self.vm.diagnostics) {
+                    let arg = self.compile_expr_values(arg);
+                    args.push(arg);
+                }
+
+                RegisterContext {
+                    value: CompilerRegister::register(old),
+                    left: None,
+                    leftctx: None,
+                    right: None,
+                    rightctx: None,
+                    args: Some(args),
+                    mapping: None,
+                    registers: 1,
                 }
             }
             NodeType::Dict => {
                 let old = self.register_index;
+                increment_reg_num!(self);
                 let mut keys = Vec::new();
-                for (arg, _) in expr
-                    .data
-                    .get_data()
-                    .mapping
-                    .expect("Node.mapping is not present")
-                {
+                for (arg, _) in expr.mapping().or_raise(self.info, &self.vm.diagnostics) {
                     let arg = self.compile_expr_values(arg);
                     keys.push(arg);
                 }
+                self.warn_duplicate_keys(expr, &keys);
 
                 let mut values = Vec::new();
-                for (_, arg) in expr
-                    .data
-                    .get_data()
-                    .mapping
-                    .expect("Node.mapping is not present")
-                {
+                for (_, arg) in expr.mapping().or_raise(self.info, &self.vm.diagnostics) {
                     let arg = self.compile_expr_values(arg);
                     values.push(arg);
                 }
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: None,
                     leftctx: None,
                     right: None,
                     rightctx: None,
                     args: None,
                     mapping: Some((keys, values)),
-                    registers: 0,
+                    registers: 1,
                 }
             }
             NodeType::AttrLoad => {
                 let old = self.register_index;
+                increment_reg_num!(self);
 
                 let left = self.compile_expr_values(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("left")
-                        .expect("Node.nodes.left not found"),
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
                 );
 
                 RegisterContext {
-                    value: CompilerRegister::R(old.try_into().unwrap()),
+                    value: CompilerRegister::register(old),
                     left: Some(left.value),
                     leftctx: Some(Box::new(left)),
                     right: None,
                     rightctx: None,
                     args: None,
                     mapping: None,
+                    registers: 1,
+                }
+            }
+            NodeType::AttrStore => {
+                let old = self.register_index;
+
+                let left = self.compile_expr_values(
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
+                );
+                let value = self.compile_expr_values(
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
+                );
+
+                RegisterContext {
+                    value: CompilerRegister::register(old),
+                    left: Some(left.value),
+                    leftctx: Some(Box::new(left)),
+                    right: Some(value.value),
+                    rightctx: Some(Box::new(value)),
+                    args: None,
+                    mapping: None,
                     registers: 0,
                 }
             }
-            NodeType::Class | NodeType::Function => {
+            NodeType::ListComp | NodeType::DictComp => {
+                let old = self.register_index;
+                increment_reg_num!(self);
+                let iter = self.compile_expr_values(
+                    expr.child("iter").or_raise(self.info, &self.vm.diagnostics),
+                );
+
+                RegisterContext {
+                    value: CompilerRegister::register(old),
+                    left: Some(iter.value),
+                    leftctx: Some(Box::new(iter)),
+                    right: None,
+                    rightctx: None,
+                    args: None,
+                    mapping: None,
+                    registers: 1,
+                }
+            }
+            NodeType::Class
+            | NodeType::Function
+            | NodeType::With
+            | NodeType::Const
+            | NodeType::Let => {
                 unreachable!()
             }
         }
     }
 
+    fn compare_instruction(
+        op: OpType,
+        a: CompilerRegister,
+        b: CompilerRegister,
+        result: CompilerRegister,
+        i: usize,
+    ) -> CompilerInstruction<'a> {
+        match op {
+            OpType::Eq => CompilerInstruction::BinaryEq { a, b, result, i },
+            OpType::Ne => CompilerInstruction::BinaryNe { a, b, result, i },
+            OpType::Lt => CompilerInstruction::BinaryLt { a, b, result, i },
+            OpType::Gt => CompilerInstruction::BinaryGt { a, b, result, i },
+            OpType::Le => CompilerInstruction::BinaryLe { a, b, result, i },
+            OpType::Ge => CompilerInstruction::BinaryGe { a, b, result, i },
+            OpType::In => CompilerInstruction::BinaryContains { a, b, result, i },
+            _ => unreachable!(),
+        }
+    }
+
+    //Whether an expression is an int literal or arithmetic on one, so it is probably an int
+    fn is_int_expr(expr: &Node) -> bool {
+        match expr.tp {
+            NodeType::Decimal => true,
+            NodeType::Binary => Self::is_int_operation(expr),
+            NodeType::Unary => {
+                matches!(expr.data.get_data().op, Some(OpType::Neg))
+                    && expr.child("expr").is_ok_and(Self::is_int_expr)
+            }
+            _ => false,
+        }
+    }
+
+    //Whether a binary operation should use the specialized int instruction: it is arithmetic,
+    //and one of the operands is probably an int
+    fn is_int_operation(expr: &Node) -> bool {
+        let data = expr.data.get_data();
+        matches!(data.op, Some(OpType::Add | OpType::Sub | OpType::Mul))
+            && (expr.child("left").is_ok_and(Self::is_int_expr)
+                || expr.child("right").is_ok_and(Self::is_int_expr))
+    }
+
     //Generate the actual instructions that use the RegisterContexts from the value compilation.
     //Do not increment the register number here!
     fn compile_expr_operation(&mut self, expr: &Node, ctx: RegisterContext) {
@@ -763,23 +2322,25 @@ impl<'a> Compiler<'a> {
             NodeType::Decimal => {}
             NodeType::Binary => {
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("left")
-                        .expect("Node.nodes.left not found"),
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
                     *ctx.leftctx.unwrap(),
                 );
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("right")
-                        .expect("Node.nodes.right not found"),
+                    expr.child("right")
+                        .or_raise(self.info, &self.vm.diagnostics),
                     *ctx.rightctx.unwrap(),
                 );
 
-                match expr.data.get_data().op.expect("Node.op is not present") {
+                match expr.op().or_raise(self.info, &self.vm.diagnostics) {
+                    OpType::Add if Self::is_int_operation(expr) => {
+                        self.instructions.push(CompilerInstruction::BinaryAddInt {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
                     OpType::Add => {
                         self.instructions.push(CompilerInstruction::BinaryAdd {
                             a: ctx.left.unwrap(),
@@ -789,6 +2350,15 @@ impl<'a> Compiler<'a> {
                         });
                         self.positions.push((expr.start, expr.end));
                     }
+                    OpType::Sub if Self::is_int_operation(expr) => {
+                        self.instructions.push(CompilerInstruction::BinarySubInt {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
                     OpType::Sub => {
                         self.instructions.push(CompilerInstruction::BinarySub {
                             a: ctx.left.unwrap(),
@@ -798,6 +2368,15 @@ impl<'a> Compiler<'a> {
                         });
                         self.positions.push((expr.start, expr.end));
                     }
+                    OpType::Mul if Self::is_int_operation(expr) => {
+                        self.instructions.push(CompilerInstruction::BinaryMulInt {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
                     OpType::Mul => {
                         self.instructions.push(CompilerInstruction::BinaryMul {
                             a: ctx.left.unwrap(),
@@ -816,54 +2395,91 @@ impl<'a> Compiler<'a> {
                         });
                         self.positions.push((expr.start, expr.end));
                     }
+                    op @ (OpType::Eq
+                    | OpType::Ne
+                    | OpType::Lt
+                    | OpType::Gt
+                    | OpType::Le
+                    | OpType::Ge
+                    | OpType::In) => {
+                        self.instructions.push(Self::compare_instruction(
+                            op,
+                            ctx.left.unwrap(),
+                            ctx.right.unwrap(),
+                            ctx.value,
+                            self.instructions.len(),
+                        ));
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    OpType::BitAnd => {
+                        self.instructions.push(CompilerInstruction::BinaryBitAnd {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    OpType::BitOr => {
+                        self.instructions.push(CompilerInstruction::BinaryBitOr {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    OpType::BitXor => {
+                        self.instructions.push(CompilerInstruction::BinaryBitXor {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    OpType::LShift => {
+                        self.instructions.push(CompilerInstruction::BinaryLShift {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    OpType::RShift => {
+                        self.instructions.push(CompilerInstruction::BinaryRShift {
+                            a: ctx.left.unwrap(),
+                            b: ctx.right.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
                     _ => {
                         unimplemented!();
                     }
                 }
             }
             NodeType::StoreNode => {
+                self.check_not_const(
+                    &expr.raw("name").or_raise(self.info, &self.vm.diagnostics),
+                    &expr.start,
+                );
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                     *ctx.leftctx.unwrap(),
                 );
 
-                let idx = if self.names.contains_key(
-                    expr.data
-                        .get_data()
-                        .raw
-                        .get("name")
-                        .expect("Node.raw.name not found"),
-                ) {
-                    *self
-                        .names
-                        .get(
-                            expr.data
-                                .get_data()
-                                .raw
-                                .get("name")
-                                .expect("Node.raw.name not found"),
-                        )
-                        .unwrap()
-                } else {
-                    self.names.insert(
-                        expr.data
-                            .get_data()
-                            .raw
-                            .get("name")
-                            .expect("Node.raw.name not found")
-                            .clone(),
-                        self.names.len() as i32,
-                    );
-                    (self.names.len() - 1) as i32
-                };
+                let idx = self.define_name(
+                    expr.raw("name")
+                        .or_raise(self.info, &self.vm.diagnostics)
+                        .clone(),
+                );
 
                 self.instructions.push(CompilerInstruction::CopyRegister {
                     from: ctx.left.unwrap(),
-                    to: CompilerRegister::V(idx.try_into().unwrap()),
+                    to: CompilerRegister::variable(idx),
                     i: self.instructions.len(),
                 });
                 self.positions.push((expr.start, expr.end));
@@ -875,22 +2491,15 @@ impl<'a> Compiler<'a> {
                     ErrorType::FunctionNotExpression,
                     &expr.start,
                     self.info,
+                    &self.vm.diagnostics,
                 );
             }
             NodeType::Call => {
-                let name = *expr
-                    .data
-                    .get_data()
-                    .nodes
-                    .get("name")
-                    .expect("Node.nodes.name not found");
+                let name = expr.child("name").or_raise(self.info, &self.vm.diagnostics);
                 self.compile_expr_operation(name, *ctx.leftctx.unwrap());
 
                 for arg in izip!(
-                    expr.data
-                        .get_data()
-                        .nodearr
-                        .expect("Node.nodearr is not present"),
+                    expr.nodearr().or_raise(self.info, &self.vm.diagnostics),
                     ctx.args.as_ref().unwrap()
                 ) {
                     self.compile_expr_operation(arg.0, arg.1.clone());
@@ -905,13 +2514,16 @@ impl<'a> Compiler<'a> {
             }
             NodeType::Return => {
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                     *ctx.leftctx.unwrap(),
                 );
+                //Leave the enclosing with statements before returning
+                for _ in 0..self.with_depth {
+                    self.instructions.push(CompilerInstruction::ExitContext {
+                        i: self.instructions.len(),
+                    });
+                    self.positions.push((expr.start, expr.end));
+                }
                 self.instructions.push(CompilerInstruction::Return {
                     register: ctx.value,
                     i: self.instructions.len(),
@@ -920,16 +2532,12 @@ impl<'a> Compiler<'a> {
             }
             NodeType::Unary => {
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("expr")
-                        .expect("Node.nodes.expr not found"),
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
                     *ctx.leftctx.unwrap(),
                 );
 
-                match expr.data.get_data().op.expect("Node.op is not present") {
-                    OpType::Add => {
+                match expr.op().or_raise(self.info, &self.vm.diagnostics) {
+                    OpType::Neg => {
                         self.instructions.push(CompilerInstruction::UnaryNeg {
                             a: ctx.left.unwrap(),
                             result: ctx.value,
@@ -937,6 +2545,14 @@ impl<'a> Compiler<'a> {
                         });
                         self.positions.push((expr.start, expr.end));
                     }
+                    OpType::Invert => {
+                        self.instructions.push(CompilerInstruction::UnaryInvert {
+                            a: ctx.left.unwrap(),
+                            result: ctx.value,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
                     _ => {
                         unimplemented!();
                     }
@@ -945,10 +2561,7 @@ impl<'a> Compiler<'a> {
             NodeType::String => {}
             NodeType::List => {
                 for arg in izip!(
-                    expr.data
-                        .get_data()
-                        .nodearr
-                        .expect("Node.nodearr is not present"),
+                    expr.nodearr().or_raise(self.info, &self.vm.diagnostics),
                     ctx.args.as_ref().unwrap()
                 ) {
                     self.compile_expr_operation(arg.0, arg.1.clone());
@@ -962,19 +2575,13 @@ impl<'a> Compiler<'a> {
             }
             NodeType::Dict => {
                 for ((key, _), keyctx) in izip!(
-                    expr.data
-                        .get_data()
-                        .mapping
-                        .expect("Node.mapping is not present"),
+                    expr.mapping().or_raise(self.info, &self.vm.diagnostics),
                     &ctx.mapping.as_ref().unwrap().0
                 ) {
                     self.compile_expr_operation(key, keyctx.clone());
                 }
                 for ((_, value), valuectx) in izip!(
-                    expr.data
-                        .get_data()
-                        .mapping
-                        .expect("Node.mapping is not present"),
+                    expr.mapping().or_raise(self.info, &self.vm.diagnostics),
                     &ctx.mapping.as_ref().unwrap().1
                 ) {
                     self.compile_expr_operation(value, valuectx.clone());
@@ -994,60 +2601,138 @@ impl<'a> Compiler<'a> {
                 });
                 self.positions.push((expr.start, expr.end));
             }
+            NodeType::Compare => {
+                let operands = expr.nodearr().or_raise(self.info, &self.vm.diagnostics);
+                let ops = expr.ops().or_raise(self.info, &self.vm.diagnostics);
+                let args = ctx.args.unwrap();
+
+                //Each operand after the second is only evaluated if the comparisons so far held,
+                //and a failed comparison leaves false in the result
+                let mut skips = Vec::new();
+                self.compile_expr_operation(&operands[0], args[0].clone());
+                for (n, op) in ops.iter().enumerate() {
+                    if n > 0 {
+                        skips.push(self.instructions.len());
+                        self.instructions.push(CompilerInstruction::SkipIfFalse {
+                            register: ctx.value,
+                            n: 0,
+                            i: self.instructions.len(),
+                        });
+                        self.positions.push((expr.start, expr.end));
+                    }
+                    self.compile_expr_operation(&operands[n + 1], args[n + 1].clone());
+                    self.instructions.push(Self::compare_instruction(
+                        *op,
+                        args[n].value,
+                        args[n + 1].value,
+                        ctx.value,
+                        self.instructions.len(),
+                    ));
+                    self.positions
+                        .push((operands[n].start, operands[n + 1].end));
+                }
+                let end = self.instructions.len();
+                for skip in skips {
+                    if let CompilerInstruction::SkipIfFalse { n, .. } = &mut self.instructions[skip]
+                    {
+                        *n = end - skip - 1;
+                    }
+                }
+            }
             NodeType::Class => {
                 raise_error(
                     "Class definition is not an expression",
                     ErrorType::FunctionNotExpression,
                     &expr.start,
                     self.info,
+                    &self.vm.diagnostics,
                 );
             }
-            NodeType::AttrLoad => {
+            NodeType::ListComp | NodeType::DictComp => {
+                let iterable = ctx.left.unwrap();
                 self.compile_expr_operation(
-                    expr.data
-                        .get_data()
-                        .nodes
-                        .get("left")
-                        .expect("Node.nodes.left not found"),
+                    expr.child("iter").or_raise(self.info, &self.vm.diagnostics),
                     *ctx.leftctx.unwrap(),
                 );
 
-                let attr = stringobject::string_from(
-                    self.vm.clone(),
-                    expr.data
-                        .get_data()
-                        .raw
-                        .get("attr")
-                        .expect("Node.raw.attr not found")
-                        .to_string(),
+                let body = self.compile_comprehension(expr);
+                let (codeidx, bodyidx) = self.add_body(body);
+
+                self.instructions.push(CompilerInstruction::Comprehension {
+                    iterable,
+                    codeidx,
+                    bodyidx,
+                    is_dict: expr.tp == NodeType::DictComp,
+                    result: ctx.value,
+                    i: self.instructions.len(),
+                });
+                self.positions.push((expr.start, expr.end));
+            }
+            NodeType::With => {
+                raise_error(
+                    "With statement is not an expression",
+                    ErrorType::FunctionNotExpression,
+                    &expr.start,
+                    self.info,
+                    &self.vm.diagnostics,
+                );
+            }
+            NodeType::Const => {
+                raise_error(
+                    "Constant declaration is not an expression",
+                    ErrorType::FunctionNotExpression,
+                    &expr.start,
+                    self.info,
+                    &self.vm.diagnostics,
+                );
+            }
+            NodeType::Let => {
+                raise_error(
+                    "Let declaration is not an expression",
+                    ErrorType::FunctionNotExpression,
+                    &expr.start,
+                    self.info,
+                    &self.vm.diagnostics,
+                );
+            }
+            NodeType::AttrLoad => {
+                self.compile_expr_operation(
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
+                    *ctx.leftctx.unwrap(),
                 );
-                let mut idx = usize::MAX;
-                for (i, var) in self.consts.iter().enumerate() {
-                    if unsafe {
-                        (var.tp.eq.unwrap())(var.clone(), attr.clone())
-                            .unwrap()
-                            .internals
-                            .bool
-                    } {
-                        idx = i;
-                        break;
-                    }
-                }
-                if idx == usize::MAX {
-                    self.consts.push(attr);
-                    idx = self.consts.len() - 1;
-                }
 
+                let nameidx =
+                    self.add_attr_name(&expr.raw("attr").or_raise(self.info, &self.vm.diagnostics));
                 self.instructions.push(CompilerInstruction::AttrLoad {
                     left: ctx.left.unwrap(),
-                    attridx: CompilerRegister::C(idx),
+                    nameidx,
                     result: ctx.value,
                     i: self.instructions.len(),
                 });
                 self.positions.push((expr.start, expr.end));
             }
+            NodeType::AttrStore => {
+                self.compile_expr_operation(
+                    expr.child("left").or_raise(self.info, &self.vm.diagnostics),
+                    *ctx.leftctx.unwrap(),
+                );
+                self.compile_expr_operation(
+                    expr.child("expr").or_raise(self.info, &self.vm.diagnostics),
+                    *ctx.rightctx.unwrap(),
+                );
+
+                let nameidx =
+                    self.add_attr_name(&expr.raw("attr").or_raise(self.info, &self.vm.diagnostics));
+                self.instructions.push(CompilerInstruction::AttrStore {
+                    left: ctx.left.unwrap(),
+                    nameidx,
+                    value: ctx.right.unwrap(),
+                    i: self.instructions.len(),
+                });
+                self.positions.push((expr.start, expr.end));
+            }
         }
 
-        self.register_index -= ctx.registers;
+        self.release_registers(ctx.registers);
     }
 }
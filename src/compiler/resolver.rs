@@ -0,0 +1,456 @@
+//Check the names of a program before it is compiled, so that every undefined or duplicate name is
+//reported at once. The scopes follow the compiler: a body may load its own names, the names of the
+//bodies that enclose it (except class bodies) as they were when it was defined, the constants that
+//are visible to it, and the builtins. Names declared with let in a block are only bound until the
+//end of the block.
+//
+//With warn_unused, the variables and functions that a body binds but never reads are reported.
+//Binding the name of a function or class again in the same scope is reported as a warning, or an
+//error if strict is set.
+
+use crate::{
+    diagnostics::Diagnostics,
+    errors::{print_error, print_warning, ErrorType, Exit, ExitCode, OrRaise, WarningType},
+    fileinfo::FileInfo,
+    parser::{
+        nodes::{Node, NodeType},
+        Position,
+    },
+};
+use hashbrown::{HashMap, HashSet};
+
+struct Scope {
+    names: HashMap<String, Position>, //Where each name was bound
+    consts: HashSet<String>,
+    is_class_body: bool,
+    blocks: Vec<Vec<Declaration>>,
+    enclosing_loads: HashMap<String, Position>, //Names that were read from an enclosing scope
+    bindings: Vec<(String, Position, Binding)>, //Every binding, to find the unused ones
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    Variable,
+    Function,
+    Class,
+}
+
+//A name declared with let in a block
+struct Declaration {
+    name: String,
+    pos: Position,
+    shadowed: Option<Position>,
+}
+
+impl Scope {
+    fn new(is_class_body: bool) -> Scope {
+        Scope {
+            names: HashMap::new(),
+            consts: HashSet::new(),
+            is_class_body,
+            blocks: Vec::new(),
+            enclosing_loads: HashMap::new(),
+            bindings: Vec::new(),
+        }
+    }
+}
+
+pub struct Resolver<'a> {
+    info: &'a FileInfo<'a>,
+    out: &'a Diagnostics, //Where the errors are printed
+    builtins: HashSet<String>,
+    scopes: Vec<Scope>,
+    errors: Vec<(String, ErrorType, Position)>,
+    pub warn_unused: bool,
+    pub strict: bool,
+    used: HashSet<(String, Position)>, //The bindings that were read, by name and position
+    warnings: Vec<(String, WarningType, Position)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(
+        info: &'a FileInfo<'a>,
+        builtins: HashSet<String>,
+        out: &'a Diagnostics,
+    ) -> Resolver<'a> {
+        Resolver {
+            info,
+            out,
+            builtins,
+            scopes: vec![Scope::new(false)],
+            errors: Vec::new(),
+            warn_unused: false,
+            strict: false,
+            used: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    //Resolve the names of a program, and print the errors and exit if there are any
+    pub fn resolve(mut self, ast: &Vec<Node>) {
+        self.resolve_body(ast);
+        self.end_scope();
+        self.warnings
+            .sort_by_key(|(_, _, pos)| (pos.line, pos.startcol));
+        for (warning, warntp, pos) in &self.warnings {
+            print_warning(warning, *warntp, pos, pos, self.info, self.out);
+        }
+        if self.errors.is_empty() {
+            return;
+        }
+        for (error, errtp, pos) in &self.errors {
+            print_error(error, *errtp, pos, self.info, self.out);
+        }
+        Exit::raise(ExitCode::Syntax);
+    }
+
+    fn resolve_body(&mut self, nodes: &Vec<Node>) {
+        for node in nodes {
+            self.resolve_statement(node);
+        }
+    }
+
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("No scope")
+    }
+
+    //Find the bindings of the current scope that were never read, before the scope ends. The
+    //names of class bodies are attributes, and main is called when the program ends.
+    fn end_scope(&mut self) {
+        if !self.warn_unused {
+            return;
+        }
+        let is_top_level = self.scopes.len() == 1;
+        let scope = self.scopes.last().expect("No scope");
+        if scope.is_class_body {
+            return;
+        }
+        for (name, pos, binding) in &scope.bindings {
+            if self.used.contains(&(name.clone(), *pos)) || (is_top_level && name == "main") {
+                continue;
+            }
+            match binding {
+                Binding::Variable => self.warnings.push((
+                    format!("Variable '{}' is assigned but never read", name),
+                    WarningType::UnusedVariable,
+                    *pos,
+                )),
+                Binding::Function => self.warnings.push((
+                    format!("Function '{}' is defined but never used", name),
+                    WarningType::UnusedFunction,
+                    *pos,
+                )),
+                Binding::Class => (),
+            }
+        }
+    }
+
+    //Where a name is bound in the innermost enclosing scope that binds it
+    fn enclosing_binding(&self, name: &String) -> Option<Position> {
+        let (_, enclosing) = self.scopes.split_last().expect("No scope");
+        enclosing
+            .iter()
+            .rev()
+            .filter(|scope| !scope.is_class_body)
+            .find_map(|scope| scope.names.get(name).copied())
+    }
+
+    fn load(&mut self, name: &String, pos: Position) {
+        if let Some(bound) = self.current().names.get(name).copied() {
+            self.used.insert((name.clone(), bound));
+            return;
+        }
+        if self.scopes.iter().any(|scope| scope.consts.contains(name)) {
+            return;
+        }
+        if let Some(bound) = self.enclosing_binding(name) {
+            self.used.insert((name.clone(), bound));
+            self.current()
+                .enclosing_loads
+                .entry(name.clone())
+                .or_insert(pos);
+            return;
+        }
+        if !self.builtins.contains(name) {
+            self.errors.push((
+                format!("Name '{}' is not defined", name),
+                ErrorType::UndefinedName,
+                pos,
+            ));
+        }
+    }
+
+    //Bind a name in the current scope. A body that read the name from an enclosing scope may not
+    //then bind it, as the name would refer to two variables.
+    fn bind(&mut self, name: &String, pos: Position, binding: Binding) {
+        if let Some(bound) = self.current().names.get(name).copied() {
+            self.redefine(name, bound, pos);
+            return;
+        }
+        if let Some(read) = self.current().enclosing_loads.get(name).copied() {
+            let shadowed = self
+                .enclosing_binding(name)
+                .expect("Read name is not bound");
+            self.errors.push((
+                format!(
+                    "Cannot bind '{}', as it was read at line {} from the binding at line {}",
+                    name,
+                    read.line + 1,
+                    shadowed.line + 1
+                ),
+                ErrorType::ShadowedName,
+                pos,
+            ));
+        }
+        self.current().names.insert(name.clone(), pos);
+        self.current().bindings.push((name.clone(), pos, binding));
+    }
+
+    //Report binding a name again, if it was bound to a function or class
+    fn redefine(&mut self, name: &String, bound: Position, pos: Position) {
+        let previous = self
+            .current()
+            .bindings
+            .iter()
+            .rev()
+            .find(|(other, at, _)| other == name && *at == bound)
+            .map(|(_, _, binding)| *binding);
+        let kind = match previous {
+            Some(Binding::Function) => "function",
+            Some(Binding::Class) => "class",
+            _ => return,
+        };
+        let message = format!(
+            "Binding '{}' replaces the {} defined at line {}, column {}",
+            name,
+            kind,
+            bound.line + 1,
+            bound.startcol + 1
+        );
+        if self.strict {
+            self.errors.push((message, ErrorType::Redefinition, pos));
+        } else {
+            self.warnings
+                .push((message, WarningType::Redefinition, pos));
+        }
+    }
+
+    //Declare a name with let: in a block, it is bound until the end of the block
+    fn declare(&mut self, name: &String, pos: Position) {
+        if self.current().blocks.is_empty() {
+            if let Some(bound) = self.current().names.get(name).copied() {
+                self.errors.push((
+                    format!(
+                        "Cannot declare '{}', as it is already bound at line {}",
+                        name,
+                        bound.line + 1
+                    ),
+                    ErrorType::DuplicateName,
+                    pos,
+                ));
+                return;
+            }
+            //Unlike an assignment, a declaration may shadow a name that was read before it
+            self.current().names.insert(name.clone(), pos);
+            self.current()
+                .bindings
+                .push((name.clone(), pos, Binding::Variable));
+            return;
+        }
+
+        let scope = self.current();
+        let block = scope.blocks.last().expect("No block");
+        if let Some(declared) = block.iter().find(|declaration| &declaration.name == name) {
+            let line = declared.pos.line + 1;
+            self.errors.push((
+                format!(
+                    "Cannot declare '{}', as it is already declared in this block at line {}",
+                    name, line
+                ),
+                ErrorType::DuplicateName,
+                pos,
+            ));
+            return;
+        }
+        let shadowed = scope.names.insert(name.clone(), pos);
+        scope.bindings.push((name.clone(), pos, Binding::Variable));
+        scope
+            .blocks
+            .last_mut()
+            .expect("No block")
+            .push(Declaration {
+                name: name.clone(),
+                pos,
+                shadowed,
+            });
+    }
+
+    //Unbind the names declared with let in a block that ended
+    fn end_block(&mut self) {
+        let scope = self.current();
+        let block = scope.blocks.pop().expect("No block");
+        for declaration in block.into_iter().rev() {
+            match declaration.shadowed {
+                Some(shadowed) => scope.names.insert(declaration.name, shadowed),
+                None => scope.names.remove(&declaration.name),
+            };
+        }
+    }
+
+    fn resolve_statement(&mut self, node: &Node) {
+        match node.tp {
+            NodeType::Function => {
+                let name = &node.raw("name").or_raise(self.info, self.out);
+                //Defined first, so that the body may refer to the function itself
+                self.bind(name, node.start, Binding::Function);
+
+                let mut scope = Scope::new(false);
+                for arg in node.args().or_raise(self.info, self.out) {
+                    if scope.names.insert(arg.clone(), node.start).is_some() {
+                        self.errors.push((
+                            format!("Duplicate parameter '{}' in function '{}'", arg, name),
+                            ErrorType::DuplicateName,
+                            node.start,
+                        ));
+                    }
+                }
+                self.scopes.push(scope);
+                self.resolve_body(node.nodearr().or_raise(self.info, self.out));
+                self.end_scope();
+                self.scopes.pop();
+            }
+            NodeType::Class => {
+                for base in node.args().or_raise(self.info, self.out) {
+                    self.load(&base, node.start);
+                }
+                //Defined first, so that methods can refer to their class
+                let name = &node.raw("name").or_raise(self.info, self.out);
+                self.bind(name, node.start, Binding::Class);
+
+                self.scopes.push(Scope::new(true));
+                self.resolve_body(node.nodearr().or_raise(self.info, self.out));
+                self.scopes.pop();
+            }
+            NodeType::Const => {
+                let name = node.raw("name").or_raise(self.info, self.out);
+                self.current().consts.insert(name.clone());
+            }
+            NodeType::Let => {
+                self.resolve_expr(node.child("expr").or_raise(self.info, self.out));
+                let name = &node.raw("name").or_raise(self.info, self.out);
+                self.declare(name, node.start);
+            }
+            NodeType::With => {
+                let manager = node.child("expr").or_raise(self.info, self.out);
+                self.resolve_expr(manager);
+                let name = &node.raw("name").or_raise(self.info, self.out);
+                self.bind(name, node.start, Binding::Variable);
+                self.current().blocks.push(Vec::new());
+                self.resolve_body(node.nodearr().or_raise(self.info, self.out));
+                self.end_block();
+            }
+            _ => self.resolve_expr(node),
+        }
+    }
+
+    //Like the compiler, load the names of an expression before binding the ones that it stores
+    fn resolve_expr(&mut self, expr: &Node) {
+        self.load_names(expr);
+        self.bind_names(expr);
+    }
+
+    //The names that an expression loads, outside of its comprehension bodies
+    fn load_names(&mut self, expr: &Node) {
+        match expr.tp {
+            NodeType::Identifier => {
+                let name = &expr.raw("name").or_raise(self.info, self.out);
+                self.load(name, expr.start);
+            }
+            NodeType::ListComp | NodeType::DictComp => {
+                self.load_names(expr.child("iter").or_raise(self.info, self.out));
+            }
+            _ => {
+                for node in self.children(expr) {
+                    self.load_names(node);
+                }
+            }
+        }
+    }
+
+    //The names that an expression stores, and the comprehension bodies, which are compiled after
+    //the rest of the expression
+    fn bind_names(&mut self, expr: &Node) {
+        let data = expr.data.get_data();
+        match expr.tp {
+            NodeType::StoreNode => {
+                self.bind_names(expr.child("expr").or_raise(self.info, self.out));
+                let name = &expr.raw("name").or_raise(self.info, self.out);
+                self.bind(name, expr.start, Binding::Variable);
+            }
+            NodeType::ListComp | NodeType::DictComp => {
+                self.bind_names(expr.child("iter").or_raise(self.info, self.out));
+
+                let mut scope = Scope::new(false);
+                scope.names.insert(
+                    expr.raw("name").or_raise(self.info, self.out).clone(),
+                    expr.start,
+                );
+                self.scopes.push(scope);
+                let mut nodes = Vec::new();
+                if let Some(key) = data.nodes.get("key") {
+                    nodes.push(*key);
+                }
+                nodes.push(expr.child("value").or_raise(self.info, self.out));
+                for node in &nodes {
+                    self.load_names(node);
+                }
+                for node in &nodes {
+                    self.bind_names(node);
+                }
+                self.scopes.pop();
+            }
+            _ => {
+                for node in self.children(expr) {
+                    self.bind_names(node);
+                }
+            }
+        }
+    }
+
+    //The subexpressions of an expression, in the order that they are compiled
+    fn children<'n>(&self, expr: &'n Node) -> Vec<&'n Node> {
+        match expr.tp {
+            NodeType::Binary => vec![
+                expr.child("left").or_raise(self.info, self.out),
+                expr.child("right").or_raise(self.info, self.out),
+            ],
+            NodeType::Unary | NodeType::Return | NodeType::StoreNode => {
+                vec![expr.child("expr").or_raise(self.info, self.out)]
+            }
+            NodeType::AttrLoad => {
+                vec![expr.child("left").or_raise(self.info, self.out)]
+            }
+            NodeType::AttrStore => vec![
+                expr.child("left").or_raise(self.info, self.out),
+                expr.child("expr").or_raise(self.info, self.out),
+            ],
+            NodeType::Call => {
+                let mut nodes = vec![expr.child("name").or_raise(self.info, self.out)];
+                nodes.extend(expr.nodearr().or_raise(self.info, self.out));
+                nodes
+            }
+            NodeType::List | NodeType::Compare => expr
+                .nodearr()
+                .or_raise(self.info, self.out)
+                .iter()
+                .collect(),
+            NodeType::Dict => expr
+                .mapping()
+                .or_raise(self.info, self.out)
+                .iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,991 @@
+//A binary format for compiled bytecode, so that a program can be run again without compiling it.
+//Integers are little endian, sizes and indices are u64, and strings and sequences are prefixed by
+//their length. Constants are tagged with their kind, and builtins are stored by name, as they
+//are created by the VM that loads the bytecode. Reading returns None for any data that it does not
+//understand, or for bytecode that uses registers, constants or names that it does not have, so a
+//damaged file is treated like a missing one.
+
+use super::{
+    linetable::LineTable, Bytecode, Code, CompilerInstruction, CompilerRegister, Names,
+    RegisterContext,
+};
+use crate::rc::Trc;
+use crate::{
+    diagnostics::Warning,
+    fileinfo::FileInfo,
+    interpreter::VM,
+    objects::{codeobject, floatobject, intobject, listobject, stringobject, Object, TypeObject},
+    parser::Position,
+    unwrap_fast,
+};
+use indexmap::IndexMap;
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+const CONST_INT: u8 = 0;
+const CONST_FLOAT: u8 = 1;
+const CONST_STR: u8 = 2;
+const CONST_LIST: u8 = 3;
+const CONST_CODE: u8 = 4;
+const CONST_BUILTIN: u8 = 5;
+
+struct Writer<'v, 'a> {
+    data: Vec<u8>,
+    vm: &'v VM<'a>,
+}
+
+impl<'v, 'a> Writer<'v, 'a> {
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.data.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn usizes(&mut self, values: &[usize]) {
+        self.usize(values.len());
+        for value in values {
+            self.usize(*value);
+        }
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.data.extend_from_slice(value.as_bytes());
+    }
+
+    fn position(&mut self, pos: &Position) {
+        self.usize(pos.startcol);
+        self.usize(pos.endcol);
+        self.usize(pos.line);
+    }
+
+    fn register(&mut self, register: &CompilerRegister) {
+        match register {
+            CompilerRegister::R(v) => {
+                self.u8(0);
+                self.usize(*v);
+            }
+            CompilerRegister::V(v) => {
+                self.u8(1);
+                self.usize(*v);
+            }
+            CompilerRegister::C(v) => {
+                self.u8(2);
+                self.usize(*v);
+            }
+            CompilerRegister::E(level, v) => {
+                self.u8(3);
+                self.usize(*level);
+                self.usize(*v);
+            }
+        }
+    }
+
+    fn registers(&mut self, registers: &[CompilerRegister]) {
+        self.usize(registers.len());
+        for register in registers {
+            self.register(register);
+        }
+    }
+
+    fn optional_register(&mut self, register: &Option<CompilerRegister>) {
+        self.bool(register.is_some());
+        if let Some(register) = register {
+            self.register(register);
+        }
+    }
+
+    fn context(&mut self, ctx: &RegisterContext) {
+        self.register(&ctx.value);
+        self.optional_register(&ctx.left);
+        self.bool(ctx.leftctx.is_some());
+        if let Some(leftctx) = &ctx.leftctx {
+            self.context(leftctx);
+        }
+        self.optional_register(&ctx.right);
+        self.bool(ctx.rightctx.is_some());
+        if let Some(rightctx) = &ctx.rightctx {
+            self.context(rightctx);
+        }
+        self.bool(ctx.args.is_some());
+        if let Some(args) = &ctx.args {
+            self.contexts(args);
+        }
+        self.bool(ctx.mapping.is_some());
+        if let Some((keys, values)) = &ctx.mapping {
+            self.contexts(keys);
+            self.contexts(values);
+        }
+        self.i32(ctx.registers);
+    }
+
+    fn contexts(&mut self, contexts: &[RegisterContext]) {
+        self.usize(contexts.len());
+        for ctx in contexts {
+            self.context(ctx);
+        }
+    }
+
+    //Write a constant, or return None if it is of a kind that can not be stored
+    fn constant(&mut self, object: &Object<'a>) -> Option<()> {
+        if let Some(value) = object.as_int() {
+            self.u8(CONST_INT);
+            self.data.extend_from_slice(&(value as i64).to_le_bytes());
+        } else if let Some(value) = object.as_float() {
+            self.u8(CONST_FLOAT);
+            self.data.extend_from_slice(&value.to_le_bytes());
+        } else if let Some(value) = object.as_str() {
+            self.u8(CONST_STR);
+            self.str(value);
+        } else if let Some(items) = object.as_list() {
+            self.u8(CONST_LIST);
+            self.usize(items.len());
+            for item in items {
+                self.constant(item)?;
+            }
+        } else if let Some(code) = object.as_code() {
+            self.u8(CONST_CODE);
+            self.code(code)?;
+        } else {
+            let (name, _) = self
+                .vm
+                .builtins
+                .iter()
+                .find(|(_, builtin)| Trc::ptr_eq(builtin, object))?;
+            self.u8(CONST_BUILTIN);
+            self.str(name);
+        }
+        Some(())
+    }
+
+    fn instruction(&mut self, instruction: &CompilerInstruction<'a>) -> Option<()> {
+        macro_rules! binary {
+            ($tag:expr, $a:expr, $b:expr, $result:expr, $i:expr) => {{
+                self.u8($tag);
+                self.register($a);
+                self.register($b);
+                self.register($result);
+                self.usize(*$i);
+            }};
+        }
+        match instruction {
+            CompilerInstruction::BinaryAdd { a, b, result, i } => binary!(0, a, b, result, i),
+            CompilerInstruction::BinarySub { a, b, result, i } => binary!(1, a, b, result, i),
+            CompilerInstruction::BinaryMul { a, b, result, i } => binary!(2, a, b, result, i),
+            CompilerInstruction::BinaryAddInt { a, b, result, i } => binary!(3, a, b, result, i),
+            CompilerInstruction::BinarySubInt { a, b, result, i } => binary!(4, a, b, result, i),
+            CompilerInstruction::BinaryMulInt { a, b, result, i } => binary!(5, a, b, result, i),
+            CompilerInstruction::BinaryDiv { a, b, result, i } => binary!(6, a, b, result, i),
+            CompilerInstruction::BinaryEq { a, b, result, i } => binary!(7, a, b, result, i),
+            CompilerInstruction::BinaryNe { a, b, result, i } => binary!(8, a, b, result, i),
+            CompilerInstruction::BinaryLt { a, b, result, i } => binary!(9, a, b, result, i),
+            CompilerInstruction::BinaryGt { a, b, result, i } => binary!(10, a, b, result, i),
+            CompilerInstruction::BinaryLe { a, b, result, i } => binary!(11, a, b, result, i),
+            CompilerInstruction::BinaryGe { a, b, result, i } => binary!(12, a, b, result, i),
+            CompilerInstruction::BinaryBitAnd { a, b, result, i } => binary!(13, a, b, result, i),
+            CompilerInstruction::BinaryBitOr { a, b, result, i } => binary!(14, a, b, result, i),
+            CompilerInstruction::BinaryBitXor { a, b, result, i } => binary!(15, a, b, result, i),
+            CompilerInstruction::BinaryLShift { a, b, result, i } => binary!(16, a, b, result, i),
+            CompilerInstruction::BinaryRShift { a, b, result, i } => binary!(17, a, b, result, i),
+            CompilerInstruction::BinaryContains { a, b, result, i } => {
+                binary!(40, a, b, result, i)
+            }
+            CompilerInstruction::CopyRegister { from, to, i } => {
+                self.u8(18);
+                self.register(from);
+                self.register(to);
+                self.usize(*i);
+            }
+            CompilerInstruction::MoveRegister { from, to, i } => {
+                self.u8(34);
+                self.register(from);
+                self.register(to);
+                self.usize(*i);
+            }
+            CompilerInstruction::MakeFunction {
+                nameidx,
+                argsidx,
+                codeidx,
+                bodyidx,
+                out,
+                is_abstract,
+            } => {
+                self.u8(19);
+                self.usize(*nameidx);
+                self.usize(*argsidx);
+                self.usize(*codeidx);
+                self.usize(*bodyidx);
+                self.register(out);
+                self.bool(*is_abstract);
+            }
+            CompilerInstruction::Call {
+                callableregister,
+                result,
+                arg_registers,
+                i,
+            } => {
+                self.u8(20);
+                self.register(callableregister);
+                self.register(result);
+                self.contexts(arg_registers);
+                self.usize(*i);
+            }
+            CompilerInstruction::Return { register, i } => {
+                self.u8(21);
+                self.register(register);
+                self.usize(*i);
+            }
+            CompilerInstruction::CallAttr {
+                left,
+                nameidx,
+                result,
+                arg_registers,
+                i,
+            } => {
+                self.u8(35);
+                self.register(left);
+                self.usize(*nameidx);
+                self.register(result);
+                self.contexts(arg_registers);
+                self.usize(*i);
+            }
+            CompilerInstruction::StoreReturn { from, to, i } => {
+                self.u8(36);
+                self.register(from);
+                self.register(to);
+                self.usize(*i);
+            }
+            CompilerInstruction::SkipIfFalse { register, n, i } => {
+                self.u8(22);
+                self.register(register);
+                self.usize(*n);
+                self.usize(*i);
+            }
+            CompilerInstruction::EnterContext { register, out, i } => {
+                self.u8(23);
+                self.register(register);
+                self.register(out);
+                self.usize(*i);
+            }
+            CompilerInstruction::ExitContext { i } => {
+                self.u8(24);
+                self.usize(*i);
+            }
+            CompilerInstruction::Comprehension {
+                iterable,
+                codeidx,
+                bodyidx,
+                is_dict,
+                result,
+                i,
+            } => {
+                self.u8(25);
+                self.register(iterable);
+                self.usize(*codeidx);
+                self.usize(*bodyidx);
+                self.bool(*is_dict);
+                self.register(result);
+                self.usize(*i);
+            }
+            CompilerInstruction::UnaryNeg { a, result, i } => {
+                self.u8(26);
+                self.register(a);
+                self.register(result);
+                self.usize(*i);
+            }
+            CompilerInstruction::UnaryInvert { a, result, i } => {
+                self.u8(27);
+                self.register(a);
+                self.register(result);
+                self.usize(*i);
+            }
+            CompilerInstruction::BuildList {
+                result,
+                value_registers,
+                i,
+            } => {
+                self.u8(28);
+                self.register(result);
+                self.registers(value_registers);
+                self.usize(*i);
+            }
+            CompilerInstruction::BuildDict {
+                result,
+                key_registers,
+                value_registers,
+                i,
+            } => {
+                self.u8(29);
+                self.register(result);
+                self.registers(key_registers);
+                self.registers(value_registers);
+                self.usize(*i);
+            }
+            CompilerInstruction::MakeClass {
+                name,
+                bases,
+                out,
+                code,
+                bodyidx,
+                i,
+            } => {
+                self.u8(30);
+                self.str(name);
+                self.registers(bases);
+                self.register(out);
+                self.code(code)?;
+                self.usize(*bodyidx);
+                self.usize(*i);
+            }
+            CompilerInstruction::AttrLoad {
+                left,
+                nameidx,
+                result,
+                i,
+            } => {
+                self.u8(31);
+                self.register(left);
+                self.usize(*nameidx);
+                self.register(result);
+                self.usize(*i);
+            }
+            CompilerInstruction::BuildClassDict {
+                result,
+                names,
+                variables,
+                i,
+            } => {
+                self.u8(32);
+                self.register(result);
+                self.usizes(names);
+                self.usizes(variables);
+                self.usize(*i);
+            }
+            CompilerInstruction::AttrStore {
+                left,
+                nameidx,
+                value,
+                i,
+            } => {
+                self.u8(33);
+                self.register(left);
+                self.usize(*nameidx);
+                self.register(value);
+                self.usize(*i);
+            }
+        }
+        Some(())
+    }
+
+    fn code(&mut self, code: &Code<'a>) -> Option<()> {
+        self.usize(code.instructions.len());
+        for instruction in &code.instructions {
+            self.instruction(instruction)?;
+        }
+        self.usize(code.consts.len());
+        for object in &code.consts {
+            self.constant(object)?;
+        }
+        self.usize(code.attr_names.len());
+        for name in &code.attr_names {
+            self.str(name);
+        }
+        self.usize(code.names.len());
+        for (idx, name) in &code.names {
+            self.i32(*idx);
+            self.str(name);
+        }
+        self.names(&code.scope_names);
+        self.usize(code.outer_names.len());
+        for ((level, idx), name) in &code.outer_names {
+            self.usize(*level);
+            self.usize(*idx);
+            self.str(name);
+        }
+        self.usize(code.named_consts.len());
+        for (name, object) in &code.named_consts {
+            self.str(name);
+            self.constant(object)?;
+        }
+        self.i32(code.n_registers);
+        self.i32(code.n_variables);
+        Some(())
+    }
+
+    //Write where a body was compiled from: its positions, and then those of the bodies defined in
+    //it, whose code is written with the instructions that make them
+    fn bytecode(&mut self, bytecode: &Bytecode<'a>) {
+        let positions: Vec<(Position, Position)> =
+            (0..).map_while(|i| bytecode.positions.get(i)).collect();
+        self.usize(positions.len());
+        for (start, end) in &positions {
+            self.position(start);
+            self.position(end);
+        }
+        self.usize(bytecode.bodies.len());
+        for body in &bytecode.bodies {
+            self.bytecode(body);
+        }
+    }
+
+    fn warning(&mut self, warning: &Warning) {
+        self.str(&warning.header);
+        self.position(&warning.start);
+        self.position(&warning.end);
+    }
+
+    fn names(&mut self, names: &IndexMap<String, i32>) {
+        self.usize(names.len());
+        for (name, idx) in names {
+            self.str(name);
+            self.i32(*idx);
+        }
+    }
+}
+
+struct Reader<'d, 'a> {
+    data: &'d [u8],
+    offset: usize,
+    vm: Trc<VM<'a>>,
+    info: &'a FileInfo<'a>,
+}
+
+impl<'d, 'a> Reader<'d, 'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'d [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(n)?)?;
+        self.offset += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+
+    fn usizes(&mut self) -> Option<Vec<usize>> {
+        (0..self.len()?).map(|_| self.usize()).collect()
+    }
+
+    //A length, which can not be more than the bytes that are left, so that damaged data does not
+    //allocate too much
+    fn len(&mut self) -> Option<usize> {
+        let len = self.usize()?;
+        (len <= self.data.len() - self.offset).then_some(len)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn position(&mut self) -> Option<Position> {
+        Some(Position {
+            startcol: self.usize()?,
+            endcol: self.usize()?,
+            line: self.usize()?,
+        })
+    }
+
+    fn register(&mut self) -> Option<CompilerRegister> {
+        Some(match self.u8()? {
+            0 => CompilerRegister::R(self.usize()?),
+            1 => CompilerRegister::V(self.usize()?),
+            2 => CompilerRegister::C(self.usize()?),
+            3 => CompilerRegister::E(self.usize()?, self.usize()?),
+            _ => return None,
+        })
+    }
+
+    fn registers(&mut self) -> Option<Vec<CompilerRegister>> {
+        (0..self.len()?).map(|_| self.register()).collect()
+    }
+
+    fn optional_register(&mut self) -> Option<Option<CompilerRegister>> {
+        Some(match self.bool()? {
+            true => Some(self.register()?),
+            false => None,
+        })
+    }
+
+    fn context(&mut self) -> Option<RegisterContext> {
+        let value = self.register()?;
+        let left = self.optional_register()?;
+        let leftctx = match self.bool()? {
+            true => Some(Box::new(self.context()?)),
+            false => None,
+        };
+        let right = self.optional_register()?;
+        let rightctx = match self.bool()? {
+            true => Some(Box::new(self.context()?)),
+            false => None,
+        };
+        let args = match self.bool()? {
+            true => Some(self.contexts()?),
+            false => None,
+        };
+        let mapping = match self.bool()? {
+            true => Some((self.contexts()?, self.contexts()?)),
+            false => None,
+        };
+        Some(RegisterContext {
+            value,
+            left,
+            leftctx,
+            right,
+            rightctx,
+            args,
+            mapping,
+            registers: self.i32()?,
+        })
+    }
+
+    fn contexts(&mut self) -> Option<Vec<RegisterContext>> {
+        (0..self.len()?).map(|_| self.context()).collect()
+    }
+
+    fn constant(&mut self) -> Option<Object<'a>> {
+        let vm = self.vm.clone();
+        Some(match self.u8()? {
+            CONST_INT => intobject::int_from(
+                vm,
+                i64::from_le_bytes(self.bytes(8)?.try_into().ok()?)
+                    .try_into()
+                    .ok()?,
+            ),
+            CONST_FLOAT => {
+                floatobject::float_from(vm, f64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+            }
+            CONST_STR => stringobject::string_from(vm, self.str()?),
+            CONST_LIST => {
+                let items = (0..self.len()?)
+                    .map(|_| self.constant())
+                    .collect::<Option<Vec<_>>>()?;
+                listobject::list_from(vm, items)
+            }
+            CONST_CODE => codeobject::code_from(vm, self.code()?),
+            CONST_BUILTIN => vm.builtins.get(&self.str()?)?.clone(),
+            _ => return None,
+        })
+    }
+
+    fn instruction(&mut self) -> Option<CompilerInstruction<'a>> {
+        macro_rules! binary {
+            ($variant:ident) => {
+                CompilerInstruction::$variant {
+                    a: self.register()?,
+                    b: self.register()?,
+                    result: self.register()?,
+                    i: self.usize()?,
+                }
+            };
+        }
+        Some(match self.u8()? {
+            0 => binary!(BinaryAdd),
+            1 => binary!(BinarySub),
+            2 => binary!(BinaryMul),
+            3 => binary!(BinaryAddInt),
+            4 => binary!(BinarySubInt),
+            5 => binary!(BinaryMulInt),
+            6 => binary!(BinaryDiv),
+            7 => binary!(BinaryEq),
+            8 => binary!(BinaryNe),
+            9 => binary!(BinaryLt),
+            10 => binary!(BinaryGt),
+            11 => binary!(BinaryLe),
+            12 => binary!(BinaryGe),
+            13 => binary!(BinaryBitAnd),
+            14 => binary!(BinaryBitOr),
+            15 => binary!(BinaryBitXor),
+            16 => binary!(BinaryLShift),
+            17 => binary!(BinaryRShift),
+            40 => binary!(BinaryContains),
+            18 => CompilerInstruction::CopyRegister {
+                from: self.register()?,
+                to: self.register()?,
+                i: self.usize()?,
+            },
+            19 => CompilerInstruction::MakeFunction {
+                nameidx: self.usize()?,
+                argsidx: self.usize()?,
+                codeidx: self.usize()?,
+                bodyidx: self.usize()?,
+                out: self.register()?,
+                is_abstract: self.bool()?,
+            },
+            20 => CompilerInstruction::Call {
+                callableregister: self.register()?,
+                result: self.register()?,
+                arg_registers: self.contexts()?,
+                i: self.usize()?,
+            },
+            21 => CompilerInstruction::Return {
+                register: self.register()?,
+                i: self.usize()?,
+            },
+            22 => CompilerInstruction::SkipIfFalse {
+                register: self.register()?,
+                n: self.usize()?,
+                i: self.usize()?,
+            },
+            23 => CompilerInstruction::EnterContext {
+                register: self.register()?,
+                out: self.register()?,
+                i: self.usize()?,
+            },
+            24 => CompilerInstruction::ExitContext { i: self.usize()? },
+            25 => CompilerInstruction::Comprehension {
+                iterable: self.register()?,
+                codeidx: self.usize()?,
+                bodyidx: self.usize()?,
+                is_dict: self.bool()?,
+                result: self.register()?,
+                i: self.usize()?,
+            },
+            26 => CompilerInstruction::UnaryNeg {
+                a: self.register()?,
+                result: self.register()?,
+                i: self.usize()?,
+            },
+            27 => CompilerInstruction::UnaryInvert {
+                a: self.register()?,
+                result: self.register()?,
+                i: self.usize()?,
+            },
+            28 => CompilerInstruction::BuildList {
+                result: self.register()?,
+                value_registers: self.registers()?,
+                i: self.usize()?,
+            },
+            29 => CompilerInstruction::BuildDict {
+                result: self.register()?,
+                key_registers: self.registers()?,
+                value_registers: self.registers()?,
+                i: self.usize()?,
+            },
+            30 => CompilerInstruction::MakeClass {
+                name: self.str()?,
+                bases: self.registers()?,
+                out: self.register()?,
+                code: self.code()?,
+                bodyidx: self.usize()?,
+                i: self.usize()?,
+            },
+            31 => CompilerInstruction::AttrLoad {
+                left: self.register()?,
+                nameidx: self.usize()?,
+                result: self.register()?,
+                i: self.usize()?,
+            },
+            32 => CompilerInstruction::BuildClassDict {
+                result: self.register()?,
+                names: self.usizes()?,
+                variables: self.usizes()?,
+                i: self.usize()?,
+            },
+            33 => CompilerInstruction::AttrStore {
+                left: self.register()?,
+                nameidx: self.usize()?,
+                value: self.register()?,
+                i: self.usize()?,
+            },
+            34 => CompilerInstruction::MoveRegister {
+                from: self.register()?,
+                to: self.register()?,
+                i: self.usize()?,
+            },
+            35 => CompilerInstruction::CallAttr {
+                left: self.register()?,
+                nameidx: self.usize()?,
+                result: self.register()?,
+                arg_registers: self.contexts()?,
+                i: self.usize()?,
+            },
+            36 => CompilerInstruction::StoreReturn {
+                from: self.register()?,
+                to: self.register()?,
+                i: self.usize()?,
+            },
+            _ => return None,
+        })
+    }
+
+    fn warning(&mut self) -> Option<Warning> {
+        Some(Warning {
+            header: self.str()?,
+            start: self.position()?,
+            end: self.position()?,
+        })
+    }
+
+    fn names(&mut self) -> Option<IndexMap<String, i32>> {
+        (0..self.len()?)
+            .map(|_| Some((self.str()?, self.i32()?)))
+            .collect()
+    }
+
+    fn code(&mut self) -> Option<Trc<Code<'a>>> {
+        let instructions = (0..self.len()?)
+            .map(|_| self.instruction())
+            .collect::<Option<Vec<_>>>()?;
+        let n_instructions = instructions.len();
+        let consts = (0..self.len()?)
+            .map(|_| self.constant())
+            .collect::<Option<Vec<_>>>()?;
+        let attr_names = (0..self.len()?)
+            .map(|_| self.str())
+            .collect::<Option<Vec<_>>>()?;
+        //The names are loaded from the strings that the VM interns, as if the body was compiled
+        for name in &attr_names {
+            VM::intern(self.vm.clone(), name);
+        }
+        let names = (0..self.len()?)
+            .map(|_| Some((self.i32()?, self.str()?)))
+            .collect::<Option<IndexMap<_, _>>>()?;
+        let scope_names = self.names()?;
+        let outer_names = (0..self.len()?)
+            .map(|_| Some(((self.usize()?, self.usize()?), self.str()?)))
+            .collect::<Option<IndexMap<_, _>>>()?;
+        let named_consts = (0..self.len()?)
+            .map(|_| Some((self.str()?, self.constant()?)))
+            .collect::<Option<IndexMap<_, _>>>()?;
+        Some(Trc::new(Code {
+            instructions,
+            consts,
+            attr_names,
+            names,
+            scope_names: Trc::new(scope_names),
+            outer_names,
+            named_consts,
+            n_registers: self.i32()?,
+            n_variables: self.i32()?,
+            feedback: vec![Cell::default(); n_instructions],
+            #[cfg(feature = "jit")]
+            jit: Cell::default(),
+            _marker: PhantomData,
+        }))
+    }
+
+    //Read where a body with the code was compiled from, and the bodies defined in it. Each of them
+    //must be made by exactly one instruction, which has its code.
+    fn bytecode(&mut self, code: Trc<Code<'a>>) -> Option<Trc<Bytecode<'a>>> {
+        let positions = (0..self.len()?)
+            .map(|_| Some((self.position()?, self.position()?)))
+            .collect::<Option<Vec<_>>>()?;
+        validate(&code, positions.len(), &self.vm)?;
+        let mut codes = vec![None; self.len()?];
+        for instruction in &code.instructions {
+            let (body, bodyidx) = match instruction {
+                CompilerInstruction::MakeFunction {
+                    codeidx, bodyidx, ..
+                }
+                | CompilerInstruction::Comprehension {
+                    codeidx, bodyidx, ..
+                } => (code.consts[*codeidx].as_code()?, bodyidx),
+                CompilerInstruction::MakeClass { code, bodyidx, .. } => (code, bodyidx),
+                _ => continue,
+            };
+            let slot = codes.get_mut(*bodyidx)?;
+            if slot.is_some() {
+                return None;
+            }
+            *slot = Some(body.clone());
+        }
+        let bodies = codes
+            .into_iter()
+            .map(|body| self.bytecode(body?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Trc::new(Bytecode {
+            code,
+            positions: LineTable::new(&positions),
+            info: self.info,
+            enclosing_names: Vec::new(),
+            bodies,
+        }))
+    }
+}
+
+//Check that the instructions of a body only use the registers, variables, constants, attribute
+//names, positions and instructions that it has, as the VM indexes them without checking. The
+//registers of enclosing scopes are not checked, as their sizes are only known when the body runs.
+//The bodies defined in it are checked when their positions are read.
+fn validate<'a>(bytecode: &Code<'a>, n_positions: usize, vm: &VM<'a>) -> Option<()> {
+    let n_registers = usize::try_from(bytecode.n_registers).ok()?;
+    let n_variables = usize::try_from(bytecode.n_variables).ok()?;
+    let n_instructions = bytecode.instructions.len();
+    let constant = |idx: usize, tp: &Option<Trc<TypeObject<'a>>>| {
+        let object = bytecode.consts.get(idx)?;
+        is_type_exact!(object, unwrap_fast!(tp.as_ref())).then_some(())
+    };
+    let attr_name = |idx: usize| (idx < bytecode.attr_names.len()).then_some(());
+
+    for (idx, instruction) in bytecode.instructions.iter().enumerate() {
+        let mut copy = instruction.clone();
+        if copy
+            .index_mut()
+            .is_some_and(|i| *i != idx || *i >= n_positions)
+        {
+            return None;
+        }
+        for register in copy.registers_mut() {
+            let exists = match *register {
+                CompilerRegister::R(v) => v < n_registers,
+                CompilerRegister::V(v) => v < n_variables,
+                CompilerRegister::C(v) => v < bytecode.consts.len(),
+                CompilerRegister::E(..) => true,
+            };
+            if !exists {
+                return None;
+            }
+        }
+        match instruction {
+            CompilerInstruction::MakeFunction {
+                nameidx,
+                argsidx,
+                codeidx,
+                ..
+            } => {
+                constant(*nameidx, &vm.types.strtp)?;
+                constant(*argsidx, &vm.types.listtp)?;
+                constant(*codeidx, &vm.types.codetp)?;
+            }
+            CompilerInstruction::Comprehension { codeidx, .. } => {
+                constant(*codeidx, &vm.types.codetp)?;
+            }
+            CompilerInstruction::AttrLoad { nameidx, .. }
+            | CompilerInstruction::AttrStore { nameidx, .. }
+            | CompilerInstruction::CallAttr { nameidx, .. } => attr_name(*nameidx)?,
+            CompilerInstruction::BuildClassDict {
+                names, variables, ..
+            } => {
+                for name in names {
+                    attr_name(*name)?;
+                }
+                if variables.iter().any(|var| *var >= n_variables) {
+                    return None;
+                }
+            }
+            //A skip may go to the end of the body, but not past it
+            CompilerInstruction::SkipIfFalse { n, .. }
+                if (idx + 1).checked_add(*n)? > n_instructions =>
+            {
+                return None;
+            }
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+//The bytes of a body, or None if it has a constant that can not be stored
+pub fn serialize<'a>(bytecode: &Bytecode<'a>, vm: &VM<'a>) -> Option<Vec<u8>> {
+    let mut writer = Writer {
+        data: Vec::new(),
+        vm,
+    };
+    writer.code(bytecode)?;
+    writer.bytecode(bytecode);
+    Some(writer.data)
+}
+
+//Load a body that serialize wrote from the given file, with the constants created by the VM. None
+//if the data is damaged or has trailing bytes.
+pub fn deserialize<'a>(
+    data: &[u8],
+    info: &'a FileInfo<'a>,
+    vm: Trc<VM<'a>>,
+) -> Option<Trc<Bytecode<'a>>> {
+    let mut reader = Reader {
+        data,
+        offset: 0,
+        vm,
+        info,
+    };
+    let code = reader.code()?;
+    let bytecode = reader.bytecode(code)?;
+    link_enclosing(&bytecode, &[], false);
+    (reader.offset == data.len()).then_some(bytecode)
+}
+
+//Give each body nested in a body the names of the scopes around it, which are not stored, as they
+//are the scope_names of the bodies that enclose it. Like when compiling, the bodies share them, and
+//the names of a class body are not in the scopes of the methods in it.
+fn link_enclosing<'a>(bytecode: &Trc<Bytecode<'a>>, enclosing: &[Names], is_class_body: bool) {
+    let mut inner = enclosing.to_vec();
+    if !is_class_body {
+        inner.push(bytecode.scope_names.clone());
+    }
+    for instruction in &bytecode.instructions {
+        match instruction {
+            CompilerInstruction::MakeFunction { bodyidx, .. }
+            | CompilerInstruction::Comprehension { bodyidx, .. } => {
+                link_enclosing(&bytecode.bodies[*bodyidx], &inner, false)
+            }
+            CompilerInstruction::MakeClass { bodyidx, .. } => {
+                link_enclosing(&bytecode.bodies[*bodyidx], &inner, true)
+            }
+            _ => {}
+        }
+    }
+    bytecode.clone().enclosing_names = enclosing.to_vec();
+}
+
+//The bytes of the warnings that were written while compiling a program, which the cache stores with
+//its body so that a run that loads it writes them again
+pub fn serialize_warnings<'a>(warnings: &[Warning], vm: &VM<'a>) -> Vec<u8> {
+    let mut writer = Writer {
+        data: Vec::new(),
+        vm,
+    };
+    writer.usize(warnings.len());
+    for warning in warnings {
+        writer.warning(warning);
+    }
+    writer.data
+}
+
+//Load the warnings that serialize_warnings wrote at the start of data, and the rest of the data
+pub fn deserialize_warnings<'d, 'a>(
+    data: &'d [u8],
+    info: &'a FileInfo<'a>,
+    vm: Trc<VM<'a>>,
+) -> Option<(Vec<Warning>, &'d [u8])> {
+    let mut reader = Reader {
+        data,
+        offset: 0,
+        vm,
+        info,
+    };
+    let warnings = (0..reader.len()?)
+        .map(|_| reader.warning())
+        .collect::<Option<Vec<_>>>()?;
+    Some((warnings, &data[reader.offset..]))
+}
@@ -0,0 +1,204 @@
+//Where errors and warnings are written. Programs that embed merlin may send them to stderr, collect
+//them in a buffer or drop them, instead of printing them to stdout.
+
+use std::{
+    cell::RefCell,
+    io::{IsTerminal, Write},
+    rc::Rc,
+};
+
+use colored::{ColoredString, Colorize};
+
+use crate::{fileinfo::FileInfo, parser::Position};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    Always,
+    Never,
+    #[default]
+    Auto, //Color only when writing to a terminal
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Option<ColorMode> {
+        match name {
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            "auto" => Some(ColorMode::Auto),
+            _ => None,
+        }
+    }
+}
+
+pub enum Sink {
+    Stdout,
+    Stderr,
+    Buffer(Vec<u8>),
+    Writer(Box<dyn Write>),
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Sink::Stdout => std::io::stdout().write(buf),
+            Sink::Stderr => std::io::stderr().write(buf),
+            Sink::Buffer(data) => data.write(buf),
+            Sink::Writer(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Sink::Stdout => std::io::stdout().flush(),
+            Sink::Stderr => std::io::stderr().flush(),
+            Sink::Buffer(_) => Ok(()),
+            Sink::Writer(writer) => writer.flush(),
+        }
+    }
+}
+
+//A warning as it was written, so that a cached program can write it again
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    pub header: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+//Clones write to the same sink
+#[derive(Clone)]
+pub struct Diagnostics {
+    sink: Rc<RefCell<Sink>>,
+    color: bool,
+    recorded: Rc<RefCell<Option<Vec<Warning>>>>, //The warnings written since record_warnings, if it was called
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Diagnostics::new(Sink::Stdout, ColorMode::Auto)
+    }
+}
+
+impl Diagnostics {
+    pub fn new(sink: Sink, mode: ColorMode) -> Diagnostics {
+        let color = match mode {
+            //colored only colors a terminal by itself
+            ColorMode::Always => {
+                colored::control::set_override(true);
+                true
+            }
+            ColorMode::Never => false,
+            ColorMode::Auto => match sink {
+                Sink::Stdout => std::io::stdout().is_terminal(),
+                Sink::Stderr => std::io::stderr().is_terminal(),
+                Sink::Buffer(_) | Sink::Writer(_) => false,
+            },
+        };
+        Diagnostics {
+            sink: Rc::new(RefCell::new(sink)),
+            color,
+            recorded: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    //What was written to a buffer since it was last taken, which is empty for the other sinks
+    #[allow(dead_code)]
+    pub fn take_buffer(&self) -> Vec<u8> {
+        match &mut *self.sink.borrow_mut() {
+            Sink::Buffer(data) => std::mem::take(data),
+            _ => Vec::new(),
+        }
+    }
+
+    fn paint(&self, text: &str, style: impl Fn(&str) -> ColoredString) -> String {
+        if self.color {
+            style(text).to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    //Failing to write a diagnostic is ignored, as there is nowhere else to report it
+    pub fn write_line(&self, line: &str) {
+        let mut sink = self.sink.borrow_mut();
+        let _ = writeln!(sink, "{}", line);
+        let _ = sink.flush();
+    }
+
+    //Write text that does not end the line, like a prompt
+    #[cfg_attr(not(feature = "debugger"), allow(dead_code))]
+    pub fn write(&self, text: &str) {
+        let mut sink = self.sink.borrow_mut();
+        let _ = write!(sink, "{}", text);
+        let _ = sink.flush();
+    }
+
+    //Write an error with its location and the line of the source that it is about, with arrows
+    //under the columns from start to end
+    pub fn report(&self, header: &str, info: &FileInfo, start: &Position, end: &Position) {
+        self.report_styled(header, info, start, end, |s| s.red());
+    }
+
+    //Write a warning like an error, which does not stop the program
+    pub fn warn(&self, header: &str, info: &FileInfo, start: &Position, end: &Position) {
+        if let Some(recorded) = &mut *self.recorded.borrow_mut() {
+            recorded.push(Warning {
+                header: header.to_string(),
+                start: *start,
+                end: *end,
+            });
+        }
+        self.report_styled(header, info, start, end, |s| s.yellow());
+    }
+
+    //Keep the warnings that are written from now on, until they are taken
+    pub fn record_warnings(&self) {
+        *self.recorded.borrow_mut() = Some(Vec::new());
+    }
+
+    //The warnings written since record_warnings, which stops recording them
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        self.recorded.borrow_mut().take().unwrap_or_default()
+    }
+
+    fn report_styled(
+        &self,
+        header: &str,
+        info: &FileInfo,
+        start: &Position,
+        end: &Position,
+        style: fn(&str) -> ColoredString,
+    ) {
+        let location = format!("{}:{}:{}", info.name, start.line + 1, start.startcol + 1);
+        self.write_line(&self.paint(header, |s| style(s).bold()));
+        self.write_line(&self.paint(&location, style));
+        //The source may not be valid utf8, and the position may be past its end
+        let line = info
+            .data
+            .split(|num| *num as char == '\n')
+            .nth(start.line)
+            .unwrap_or_default();
+        let line = String::from_utf8_lossy(line);
+
+        //There is at least one arrow, also for a position at the end of the line
+        let mut arrows: String = String::new();
+        for idx in 0..line.len().max(start.startcol + 1) {
+            if idx == start.startcol || (idx > start.startcol && idx < end.endcol) {
+                arrows += "^";
+            } else {
+                arrows += " ";
+            }
+        }
+        let linestr = (start.line + 1).to_string();
+        self.write_line(&format!(
+            "{} | {}",
+            self.paint(&linestr, |s| s.blue().bold()),
+            self.paint(&line, |s| s.blue())
+        ));
+        self.write_line(&format!(
+            "{} | {}",
+            " ".repeat(linestr.len()),
+            self.paint(&arrows, |s| s.green())
+        ));
+    }
+}
@@ -1,14 +1,28 @@
 //Pretty error generation
 
-use colored::Colorize;
+use crate::{diagnostics::Diagnostics, fileinfo::FileInfo};
 
-#[derive(Clone, FromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum ErrorType {
     UnexpectedToken,
     UnknownKeyword,
     UnexpectedEOF,
     FunctionNotExpression,
     TrailingAtomics,
+    ExpectedExpression,
+    MalformedExponent,
+    ConstantReassignment,
+    ConstantNotLiteral,
+    ReservedKeyword,
+    InvalidEscapedIdentifier,
+    UndefinedName,
+    DuplicateName,
+    ShadowedName,
+    TooManyRegisters,
+    InternalError,
+    NestingTooDeep,
+    Redefinition,
+    MalformedNumber,
 }
 
 impl std::fmt::Display for ErrorType {
@@ -30,6 +44,135 @@ pub fn repr_err(tp: ErrorType) -> &'static str {
         ErrorType::TrailingAtomics => {
             "Trailing atomic tokens are not allowed: Code like: `1a` or `a 1` is not allowed."
         }
+        ErrorType::ExpectedExpression => {
+            "Expected an expression: eval accepts one expression, and not statements like assignments and definitions."
+        }
+        ErrorType::MalformedExponent => {
+            "Malformed exponent: The exponent of a number needs digits, like `1e9` or `2.5e-3`."
+        }
+        ErrorType::ConstantReassignment => {
+            "Constant reassignment: A name declared with `const` may not be assigned or defined again."
+        }
+        ErrorType::ConstantNotLiteral => {
+            "Constant is not a literal: The value of a `const` declaration must be a number or string literal."
+        }
+        ErrorType::ReservedKeyword => {
+            "Reserved keyword: Keywords may not be used as names unless escaped with backticks, like `in`."
+        }
+        ErrorType::InvalidEscapedIdentifier => {
+            "Invalid escaped identifier: Backticks must enclose exactly one identifier, like `in`."
+        }
+        ErrorType::UndefinedName => {
+            "Undefined name: A name must be defined before it is used, in its scope or an enclosing one."
+        }
+        ErrorType::DuplicateName => {
+            "Duplicate name: A name may only be bound once in a list of parameters, or declared once in a block."
+        }
+        ErrorType::ShadowedName => {
+            "Shadowed name: A body that reads a name of an enclosing scope may not then bind it, use another name."
+        }
+        ErrorType::TooManyRegisters => {
+            "Too many registers: A statement may only hold up to 65535 intermediate values at once."
+        }
+        ErrorType::InternalError => {
+            "Internal error: The compiler found a node without the data that it needs. This is a bug in merlin, please report it with the code that caused it."
+        }
+        ErrorType::NestingTooDeep => {
+            "Nesting too deep: Expressions and blocks may only be nested up to 256 levels."
+        }
+        ErrorType::Redefinition => {
+            "Redefinition: With --strict, a function or class may not be bound again in the same scope, use another name."
+        }
+        ErrorType::MalformedNumber => {
+            "Malformed number: Underscores may only separate digits, and a number may have one `.` followed by digits, like `1_000.5`."
+        }
+    }
+}
+
+//Mistakes that do not stop a program from running, which are reported when they are enabled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarningType {
+    UnusedVariable,
+    UnusedFunction,
+    Redefinition,
+    UnusedValue,
+    DuplicateKey,
+}
+
+//The exit status of merlin, by what stopped the program, so that scripts can tell failures apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success,
+    Usage,     //2: Invalid command line arguments, or a file that can not be read
+    Syntax,    //3: An error found while lexing, parsing or compiling the program
+    Runtime,   //4: An exception that was not caught
+    Exit(i32), //The status that the program exited with, with env.exit
+}
+
+impl ExitCode {
+    pub fn status(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Usage => 2,
+            ExitCode::Syntax => 3,
+            ExitCode::Runtime => 4,
+            ExitCode::Exit(status) => status,
+        }
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code.status() as u8)
+    }
+}
+
+//The panic payload of a program that stops before its end. It unwinds to run_data, which returns
+//the status, so that the frames and objects of the program are dropped and the code that ran it
+//sees why it stopped, instead of the process exiting where the program stopped.
+pub struct Exit(pub ExitCode);
+
+impl Exit {
+    pub fn raise(code: ExitCode) -> ! {
+        std::panic::resume_unwind(Box::new(Exit(code)))
+    }
+}
+
+//An error in merlin itself, at the node that caused it
+#[derive(Clone, Debug)]
+pub struct InternalError {
+    pub message: String,
+    pub pos: crate::parser::Position,
+}
+
+//An error in the source of a program, found while lexing or parsing it
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub tp: ErrorType,
+    pub pos: crate::parser::Position,
+}
+
+//Report an internal error like the errors of a program, instead of panicking without a position
+pub trait OrRaise<T> {
+    fn or_raise(self, info: &FileInfo, out: &Diagnostics) -> T;
+}
+
+impl<T> OrRaise<T> for Result<T, ParseError> {
+    fn or_raise(self, info: &FileInfo, out: &Diagnostics) -> T {
+        match self {
+            Ok(value) => value,
+            Err(err) => raise_error(&err.message, err.tp, &err.pos, info, out),
+        }
+    }
+}
+
+impl<T> OrRaise<T> for Result<T, InternalError> {
+    fn or_raise(self, info: &FileInfo, out: &Diagnostics) -> T {
+        match self {
+            Ok(value) => value,
+            Err(err) => raise_error(&err.message, ErrorType::InternalError, &err.pos, info, out),
+        }
     }
 }
 
@@ -37,35 +180,34 @@ pub fn raise_error(
     error: &str,
     errtp: ErrorType,
     pos: &crate::parser::Position,
-    info: &crate::fileinfo::FileInfo,
+    info: &FileInfo,
+    out: &Diagnostics,
 ) -> ! {
+    print_error(error, errtp, pos, info, out);
+    Exit::raise(ExitCode::Syntax);
+}
+
+//Print a warning about the code from start to end
+pub fn print_warning(
+    warning: &str,
+    warntp: WarningType,
+    start: &crate::parser::Position,
+    end: &crate::parser::Position,
+    info: &FileInfo,
+    out: &Diagnostics,
+) {
+    let header: String = format!("warning[W{:0>3}]: {}", warntp as u8 + 1, warning);
+    out.warn(&header, info, start, end);
+}
+
+//Print an error without stopping, so that several errors can be reported at once
+pub fn print_error(
+    error: &str,
+    errtp: ErrorType,
+    pos: &crate::parser::Position,
+    info: &FileInfo,
+    out: &Diagnostics,
+) {
     let header: String = format!("error[E{:0>3}]: {}", errtp as u8 + 1, error);
-    let location: String = format!("{}:{}:{}", info.name, pos.line + 1, pos.startcol + 1);
-    println!("{}", header.red().bold());
-    println!("{}", location.red());
-    let lines = Vec::from_iter(info.data.split(|num| *num as char == '\n'));
-
-    let snippet: String = format!(
-        "{}",
-        String::from_utf8(
-            lines
-                .get(pos.line)
-                .expect("Line index out of range")
-                .to_vec()
-        )
-        .expect("utf8 conversion failed")
-        .blue()
-    );
-    let mut arrows: String = String::new();
-    for idx in 0..snippet.len() {
-        if idx >= pos.startcol && idx < pos.endcol {
-            arrows += "^";
-        } else {
-            arrows += " ";
-        }
-    }
-    let linestr = (pos.line + 1).to_string().blue().bold();
-    println!("{} | {}", linestr, snippet);
-    println!("{} | {}", " ".repeat(linestr.len()), arrows.green());
-    std::process::exit(1);
+    out.report(&header, info, pos, pos);
 }
@@ -1,6 +1,6 @@
 //Simple struct containing file data & info
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FileInfo<'life> {
     pub data: &'life [u8],
     pub name: String,
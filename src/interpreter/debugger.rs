@@ -0,0 +1,109 @@
+//The debugger, with the debugger feature: breakpoint() pauses the program at the call, and commands
+//are read to show where it is and the values of its variables, until it is continued.
+
+use super::{trace::operand_repr, VM};
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use std::cell::RefCell;
+use std::io::BufRead;
+use std::rc::Rc;
+
+const HELP: &str = "Commands: where, locals, p <name>, c (continue), help";
+
+//Where the debugger reads its commands, shared by the clones of the VM
+#[derive(Clone)]
+pub struct Input(Rc<RefCell<Box<dyn BufRead>>>);
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::new(std::io::BufReader::new(std::io::stdin()))
+    }
+}
+
+impl Input {
+    pub fn new(reader: impl BufRead + 'static) -> Self {
+        Input(Rc::new(RefCell::new(Box::new(reader))))
+    }
+
+    //The next command, or None at the end of the input
+    fn read_command(&self) -> Option<String> {
+        let mut line = String::new();
+        match self.0.borrow_mut().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+}
+
+//Pause at the breakpoint() call of the running frame, and run the commands that are read until the
+//program is continued or the input ends
+pub fn debug(vm: &Trc<VM<'_>>) {
+    let interpreter = unwrap_fast!(vm.interpreters.last()).clone();
+    let frame = unwrap_fast!(interpreter.frames.last());
+    let variables = unwrap_fast!(vm.namespaces.variables.last()).clone();
+    //The frame is running, so its body is alive
+    let bytecode = unsafe { &*frame.bytecode };
+
+    let location = match bytecode.positions.get(frame.call) {
+        Some((start, _)) => {
+            let source = String::from_utf8_lossy(bytecode.info.data);
+            format!(
+                "{}:{} | {}",
+                bytecode.info.name,
+                start.line + 1,
+                source.lines().nth(start.line).unwrap_or_default().trim()
+            )
+        }
+        None => bytecode.info.name.clone(),
+    };
+    vm.diagnostics
+        .write_line(&format!("[debug] Paused at {location}"));
+
+    loop {
+        vm.diagnostics.write("(merlin) ");
+        let command = match vm.debug_input.read_command() {
+            Some(command) => command,
+            None => break,
+        };
+        match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => (),
+            ["c"] | ["continue"] => break,
+            ["where"] => vm.diagnostics.write_line(&location),
+            ["locals"] => {
+                for (name, value) in VM::locals(vm) {
+                    vm.diagnostics
+                        .write_line(&format!("{} = {}", name, operand_repr(value)));
+                }
+            }
+            ["p", name] => {
+                let local = bytecode
+                    .names
+                    .iter()
+                    .find(|(_, local)| local.as_str() == *name)
+                    .map(|(idx, _)| variables.get(*idx as usize).cloned().flatten());
+                let outer = || {
+                    bytecode
+                        .outer_names
+                        .iter()
+                        .find(|(_, outer)| outer.as_str() == *name)
+                        .map(|((level, idx), _)| {
+                            frame
+                                .enclosing
+                                .get(*level)
+                                .and_then(|scope| scope.get(*idx).cloned().flatten())
+                        })
+                };
+                match local.or_else(outer) {
+                    Some(Some(value)) => vm.diagnostics.write_line(&operand_repr(value)),
+                    Some(None) => vm
+                        .diagnostics
+                        .write_line(&format!("'{name}' is not yet assigned")),
+                    None => vm
+                        .diagnostics
+                        .write_line(&format!("'{name}' is not a variable of this scope")),
+                }
+            }
+            _ => vm.diagnostics.write_line(HELP),
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//An experimental JIT, built with the jit feature. A function body that was called JIT_CALLS times is
+//compiled to native code with cranelift if it only does int arithmetic on its arguments and on int
+//constants, and returns the result. A call whose arguments are all ints then runs the native code.
+//The native code gives up when a result overflows, and the call is run by the interpreter instead,
+//which handles the overflow like any other. Bodies with other instructions are left to the
+//interpreter, and so are runs that count, limit or trace instructions, or that must be deterministic.
+
+use super::VM;
+use crate::compiler::{Bytecode, CompilerInstruction, CompilerRegister};
+use crate::objects::{intobject, Object};
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags, Type, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const JIT_CALLS: u32 = 100;
+//The most arguments of a compiled body, which are passed to it in an array on the stack
+const MAX_ARGS: usize = 8;
+
+//Reads the arguments and writes the result, and returns 1, or 0 if the call must be interpreted
+type NativeFn = unsafe extern "C" fn(*const isize, *mut isize) -> u8;
+
+#[derive(Clone, Copy, Debug)]
+pub enum JitState {
+    Counting(u32),             //Calls so far
+    Compiled(NativeFn, usize), //The native code, for calls with this many arguments
+    Unsupported,
+}
+
+impl Default for JitState {
+    fn default() -> Self {
+        JitState::Counting(0)
+    }
+}
+
+//The module that owns the native code, created on the first compile. The code lives as long as
+//the VM.
+#[derive(Clone, Default)]
+pub struct Jit {
+    module: Rc<RefCell<Option<JITModule>>>,
+}
+
+//Run a call with native code, or return None to interpret it
+pub fn try_call<'a>(
+    vm: &Trc<VM<'a>>,
+    bytecode: &Bytecode<'a>,
+    args: &[Object<'a>],
+) -> Option<Object<'a>> {
+    if vm.deterministic
+        || vm.instruction_counts.is_some()
+        || vm.step_limit.is_some()
+        || vm.trace.is_some()
+    {
+        return None;
+    }
+    let (native, n_args) = match bytecode.jit.get() {
+        JitState::Compiled(native, n_args) => (native, n_args),
+        JitState::Unsupported => return None,
+        JitState::Counting(calls) if calls + 1 < JIT_CALLS => {
+            bytecode.jit.set(JitState::Counting(calls + 1));
+            return None;
+        }
+        JitState::Counting(_) => {
+            let state = match vm.jit.compile(bytecode, args.len()) {
+                Some(native) => JitState::Compiled(native, args.len()),
+                None => JitState::Unsupported,
+            };
+            bytecode.jit.set(state);
+            match state {
+                JitState::Compiled(native, n_args) => (native, n_args),
+                _ => return None,
+            }
+        }
+    };
+    if args.len() != n_args {
+        return None;
+    }
+
+    let mut values = [0; MAX_ARGS];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.as_int()?;
+    }
+    let mut result = 0;
+    if unsafe { native(values.as_ptr(), &mut result) } == 0 {
+        return None;
+    }
+    Some(intobject::int_from(vm.clone(), result))
+}
+
+impl Jit {
+    fn compile(&self, bytecode: &Bytecode<'_>, n_args: usize) -> Option<NativeFn> {
+        if n_args > MAX_ARGS {
+            return None;
+        }
+        let mut module = self.module.borrow_mut();
+        if module.is_none() {
+            *module = Some(new_module()?);
+        }
+        let module = unwrap_fast!(module.as_mut());
+
+        let ptr = module.target_config().pointer_type();
+        let mut ctx = module.make_context();
+        ctx.func.signature.params.push(AbiParam::new(ptr));
+        ctx.func.signature.params.push(AbiParam::new(ptr));
+        ctx.func.signature.returns.push(AbiParam::new(types::I8));
+        let mut builder_ctx = FunctionBuilderContext::new();
+        let builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+        let translated = Translator::new(builder, bytecode, ptr).translate(bytecode, n_args);
+        if translated.is_none() {
+            module.clear_context(&mut ctx);
+            return None;
+        }
+
+        let id = module
+            .declare_anonymous_function(&ctx.func.signature)
+            .ok()?;
+        let defined = module.define_function(id, &mut ctx);
+        module.clear_context(&mut ctx);
+        defined.ok()?;
+        module.finalize_definitions().ok()?;
+        let code = module.get_finalized_function(id);
+        Some(unsafe { std::mem::transmute::<*const u8, NativeFn>(code) })
+    }
+}
+
+//None if the host is not supported by cranelift
+fn new_module() -> Option<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+    Some(JITModule::new(JITBuilder::with_isa(
+        isa,
+        cranelift_module::default_libcall_names(),
+    )))
+}
+
+//Builds the native code of a body, whose registers and variables are cranelift variables
+struct Translator<'f> {
+    builder: FunctionBuilder<'f>,
+    ptr: Type,
+    n_registers: usize,
+    defined: Vec<bool>, //The registers and variables that were written, which may be read
+    bail: cranelift_codegen::ir::Block,
+}
+
+impl<'f> Translator<'f> {
+    fn new(mut builder: FunctionBuilder<'f>, bytecode: &Bytecode<'_>, ptr: Type) -> Self {
+        let n_registers = bytecode.n_registers as usize;
+        let n = n_registers + bytecode.n_variables as usize;
+        for idx in 0..n {
+            builder.declare_var(Variable::from_u32(idx as u32), ptr);
+        }
+        let bail = builder.create_block();
+        Translator {
+            builder,
+            ptr,
+            n_registers,
+            defined: vec![false; n],
+            bail,
+        }
+    }
+
+    fn index(&self, register: CompilerRegister) -> Option<usize> {
+        match register {
+            CompilerRegister::R(v) => Some(v),
+            CompilerRegister::V(v) => Some(self.n_registers + v),
+            CompilerRegister::C(_) | CompilerRegister::E(_, _) => None,
+        }
+    }
+
+    fn read(&mut self, bytecode: &Bytecode<'_>, register: CompilerRegister) -> Option<Value> {
+        if let CompilerRegister::C(v) = register {
+            let value = bytecode.consts[v].as_int()? as i64;
+            return Some(self.builder.ins().iconst(self.ptr, value));
+        }
+        let idx = self.index(register)?;
+        //The interpreter raises for a variable that is read before it is assigned
+        if !*self.defined.get(idx)? {
+            return None;
+        }
+        Some(self.builder.use_var(Variable::from_u32(idx as u32)))
+    }
+
+    fn write(&mut self, register: CompilerRegister, value: Value) -> Option<()> {
+        let idx = self.index(register)?;
+        *self.defined.get_mut(idx)? = true;
+        self.builder.def_var(Variable::from_u32(idx as u32), value);
+        Some(())
+    }
+
+    //Continue if the overflow flag is not set, or give the call to the interpreter
+    fn check_overflow(&mut self, overflow: Value) {
+        let next = self.builder.create_block();
+        self.builder.ins().brif(overflow, self.bail, &[], next, &[]);
+        self.builder.switch_to_block(next);
+        self.builder.seal_block(next);
+    }
+
+    fn ret(&mut self, result_ptr: Value, value: Value) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, result_ptr, 0);
+        let ok = self.builder.ins().iconst(types::I8, 1);
+        self.builder.ins().return_(&[ok]);
+    }
+
+    fn translate(mut self, bytecode: &Bytecode<'_>, n_args: usize) -> Option<()> {
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        self.builder.seal_block(entry);
+        let args_ptr = self.builder.block_params(entry)[0];
+        let result_ptr = self.builder.block_params(entry)[1];
+        for arg in 0..n_args {
+            let offset = (arg * self.ptr.bytes() as usize) as i32;
+            let value = self
+                .builder
+                .ins()
+                .load(self.ptr, MemFlags::trusted(), args_ptr, offset);
+            self.write(CompilerRegister::V(arg), value)?;
+        }
+
+        let mut returned = false;
+        for instruction in &bytecode.instructions {
+            match instruction {
+                CompilerInstruction::BinaryAdd { a, b, result, .. }
+                | CompilerInstruction::BinaryAddInt { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let (value, overflow) = self.builder.ins().sadd_overflow(x, y);
+                    self.check_overflow(overflow);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::BinarySub { a, b, result, .. }
+                | CompilerInstruction::BinarySubInt { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let (value, overflow) = self.builder.ins().ssub_overflow(x, y);
+                    self.check_overflow(overflow);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::BinaryMul { a, b, result, .. }
+                | CompilerInstruction::BinaryMulInt { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let (value, overflow) = self.builder.ins().smul_overflow(x, y);
+                    self.check_overflow(overflow);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::BinaryBitAnd { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let value = self.builder.ins().band(x, y);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::BinaryBitOr { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let value = self.builder.ins().bor(x, y);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::BinaryBitXor { a, b, result, .. } => {
+                    let (x, y) = (self.read(bytecode, *a)?, self.read(bytecode, *b)?);
+                    let value = self.builder.ins().bxor(x, y);
+                    self.write(*result, value)?;
+                }
+                CompilerInstruction::CopyRegister { from, to, .. }
+                | CompilerInstruction::MoveRegister { from, to, .. } => {
+                    let value = self.read(bytecode, *from)?;
+                    self.write(*to, value)?;
+                }
+                CompilerInstruction::Return { register, .. } => {
+                    let value = self.read(bytecode, *register)?;
+                    self.ret(result_ptr, value);
+                    returned = true;
+                    break;
+                }
+                CompilerInstruction::StoreReturn { from, to, .. } => {
+                    let value = self.read(bytecode, *from)?;
+                    self.write(*to, value)?;
+                    self.ret(result_ptr, value);
+                    returned = true;
+                    break;
+                }
+                _ => return None,
+            }
+        }
+        //A body that ends without returning returns none
+        if !returned {
+            return None;
+        }
+
+        self.builder.switch_to_block(self.bail);
+        let bail = self.builder.ins().iconst(types::I8, 0);
+        self.builder.ins().return_(&[bail]);
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+        Some(())
+    }
+}
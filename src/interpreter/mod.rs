@@ -1,37 +1,149 @@
 // Interpret bytecode
 
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod quicken;
+pub mod trace;
+
 use crate::objects::exceptionobject::{self, methodnotdefinedexc_from_str};
 use crate::objects::{
-    classtype, dictobject, mhash, noneobject, stringobject, RawObject, TypeObject,
+    call_with_first, classtype, dictobject, mhash, noneobject, shape::Shape, stringobject, IdSet,
+    MethodValue, RawObject, TypeObject,
 };
 use crate::parser::Position;
+use crate::rc::Trc;
 use crate::{
-    compiler::{Bytecode, CompilerInstruction, CompilerRegister},
+    builtins::LogLevel,
+    compiler::{Bytecode, Compiler, CompilerInstruction, CompilerRegister},
+    diagnostics::Diagnostics,
+    errors::{Exit, ExitCode},
     fileinfo::FileInfo,
-    none_from,
-    objects::{boolobject, fnobject, intobject, listobject, Object},
-    stats, TimeitHolder,
+    objects::{
+        boolobject, floatobject, fnobject,
+        intobject::{self, IntOverflow},
+        listobject, Object,
+    },
+    stats,
+    value::{MerlinError, Value},
+    TimeitHolder,
 };
-use colored::Colorize;
 use std::marker::PhantomData;
 use std::ops::DerefMut;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
 use std::time::Instant;
-use trc::Trc;
+
+//The variables of one frame, shared with the functions defined in that frame
+pub type Scope<'a> = Trc<Vec<Option<Object<'a>>>>;
+
+//Global variables that persist across executions. Code compiled in a namespace and executed with
+//it sees the variables that earlier executions defined.
+#[derive(Clone, Default)]
+pub struct Namespace<'a> {
+    names: indexmap::IndexMap<String, i32>,
+    variables: Scope<'a>,
+}
+
+impl<'a> Namespace<'a> {
+    pub fn new() -> Self {
+        Namespace {
+            names: indexmap::IndexMap::new(),
+            variables: Trc::new(Vec::new()),
+        }
+    }
+
+    //The index of each variable, for compiling code in this namespace
+    pub fn names(&self) -> &indexmap::IndexMap<String, i32> {
+        &self.names
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object<'a>> {
+        let idx = *self.names.get(name)? as usize;
+        self.variables.get(idx)?.clone()
+    }
+
+    //The variables by index, None where they are not yet assigned
+    pub fn variables(&self) -> &[Option<Object<'a>>] {
+        &self.variables
+    }
+}
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct Namespaces<'a> {
-    variables: Vec<Vec<Option<Object<'a>>>>,
+    variables: Vec<Scope<'a>>,
     _marker: PhantomData<&'a ()>,
 }
 
+//The most frames of each kind that are kept for reuse
+const FRAME_POOL_SIZE: usize = 64;
+pub const METHOD_CACHE_SIZE: usize = 64;
+
+//Register and variable vectors of finished frames, reused by the next calls so that calls do
+//not allocate them
+#[derive(Clone, Default)]
+pub struct FramePool<'a> {
+    registers: Vec<Vec<Object<'a>>>,
+    scopes: Vec<Scope<'a>>,
+}
+
+impl<'a> FramePool<'a> {
+    fn take_registers(&mut self, n: usize, none: Object<'a>) -> Vec<Object<'a>> {
+        let mut registers = self.registers.pop().unwrap_or_default();
+        registers.resize(n, none);
+        registers
+    }
+
+    fn take_scope(&mut self, n: usize) -> Scope<'a> {
+        match self.scopes.pop() {
+            Some(mut scope) => {
+                scope.resize(n, None);
+                scope
+            }
+            None => Trc::new(vec![None; n]),
+        }
+    }
+
+    //The register and variable vectors that are kept
+    fn sizes(&self) -> (usize, usize) {
+        (self.registers.len(), self.scopes.len())
+    }
+
+    fn recycle_registers(&mut self, mut registers: Vec<Object<'a>>) {
+        if self.registers.len() < FRAME_POOL_SIZE {
+            registers.clear();
+            self.registers.push(registers);
+        }
+    }
+
+    fn recycle(&mut self, registers: Vec<Object<'a>>, mut scope: Scope<'a>) {
+        self.recycle_registers(registers);
+        //Functions defined in the frame may still refer to its variables
+        if self.scopes.len() < FRAME_POOL_SIZE
+            && Trc::local_refcount(&scope) == 1
+            && Trc::atomic_count(&scope) == 1
+        {
+            scope.clear();
+            self.scopes.push(scope);
+        }
+    }
+}
+
 pub const MIN_INT_CACHE: isize = -5;
+//The largest cached int, unless the cache is resized with --int-cache
 pub const MAX_INT_CACHE: isize = 256;
-pub const INT_CACHE_SIZE: isize = MAX_INT_CACHE - MIN_INT_CACHE;
-pub const INT_CACHE_OFFSET: isize = MIN_INT_CACHE.abs();
+//The largest int that --int-cache may cache, as every cached int is kept for as long as the VM
+pub const MAX_INT_CACHE_LIMIT: isize = 1_000_000;
+//The default for VM::repr_limit
+pub const REPR_LIMIT: usize = 100;
+//The most calls that a timed run with --step-limit may nest, so that runaway recursion is reported
+//before it overflows the stack
+pub const MAX_CALL_DEPTH: usize = 1000;
 
 #[derive(Clone)]
 pub struct SingletonCache<'a> {
-    pub int_cache: [Option<Object<'a>>; INT_CACHE_SIZE as usize],
+    pub int_cache: Vec<Object<'a>>, //The ints from MIN_INT_CACHE up, which int_from returns instead of allocating
     pub bool_cache: (Option<Object<'a>>, Option<Object<'a>>),
     pub none_singleton: Option<Object<'a>>,
     _marker: PhantomData<&'a ()>,
@@ -42,6 +154,8 @@ pub struct Types<'a> {
     pub typetp: Option<Trc<TypeObject<'a>>>,
     pub objecttp: Option<Trc<TypeObject<'a>>>,
     pub inttp: Option<Trc<TypeObject<'a>>>,
+    pub bigtp: Option<Trc<TypeObject<'a>>>,
+    pub floattp: Option<Trc<TypeObject<'a>>>,
     pub booltp: Option<Trc<TypeObject<'a>>>,
     pub codetp: Option<Trc<TypeObject<'a>>>,
     pub dicttp: Option<Trc<TypeObject<'a>>>,
@@ -60,6 +174,17 @@ pub struct Types<'a> {
     pub classtp: Option<Trc<TypeObject<'a>>>,
     pub attrexctp: Option<Trc<TypeObject<'a>>>,
     pub methodtp: Option<Trc<TypeObject<'a>>>,
+    pub builtintp: Option<Trc<TypeObject<'a>>>,
+    pub supertp: Option<Trc<TypeObject<'a>>>,
+    pub unhashableexctp: Option<Trc<TypeObject<'a>>>,
+    pub indexexctp: Option<Trc<TypeObject<'a>>>,
+    pub argumentexctp: Option<Trc<TypeObject<'a>>>,
+    pub ioexctp: Option<Trc<TypeObject<'a>>>,
+    pub notimplexctp: Option<Trc<TypeObject<'a>>>,
+    pub permissionexctp: Option<Trc<TypeObject<'a>>>,
+    pub timeoutexctp: Option<Trc<TypeObject<'a>>>,
+    pub memoryexctp: Option<Trc<TypeObject<'a>>>,
+    pub moduletp: Option<Trc<TypeObject<'a>>>,
 
     pub n_types: u32,
 }
@@ -69,8 +194,43 @@ pub struct VM<'a> {
     pub types: Trc<Types<'a>>,
     pub interpreters: Vec<Trc<Interpreter<'a>>>,
     pub namespaces: Trc<Namespaces<'a>>,
-    info: FileInfo<'a>,
+    pub builtins: hashbrown::HashMap<String, Object<'a>>,
+    pub eq_in_progress: hashbrown::HashSet<(usize, usize)>, //Pairs of containers being compared, to stop cycles
+    pub repr_in_progress: IdSet, //Containers being rendered, to stop cycles
+    pub contexts: Vec<Object<'a>>, //Context managers of the active with statements
+    pub info: FileInfo<'a>,      //The file that is running, for errors raised outside of its code
+    pub args: Vec<String>,       //Command line arguments after the script name
+    pub alloc_stats: Option<hashbrown::HashMap<u32, (String, usize)>>, //Objects allocated per type id, with --stats
+    pub int_cache_stats: Option<(usize, usize)>, //Ints that were found in the cache and that were allocated, with --stats
+    pub deterministic: bool, //Fixed iteration order and no adaptive optimizations, with --deterministic
+    pub instruction_counts: Option<hashbrown::HashMap<&'static str, usize>>, //Instructions executed per opcode, with --count-instructions
+    pub restricted: bool, //Running code that eval was asked to restrict, which may not call IO builtins
+    pub is_main: bool,    //Running the file that merlin was started with, rather than embedded code
+    pub diagnostics: Diagnostics, //Where errors are written
+    pub catch_errors: bool, //Unwind with Terminated instead of exiting when the program raises
+    pub raised: Option<String>, //The repr of the exception that unwound with Terminated
+    pub step_limit: Option<usize>, //The most instructions that a timed run may execute, with --step-limit
+    pub trace: Option<trace::Trace>, //The instructions to print as they run, with --trace
+    steps: usize,                  //Instructions executed by the current timed run
+    pub log_level: LogLevel,       //The minimum level of the messages that the log module prints
+    pub int_overflow: IntOverflow, //What int arithmetic does when a result does not fit, with --int-overflow
+    pub repr_limit: Option<usize>, //The most items that the repr of a list or dict shows, with --repr-limit
+    pub interned: hashbrown::HashMap<String, Object<'a>>, //Strings of attribute names, shared by all bodies
+    pub globals: Namespace<'a>, //The namespace of the last code executed with one, for call_function
+    sources: Vec<(Rc<[u8]>, Rc<FileInfo<'a>>)>, //Code compiled by eval, which its bytecode borrows, see VM::own_source
+    frame_pool: FramePool<'a>,
+    pub method_cache: Vec<Option<(Trc<TypeObject<'a>>, Object<'a>, Object<'a>)>>, //Type, name and function of recently found methods, see RawObject::generic_getattr
+    pub shapes: Vec<Shape>, //Layouts of instance attributes, see objects::shape
+    pub class_shapes: hashbrown::HashMap<u32, usize>, //The empty shape of each class, by type id
+    pub drop_classes: hashbrown::HashSet<u32>, //The classes whose instances call their drop method when they are freed
+    pub dropped: IdSet, //Instances that drop was called with, which do not call it again
     pub cache: SingletonCache<'a>,
+    #[cfg(feature = "debugger")]
+    pub debug_input: debugger::Input, //Where the debugger that breakpoint() opens reads its commands
+    #[cfg(feature = "jit")]
+    pub jit: jit::Jit, //Native code of hot functions, see interpreter::jit
+    #[cfg(feature = "pool")]
+    _pool: crate::pool::Scope, //Pools the small allocations of this thread while the VM is alive
 }
 
 impl<'a> Eq for VM<'a> {}
@@ -82,6 +242,9 @@ impl<'a> PartialEq for VM<'a> {
 }
 
 #[derive(Clone)]
+//The panic payload of a program that stopped with an error while VM::catch_errors is set
+pub struct Terminated;
+
 pub struct Interpreter<'a> {
     frames: Vec<Frame<'a>>,
     namespaces: Trc<Namespaces<'a>>,
@@ -91,28 +254,43 @@ pub struct Interpreter<'a> {
 #[derive(Clone, PartialEq, Eq)]
 struct Frame<'a> {
     registers: Vec<Object<'a>>,
+    enclosing: Vec<Scope<'a>>,
+    bytecode: *const Bytecode<'a>, //The running body, which outlives the frame
+    #[cfg(feature = "debugger")]
+    call: usize, //The instruction of the last call made by the frame, where breakpoint() pauses
 }
 
 macro_rules! pop_frame {
     ($interp:expr) => {{
-        (*$interp.namespaces).variables.pop();
-        $interp.frames.pop();
+        let scope = unwrap_fast!((*$interp.namespaces).variables.pop());
+        let frame = unwrap_fast!($interp.frames.pop());
+        //Deterministic runs allocate each frame, so that a call does not depend on the calls before it
+        if !$interp.vm.deterministic {
+            $interp
+                .vm
+                .clone()
+                .frame_pool
+                .recycle(frame.registers, scope);
+        }
     }};
 }
 
 macro_rules! add_frame {
-    ($interp:expr, $n_registers:expr, $n_vars:expr) => {{
-        let mut variables = Vec::new();
-        for _ in 0..$n_vars {
-            variables.push(None);
-        }
+    ($interp:expr, $n_registers:expr, $n_vars:expr, $enclosing:expr) => {{
+        let mut vm = $interp.vm.clone();
+        let variables = vm.frame_pool.take_scope($n_vars);
         (*$interp.namespaces).variables.push(variables);
 
-        let mut registers = Vec::new();
-        for _ in 0..$n_registers {
-            registers.push(none_from!($interp.vm.clone()));
-        }
-        $interp.frames.push(Frame { registers })
+        let registers = vm
+            .frame_pool
+            .take_registers($n_registers, none_from!($interp.vm.clone()));
+        $interp.frames.push(Frame {
+            registers,
+            enclosing: $enclosing,
+            bytecode: std::ptr::null(),
+            #[cfg(feature = "debugger")]
+            call: 0,
+        })
     }};
 }
 
@@ -124,9 +302,12 @@ macro_rules! unwrap_fast {
 }
 
 impl<'a> VM<'a> {
-    pub fn new(info: FileInfo<'a>) -> VM<'a> {
+    pub fn new(info: FileInfo<'a>, args: Vec<String>) -> VM<'a> {
+        //Before anything is allocated, so that the objects of the VM come from the pool
+        #[cfg(feature = "pool")]
+        let pool = crate::pool::Scope::new();
         let singleton = SingletonCache {
-            int_cache: intobject::init_cache(),
+            int_cache: Vec::new(),
             bool_cache: (None, None),
             none_singleton: None,
             _marker: PhantomData,
@@ -136,6 +317,8 @@ impl<'a> VM<'a> {
                 typetp: None,
                 objecttp: None,
                 inttp: None,
+                bigtp: None,
+                floattp: None,
                 booltp: None,
                 codetp: None,
                 dicttp: None,
@@ -154,6 +337,17 @@ impl<'a> VM<'a> {
                 classtp: None,
                 attrexctp: None,
                 methodtp: None,
+                builtintp: None,
+                supertp: None,
+                unhashableexctp: None,
+                indexexctp: None,
+                argumentexctp: None,
+                ioexctp: None,
+                notimplexctp: None,
+                permissionexctp: None,
+                timeoutexctp: None,
+                memoryexctp: None,
+                moduletp: None,
                 n_types: 0,
             }),
             interpreters: Vec::new(),
@@ -161,20 +355,100 @@ impl<'a> VM<'a> {
                 variables: Vec::new(),
                 _marker: PhantomData,
             }),
+            builtins: hashbrown::HashMap::new(),
+            eq_in_progress: hashbrown::HashSet::new(),
+            repr_in_progress: IdSet::new(),
+            contexts: Vec::new(),
             info,
+            args,
+            alloc_stats: None,
+            int_cache_stats: None,
+            deterministic: false,
+            instruction_counts: None,
+            restricted: false,
+            is_main: false,
+            diagnostics: Diagnostics::default(),
+            catch_errors: false,
+            raised: None,
+            step_limit: None,
+            trace: None,
+            steps: 0,
+            log_level: LogLevel::Info,
+            int_overflow: IntOverflow::Raise,
+            repr_limit: Some(REPR_LIMIT),
+            interned: hashbrown::HashMap::new(),
+            globals: Namespace::new(),
+            sources: Vec::new(),
+            frame_pool: FramePool::default(),
+            method_cache: vec![None; METHOD_CACHE_SIZE],
+            shapes: Vec::new(),
+            class_shapes: hashbrown::HashMap::new(),
+            drop_classes: hashbrown::HashSet::new(),
+            dropped: IdSet::new(),
             cache: singleton,
+            #[cfg(feature = "debugger")]
+            debug_input: debugger::Input::default(),
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::default(),
+            #[cfg(feature = "pool")]
+            _pool: pool,
+        }
+    }
+
+    //The string of an attribute name, created once per VM
+    pub fn intern(mut this: Trc<Self>, name: &str) -> Object<'a> {
+        if let Some(object) = this.interned.get(name) {
+            return object.clone();
+        }
+        let object = stringobject::string_from(this.clone(), name.to_string());
+        this.interned.insert(name.to_string(), object.clone());
+        object
+    }
+
+    //Keep source code that is compiled at runtime for as long as the VM. Its bytecode, and the
+    //objects that the bytecode makes, hold the VM, so they never outlive the file.
+    pub fn own_source(mut this: Trc<Self>, source: &str, name: &str) -> &'a FileInfo<'a> {
+        let data: Rc<[u8]> = source.as_bytes().into();
+        let info = Rc::new(FileInfo {
+            data: unsafe { &*Rc::as_ptr(&data) },
+            name: name.to_string(),
+        });
+        let res = unsafe { &*Rc::as_ptr(&info) };
+        this.sources.push((data, info));
+        res
+    }
+
+    //Clear what the last program left behind, even one that stopped early, so that the VM can run
+    //another program from a clean state. The types, builtins and caches are kept.
+    pub fn reset(mut this: Trc<Self>) {
+        this.interpreters.clear();
+        this.namespaces.variables.clear();
+        this.contexts.clear();
+        this.eq_in_progress.clear();
+        this.repr_in_progress.clear();
+        this.globals = Namespace::new();
+        this.restricted = false;
+        this.catch_errors = false;
+        this.steps = 0;
+        if let Some(stats) = this.alloc_stats.as_mut() {
+            stats.clear();
         }
+        if let Some(counts) = this.instruction_counts.as_mut() {
+            counts.clear();
+        }
+        if this.int_cache_stats.is_some() {
+            this.int_cache_stats = Some((0, 0));
+        }
+    }
+
+    //The register and variable vectors of finished frames that the next calls will reuse
+    #[allow(dead_code)]
+    pub fn pooled_frames(&self) -> (usize, usize) {
+        self.frame_pool.sizes()
     }
 
     pub fn init_cache(this: Trc<Self>) {
-        let int_cache_arr_ref = &this.cache.int_cache;
-        let ptr = int_cache_arr_ref as *const [Option<Object>; INT_CACHE_SIZE as usize]
-            as *mut [Option<Object>; INT_CACHE_SIZE as usize];
-        intobject::generate_cache(
-            this.clone(),
-            this.types.inttp.as_ref().unwrap().clone(),
-            ptr,
-        );
+        Self::resize_int_cache(this.clone(), MAX_INT_CACHE);
 
         let bool_cache_tup_ref = &this.cache.bool_cache;
         let ptr = bool_cache_tup_ref as *const (Option<Object>, Option<Object>)
@@ -194,6 +468,15 @@ impl<'a> VM<'a> {
         );
     }
 
+    //Cache the ints from MIN_INT_CACHE to max. The ints that were made before keep their objects.
+    pub fn resize_int_cache(mut this: Trc<Self>, max: isize) {
+        this.cache.int_cache = intobject::generate_cache(
+            this.clone(),
+            this.types.inttp.as_ref().unwrap().clone(),
+            max,
+        );
+    }
+
     pub fn execute(mut this: Trc<Self>, bytecode: &Bytecode<'a>) -> Object<'a> {
         let interpreter = Interpreter::new(this.namespaces.clone(), this.clone());
 
@@ -202,27 +485,29 @@ impl<'a> VM<'a> {
         return last.run_interpreter(bytecode);
     }
 
+    //Time the bytecode, and store the mean time in timeit. If the program raises, the error is
+    //reported and None is returned, and timeit holds how many runs completed.
     pub fn execute_timeit(
-        mut this: Trc<Self>,
+        this: Trc<Self>,
         bytecode: &Bytecode<'a>,
         timeit: &mut TimeitHolder,
-    ) -> Object<'a> {
+    ) -> Option<Object<'a>> {
         //See bench.rs, this is a very similar implementation (pub fn iter<T, F>(inner: &mut F) -> stats::Summary)
 
-        let samples = &mut [0f64; 50];
+        let samples = &mut vec![0f64; timeit.groups];
 
         //Get initial result
-        let mut res =
-            (unwrap_fast!(this.deref_mut().interpreters.last_mut())).run_interpreter(bytecode);
+        let mut res = Self::run_timed(this.clone(), bytecode)?;
+        timeit.iterations += 1;
 
-        for p in &mut *samples {
+        for p in samples.iter_mut() {
             let mut time = 0;
             let mut i = 0;
             while time == 0 && i < 10 {
-                let last = unwrap_fast!(this.deref_mut().interpreters.last_mut());
                 let start = Instant::now();
                 for _ in 0..5 {
-                    res = last.run_interpreter(bytecode);
+                    res = Self::run_timed(this.clone(), bytecode)?;
+                    timeit.iterations += 1;
                 }
                 let delta = start.elapsed().as_nanos();
                 time = if (delta as i128 / 5_i128) - (timeit.baseline as i128) < 0 {
@@ -245,39 +530,281 @@ impl<'a> VM<'a> {
 
         timeit.time = sum / samples.len() as f64;
 
-        res
+        Some(res)
+    }
+
+    //Run the bytecode once with the last interpreter. If the program raises, the interpreter is
+    //removed along with the interpreters and scopes that the program left behind, and None is
+    //returned.
+    fn run_timed(mut this: Trc<Self>, bytecode: &Bytecode<'a>) -> Option<Object<'a>> {
+        let n_interpreters = this.interpreters.len();
+        let depth = this.namespaces.variables.len();
+        let catch_errors = this.catch_errors;
+        this.catch_errors = true;
+        this.steps = 0;
+        let mut vm = this.clone();
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            unwrap_fast!(vm.interpreters.last_mut()).run_interpreter(bytecode)
+        }));
+        this.catch_errors = catch_errors;
+        match res {
+            Ok(res) => Some(res),
+            Err(payload) if payload.is::<Terminated>() => {
+                this.interpreters.truncate(n_interpreters - 1);
+                this.namespaces.variables.truncate(depth);
+                None
+            }
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
     }
 
-    pub fn execute_vars(
+    //Execute top-level code with the variables of a namespace, and return the result along with
+    //the namespace, which now also holds the variables that the code defined
+    pub fn execute_with_namespace(
         mut this: Trc<Self>,
         bytecode: &Bytecode<'a>,
-        vars: hashbrown::HashMap<isize, Object<'a>>,
-    ) -> Object<'a> {
+        namespace: Namespace<'a>,
+    ) -> (Object<'a>, Namespace<'a>) {
+        //The namespace shares its variables, so the code that runs sees its own globals
+        let mut globals = namespace.clone();
+        for (name, idx) in bytecode.scope_names.iter() {
+            globals.names.insert(name.clone(), *idx);
+        }
+        this.globals = globals;
+        let (res, namespace) =
+            Self::execute_in_namespace(this.clone(), bytecode, namespace, Vec::new());
+        this.globals = namespace.clone();
+        (res, namespace)
+    }
+
+    //Call a function that the last code executed with a namespace defined, for programs that
+    //embed merlin. An exception that the function does not catch is reported, and returned as an
+    //error instead of stopping the program, and a program that stops with env.exit or an error
+    //of eval returns its exit code.
+    #[allow(dead_code)]
+    pub fn call_function(
+        mut this: Trc<Self>,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, MerlinError> {
+        let function = this
+            .globals
+            .get(name)
+            .ok_or_else(|| MerlinError::NotDefined(name.to_string()))?;
+        if !is_type_exact!(&function, unwrap_fast!(this.types.fntp.as_ref()).clone()) {
+            return Err(MerlinError::NotAFunction(
+                name.to_string(),
+                function.tp.typename.clone(),
+            ));
+        }
+        let args = args
+            .iter()
+            .map(|arg| arg.to_object(this.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let n_interpreters = this.interpreters.len();
+        let depth = this.namespaces.variables.len();
+        let catch_errors = this.catch_errors;
+        this.catch_errors = true;
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            unwrap_fast!(function.tp.call)(function.clone(), &args)
+        }));
+        this.catch_errors = catch_errors;
+        let res = match res {
+            Ok(res) => res,
+            Err(payload) => {
+                //Remove what the function left behind, like run_timed
+                this.interpreters.truncate(n_interpreters);
+                this.namespaces.variables.truncate(depth);
+                if payload.is::<Terminated>() {
+                    let raised = this.raised.take();
+                    return Err(MerlinError::Exception(raised.unwrap_or_default()));
+                }
+                return match payload.downcast::<Exit>() {
+                    Ok(exit) => Err(MerlinError::Exited(exit.0)),
+                    Err(payload) => std::panic::resume_unwind(payload),
+                };
+            }
+        };
+        match res {
+            MethodValue::Some(res) => Ok(Value::try_from(res)?),
+            MethodValue::Error(exc) => Err(MerlinError::Exception(
+                match RawObject::object_repr_safe(exc.clone()) {
+                    MethodValue::Some(repr) => repr,
+                    MethodValue::Error(_) => exc.tp.typename.clone(),
+                },
+            )),
+        }
+    }
+
+    //Call the main function of the executed program, if it defines one. main takes no parameters,
+    //or one, which is passed the command line arguments.
+    pub fn run_main(this: Trc<Self>) {
+        let main = match this.globals.get("main") {
+            Some(main) if is_type_exact!(&main, unwrap_fast!(this.types.fntp.as_ref())) => main,
+            _ => return,
+        };
+        let args = match fnobject::n_args(&main) {
+            0 => Vec::new(),
+            1 => vec![listobject::list_from(
+                this.clone(),
+                this.args
+                    .iter()
+                    .map(|arg| stringobject::string_from(this.clone(), arg.clone()))
+                    .collect(),
+            )],
+            n => {
+                let exc = exceptionobject::argumentexc_from_str(
+                    this.clone(),
+                    &format!("Function 'main' takes 0 or 1 argument(s), not {}", n),
+                    Position::default(),
+                    Position::default(),
+                );
+                Interpreter::new(this.namespaces.clone(), this.clone()).raise_exc(exc);
+            }
+        };
+        let res = unwrap_fast!(main.tp.call)(main.clone(), &args);
+        if res.is_error() {
+            Interpreter::new(this.namespaces.clone(), this.clone()).raise_exc(res.unwrap_err());
+        }
+    }
+
+    //Execute a body, like a class body, which may load the names of the enclosing scopes
+    pub fn execute_in_namespace(
+        mut this: Trc<Self>,
+        bytecode: &Bytecode<'a>,
+        mut namespace: Namespace<'a>,
+        enclosing: Vec<Scope<'a>>,
+    ) -> (Object<'a>, Namespace<'a>) {
         let interpreter = Interpreter::new(this.namespaces.clone(), this.clone());
         this.interpreters.push(Trc::new(interpreter));
 
-        let res = (unwrap_fast!(this.deref_mut().interpreters.last_mut()))
-            .run_interpreter_vars(bytecode, vars);
+        let (res, variables) = (unwrap_fast!(this.deref_mut().interpreters.last_mut()))
+            .run_interpreter_namespace(bytecode, namespace.variables, enclosing);
         this.interpreters.pop();
-        res
+
+        namespace.variables = variables;
+        for (name, idx) in bytecode.scope_names.iter() {
+            namespace.names.insert(name.clone(), *idx);
+        }
+        (res, namespace)
     }
 
-    pub fn execute_extract_namespace(
+    //Execute the body of a function with its arguments as the first variables
+    pub fn execute_call(
         mut this: Trc<Self>,
         bytecode: &Bytecode<'a>,
-    ) -> Vec<Option<Trc<RawObject<'a>>>> {
+        args: &[Object<'a>],
+        enclosing: Vec<Scope<'a>>,
+    ) -> Object<'a> {
+        #[cfg(feature = "jit")]
+        if let Some(res) = jit::try_call(&this, bytecode, args) {
+            return res;
+        }
         let interpreter = Interpreter::new(this.namespaces.clone(), this.clone());
         this.interpreters.push(Trc::new(interpreter));
 
         let res = (unwrap_fast!(this.deref_mut().interpreters.last_mut()))
-            .run_interpreter_extract_namespace(bytecode);
+            .run_interpreter_call(bytecode, args, enclosing);
         this.interpreters.pop();
         res
     }
 
-    pub fn terminate(_: Trc<Self>) -> ! {
-        //Clean up child threads here
-        std::process::exit(1);
+    //Evaluate an expression in the scope of the running body: it may load the variables of that
+    //body and of the scopes that enclose it. Restricted code, and the code that it calls, may not
+    //call the builtins that do IO or control the process.
+    pub fn eval(mut this: Trc<Self>, source: &str, restricted: bool) -> Object<'a> {
+        let interpreter = unwrap_fast!(this.interpreters.last()).clone();
+        let frame = unwrap_fast!(interpreter.frames.last());
+        let variables = unwrap_fast!(this.namespaces.variables.last()).clone();
+
+        //The frame is running, so its body is alive
+        let scope = unsafe { &*frame.bytecode };
+        let bytecode = Compiler::new_in_scope(this.clone(), scope).compile_expr(source);
+        let namespace = Namespace {
+            names: indexmap::IndexMap::new(),
+            variables,
+        };
+
+        //Code that is already restricted stays restricted
+        let was_restricted = this.restricted;
+        this.restricted |= restricted;
+        let res =
+            Self::execute_in_namespace(this.clone(), &bytecode, namespace, frame.enclosing.clone())
+                .0;
+        this.restricted = was_restricted;
+        res
+    }
+
+    //The variables of the running body that are assigned, with their names
+    pub fn locals(this: &Trc<Self>) -> Vec<(String, Object<'a>)> {
+        let interpreter = unwrap_fast!(this.interpreters.last());
+        let frame = unwrap_fast!(interpreter.frames.last());
+        let variables = unwrap_fast!(this.namespaces.variables.last());
+        //The frame is running, so its body is alive
+        let bytecode = unsafe { &*frame.bytecode };
+        bytecode
+            .names
+            .iter()
+            .filter_map(|(idx, name)| Some((name.clone(), variables.get(*idx as usize)?.clone()?)))
+            .collect()
+    }
+
+    //The global variables of the running file, or of the last one that ran, that are assigned
+    pub fn globals(this: &Trc<Self>) -> Vec<(String, Object<'a>)> {
+        this.globals
+            .names
+            .keys()
+            .filter_map(|name| Some((name.clone(), this.globals.get(name)?)))
+            .collect()
+    }
+
+    pub fn terminate(this: Trc<Self>) -> ! {
+        if this.catch_errors {
+            std::panic::resume_unwind(Box::new(Terminated));
+        }
+        this.print_stats();
+        Exit::raise(ExitCode::Runtime);
+    }
+
+    //Print the allocation statistics and instruction counts that are enabled, most frequent first
+    pub fn print_stats(&self) {
+        if let Some(counts) = &self.instruction_counts {
+            let mut counts: Vec<(&&str, &usize)> = counts.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+            println!("Instructions executed per opcode:");
+            for (name, count) in &counts {
+                println!("    {}: {}", name, count);
+            }
+            println!(
+                "Total instructions executed: {}",
+                counts.iter().map(|(_, count)| *count).sum::<usize>()
+            );
+        }
+
+        if let Some(stats) = &self.alloc_stats {
+            let mut stats: Vec<&(String, usize)> = stats.values().collect();
+            stats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+            println!("Objects allocated per type:");
+            for (typename, count) in &stats {
+                println!("    {}: {}", typename, count);
+            }
+            println!(
+                "Total objects allocated: {}",
+                stats.iter().map(|(_, count)| count).sum::<usize>()
+            );
+        }
+
+        if let Some((hits, misses)) = self.int_cache_stats {
+            println!(
+                "Ints from the cache: {}, allocated: {} (the cache holds {} to {})",
+                hits,
+                misses,
+                MIN_INT_CACHE,
+                MIN_INT_CACHE + self.cache.int_cache.len() as isize - 1
+            );
+        }
     }
 }
 
@@ -286,7 +813,7 @@ macro_rules! load_register {
         match $register {
             CompilerRegister::R(v) => $last.registers[v].clone(),
             CompilerRegister::V(v) => match &$last_vars[v] {
-                Some(v) => v.clone(),
+                Some(var) => var.clone(),
                 None => {
                     let pos = $bytecode
                         .positions
@@ -296,7 +823,7 @@ macro_rules! load_register {
                         $this.vm.clone(),
                         &format!(
                             "Name '{}' not defined",
-                            $bytecode.names.get(&($i as i32)).unwrap()
+                            $bytecode.names.get(&(v as i32)).unwrap()
                         ),
                         pos.0,
                         pos.1,
@@ -305,6 +832,25 @@ macro_rules! load_register {
                 }
             },
             CompilerRegister::C(v) => unwrap_fast!($bytecode.consts.get(v)).clone(),
+            CompilerRegister::E(level, v) => match &$last.enclosing[level][v] {
+                Some(var) => var.clone(),
+                None => {
+                    let pos = $bytecode
+                        .positions
+                        .get($i)
+                        .expect("Instruction out of range");
+                    let exc = exceptionobject::nameexc_from_str(
+                        $this.vm.clone(),
+                        &format!(
+                            "Name '{}' not defined",
+                            $bytecode.outer_names.get(&(level, v)).unwrap()
+                        ),
+                        pos.0,
+                        pos.1,
+                    );
+                    $this.raise_exc_pos(exc, pos.0, pos.1);
+                }
+            },
         }
     };
 }
@@ -314,11 +860,292 @@ macro_rules! store_register {
         match $register {
             CompilerRegister::R(v) => $last.registers[v] = $value,
             CompilerRegister::V(v) => $last_vars[v] = Some($value),
-            CompilerRegister::C(_) => unreachable!("Impossible."),
+            CompilerRegister::C(_) | CompilerRegister::E(_, _) => unreachable!("Impossible."),
         }
     };
 }
 
+//Call a callable with the values of the argument registers, which are loaded into an array on the
+//stack unless there are many of them. A first argument, like the instance of a method, is passed
+//before them.
+macro_rules! call_registers {
+    ($this:ident, $last:expr, $last_vars:expr, $bytecode:expr, $i:expr, $callable:expr, $arg_registers:expr $(, $first:expr)?) => {{
+        let callable = $callable;
+        #[cfg(feature = "debugger")]
+        {
+            $last.call = $i;
+        }
+        match $arg_registers.as_slice() {
+            [] => call_args!($this, $bytecode, $i, callable, [] $(, $first)?),
+            [a] => call_args!(
+                $this,
+                $bytecode,
+                $i,
+                callable,
+                [load_register!(
+                    $this, $last, $last_vars, $bytecode, $i, a.value
+                )]
+                $(, $first)?
+            ),
+            [a, b] => call_args!(
+                $this,
+                $bytecode,
+                $i,
+                callable,
+                [
+                    load_register!($this, $last, $last_vars, $bytecode, $i, a.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, b.value)
+                ]
+                $(, $first)?
+            ),
+            [a, b, c] => call_args!(
+                $this,
+                $bytecode,
+                $i,
+                callable,
+                [
+                    load_register!($this, $last, $last_vars, $bytecode, $i, a.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, b.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, c.value)
+                ]
+                $(, $first)?
+            ),
+            [a, b, c, d] => call_args!(
+                $this,
+                $bytecode,
+                $i,
+                callable,
+                [
+                    load_register!($this, $last, $last_vars, $bytecode, $i, a.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, b.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, c.value),
+                    load_register!($this, $last, $last_vars, $bytecode, $i, d.value)
+                ]
+                $(, $first)?
+            ),
+            registers => {
+                let mut args = Vec::with_capacity(registers.len());
+                for register in registers {
+                    args.push(load_register!(
+                        $this,
+                        $last,
+                        $last_vars,
+                        $bytecode,
+                        $i,
+                        register.value
+                    ));
+                }
+                call_args!($this, $bytecode, $i, callable, args $(, $first)?)
+            }
+        }
+    }};
+}
+
+macro_rules! call_args {
+    (@slot $call:expr, $callable:expr, $args:expr) => {
+        ($call)($callable, &$args)
+    };
+    (@slot $call:expr, $callable:expr, $args:expr, $first:expr) => {
+        call_with_first($call, $callable, $first, &$args)
+    };
+    ($this:ident, $bytecode:expr, $i:expr, $callable:expr, $args:expr $(, $first:expr)?) => {{
+        let args = $args;
+        if $callable.tp.call.is_none() {
+            let pos = $bytecode
+                .positions
+                .get($i)
+                .expect("Instruction out of range");
+            let exc = methodnotdefinedexc_from_str(
+                $this.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    $callable.tp.typename
+                ),
+                pos.0,
+                pos.1,
+            );
+            $this.raise_exc(exc);
+        }
+        call_args!(@slot unwrap_fast!($callable.tp.call), $callable, args $(, $first)?)
+    }};
+}
+
+//Int arithmetic without the slot call when both operands are ints and the result fits, otherwise the
+//slot is called like in the generic instruction, which handles overflow by VM::int_overflow. A
+//quickened instruction that meets other operands runs as the generic one again.
+macro_rules! int_binary_op {
+    ($this:ident, $last:expr, $last_vars:expr, $bytecode:expr, $i:expr, $a:expr, $b:expr, $result:expr, $checked:ident, $slot:ident) => {{
+        let selfv = load_register!($this, $last, $last_vars, $bytecode, $i, $a);
+        let other = load_register!($this, $last, $last_vars, $bytecode, $i, $b);
+        let (x, y) = (selfv.as_int(), other.as_int());
+        let ints = x.is_some() && y.is_some();
+        let fast = x.zip(y).and_then(|(x, y)| x.$checked(y));
+        if let Some(res) = fast {
+            store_register!(
+                $last,
+                $last_vars,
+                $result,
+                intobject::int_from($this.vm.clone(), res)
+            );
+        } else {
+            if selfv.tp.$slot.is_none() {
+                let pos = $bytecode
+                    .positions
+                    .get($i)
+                    .expect("Instruction out of range");
+                let exc = methodnotdefinedexc_from_str(
+                    $this.vm.clone(),
+                    &format!(
+                        "Method '{}' is not defined for '{}' type",
+                        stringify!($slot),
+                        selfv.tp.typename
+                    ),
+                    pos.0,
+                    pos.1,
+                );
+                $this.raise_exc(exc);
+            }
+            let res = unwrap_fast!(selfv.tp.$slot)(selfv, other);
+            maybe_handle_exception!($this, res, $bytecode, $i);
+            store_register!($last, $last_vars, $result, unwrap_fast!(res));
+            if !ints {
+                quicken::deoptimize($bytecode, $i);
+            }
+        }
+    }};
+}
+
+//Float arithmetic for instructions that quickening specialized, which fall back to the slot and run
+//as the generic instruction again when an operand is not a float
+macro_rules! float_binary_op {
+    ($this:ident, $last:expr, $last_vars:expr, $bytecode:expr, $i:expr, $a:expr, $b:expr, $result:expr, $slot:ident, $op:tt) => {{
+        let selfv = load_register!($this, $last, $last_vars, $bytecode, $i, $a);
+        let other = load_register!($this, $last, $last_vars, $bytecode, $i, $b);
+        if let (Some(x), Some(y)) = (selfv.as_float(), other.as_float()) {
+            let res = x $op y;
+            store_register!(
+                $last,
+                $last_vars,
+                $result,
+                floatobject::float_from($this.vm.clone(), res)
+            );
+        } else {
+            if selfv.tp.$slot.is_none() {
+                let pos = $bytecode
+                    .positions
+                    .get($i)
+                    .expect("Instruction out of range");
+                let exc = methodnotdefinedexc_from_str(
+                    $this.vm.clone(),
+                    &format!(
+                        "Method '{}' is not defined for '{}' type",
+                        stringify!($slot),
+                        selfv.tp.typename
+                    ),
+                    pos.0,
+                    pos.1,
+                );
+                $this.raise_exc(exc);
+            }
+            let res = unwrap_fast!(selfv.tp.$slot)(selfv, other);
+            maybe_handle_exception!($this, res, $bytecode, $i);
+            store_register!($last, $last_vars, $result, unwrap_fast!(res));
+            quicken::deoptimize($bytecode, $i);
+        }
+    }};
+}
+
+//With the checked feature, every instruction is checked before it runs: the registers, variables,
+//constants and names that it uses must exist, and the constants must have the types that it reads.
+//Malformed bytecode, as from a corrupted cache, is then reported as an internal error at the
+//instruction instead of panicking or reading out of bounds.
+#[cfg(feature = "checked")]
+fn check_instruction<'a>(
+    vm: &VM<'a>,
+    bytecode: &Bytecode<'a>,
+    idx: usize,
+    instruction: &CompilerInstruction<'a>,
+    frame: &Frame<'a>,
+    variables: &[Option<Object<'a>>],
+) -> Result<(), String> {
+    let mut copy = instruction.clone();
+    if copy.index_mut().is_some_and(|i| *i != idx) {
+        return Err(String::from("its index is wrong"));
+    }
+    for register in copy.registers_mut() {
+        let exists = match *register {
+            CompilerRegister::R(v) => v < frame.registers.len(),
+            CompilerRegister::V(v) => v < variables.len(),
+            CompilerRegister::C(v) => v < bytecode.consts.len(),
+            CompilerRegister::E(level, v) => frame
+                .enclosing
+                .get(level)
+                .is_some_and(|scope| v < scope.len()),
+        };
+        if !exists {
+            return Err(format!("register {:?} does not exist", register));
+        }
+    }
+
+    let constant =
+        |idx: usize, tp: &Option<Trc<TypeObject<'a>>>, what: &str| match bytecode.consts.get(idx) {
+            Some(object) if is_type_exact!(object, unwrap_fast!(tp.as_ref())) => Ok(()),
+            _ => Err(format!("constant {} is not a {}", idx, what)),
+        };
+    let attr_name = |idx: usize| {
+        if idx < bytecode.attr_names.len() {
+            Ok(())
+        } else {
+            Err(format!("attribute name {} does not exist", idx))
+        }
+    };
+    let body = |idx: usize| {
+        if idx < bytecode.bodies.len() {
+            Ok(())
+        } else {
+            Err(format!("body {} does not exist", idx))
+        }
+    };
+    match instruction {
+        CompilerInstruction::MakeFunction {
+            nameidx,
+            argsidx,
+            codeidx,
+            bodyidx,
+            ..
+        } => {
+            constant(*nameidx, &vm.types.strtp, "string")?;
+            constant(*argsidx, &vm.types.listtp, "list")?;
+            constant(*codeidx, &vm.types.codetp, "code object")?;
+            body(*bodyidx)?;
+        }
+        CompilerInstruction::Comprehension {
+            codeidx, bodyidx, ..
+        } => {
+            constant(*codeidx, &vm.types.codetp, "code object")?;
+            body(*bodyidx)?;
+        }
+        CompilerInstruction::MakeClass { bodyidx, .. } => body(*bodyidx)?,
+        CompilerInstruction::AttrLoad { nameidx, .. }
+        | CompilerInstruction::AttrStore { nameidx, .. }
+        | CompilerInstruction::CallAttr { nameidx, .. } => attr_name(*nameidx)?,
+        CompilerInstruction::BuildClassDict {
+            names,
+            variables: vars,
+            ..
+        } => {
+            for name in names {
+                attr_name(*name)?;
+            }
+            if let Some(var) = vars.iter().find(|var| **var >= variables.len()) {
+                return Err(format!("variable {} does not exist", var));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 impl<'a> Interpreter<'a> {
     pub fn new(namespaces: Trc<Namespaces<'a>>, vm: Trc<VM<'a>>) -> Interpreter<'a> {
         Interpreter {
@@ -330,10 +1157,28 @@ impl<'a> Interpreter<'a> {
 
     #[allow(dead_code)]
     pub fn raise_exc(&self, exc_obj: Object<'a>) -> ! {
-        let exc = unsafe { &exc_obj.internals.exc }.clone();
+        let exc = unsafe { exc_obj.internals.exc() }.clone();
         self.raise_exc_pos(exc_obj, exc.start, exc.end);
     }
 
+    //Report bytecode that the VM can not run, and stop like for an exception
+    fn raise_internal_error(&self, bytecode: &Bytecode<'a>, idx: usize, message: &str) -> ! {
+        let pos = bytecode.positions.get(idx).unwrap_or_default();
+        crate::errors::print_error(
+            &format!(
+                "Malformed bytecode: instruction {} ({}): {}",
+                idx,
+                bytecode.instructions[idx].name(),
+                message
+            ),
+            crate::errors::ErrorType::InternalError,
+            &pos.0,
+            bytecode.info,
+            &self.vm.diagnostics,
+        );
+        VM::terminate(self.vm.clone());
+    }
+
     fn raise_exc_pos(&self, exc_obj: Object<'a>, start: Position, end: Position) -> ! {
         let header: String = match RawObject::object_repr_safe(exc_obj) {
             crate::objects::MethodValue::Some(v) => v,
@@ -341,61 +1186,47 @@ impl<'a> Interpreter<'a> {
                 unimplemented!()
             }
         };
-        let location: String = format!(
-            "{}:{}:{}",
-            self.vm.info.name,
-            start.line + 1,
-            start.startcol + 1
-        );
-        println!("{}", header.red().bold());
-        println!("{}", location.red());
-        let lines = Vec::from_iter(self.vm.info.data.split(|num| *num as char == '\n'));
-
-        let snippet: String = format!(
-            "{}",
-            String::from_utf8(
-                lines
-                    .get(start.line)
-                    .expect("Line index out of range")
-                    .to_vec()
-            )
-            .expect("utf8 conversion failed")
-            .blue()
-        );
-        let mut arrows: String = String::new();
-        for idx in 0..snippet.len() {
-            if idx >= start.startcol && idx < end.endcol {
-                arrows += "^";
-            } else {
-                arrows += " ";
+        //The file of the running body, which is not the file of the program for code run by eval
+        let info = match self.frames.last() {
+            Some(frame) if !frame.bytecode.is_null() => unsafe { (*frame.bytecode).info },
+            _ => &self.vm.info,
+        };
+        self.vm.diagnostics.report(&header, info, &start, &end);
+        if self.vm.catch_errors {
+            self.vm.clone().raised = Some(header);
+        }
+
+        //Exit the active with statements, innermost first, after the exception is reported
+        let mut vm = self.vm.clone();
+        while let Some(manager) = vm.contexts.pop() {
+            let res = classtype::call_method(manager, "exit", Vec::new());
+            if res.is_error() {
+                self.raise_exc(res.unwrap_err());
             }
         }
-        let linestr = (start.line + 1).to_string().blue().bold();
-        println!("{} | {}", linestr, snippet);
-        println!("{} | {}", " ".repeat(linestr.len()), arrows.green());
 
         //Should this happen??
         VM::terminate(self.vm.clone());
     }
 
-    pub fn run_interpreter_vars(
+    pub fn run_interpreter_call(
         &mut self,
         bytecode: &Bytecode<'a>,
-        vars: hashbrown::HashMap<isize, Object<'a>>,
+        args: &[Object<'a>],
+        enclosing: Vec<Scope<'a>>,
     ) -> Object<'a> {
         add_frame!(
             self,
             bytecode.n_registers as usize,
-            bytecode.n_variables as usize
+            bytecode.n_variables as usize,
+            enclosing
         );
 
-        for (i, var) in unwrap_fast!(self.namespaces.variables.last_mut())
+        for (var, arg) in unwrap_fast!(self.namespaces.variables.last_mut())
             .iter_mut()
-            .enumerate()
+            .zip(args)
         {
-            if vars.get(&(i as isize)).is_some() {
-                *var = Some(unwrap_fast!(vars.get(&(i as isize))).clone());
-            }
+            *var = Some(arg.clone());
         }
 
         let res = self.run_interpreter_raw(bytecode);
@@ -408,7 +1239,8 @@ impl<'a> Interpreter<'a> {
             add_frame!(
                 self,
                 bytecode.n_registers as usize,
-                bytecode.n_variables as usize
+                bytecode.n_variables as usize,
+                Vec::new()
             );
             let res = self.run_interpreter_raw(bytecode);
             pop_frame!(self);
@@ -417,35 +1249,292 @@ impl<'a> Interpreter<'a> {
         none_from!(self.vm)
     }
 
-    pub fn run_interpreter_extract_namespace(
+    //Run with the given variables instead of a new scope, and give them back afterwards
+    pub fn run_interpreter_namespace(
         &mut self,
         bytecode: &Bytecode<'a>,
-    ) -> Vec<Option<Trc<RawObject<'a>>>> {
-        add_frame!(
-            self,
-            bytecode.n_registers as usize,
-            bytecode.n_variables as usize
-        );
-
-        if !bytecode.instructions.is_empty() {
-            self.run_interpreter_raw(bytecode);
+        mut variables: Scope<'a>,
+        enclosing: Vec<Scope<'a>>,
+    ) -> (Object<'a>, Scope<'a>) {
+        if variables.len() < bytecode.n_variables as usize {
+            variables.resize(bytecode.n_variables as usize, None);
         }
+        self.namespaces.variables.push(variables);
+        let registers = self
+            .vm
+            .clone()
+            .frame_pool
+            .take_registers(bytecode.n_registers as usize, none_from!(self.vm));
+        self.frames.push(Frame {
+            registers,
+            enclosing,
+            bytecode: std::ptr::null(),
+            #[cfg(feature = "debugger")]
+            call: 0,
+        });
+
+        let res = if bytecode.instructions.is_empty() {
+            none_from!(self.vm)
+        } else {
+            self.run_interpreter_raw(bytecode)
+        };
 
-        let last = self.namespaces.variables.last().unwrap().clone();
-        pop_frame!(self);
-        last
+        let frame = unwrap_fast!(self.frames.pop());
+        if !self.vm.deterministic {
+            self.vm
+                .clone()
+                .frame_pool
+                .recycle_registers(frame.registers);
+        }
+        (res, unwrap_fast!(self.namespaces.variables.pop()))
     }
 
     #[inline]
     pub fn run_interpreter_raw(&mut self, bytecode: &Bytecode<'a>) -> Object<'a> {
         let last = unwrap_fast!(self.frames.last_mut());
-        let last_vars = unwrap_fast!(self.namespaces.variables.last_mut());
-        for instruction in bytecode.instructions.iter() {
+        last.bytecode = bytecode;
+        //Hold our own reference: nested calls push to the shared namespaces
+        let mut last_vars = unwrap_fast!(self.namespaces.variables.last()).clone();
+        let counting = self.vm.instruction_counts.is_some();
+        let step_limit = self.vm.step_limit;
+        let tracing = self
+            .vm
+            .trace
+            .as_ref()
+            .is_some_and(|trace| trace.traces(bytecode));
+        let mut pc = 0;
+        while let Some(instruction) = bytecode.instructions.get(pc) {
+            pc += 1;
+            #[cfg(feature = "checked")]
+            if let Err(message) =
+                check_instruction(&self.vm, bytecode, pc - 1, instruction, last, &last_vars)
+            {
+                self.raise_internal_error(bytecode, pc - 1, &message);
+            }
+            if tracing {
+                trace::trace_instruction(&self.vm, bytecode, pc - 1, instruction, last, &last_vars);
+            }
+            if counting {
+                *unwrap_fast!(self.vm.clone().instruction_counts.as_mut())
+                    .entry(quicken::name(bytecode, pc - 1, instruction))
+                    .or_insert(0) += 1;
+            }
+            if let Some(limit) = step_limit {
+                let mut vm = self.vm.clone();
+                vm.steps += 1;
+                //Each call runs in an interpreter of its own
+                let exceeded = if vm.steps > limit {
+                    Some(format!(
+                        "Run exceeded the step limit of {} instructions",
+                        limit
+                    ))
+                } else if vm.interpreters.len() > MAX_CALL_DEPTH {
+                    Some(format!(
+                        "Run exceeded the call depth limit of {} calls",
+                        MAX_CALL_DEPTH
+                    ))
+                } else {
+                    None
+                };
+                if let Some(message) = exceeded {
+                    let pos = bytecode
+                        .positions
+                        .get(pc - 1)
+                        .expect("Instruction out of range");
+                    let exc = exceptionobject::timeoutexc_from_str(
+                        self.vm.clone(),
+                        &message,
+                        pos.0,
+                        pos.1,
+                    );
+                    self.raise_exc(exc);
+                }
+            }
             match instruction {
                 //Binary operations
                 CompilerInstruction::BinaryAdd { a, b, result, i } => {
+                    match quicken::specialized(bytecode, *i) {
+                        quicken::Operands::Ints => int_binary_op!(
+                            self,
+                            last,
+                            last_vars,
+                            bytecode,
+                            *i,
+                            *a,
+                            *b,
+                            *result,
+                            checked_add,
+                            add
+                        ),
+                        quicken::Operands::Floats => float_binary_op!(
+                            self, last, last_vars, bytecode, *i, *a, *b, *result, add, +
+                        ),
+                        quicken::Operands::Other => {
+                            let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                            if selfv.tp.add.is_none() {
+                                let pos = bytecode
+                                    .positions
+                                    .get(*i)
+                                    .expect("Instruction out of range");
+                                let exc = methodnotdefinedexc_from_str(
+                                    self.vm.clone(),
+                                    &format!(
+                                        "Method 'add' is not defined for '{}' type",
+                                        selfv.tp.typename
+                                    ),
+                                    pos.0,
+                                    pos.1,
+                                );
+                                self.raise_exc(exc);
+                            }
+                            let other = load_register!(self, last, last_vars, bytecode, *i, *b);
+                            let operands = quicken::Operands::of(&selfv, &other);
+                            let res = unwrap_fast!(selfv.tp.add)(selfv, other);
+                            maybe_handle_exception!(self, res, bytecode, *i);
+                            store_register!(last, last_vars, *result, unwrap_fast!(res));
+                            if !self.vm.deterministic {
+                                quicken::observe(bytecode, *i, operands);
+                            }
+                        }
+                    }
+                }
+                CompilerInstruction::BinarySub { a, b, result, i } => {
+                    match quicken::specialized(bytecode, *i) {
+                        quicken::Operands::Ints => int_binary_op!(
+                            self,
+                            last,
+                            last_vars,
+                            bytecode,
+                            *i,
+                            *a,
+                            *b,
+                            *result,
+                            checked_sub,
+                            sub
+                        ),
+                        quicken::Operands::Floats => float_binary_op!(
+                            self, last, last_vars, bytecode, *i, *a, *b, *result, sub, -
+                        ),
+                        quicken::Operands::Other => {
+                            let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                            if selfv.tp.sub.is_none() {
+                                let pos = bytecode
+                                    .positions
+                                    .get(*i)
+                                    .expect("Instruction out of range");
+                                let exc = methodnotdefinedexc_from_str(
+                                    self.vm.clone(),
+                                    &format!(
+                                        "Method 'sub' is not defined for '{}' type",
+                                        selfv.tp.typename
+                                    ),
+                                    pos.0,
+                                    pos.1,
+                                );
+                                self.raise_exc(exc);
+                            }
+                            let other = load_register!(self, last, last_vars, bytecode, *i, *b);
+                            let operands = quicken::Operands::of(&selfv, &other);
+                            let res = unwrap_fast!(selfv.tp.sub)(selfv, other);
+                            maybe_handle_exception!(self, res, bytecode, *i);
+                            store_register!(last, last_vars, *result, unwrap_fast!(res));
+                            if !self.vm.deterministic {
+                                quicken::observe(bytecode, *i, operands);
+                            }
+                        }
+                    }
+                }
+                CompilerInstruction::BinaryMul { a, b, result, i } => {
+                    match quicken::specialized(bytecode, *i) {
+                        quicken::Operands::Ints => int_binary_op!(
+                            self,
+                            last,
+                            last_vars,
+                            bytecode,
+                            *i,
+                            *a,
+                            *b,
+                            *result,
+                            checked_mul,
+                            mul
+                        ),
+                        quicken::Operands::Floats => float_binary_op!(
+                            self, last, last_vars, bytecode, *i, *a, *b, *result, mul, *
+                        ),
+                        quicken::Operands::Other => {
+                            let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                            if selfv.tp.mul.is_none() {
+                                let pos = bytecode
+                                    .positions
+                                    .get(*i)
+                                    .expect("Instruction out of range");
+                                let exc = methodnotdefinedexc_from_str(
+                                    self.vm.clone(),
+                                    &format!(
+                                        "Method 'mul' is not defined for '{}' type",
+                                        selfv.tp.typename
+                                    ),
+                                    pos.0,
+                                    pos.1,
+                                );
+                                self.raise_exc(exc);
+                            }
+                            let other = load_register!(self, last, last_vars, bytecode, *i, *b);
+                            let operands = quicken::Operands::of(&selfv, &other);
+                            let res = unwrap_fast!(selfv.tp.mul)(selfv, other);
+                            maybe_handle_exception!(self, res, bytecode, *i);
+                            store_register!(last, last_vars, *result, unwrap_fast!(res));
+                            if !self.vm.deterministic {
+                                quicken::observe(bytecode, *i, operands);
+                            }
+                        }
+                    }
+                }
+                CompilerInstruction::BinaryAddInt { a, b, result, i } => {
+                    int_binary_op!(
+                        self,
+                        last,
+                        last_vars,
+                        bytecode,
+                        *i,
+                        *a,
+                        *b,
+                        *result,
+                        checked_add,
+                        add
+                    );
+                }
+                CompilerInstruction::BinarySubInt { a, b, result, i } => {
+                    int_binary_op!(
+                        self,
+                        last,
+                        last_vars,
+                        bytecode,
+                        *i,
+                        *a,
+                        *b,
+                        *result,
+                        checked_sub,
+                        sub
+                    );
+                }
+                CompilerInstruction::BinaryMulInt { a, b, result, i } => {
+                    int_binary_op!(
+                        self,
+                        last,
+                        last_vars,
+                        bytecode,
+                        *i,
+                        *a,
+                        *b,
+                        *result,
+                        checked_mul,
+                        mul
+                    );
+                }
+                CompilerInstruction::BinaryDiv { a, b, result, i } => {
                     let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
-                    if selfv.tp.add.is_none() {
+                    if selfv.tp.div.is_none() {
                         let pos = bytecode
                             .positions
                             .get(*i)
@@ -453,7 +1542,7 @@ impl<'a> Interpreter<'a> {
                         let exc = methodnotdefinedexc_from_str(
                             self.vm.clone(),
                             &format!(
-                                "Method 'add' is not defined for '{}' type",
+                                "Method 'div' is not defined for '{}' type",
                                 selfv.tp.typename
                             ),
                             pos.0,
@@ -461,16 +1550,16 @@ impl<'a> Interpreter<'a> {
                         );
                         self.raise_exc(exc);
                     }
-                    let res = unwrap_fast!(selfv.tp.add)(
+                    let res = unwrap_fast!(selfv.tp.div)(
                         selfv,
                         load_register!(self, last, last_vars, bytecode, *i, *b),
                     );
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
-                CompilerInstruction::BinarySub { a, b, result, i } => {
+                CompilerInstruction::BinaryBitAnd { a, b, result, i } => {
                     let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
-                    if selfv.tp.sub.is_none() {
+                    if selfv.tp.bitand.is_none() {
                         let pos = bytecode
                             .positions
                             .get(*i)
@@ -478,7 +1567,7 @@ impl<'a> Interpreter<'a> {
                         let exc = methodnotdefinedexc_from_str(
                             self.vm.clone(),
                             &format!(
-                                "Method 'sub' is not defined for '{}' type",
+                                "Method 'bitand' is not defined for '{}' type",
                                 selfv.tp.typename
                             ),
                             pos.0,
@@ -486,16 +1575,16 @@ impl<'a> Interpreter<'a> {
                         );
                         self.raise_exc(exc);
                     }
-                    let res = unwrap_fast!(selfv.tp.sub)(
+                    let res = unwrap_fast!(selfv.tp.bitand)(
                         selfv,
                         load_register!(self, last, last_vars, bytecode, *i, *b),
                     );
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
-                CompilerInstruction::BinaryMul { a, b, result, i } => {
+                CompilerInstruction::BinaryBitOr { a, b, result, i } => {
                     let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
-                    if selfv.tp.mul.is_none() {
+                    if selfv.tp.bitor.is_none() {
                         let pos = bytecode
                             .positions
                             .get(*i)
@@ -503,7 +1592,7 @@ impl<'a> Interpreter<'a> {
                         let exc = methodnotdefinedexc_from_str(
                             self.vm.clone(),
                             &format!(
-                                "Method 'mul' is not defined for '{}' type",
+                                "Method 'bitor' is not defined for '{}' type",
                                 selfv.tp.typename
                             ),
                             pos.0,
@@ -511,16 +1600,16 @@ impl<'a> Interpreter<'a> {
                         );
                         self.raise_exc(exc);
                     }
-                    let res = unwrap_fast!(selfv.tp.mul)(
+                    let res = unwrap_fast!(selfv.tp.bitor)(
                         selfv,
                         load_register!(self, last, last_vars, bytecode, *i, *b),
                     );
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
-                CompilerInstruction::BinaryDiv { a, b, result, i } => {
+                CompilerInstruction::BinaryBitXor { a, b, result, i } => {
                     let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
-                    if selfv.tp.div.is_none() {
+                    if selfv.tp.bitxor.is_none() {
                         let pos = bytecode
                             .positions
                             .get(*i)
@@ -528,7 +1617,7 @@ impl<'a> Interpreter<'a> {
                         let exc = methodnotdefinedexc_from_str(
                             self.vm.clone(),
                             &format!(
-                                "Method 'div' is not defined for '{}' type",
+                                "Method 'bitxor' is not defined for '{}' type",
                                 selfv.tp.typename
                             ),
                             pos.0,
@@ -536,13 +1625,165 @@ impl<'a> Interpreter<'a> {
                         );
                         self.raise_exc(exc);
                     }
-                    let res = unwrap_fast!(selfv.tp.div)(
+                    let res = unwrap_fast!(selfv.tp.bitxor)(
                         selfv,
                         load_register!(self, last, last_vars, bytecode, *i, *b),
                     );
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
+                CompilerInstruction::BinaryLShift { a, b, result, i } => {
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    if selfv.tp.lshift.is_none() {
+                        let pos = bytecode
+                            .positions
+                            .get(*i)
+                            .expect("Instruction out of range");
+                        let exc = methodnotdefinedexc_from_str(
+                            self.vm.clone(),
+                            &format!(
+                                "Method 'lshift' is not defined for '{}' type",
+                                selfv.tp.typename
+                            ),
+                            pos.0,
+                            pos.1,
+                        );
+                        self.raise_exc(exc);
+                    }
+                    let res = unwrap_fast!(selfv.tp.lshift)(
+                        selfv,
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(last, last_vars, *result, unwrap_fast!(res));
+                }
+                CompilerInstruction::BinaryRShift { a, b, result, i } => {
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    if selfv.tp.rshift.is_none() {
+                        let pos = bytecode
+                            .positions
+                            .get(*i)
+                            .expect("Instruction out of range");
+                        let exc = methodnotdefinedexc_from_str(
+                            self.vm.clone(),
+                            &format!(
+                                "Method 'rshift' is not defined for '{}' type",
+                                selfv.tp.typename
+                            ),
+                            pos.0,
+                            pos.1,
+                        );
+                        self.raise_exc(exc);
+                    }
+                    let res = unwrap_fast!(selfv.tp.rshift)(
+                        selfv,
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(last, last_vars, *result, unwrap_fast!(res));
+                }
+
+                CompilerInstruction::BinaryEq { a, b, result, i } => {
+                    let res = RawObject::object_eq_safe(
+                        load_register!(self, last, last_vars, bytecode, *i, *a),
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
+                CompilerInstruction::BinaryNe { a, b, result, i } => {
+                    let res = RawObject::object_eq_safe(
+                        load_register!(self, last, last_vars, bytecode, *i, *a),
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), !unwrap_fast!(res))
+                    );
+                }
+                CompilerInstruction::BinaryLt { a, b, result, i } => {
+                    let res = RawObject::object_lt_safe(
+                        load_register!(self, last, last_vars, bytecode, *i, *a),
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
+                CompilerInstruction::BinaryGt { a, b, result, i } => {
+                    let res = RawObject::object_gt_safe(
+                        load_register!(self, last, last_vars, bytecode, *i, *a),
+                        load_register!(self, last, last_vars, bytecode, *i, *b),
+                    );
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
+                //Checked as less (or greater) and then equal, so that unordered values are neither
+                CompilerInstruction::BinaryLe { a, b, result, i } => {
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    let other = load_register!(self, last, last_vars, bytecode, *i, *b);
+                    let res = RawObject::object_lt_safe(selfv.clone(), other.clone());
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    let res = if unwrap_fast!(res) {
+                        MethodValue::Some(true)
+                    } else {
+                        RawObject::object_eq_safe(selfv, other)
+                    };
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
+                CompilerInstruction::BinaryGe { a, b, result, i } => {
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    let other = load_register!(self, last, last_vars, bytecode, *i, *b);
+                    let res = RawObject::object_gt_safe(selfv.clone(), other.clone());
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    let res = if unwrap_fast!(res) {
+                        MethodValue::Some(true)
+                    } else {
+                        RawObject::object_eq_safe(selfv, other)
+                    };
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
+                CompilerInstruction::BinaryContains { a, b, result, i } => {
+                    let item = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    let container = load_register!(self, last, last_vars, bytecode, *i, *b);
+                    let res = RawObject::object_contains_safe(container, item);
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(
+                        last,
+                        last_vars,
+                        *result,
+                        boolobject::bool_from(self.vm.clone(), unwrap_fast!(res))
+                    );
+                }
 
                 //Unary operations
                 CompilerInstruction::UnaryNeg { a, result, i } => {
@@ -567,6 +1808,28 @@ impl<'a> Interpreter<'a> {
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
+                CompilerInstruction::UnaryInvert { a, result, i } => {
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *a);
+                    if selfv.tp.invert.is_none() {
+                        let pos = bytecode
+                            .positions
+                            .get(*i)
+                            .expect("Instruction out of range");
+                        let exc = methodnotdefinedexc_from_str(
+                            self.vm.clone(),
+                            &format!(
+                                "Method 'invert' is not defined for '{}' type",
+                                selfv.tp.typename
+                            ),
+                            pos.0,
+                            pos.1,
+                        );
+                        self.raise_exc(exc);
+                    }
+                    let res = unwrap_fast!(selfv.tp.invert)(selfv);
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                    store_register!(last, last_vars, *result, unwrap_fast!(res));
+                }
 
                 //Register manipulation
                 CompilerInstruction::CopyRegister { from, to, i } => {
@@ -577,13 +1840,36 @@ impl<'a> Interpreter<'a> {
                         load_register!(self, last, last_vars, bytecode, *i, *from)
                     );
                 }
+                //The old value of the destination is swapped into the register, which is not read
+                //again, so it is dropped when the register is next written
+                CompilerInstruction::MoveRegister { from, to, .. } => {
+                    let from = match *from {
+                        CompilerRegister::R(v) => v,
+                        _ => unreachable!("Impossible."),
+                    };
+                    match *to {
+                        CompilerRegister::R(v) => last.registers.swap(from, v),
+                        CompilerRegister::V(v) => {
+                            let old = last_vars[v].take().unwrap_or_else(|| none_from!(self.vm));
+                            last_vars[v] = Some(std::mem::replace(&mut last.registers[from], old));
+                        }
+                        CompilerRegister::C(_) | CompilerRegister::E(_, _) => {
+                            unreachable!("Impossible.")
+                        }
+                    }
+                }
                 CompilerInstruction::AttrLoad {
                     left,
-                    attridx,
+                    nameidx,
                     result,
                     i,
                 } => {
-                    let attr = load_register!(self, last, last_vars, bytecode, *i, *attridx);
+                    let attr = self
+                        .vm
+                        .interned
+                        .get(&bytecode.attr_names[*nameidx])
+                        .expect("Attribute name is not interned")
+                        .clone();
                     let selfv = load_register!(self, last, last_vars, bytecode, *i, *left);
 
                     if selfv.tp.getattr.is_none() {
@@ -607,18 +1893,55 @@ impl<'a> Interpreter<'a> {
                     maybe_handle_exception!(self, res, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(res));
                 }
+                CompilerInstruction::AttrStore {
+                    left,
+                    nameidx,
+                    value,
+                    i,
+                } => {
+                    let attr = self
+                        .vm
+                        .interned
+                        .get(&bytecode.attr_names[*nameidx])
+                        .expect("Attribute name is not interned")
+                        .clone();
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *left);
+                    let value = load_register!(self, last, last_vars, bytecode, *i, *value);
+
+                    if selfv.tp.setattr.is_none() {
+                        let pos = bytecode
+                            .positions
+                            .get(*i)
+                            .expect("Instruction out of range");
+                        let exc = methodnotdefinedexc_from_str(
+                            self.vm.clone(),
+                            &format!(
+                                "Method 'setattr' is not defined for '{}' type",
+                                selfv.tp.typename
+                            ),
+                            pos.0,
+                            pos.1,
+                        );
+                        self.raise_exc(exc);
+                    }
+
+                    let res = unwrap_fast!(selfv.tp.setattr)(selfv, attr, value);
+                    maybe_handle_exception!(self, res, bytecode, *i);
+                }
 
                 //Functions, arguments
                 CompilerInstruction::MakeFunction {
                     nameidx,
                     argsidx,
-                    codeidx,
+                    bodyidx,
                     out,
+                    is_abstract,
+                    ..
                 } => {
-                    let code = bytecode
-                        .consts
-                        .get(*codeidx)
-                        .expect("Bytecode consts index out of range")
+                    let body = bytecode
+                        .bodies
+                        .get(*bodyidx)
+                        .expect("Bytecode bodies index out of range")
                         .clone();
                     let args = bytecode
                         .consts
@@ -630,11 +1953,26 @@ impl<'a> Interpreter<'a> {
                         .get(*nameidx)
                         .expect("Bytecode names index out of range")
                         .clone();
+                    let span = bytecode
+                        .positions
+                        .get(pc - 1)
+                        .expect("Instruction out of range");
+                    let mut enclosing = last.enclosing.clone();
+                    enclosing.push(last_vars.clone());
+                    let Some(args) = args.as_list() else {
+                        self.raise_internal_error(bytecode, pc - 1, "the arguments are not a list");
+                    };
+                    let Some(name) = name.as_str() else {
+                        self.raise_internal_error(bytecode, pc - 1, "the name is not a string");
+                    };
                     let func = fnobject::fn_from(
                         self.vm.clone(),
-                        code,
-                        unsafe { &args.internals.arr }.to_vec(),
-                        unsafe { &name.internals.str }.to_string(),
+                        body,
+                        args.to_vec(),
+                        name.to_string(),
+                        span,
+                        enclosing,
+                        *is_abstract,
                     );
                     store_register!(last, last_vars, *out, func);
                 }
@@ -646,18 +1984,34 @@ impl<'a> Interpreter<'a> {
                 } => {
                     let callable =
                         load_register!(self, last, last_vars, bytecode, *i, *callableregister);
-                    let mut args = Vec::new();
-                    for register in arg_registers {
-                        args.push(load_register!(
-                            self,
-                            last,
-                            last_vars,
-                            bytecode,
-                            *i,
-                            register.value
-                        ));
-                    }
-                    if callable.tp.call.is_none() {
+                    let value = call_registers!(
+                        self,
+                        last,
+                        last_vars,
+                        bytecode,
+                        *i,
+                        callable,
+                        arg_registers
+                    );
+                    maybe_handle_exception!(self, value, bytecode, *i);
+                    store_register!(last, last_vars, *result, unwrap_fast!(value));
+                }
+
+                CompilerInstruction::CallAttr {
+                    left,
+                    nameidx,
+                    result,
+                    arg_registers,
+                    i,
+                } => {
+                    let attr = self
+                        .vm
+                        .interned
+                        .get(&bytecode.attr_names[*nameidx])
+                        .expect("Attribute name is not interned")
+                        .clone();
+                    let selfv = load_register!(self, last, last_vars, bytecode, *i, *left);
+                    if selfv.tp.getattr.is_none() {
                         let pos = bytecode
                             .positions
                             .get(*i)
@@ -665,28 +2019,75 @@ impl<'a> Interpreter<'a> {
                         let exc = methodnotdefinedexc_from_str(
                             self.vm.clone(),
                             &format!(
-                                "Method 'call' is not defined for '{}' type",
-                                callable.tp.typename
+                                "Method 'getattr' is not defined for '{}' type",
+                                selfv.tp.typename
                             ),
                             pos.0,
                             pos.1,
                         );
                         self.raise_exc(exc);
                     }
-
-                    let value = (callable.tp.call.expect("Method is not defined"))(
-                        callable,
-                        listobject::list_from(self.vm.clone(), args),
-                    );
+                    //A method that was found before is called with the instance before the
+                    //arguments, instead of binding it to the instance
+                    let value = match RawObject::cached_method(&selfv, &attr) {
+                        Some(fun) => call_registers!(
+                            self,
+                            last,
+                            last_vars,
+                            bytecode,
+                            *i,
+                            fun,
+                            arg_registers,
+                            selfv
+                        ),
+                        None => {
+                            let callable = unwrap_fast!(selfv.tp.getattr)(selfv, attr);
+                            maybe_handle_exception!(self, callable, bytecode, *i);
+                            call_registers!(
+                                self,
+                                last,
+                                last_vars,
+                                bytecode,
+                                *i,
+                                unwrap_fast!(callable),
+                                arg_registers
+                            )
+                        }
+                    };
                     maybe_handle_exception!(self, value, bytecode, *i);
                     store_register!(last, last_vars, *result, unwrap_fast!(value));
                 }
 
                 //Control flow
                 CompilerInstruction::Return { register, i } => {
-                    let res = load_register!(self, last, last_vars, bytecode, *i, *register);
-                    pop_frame!(self);
-                    return res;
+                    return load_register!(self, last, last_vars, bytecode, *i, *register);
+                }
+                CompilerInstruction::StoreReturn { from, to, i } => {
+                    let value = load_register!(self, last, last_vars, bytecode, *i, *from);
+                    store_register!(last, last_vars, *to, value.clone());
+                    return value;
+                }
+                CompilerInstruction::SkipIfFalse { register, n, i } => {
+                    let value = load_register!(self, last, last_vars, bytecode, *i, *register);
+                    let Some(value) = value.as_bool() else {
+                        self.raise_internal_error(bytecode, *i, "the condition is not a bool");
+                    };
+                    if !value {
+                        pc += *n;
+                    }
+                }
+
+                CompilerInstruction::EnterContext { register, out, i } => {
+                    let manager = load_register!(self, last, last_vars, bytecode, *i, *register);
+                    let value = classtype::call_method(manager.clone(), "enter", Vec::new());
+                    maybe_handle_exception!(self, value, bytecode, *i);
+                    self.vm.clone().contexts.push(manager);
+                    store_register!(last, last_vars, *out, unwrap_fast!(value));
+                }
+                CompilerInstruction::ExitContext { i } => {
+                    let manager = unwrap_fast!(self.vm.clone().contexts.pop());
+                    let value = classtype::call_method(manager, "exit", Vec::new());
+                    maybe_handle_exception!(self, value, bytecode, *i);
                 }
 
                 //Data structures
@@ -710,6 +2111,7 @@ impl<'a> Interpreter<'a> {
                     value_registers,
                     i,
                 } => {
+                    //A key that is given again replaces the value of the first, so the last wins
                     let mut map = mhash::HashMap::new();
                     for (key, value) in std::iter::zip(key_registers, value_registers) {
                         let key = load_register!(self, last, last_vars, bytecode, *i, *key);
@@ -722,33 +2124,125 @@ impl<'a> Interpreter<'a> {
                     store_register!(last, last_vars, *result, dict);
                 }
 
+                CompilerInstruction::Comprehension {
+                    iterable,
+                    bodyidx,
+                    is_dict,
+                    result,
+                    i,
+                    ..
+                } => {
+                    let iterable = load_register!(self, last, last_vars, bytecode, *i, *iterable);
+                    let items = RawObject::object_iter_safe(iterable);
+                    maybe_handle_exception!(self, items, bytecode, *i);
+
+                    let body = bytecode
+                        .bodies
+                        .get(*bodyidx)
+                        .expect("Bytecode bodies index out of range");
+                    let mut enclosing = last.enclosing.clone();
+                    enclosing.push(last_vars.clone());
+
+                    let mut values = Vec::new();
+                    for item in unwrap_fast!(items) {
+                        values.push(VM::execute_call(
+                            self.vm.clone(),
+                            body,
+                            &[item],
+                            enclosing.clone(),
+                        ));
+                    }
+
+                    let out = if *is_dict {
+                        let mut map = mhash::HashMap::new();
+                        for pair in values {
+                            let Some(pair) = pair.as_list() else {
+                                self.raise_internal_error(bytecode, *i, "an item is not a pair");
+                            };
+                            let res = map.insert(pair[0].clone(), pair[1].clone());
+                            maybe_handle_exception!(self, res, bytecode, *i);
+                        }
+                        dictobject::dict_from(self.vm.clone(), map)
+                    } else {
+                        listobject::list_from(self.vm.clone(), values)
+                    };
+                    store_register!(last, last_vars, *result, out);
+                }
+
                 //Class
+                CompilerInstruction::BuildClassDict {
+                    result,
+                    names,
+                    variables,
+                    i: _,
+                } => {
+                    let mut map = mhash::HashMap::new();
+                    for (nameidx, var) in std::iter::zip(names, variables) {
+                        //A name may be declared in the body without being bound when it ends
+                        if let Some(value) = &last_vars[*var] {
+                            let name =
+                                unwrap_fast!(self.vm.interned.get(&bytecode.attr_names[*nameidx]));
+                            map.insert(name.clone(), value.clone());
+                        }
+                    }
+                    let dict = dictobject::dict_from(self.vm.clone(), map);
+                    store_register!(last, last_vars, *result, dict);
+                }
+
                 CompilerInstruction::MakeClass {
                     name,
-                    methods,
-                    bytecode: class_body,
+                    bases,
+                    bodyidx,
                     out,
+                    i,
+                    ..
                 } => {
-                    let mut method_map = mhash::HashMap::new();
-
-                    let namespace =
-                        VM::<'a>::execute_extract_namespace(self.vm.clone(), class_body);
-                    for i in 0..namespace.len() {
-                        let var = namespace.get(i).unwrap();
-                        debug_assert!(var.is_some());
-                        method_map.insert(
-                            stringobject::string_from(
-                                self.vm.clone(),
-                                methods.get(&(i as i32)).unwrap().to_string(),
-                            ),
-                            var.as_ref().unwrap().clone(),
-                        );
+                    let mut base_types = Vec::new();
+                    for base in bases {
+                        let base = load_register!(self, last, last_vars, bytecode, *i, *base);
+                        //Only classes can be inherited from, as the builtin types store their data natively
+                        let tp = match base.as_type() {
+                            Some(tp) if tp.dict.is_some() => tp,
+                            _ => {
+                                let pos = bytecode
+                                    .positions
+                                    .get(*i)
+                                    .expect("Instruction out of range");
+                                let exc = exceptionobject::typemismatchexc_from_str(
+                                    self.vm.clone(),
+                                    &format!(
+                                        "Cannot inherit from '{}'",
+                                        RawObject::object_repr(&base)
+                                    ),
+                                    pos.0,
+                                    pos.1,
+                                );
+                                self.raise_exc(exc);
+                            }
+                        };
+                        base_types.push(Trc::new(tp.clone()));
                     }
 
-                    let method_dict = dictobject::dict_from(self.vm.clone(), method_map);
+                    let class_body = bytecode
+                        .bodies
+                        .get(*bodyidx)
+                        .expect("Bytecode bodies index out of range");
+                    let mut enclosing = last.enclosing.clone();
+                    enclosing.push(last_vars.clone());
+                    //The body returns the dict of the names that it bound, see BuildClassDict
+                    let (method_dict, _) = VM::<'a>::execute_in_namespace(
+                        self.vm.clone(),
+                        class_body,
+                        Namespace::new(),
+                        enclosing,
+                    );
 
-                    let new_class =
-                        classtype::create_class(self.vm.clone(), name.clone(), method_dict);
+                    let new_class = classtype::create_class(
+                        self.vm.clone(),
+                        name.clone(),
+                        method_dict,
+                        base_types,
+                    );
 
                     store_register!(last, last_vars, *out, new_class);
                 }
@@ -0,0 +1,101 @@
+//Quickening: the generic binary instructions record the types of their operands, and one that saw
+//the same types QUICKEN_HITS times in a row is specialized for them, and then runs like the int or
+//float instruction. A specialized instruction that meets other operands falls back to the slot and
+//runs as the generic one again, which gives up after MAX_DEOPTS times so that a site that sees mixed
+//types does not keep switching. The instructions of a body are shared by the frames that run it and
+//are never changed: what an instruction is specialized for is kept beside it, in Code::feedback.
+//Nothing is specialized with --deterministic, which runs every instruction the same way each time.
+
+use crate::compiler::{Bytecode, CompilerInstruction};
+use crate::objects::Object;
+use crate::unwrap_fast;
+
+pub const QUICKEN_HITS: u8 = 16;
+pub const MAX_DEOPTS: u8 = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Operands {
+    #[default]
+    Other,
+    Ints,
+    Floats,
+}
+
+impl Operands {
+    pub fn of<'a>(a: &Object<'a>, b: &Object<'a>) -> Operands {
+        let types = &a.vm.types;
+        let inttp = unwrap_fast!(types.inttp.as_ref());
+        let floattp = unwrap_fast!(types.floattp.as_ref());
+        if is_type_exact!(a, inttp) && is_type_exact!(b, inttp) {
+            Operands::Ints
+        } else if is_type_exact!(a, floattp) && is_type_exact!(b, floattp) {
+            Operands::Floats
+        } else {
+            Operands::Other
+        }
+    }
+}
+
+//What an instruction has seen, kept beside it in Code::feedback
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Feedback {
+    operands: Operands,
+    hits: u8,              //Runs in a row that saw these operands
+    deopts: u8,            //Times that the instruction was specialized and met other operands
+    specialized: Operands, //The operands that it runs for, or Other while it is generic
+}
+
+//The operands that the instruction at idx is specialized for, or Other if it runs as generic
+pub fn specialized(bytecode: &Bytecode<'_>, idx: usize) -> Operands {
+    bytecode.feedback[idx].get().specialized
+}
+
+//Record a run of the generic instruction at idx, after it finished
+pub fn observe(bytecode: &Bytecode<'_>, idx: usize, operands: Operands) {
+    let cell = &bytecode.feedback[idx];
+    let mut feedback = cell.get();
+    if feedback.deopts >= MAX_DEOPTS {
+        return;
+    }
+    if feedback.operands != operands {
+        feedback.operands = operands;
+        feedback.hits = 0;
+    }
+    if operands != Operands::Other {
+        feedback.hits += 1;
+        if feedback.hits >= QUICKEN_HITS {
+            feedback.hits = 0;
+            feedback.specialized = operands;
+        }
+    }
+    cell.set(feedback);
+}
+
+//Run the specialized instruction at idx, whose guard failed, as the generic one again
+pub fn deoptimize(bytecode: &Bytecode<'_>, idx: usize) {
+    let cell = &bytecode.feedback[idx];
+    let mut feedback = cell.get();
+    feedback.operands = Operands::Other;
+    feedback.hits = 0;
+    feedback.deopts = feedback.deopts.saturating_add(1);
+    feedback.specialized = Operands::Other;
+    cell.set(feedback);
+}
+
+//The name that a run of the instruction at idx is counted under: the name of the instruction that
+//it runs like when it is specialized
+pub fn name(
+    bytecode: &Bytecode<'_>,
+    idx: usize,
+    instruction: &CompilerInstruction<'_>,
+) -> &'static str {
+    match (instruction, specialized(bytecode, idx)) {
+        (CompilerInstruction::BinaryAdd { .. }, Operands::Ints) => "BinaryAddInt",
+        (CompilerInstruction::BinarySub { .. }, Operands::Ints) => "BinarySubInt",
+        (CompilerInstruction::BinaryMul { .. }, Operands::Ints) => "BinaryMulInt",
+        (CompilerInstruction::BinaryAdd { .. }, Operands::Floats) => "BinaryAddFloat",
+        (CompilerInstruction::BinarySub { .. }, Operands::Floats) => "BinarySubFloat",
+        (CompilerInstruction::BinaryMul { .. }, Operands::Floats) => "BinaryMulFloat",
+        _ => instruction.name(),
+    }
+}
@@ -0,0 +1,118 @@
+//Tracing, with --trace: every instruction that runs is printed with the reprs of the registers that
+//it reads, below the source line that it runs for. With a function name, only the bodies of the
+//functions with that name or qualified name are traced, and not the functions that they call.
+
+use super::{Frame, VM};
+use crate::compiler::{Bytecode, CompilerInstruction, CompilerRegister};
+use crate::objects::{shape, MethodValue, Object, RawObject};
+use crate::rc::Trc;
+use crate::unwrap_fast;
+
+#[derive(Clone, Default)]
+pub struct Trace {
+    function: Option<String>, //The name or qualified name of the functions to trace
+    bodies: hashbrown::HashSet<usize>, //The bodies of the functions with that name that were called
+    line: Option<(usize, usize)>, //The body and line of the last source line printed
+}
+
+impl Trace {
+    pub fn new(function: Option<String>) -> Self {
+        Trace {
+            function,
+            ..Default::default()
+        }
+    }
+
+    //Remember the body of a function that is called, if its instructions are traced
+    pub fn called(&mut self, name: &str, qualname: &str, bytecode: &Bytecode<'_>) {
+        if self
+            .function
+            .as_ref()
+            .is_some_and(|function| function == name || function == qualname)
+        {
+            self.bodies.insert(bytecode as *const Bytecode<'_> as usize);
+        }
+    }
+
+    pub fn traces(&self, bytecode: &Bytecode<'_>) -> bool {
+        self.function.is_none()
+            || self
+                .bodies
+                .contains(&(bytecode as *const Bytecode<'_> as usize))
+    }
+}
+
+//Print an instruction that is about to run, after its source line if that is not the line of the
+//instruction printed before it
+pub(super) fn trace_instruction<'a>(
+    vm: &Trc<VM<'a>>,
+    bytecode: &Bytecode<'a>,
+    idx: usize,
+    instruction: &CompilerInstruction<'a>,
+    frame: &Frame<'a>,
+    variables: &[Option<Object<'a>>],
+) {
+    let body = bytecode as *const Bytecode<'a> as usize;
+    if let Some((start, _)) = bytecode.positions.get(idx) {
+        let mut this = vm.clone();
+        let trace = unwrap_fast!(this.trace.as_mut());
+        if trace.line != Some((body, start.line)) {
+            trace.line = Some((body, start.line));
+            let source = String::from_utf8_lossy(bytecode.info.data);
+            vm.diagnostics.write_line(&format!(
+                "[trace] {}:{} | {}",
+                bytecode.info.name,
+                start.line + 1,
+                source.lines().nth(start.line).unwrap_or_default().trim()
+            ));
+        }
+    }
+
+    let operands = instruction
+        .read_registers()
+        .into_iter()
+        .map(|register| {
+            let value = match register {
+                CompilerRegister::R(v) => frame.registers.get(v).cloned(),
+                CompilerRegister::V(v) => variables.get(v).cloned().flatten(),
+                CompilerRegister::C(v) => bytecode.consts.get(v).cloned(),
+                CompilerRegister::E(level, v) => frame
+                    .enclosing
+                    .get(level)
+                    .and_then(|scope| scope.get(v).cloned().flatten()),
+            };
+            let name = match register {
+                CompilerRegister::V(v) => bytecode.names.get(&(v as i32)).cloned(),
+                CompilerRegister::E(level, v) => bytecode.outer_names.get(&(level, v)).cloned(),
+                _ => None,
+            };
+            format!(
+                "{} = {}",
+                name.unwrap_or_else(|| format!("{:?}", register)),
+                value.map_or_else(|| String::from("<unbound>"), operand_repr)
+            )
+        })
+        .collect::<Vec<_>>();
+    if operands.is_empty() {
+        vm.diagnostics
+            .write_line(&format!("[trace]     {}", instruction.name()));
+    } else {
+        vm.diagnostics.write_line(&format!(
+            "[trace]     {} {}",
+            instruction.name(),
+            operands.join(", ")
+        ));
+    }
+}
+
+//The repr of an operand. The repr of an instance is not shown, because it would run the code of its
+//class while the instruction is being traced.
+pub(super) fn operand_repr(object: Object<'_>) -> String {
+    if shape::is_instance(&object) {
+        return format!("<{} instance>", object.tp.typename);
+    }
+    match RawObject::object_repr_safe(object.clone()) {
+        MethodValue::Some(repr) => repr,
+        MethodValue::Error(_) => format!("<{}>", object.tp.typename),
+    }
+}
@@ -1,5 +1,15 @@
 //Generate tokens from text
 
+use crate::errors::{ErrorType, ParseError};
+use crate::parser::Position;
+
+//Keywords may not be used as identifiers unless they are escaped with backticks, as in `in`.
+//Some are reserved for syntax that is not supported yet.
+pub const KEYWORDS: [&str; 16] = [
+    "fn", "return", "class", "abstract", "with", "as", "for", "in", "const", "if", "else", "while",
+    "break", "continue", "import", "let",
+];
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum TokenType {
     Decimal,
@@ -23,6 +33,18 @@ pub enum TokenType {
     RSquare,
     Colon,
     Period,
+    DoubleEquals,
+    NotEquals,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    LeftShift,
+    RightShift,
+    Less,
+    Greater,
+    LessEquals,
+    GreaterEquals,
 }
 
 impl std::fmt::Display for TokenType {
@@ -49,6 +71,18 @@ impl std::fmt::Display for TokenType {
             Self::RSquare => write!(f, "r-square"),
             Self::Colon => write!(f, "colon"),
             Self::Period => write!(f, "period"),
+            Self::DoubleEquals => write!(f, "double-equals"),
+            Self::NotEquals => write!(f, "not-equals"),
+            Self::Ampersand => write!(f, "ampersand"),
+            Self::Pipe => write!(f, "pipe"),
+            Self::Caret => write!(f, "caret"),
+            Self::Tilde => write!(f, "tilde"),
+            Self::LeftShift => write!(f, "left-shift"),
+            Self::RightShift => write!(f, "right-shift"),
+            Self::Less => write!(f, "less"),
+            Self::Greater => write!(f, "greater"),
+            Self::LessEquals => write!(f, "less-equals"),
+            Self::GreaterEquals => write!(f, "greater-equals"),
         }
     }
 }
@@ -61,11 +95,12 @@ pub struct Lexer<'life> {
     pub line: usize,
     pub col: usize,
     pub info: &'life crate::fileinfo::FileInfo<'life>,
-    pub kwds: Vec<String>,
 }
 
+//Tokens, or the error that stops lexing. Lexing errors are returned, not raised, so that a
+//parser can report them however it needs to.
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    type Item = Result<Token, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let cur: char = self.current.into();
@@ -73,11 +108,13 @@ impl<'a> Iterator for Lexer<'a> {
         if cur.is_ascii_digit() {
             Some(make_decimal(self))
         } else if cur.is_alphabetic() {
-            Some(make_identifier(self))
+            Some(Ok(make_identifier(self)))
         } else if cur == '"' {
-            Some(make_string(self))
+            Some(Ok(make_string(self)))
+        } else if cur == '`' {
+            Some(make_escaped_identifier(self))
         } else if cur == '\n' {
-            Some(add_char_token(self, cur, TokenType::Newline))
+            Some(Ok(add_char_token(self, cur, TokenType::Newline)))
         } else if cur == '#' {
             advance(self);
             while (self.current as char) != '\n' && (self.current as char) != '\0' {
@@ -91,52 +128,85 @@ impl<'a> Iterator for Lexer<'a> {
             }
             self.next()
         } else if cur == '+' {
-            Some(add_char_token(self, cur, TokenType::Plus))
+            Some(Ok(add_char_token(self, cur, TokenType::Plus)))
         } else if cur == '*' {
-            Some(add_char_token(self, cur, TokenType::Asterisk))
+            Some(Ok(add_char_token(self, cur, TokenType::Asterisk)))
         } else if cur == '/' {
-            Some(add_char_token(self, cur, TokenType::Slash))
+            Some(Ok(add_char_token(self, cur, TokenType::Slash)))
         } else if cur == '-' {
-            Some(add_char_token(self, cur, TokenType::Hyphen))
+            Some(Ok(add_char_token(self, cur, TokenType::Hyphen)))
+        } else if cur == '=' && peek(self) == b'=' {
+            Some(Ok(add_double_char_token(
+                self,
+                "==",
+                TokenType::DoubleEquals,
+            )))
         } else if cur == '=' {
-            Some(add_char_token(self, cur, TokenType::Equals))
+            Some(Ok(add_char_token(self, cur, TokenType::Equals)))
+        } else if cur == '!' && peek(self) == b'=' {
+            Some(Ok(add_double_char_token(self, "!=", TokenType::NotEquals)))
+        } else if cur == '<' && peek(self) == b'<' {
+            Some(Ok(add_double_char_token(self, "<<", TokenType::LeftShift)))
+        } else if cur == '>' && peek(self) == b'>' {
+            Some(Ok(add_double_char_token(self, ">>", TokenType::RightShift)))
+        } else if cur == '<' && peek(self) == b'=' {
+            Some(Ok(add_double_char_token(self, "<=", TokenType::LessEquals)))
+        } else if cur == '>' && peek(self) == b'=' {
+            Some(Ok(add_double_char_token(
+                self,
+                ">=",
+                TokenType::GreaterEquals,
+            )))
+        } else if cur == '<' {
+            Some(Ok(add_char_token(self, cur, TokenType::Less)))
+        } else if cur == '>' {
+            Some(Ok(add_char_token(self, cur, TokenType::Greater)))
+        } else if cur == '&' {
+            Some(Ok(add_char_token(self, cur, TokenType::Ampersand)))
+        } else if cur == '|' {
+            Some(Ok(add_char_token(self, cur, TokenType::Pipe)))
+        } else if cur == '^' {
+            Some(Ok(add_char_token(self, cur, TokenType::Caret)))
+        } else if cur == '~' {
+            Some(Ok(add_char_token(self, cur, TokenType::Tilde)))
         } else if cur == '(' {
-            Some(add_char_token(self, cur, TokenType::LParen))
+            Some(Ok(add_char_token(self, cur, TokenType::LParen)))
         } else if cur == ')' {
-            Some(add_char_token(self, cur, TokenType::RParen))
+            Some(Ok(add_char_token(self, cur, TokenType::RParen)))
         } else if cur == '{' {
-            Some(add_char_token(self, cur, TokenType::LCurly))
+            Some(Ok(add_char_token(self, cur, TokenType::LCurly)))
         } else if cur == '}' {
-            Some(add_char_token(self, cur, TokenType::RCurly))
+            Some(Ok(add_char_token(self, cur, TokenType::RCurly)))
         } else if cur == ',' {
-            Some(add_char_token(self, cur, TokenType::Comma))
+            Some(Ok(add_char_token(self, cur, TokenType::Comma)))
         } else if cur == '[' {
-            Some(add_char_token(self, cur, TokenType::LSquare))
+            Some(Ok(add_char_token(self, cur, TokenType::LSquare)))
         } else if cur == ']' {
-            Some(add_char_token(self, cur, TokenType::RSquare))
+            Some(Ok(add_char_token(self, cur, TokenType::RSquare)))
         } else if cur == ':' {
-            Some(add_char_token(self, cur, TokenType::Colon))
+            Some(Ok(add_char_token(self, cur, TokenType::Colon)))
         } else if cur == '.' {
-            Some(add_char_token(self, cur, TokenType::Period))
+            Some(Ok(add_char_token(self, cur, TokenType::Period)))
         } else if cur == '\0' {
             if self.len == 0 {
                 self.len = 1;
-                return Some(add_char_token(self, cur, TokenType::Eof));
+                return Some(Ok(add_char_token(self, cur, TokenType::Eof)));
             }
             None
         } else {
-            Some(add_char_token(self, cur, TokenType::Unknown))
+            Some(Ok(add_char_token(self, cur, TokenType::Unknown)))
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub data: String,
     pub tp: TokenType,
     pub line: usize,
     pub startcol: usize, //Inclusive
     pub endcol: usize,   //Exclusive
+    pub offset: usize,   //Of the first byte in the file
 }
 
 impl std::fmt::Display for Token {
@@ -145,11 +215,7 @@ impl std::fmt::Display for Token {
     }
 }
 
-pub fn new<'a>(
-    data: &'a [u8],
-    info: &'a crate::fileinfo::FileInfo,
-    kwds: Vec<String>,
-) -> Lexer<'a> {
+pub fn new<'a>(data: &'a [u8], info: &'a crate::fileinfo::FileInfo) -> Lexer<'a> {
     Lexer {
         idx: 0,
         current: if !data.is_empty() { data[0] } else { b'\0' },
@@ -157,10 +223,88 @@ pub fn new<'a>(
         line: 0,
         col: 0,
         info,
-        kwds,
     }
 }
 
+//A lexer that starts at a byte offset in the file, with the line and column that lexing from the
+//start would have there
+fn resume<'a>(info: &'a crate::fileinfo::FileInfo, offset: usize) -> Lexer<'a> {
+    let before = &info.data[..offset];
+    //Like advance, which starts a line after both '\r' and '\n'
+    let line = before
+        .iter()
+        .filter(|c| **c == b'\n' || **c == b'\r')
+        .count();
+    let col = match before.iter().rposition(|c| *c == b'\n' || *c == b'\r') {
+        Some(idx) => offset - idx - 1,
+        None => offset,
+    };
+    Lexer {
+        idx: offset,
+        current: info.data.get(offset).copied().unwrap_or(b'\0'),
+        len: info.data.len(),
+        line,
+        col,
+        info,
+    }
+}
+
+//A change to a file: `removed` bytes at `start` were replaced by `inserted` bytes
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct Edit {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+//The tokens of a file after an edit, from its tokens before the edit. Only the tokens from the one
+//before the edit to the first line after it that lexes the same as before are lexed again; the
+//tokens after that are moved by the edit. info is the file after the edit.
+#[allow(dead_code)]
+pub fn relex(
+    info: &crate::fileinfo::FileInfo,
+    old: &[Token],
+    edit: Edit,
+) -> Result<Vec<Token>, ParseError> {
+    //A token before the edit may continue into it, so lexing starts again at the last token that
+    //starts before the edit
+    let first = old.partition_point(|tok| tok.offset < edit.start);
+    let restart = first.saturating_sub(1);
+    let offset = if first == 0 { 0 } else { old[restart].offset };
+    let mut tokens = old[..restart].to_vec();
+
+    let inserted_end = edit.start + edit.inserted;
+    let removed_end = edit.start + edit.removed;
+    for tok in resume(info, offset) {
+        let tok = tok?;
+        //Tokens at the start of a line after the edit that match the old ones keep their columns,
+        //so the rest of the old tokens only move by lines and bytes
+        let at_line_start = !matches!(tokens.last(), Some(prev) if prev.tp != TokenType::Newline);
+        if tok.offset >= inserted_end && at_line_start {
+            let old_offset = tok.offset - edit.inserted + edit.removed;
+            let idx = old.partition_point(|other| other.offset < old_offset);
+            if let Some(same) = old.get(idx).filter(|same| {
+                same.offset == old_offset
+                    && same.offset >= removed_end
+                    && same.tp == tok.tp
+                    && same.data == tok.data
+                    && same.startcol == tok.startcol
+            }) {
+                let lines = tok.line as isize - same.line as isize;
+                tokens.extend(old[idx..].iter().map(|other| Token {
+                    line: (other.line as isize + lines) as usize,
+                    offset: other.offset - edit.removed + edit.inserted,
+                    ..other.clone()
+                }));
+                return Ok(tokens);
+            }
+        }
+        tokens.push(tok);
+    }
+    Ok(tokens)
+}
+
 fn advance(lexer: &mut Lexer) {
     lexer.idx += 1;
 
@@ -176,7 +320,14 @@ fn advance(lexer: &mut Lexer) {
         lexer.col = 0;
     }
 
-    lexer.current = lexer.info.data[lexer.idx];
+    lexer.current = lexer.info.data.get(lexer.idx).copied().unwrap_or(b'\0');
+}
+
+fn peek(lexer: &Lexer) -> u8 {
+    match lexer.info.data.get(lexer.idx + 1) {
+        Some(v) => *v,
+        None => b'\0',
+    }
 }
 
 #[allow(dead_code)]
@@ -185,7 +336,10 @@ pub fn print_tokens(lexer: Lexer) {
     println!("------------------------");
     let mut idx: usize = 1;
     for tok in lexer.into_iter() {
-        println!("{} | {} {}", idx, tok, tok.line);
+        match tok {
+            Ok(tok) => println!("{} | {} {}", idx, tok, tok.line),
+            Err(err) => println!("{} | error: {}", idx, err.message),
+        }
         idx += 1;
     }
     println!("========================");
@@ -198,42 +352,132 @@ pub fn add_char_token(lexer: &mut Lexer, val: char, tp: TokenType) -> Token {
         line: lexer.line,
         startcol: lexer.col,
         endcol: lexer.col + 1,
+        offset: lexer.idx,
+    };
+    advance(lexer);
+
+    res
+}
+
+pub fn add_double_char_token(lexer: &mut Lexer, val: &str, tp: TokenType) -> Token {
+    let res = Token {
+        data: String::from(val),
+        tp,
+        line: lexer.line,
+        startcol: lexer.col,
+        endcol: lexer.col + 2,
+        offset: lexer.idx,
     };
     advance(lexer);
+    advance(lexer);
 
     res
 }
 
-fn make_decimal(lexer: &mut Lexer) -> Token {
+//A number: digits, then an optional fraction and exponent, as in 1_000.5e-3. Underscores may only
+//separate two digits, and there is at most one '.', which must be followed by a digit.
+fn make_decimal(lexer: &mut Lexer) -> Result<Token, ParseError> {
     let mut data = String::from("");
     let start = lexer.col;
-
+    let offset = lexer.idx;
+    let line = lexer.line;
     let mut end = lexer.col;
-    let mut line = lexer.line;
 
-    while (lexer.current as char).is_numeric() || lexer.current == b'_' {
+    make_digits(lexer, &mut data, &mut end)?;
+    if lexer.current == b'.' {
+        data.push('.');
+        end = lexer.col;
+        advance(lexer);
+        if !lexer.current.is_ascii_digit() {
+            return Err(malformed_number(
+                format!("Malformed number '{data}', expected a digit after '.'."),
+                lexer.col,
+                line,
+            ));
+        }
+        make_digits(lexer, &mut data, &mut end)?;
+    }
+
+    //Scientific notation, with an optional sign: 1e9, 2.5e-3
+    if lexer.current == b'e' || lexer.current == b'E' {
+        let exponent = lexer.col;
         data.push(lexer.current as char);
         end = lexer.col;
-        line = lexer.line;
         advance(lexer);
-        if lexer.current == b'.' {
+        if lexer.current == b'+' || lexer.current == b'-' {
             data.push(lexer.current as char);
+            end = lexer.col;
             advance(lexer);
         }
+        if !lexer.current.is_ascii_digit() {
+            return Err(ParseError {
+                message: format!("Malformed exponent in '{data}', expected a digit."),
+                tp: ErrorType::MalformedExponent,
+                pos: Position {
+                    startcol: exponent,
+                    endcol: end + 1,
+                    line,
+                },
+            });
+        }
+        make_digits(lexer, &mut data, &mut end)?;
     }
 
-    Token {
+    //Like 1.2.3, or 1e5.3
+    if lexer.current == b'.' {
+        return Err(malformed_number(
+            format!(
+                "Malformed number '{data}.', a number may only have one '.', before its exponent."
+            ),
+            lexer.col,
+            line,
+        ));
+    }
+
+    Ok(Token {
         data,
         tp: TokenType::Decimal,
         line,
         startcol: start,
         endcol: end + 1,
+        offset,
+    })
+}
+
+//Read digits from the current one, which are separated by single underscores
+fn make_digits(lexer: &mut Lexer, data: &mut String, end: &mut usize) -> Result<(), ParseError> {
+    while lexer.current.is_ascii_digit() || lexer.current == b'_' {
+        if lexer.current == b'_' && !peek(lexer).is_ascii_digit() {
+            return Err(malformed_number(
+                format!("Malformed number '{data}_', an underscore must be between two digits."),
+                lexer.col,
+                lexer.line,
+            ));
+        }
+        data.push(lexer.current as char);
+        *end = lexer.col;
+        advance(lexer);
+    }
+    Ok(())
+}
+
+//An error at the character of a number that makes it malformed
+fn malformed_number(message: String, col: usize, line: usize) -> ParseError {
+    ParseError {
+        message,
+        tp: ErrorType::MalformedNumber,
+        pos: Position {
+            startcol: col,
+            endcol: col + 1,
+            line,
+        },
     }
 }
 
 fn make_identifier(lexer: &mut Lexer) -> Token {
     let mut data = String::from("");
     let start = lexer.col;
+    let offset = lexer.idx;
 
     let mut end = lexer.col;
     let mut line = lexer.line;
@@ -244,13 +488,14 @@ fn make_identifier(lexer: &mut Lexer) -> Token {
         advance(lexer);
     }
 
-    if lexer.kwds.contains(&data) {
+    if KEYWORDS.contains(&data.as_str()) {
         return Token {
             data,
             tp: TokenType::Keyword,
             line,
             startcol: start,
             endcol: end + 1,
+            offset,
         };
     }
 
@@ -260,34 +505,71 @@ fn make_identifier(lexer: &mut Lexer) -> Token {
         line,
         startcol: start,
         endcol: end + 1,
+        offset,
     }
 }
 
-fn make_string(lexer: &mut Lexer) -> Token {
+//An identifier between backticks, which may be a keyword
+fn make_escaped_identifier(lexer: &mut Lexer) -> Result<Token, ParseError> {
     let mut data = String::from("");
     let start = lexer.col;
+    let offset = lexer.idx;
+    let line = lexer.line;
+    advance(lexer);
+
+    while (lexer.current as char).is_alphanumeric() || lexer.current == b'_' {
+        data.push(lexer.current as char);
+        advance(lexer);
+    }
+    let end = lexer.col;
+    if lexer.current != b'`' || data.is_empty() || data.as_bytes()[0].is_ascii_digit() {
+        return Err(ParseError {
+            message: String::from(
+                "Invalid escaped identifier, expected an identifier between backticks.",
+            ),
+            tp: ErrorType::InvalidEscapedIdentifier,
+            pos: Position {
+                startcol: start,
+                endcol: end + 1,
+                line,
+            },
+        });
+    }
+    advance(lexer);
+
+    Ok(Token {
+        data,
+        tp: TokenType::Identifier,
+        line,
+        startcol: start,
+        endcol: end + 1,
+        offset,
+    })
+}
+
+fn make_string(lexer: &mut Lexer) -> Token {
+    let mut data = Vec::new();
+    let start = lexer.col;
+    let offset = lexer.idx;
 
     let mut end = lexer.col;
     let mut line = lexer.line;
     advance(lexer);
 
-    while (lexer.current as char).is_alphanumeric() && (lexer.current as char) != '"' {
-        data.push(lexer.current as char);
+    while (lexer.current as char) != '"' && lexer.current != b'\0' {
+        data.push(lexer.current);
         end = lexer.col;
         line = lexer.line;
         advance(lexer);
-        if lexer.current == b'.' {
-            data.push(lexer.current as char);
-            advance(lexer);
-        }
     }
     advance(lexer);
 
     Token {
-        data,
+        data: String::from_utf8_lossy(&data).to_string(),
         tp: TokenType::String,
         line,
         startcol: start,
         endcol: end + 2,
+        offset,
     }
 }
@@ -1,7 +1,8 @@
-use clap::Parser;
+use crate::rc::Trc;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
+use std::path::PathBuf;
 use std::time::Instant;
-use trc::Trc;
 extern crate num;
 #[macro_use]
 extern crate num_derive;
@@ -13,6 +14,7 @@ mod lexer;
 
 mod parser;
 
+mod diagnostics;
 mod errors;
 
 #[macro_use]
@@ -20,151 +22,596 @@ mod objects;
 
 mod compiler;
 
+mod bench;
+mod builtins;
+mod cache;
+use builtins::LogLevel;
+use diagnostics::ColorMode;
+use errors::{ExitCode, OrRaise};
+use objects::intobject::IntOverflow;
 mod interpreter;
+#[cfg(feature = "pool")]
+mod pool;
+mod rc;
 mod stats;
+mod value;
+mod watch;
 
 pub struct TimeitHolder {
     baseline: u128,
     time: f64,
+    iterations: usize, //Runs of the program that completed
+    groups: usize,     //Groups of 5 runs that each sample times
 }
 
-fn run_file(file: &String, time: Option<i32>) {
-    let res = std::fs::read_to_string(file);
-    let file_data = match res {
-        Ok(v) => v,
-        Err(_) => {
-            println!("File '{}' is unable to be opened or read.", file);
-            return;
-        }
-    };
+//How a program is run, from the command line flags
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    pub time: Option<i32>,
+    pub step_limit: Option<usize>, //The most instructions that each timed run may execute
+    pub json: bool,                //Print the times as JSON
+    pub stats: bool,
+    pub optimize: bool,
+    pub deterministic: bool,
+    pub count_instructions: bool,
+    pub dump_consts: bool,
+    pub dump_ast: bool,
+    pub log_level: LogLevel,
+    pub int_overflow: IntOverflow,
+    pub repr_limit: Option<usize>, //The most items that the repr of a list or dict shows, None for all
+    pub int_cache: Option<isize>,  //The largest cached int, if the cache is resized
+    pub color: ColorMode,
+    pub warn_unused: bool, //Report the variables and functions that are never used
+    pub strict: bool,      //Make redefining a function or class an error
+    pub cache_dir: Option<PathBuf>, //Where compiled programs are cached, if they are
+    pub trace: Option<String>, //Print the instructions as they run, only in the functions with this name if it is not empty
+}
 
-    run_data(file_data, file.clone(), time);
+//Read files and run them in order, and return the exit status that merlin should have if the
+//programs ran to their end
+fn run_files(files: &[String], options: &RunOptions, args: Vec<String>) -> ExitCode {
+    let mut sources = Vec::new();
+    for file in files {
+        match std::fs::read_to_string(file) {
+            Ok(data) => sources.push((data, file.clone())),
+            Err(_) => {
+                println!("File '{}' is unable to be opened or read.", file);
+                return ExitCode::Usage;
+            }
+        }
+    }
+    run_data(&sources, options, args)
 }
 
-fn run_data(file_data: String, name: String, time: Option<i32>) {
-    let file_data_bytes = file_data.as_bytes();
+//Run a file, and return the exit status that merlin should have if the program ran to its end
+#[cfg(test)]
+fn run_file(file: &String, options: &RunOptions, args: Vec<String>) -> ExitCode {
+    run_files(std::slice::from_ref(file), options, args)
+}
 
-    let file_info = FileInfo {
-        data: file_data_bytes,
-        name,
+//Run a program that is not read from a file, under a name that is shown in its errors. It is not
+//cached, as the cache finds programs by their path.
+fn run_source(source: String, name: String, options: &RunOptions, args: Vec<String>) -> ExitCode {
+    let options = RunOptions {
+        cache_dir: None,
+        ..options.clone()
     };
+    run_data(&[(source, name)], &options, args)
+}
 
-    let keywords = vec![
-        String::from("fn"),
-        String::from("return"),
-        String::from("class"),
-    ];
-    let lexer = lexer::new(file_data_bytes, &file_info, keywords);
-
+fn parse(file_info: &FileInfo, out: &diagnostics::Diagnostics) -> Vec<parser::nodes::Node> {
     if cfg!(debug_assertions) {
-        lexer::print_tokens(lexer.to_owned());
+        lexer::print_tokens(lexer::new(file_info.data, file_info));
     }
 
     if cfg!(debug_assertions) {
         println!("\n===== Running parser =====");
     }
-    let ast = parser::new(lexer, &file_info).generate_ast();
+    let ast = parser::parse_safe(file_info).or_raise(file_info, out);
     if cfg!(debug_assertions) {
         println!("===== Done with parsing =====");
     }
+    ast
+}
 
-    let mut vm = Trc::new(interpreter::VM::new(file_info.clone()));
+//Create a VM with the builtin types, functions and modules
+fn new_vm<'a>(file_info: FileInfo<'a>, args: Vec<String>) -> Trc<interpreter::VM<'a>> {
+    let vm = Trc::new(interpreter::VM::new(file_info, args));
     objects::init_types(vm.clone());
     interpreter::VM::init_cache(vm.clone());
+    objects::init_methods(vm.clone());
+    builtins::init_builtins(vm.clone());
+    vm
+}
 
-    if cfg!(debug_assertions) {
-        println!("\n===== Running compiler =====");
+//A time in nanoseconds, in the largest unit that it is at least one of
+fn format_time(ns: f64) -> String {
+    if ns >= 1e9 {
+        format!("{:.3} s", ns / 1e9)
+    } else if ns >= 1e6 {
+        format!("{:.3} ms", ns / 1e6)
+    } else if ns >= 1e3 {
+        format!("{:.3} µs", ns / 1e3)
+    } else {
+        format!("{:.3} ns", ns)
+    }
+}
+
+//Print the times of the timed executions, in nanoseconds, and how many times the program ran
+fn print_timeit(times: &[f64], iterations: usize, json: bool) {
+    let best = times.iter().copied().fold(f64::MAX, f64::min);
+    let mean = stats::mean(times);
+    let std_dev = stats::std_dev(times);
+    if json {
+        println!(
+            "{{\"best_ns\": {:.3}, \"mean_ns\": {:.3}, \"std_dev_ns\": {:.3}, \"samples\": {}, \"iterations\": {}}}",
+            best,
+            mean,
+            std_dev,
+            times.len(),
+            iterations
+        );
+        return;
+    }
+    let rows = [
+        ("Best", format_time(best)),
+        (
+            "Mean",
+            format!("{} ± {}", format_time(mean), format_time(std_dev)),
+        ),
+        ("Samples", format!("{} ({} runs)", times.len(), iterations)),
+    ];
+    for (label, value) in rows {
+        println!(
+            "{} {}",
+            format!("{:<8}", format!("{label}:")).bold(),
+            value.green()
+        );
+    }
+}
+
+//Run programs, given by their source and name, and return the exit status that merlin should
+//have. A program that stops early unwinds to here, so that the VM and the objects of the programs
+//are dropped before it returns.
+fn run_data(sources: &[(String, String)], options: &RunOptions, args: Vec<String>) -> ExitCode {
+    catch_exit(|| run_programs(sources, options, args)).unwrap_or_else(|code| code)
+}
+
+//Call f, and return the status of a program that stops early in it as the error
+fn catch_exit<T>(f: impl FnOnce() -> T) -> Result<T, ExitCode> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(res) => Ok(res),
+        Err(payload) => match payload.downcast::<errors::Exit>() {
+            Ok(exit) => Err(exit.0),
+            Err(payload) => std::panic::resume_unwind(payload),
+        },
     }
+}
 
-    let mut compiler = compiler::Compiler::new(&file_info, vm.clone());
-    let bytecode = compiler.generate_bytecode(&ast);
+//Run programs one after another in one VM, so that the types, builtins and caches are only created
+//once. Each program runs in a namespace of its own, as if it was run alone, and the first one that
+//fails stops the others. Only the first program is the main one, whose main function is called.
+fn run_programs(sources: &[(String, String)], options: &RunOptions, args: Vec<String>) -> ExitCode {
+    let file_infos = sources
+        .iter()
+        .map(|(data, name)| FileInfo {
+            data: data.as_bytes(),
+            name: name.clone(),
+        })
+        .collect::<Vec<_>>();
 
+    let mut vm = new_run_vm(file_infos[0].clone(), options, args);
+    for (idx, file_info) in file_infos.iter().enumerate() {
+        vm.info = file_info.clone();
+        vm.is_main = idx == 0;
+        match run_program(vm.clone(), file_info, options) {
+            ExitCode::Success => (),
+            code => return code,
+        }
+    }
+    if options.dump_ast || options.dump_consts {
+        return ExitCode::Success;
+    }
+    vm.print_stats();
     if cfg!(debug_assertions) {
-        println!("{:?}", &bytecode.instructions);
-        for c in &bytecode.consts {
+        println!("\n===== Done with interpreter =====");
+    }
+    ExitCode::Success
+}
+
+//Create a VM for running programs, set up by the options
+fn new_run_vm<'a>(
+    file_info: FileInfo<'a>,
+    options: &RunOptions,
+    args: Vec<String>,
+) -> Trc<interpreter::VM<'a>> {
+    let mut vm = new_vm(file_info, args);
+    if let Some(max) = options.int_cache {
+        interpreter::VM::resize_int_cache(vm.clone(), max);
+    }
+    //Only count the objects allocated by the program, not the preallocated ones
+    if options.stats {
+        vm.alloc_stats = Some(hashbrown::HashMap::new());
+        vm.int_cache_stats = Some((0, 0));
+    }
+    vm.deterministic = options.deterministic;
+    vm.log_level = options.log_level;
+    vm.int_overflow = options.int_overflow;
+    vm.repr_limit = options.repr_limit;
+    vm.diagnostics = diagnostics::Diagnostics::new(diagnostics::Sink::Stdout, options.color);
+    vm.is_main = true;
+    if options.count_instructions {
+        vm.instruction_counts = Some(hashbrown::HashMap::new());
+    }
+    if let Some(function) = &options.trace {
+        vm.trace = Some(interpreter::trace::Trace::new(
+            Some(function.clone()).filter(|function| !function.is_empty()),
+        ));
+    }
+    vm
+}
+
+fn run_program<'a>(
+    vm: Trc<interpreter::VM<'a>>,
+    file_info: &'a FileInfo<'a>,
+    options: &RunOptions,
+) -> ExitCode {
+    if options.dump_ast {
+        let ast = parse(file_info, &vm.diagnostics);
+        println!("{}", parser::nodes::json_array(&ast, 0));
+        return ExitCode::Success;
+    }
+
+    let bytecode = compile_program(vm.clone(), file_info, options);
+
+    if options.dump_consts {
+        println!("Constants:");
+        for entry in bytecode.const_table() {
             println!(
-                "{} = 0x{:x}",
-                objects::RawObject::object_repr(c),
-                Trc::as_ptr(c) as usize
+                "{}{}: {} {}",
+                "    ".repeat(entry.depth + 1),
+                entry.idx,
+                entry.typename,
+                entry.repr
             );
         }
-        println!("===== Done with compiler =====");
+        return ExitCode::Success;
     }
 
     if cfg!(debug_assertions) {
         println!("\n===== Running interpreter =====");
     }
 
-    if let Some(n_exec) = time {
-        let mut min = f64::MAX;
-        let mut baseline = u128::MAX;
-        for _ in 0..1000 {
-            let start = Instant::now();
-            let delta = start.elapsed().as_nanos();
-            if delta < baseline && delta > 0 {
-                baseline = delta;
+    if let Some(n_exec) = options.time {
+        match time_bytecode(vm.clone(), &bytecode, n_exec, options.step_limit) {
+            Ok(timings) => print_timeit(&timings.times, timings.iterations, options.json),
+            Err(iterations) => {
+                println!(
+                    "{}",
+                    format!("Errored after {} iterations.", iterations).red()
+                );
+                vm.print_stats();
+                return ExitCode::Runtime;
+            }
+        }
+    } else {
+        interpreter::VM::execute_with_namespace(
+            vm.clone(),
+            &bytecode,
+            interpreter::Namespace::new(),
+        );
+        if vm.is_main {
+            interpreter::VM::run_main(vm.clone());
+        }
+    }
+    ExitCode::Success
+}
+
+//Compile a program, or load it from the cache if it is cached
+fn compile_program<'a>(
+    vm: Trc<interpreter::VM<'a>>,
+    file_info: &'a FileInfo<'a>,
+    options: &RunOptions,
+) -> Trc<compiler::Bytecode<'a>> {
+    //Unused names and redefinitions with --strict are found while compiling, so a program is
+    //compiled again to report them
+    let cached = options
+        .cache_dir
+        .as_ref()
+        .filter(|_| !options.warn_unused && !options.strict)
+        .and_then(|dir| cache::load(dir, file_info, options.optimize, vm.clone()));
+    let bytecode = match cached {
+        Some((bytecode, warnings)) => {
+            for warning in warnings {
+                vm.diagnostics
+                    .warn(&warning.header, file_info, &warning.start, &warning.end);
             }
+            bytecode
         }
+        None => {
+            //The warnings are stored with the bytecode, so that the runs that load it print them
+            if options.cache_dir.is_some() {
+                vm.diagnostics.record_warnings();
+            }
+            let ast = parse(file_info, &vm.diagnostics);
 
-        let interpreter = interpreter::Interpreter::new(vm.clone().namespaces.clone(), vm.clone());
+            let mut resolver = compiler::resolver::Resolver::new(
+                file_info,
+                vm.builtins.keys().cloned().collect(),
+                &vm.diagnostics,
+            );
+            resolver.warn_unused = options.warn_unused;
+            resolver.strict = options.strict;
+            resolver.resolve(&ast);
 
-        vm.interpreters.push(Trc::new(interpreter));
+            if cfg!(debug_assertions) {
+                println!("\n===== Running compiler =====");
+            }
 
-        let mut means = Vec::new();
-        for _ in 0..n_exec {
-            let mut holder = TimeitHolder { baseline, time: 0. };
-            interpreter::VM::execute_timeit(vm.clone(), &bytecode, &mut holder);
-            let time = holder.time;
-            if time < min && time >= 0. {
-                min = time;
+            let mut compiler = compiler::Compiler::new(file_info, vm.clone(), options.optimize);
+            let bytecode = compiler.generate_bytecode(&ast);
+            if let Some(dir) = &options.cache_dir {
+                cache::store(
+                    dir,
+                    &file_info.name,
+                    file_info.data,
+                    options.optimize,
+                    &bytecode,
+                    &vm.diagnostics.take_warnings(),
+                    &vm,
+                );
             }
-            means.push(time);
+            bytecode
         }
-        println!("Best execution time: {:.3} ns.", min);
-        println!("Best execution time: {:.3} µs.", min / 1000.0);
-        println!("Best execution time: {:.3} ms.", min / 1000000.0);
+    };
 
-        println!();
+    if cfg!(debug_assertions) {
+        print!("{}", bytecode.dump());
+        println!("===== Done with compiler =====");
+    }
+    bytecode
+}
 
-        let mean = {
-            let mut sum = 0.0;
-            for mean in &means {
-                sum += mean
-            }
-            sum
-        } / means.len() as f64;
-        println!("Mean execution time: {:.3} ns.", mean);
-        println!("Mean execution time: {:.3} µs.", mean / 1000.0);
-        println!("Mean execution time: {:.3} ms.", mean / 1000000.0);
-    } else {
-        interpreter::VM::execute(vm, &bytecode);
+//The times of the timed executions of a program, in nanoseconds, and how many times it ran
+pub struct Timings {
+    pub times: Vec<f64>,
+    pub iterations: usize,
+}
+
+//The most groups of 5 runs that a sample of --time times
+const SAMPLE_GROUPS: usize = 50;
+//About the most runs that --time makes over all of its samples. A run does not free all of the
+//objects that it allocates, so with more samples each one times fewer runs, instead of the memory
+//of the program growing with each sample.
+const MAX_TIMED_RUNS: usize = 300;
+
+//Time the bytecode n_exec times, as --time does. If a run raises, the number of runs that
+//completed is returned instead.
+fn time_bytecode<'a>(
+    mut vm: Trc<interpreter::VM<'a>>,
+    bytecode: &compiler::Bytecode<'a>,
+    n_exec: i32,
+    step_limit: Option<usize>,
+) -> Result<Timings, usize> {
+    let mut baseline = u128::MAX;
+    for _ in 0..1000 {
+        let start = Instant::now();
+        let delta = start.elapsed().as_nanos();
+        if delta < baseline && delta > 0 {
+            baseline = delta;
+        }
     }
-    if cfg!(debug_assertions) {
-        println!("\n===== Done with interpreter =====");
+
+    let interpreter = interpreter::Interpreter::new(vm.clone().namespaces.clone(), vm.clone());
+    vm.step_limit = step_limit;
+
+    vm.interpreters.push(Trc::new(interpreter));
+
+    let mut means = Vec::new();
+    let mut holder = TimeitHolder {
+        baseline,
+        time: 0.,
+        iterations: 0,
+        groups: (MAX_TIMED_RUNS / (5 * n_exec.max(1) as usize)).clamp(1, SAMPLE_GROUPS),
+    };
+    for _ in 0..n_exec {
+        if interpreter::VM::execute_timeit(vm.clone(), bytecode, &mut holder).is_none() {
+            return Err(holder.iterations);
+        }
+        means.push(holder.time);
+    }
+    Ok(Timings {
+        times: means,
+        iterations: holder.iterations,
+    })
+}
+
+//Time a program, given by its source and name, in a VM of its own. The error is the exit status
+//that merlin would have with --time if the program does not compile or raises.
+pub fn time_source(
+    source: &str,
+    name: &str,
+    n_exec: i32,
+    options: &RunOptions,
+) -> Result<Timings, ExitCode> {
+    catch_exit(|| {
+        let file_info = FileInfo {
+            data: source.as_bytes(),
+            name: String::from(name),
+        };
+        let vm = new_run_vm(file_info.clone(), options, Vec::new());
+        let bytecode = compile_program(vm.clone(), &file_info, options);
+        time_bytecode(vm, &bytecode, n_exec, options.step_limit).map_err(|_| ExitCode::Runtime)
+    })
+    .unwrap_or_else(Err)
+}
+
+//Split the file and the arguments after it into the files to run and the arguments of the
+//programs. The .me files after the file are run after it, and the arguments after them are passed
+//to every program. clap only takes a `--` that comes right after the file, so the first `--`
+//among the other arguments is removed here.
+fn split_files(
+    file: String,
+    mut args: Vec<String>,
+    program_args: Vec<String>,
+) -> (Vec<String>, Vec<String>) {
+    let n_files = args.iter().take_while(|arg| arg.ends_with(".me")).count();
+    let files = std::iter::once(file)
+        .chain(args.drain(..n_files))
+        .collect::<Vec<_>>();
+    if let Some(idx) = args.iter().position(|arg| arg == "--") {
+        args.remove(idx);
     }
+    args.extend(program_args);
+    (files, args)
 }
 
 //Version: major.minor
 #[derive(Parser, Debug)]
-#[command(author, version = "1.3", about, long_about = None)]
+#[command(author, version = "1.3", about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    /// File to execute
-    #[arg(required = true, name = "file")]
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// File to execute, or `-` to read the program from stdin.
+    #[arg(required_unless_present = "eval", name = "file")]
+    file: Option<String>,
+
+    /// Run this program instead of a file, as in `merlin -e 'log.info(1 + 2)'`. The arguments after it are passed to the program.
+    #[arg(long, short, name = "eval")]
+    eval: Option<String>,
+
+    /// More .me files, which are run after the file in the same VM, each in a namespace of its own, followed by the arguments passed to the programs, which they can read with env.args(). The arguments after `--` are always passed to the programs, even if they end in .me or are the name of a command.
+    #[arg(name = "args", allow_hyphen_values = true)]
+    args: Vec<String>,
+
+    #[arg(name = "program-args", last = true, hide = true)]
+    program_args: Vec<String>,
 
     /// Run the code n times to get the best execution time (this is the most accurate because all others are worse due to external factor).
-    /// No more tests are run if an error occurs.
+    /// No more tests are run if an error occurs, and the number of runs that completed is printed.
     #[arg(long, short, name = "time", default_value_t = 0)]
     time: i32,
 
+    /// With --time, stop a run that executes more than this many instructions, or that nests more than 1000 calls, and report it, so that a program that never ends does not stop the benchmark.
+    #[arg(long, name = "step-limit", requires = "time")]
+    step_limit: Option<usize>,
+
+    /// With --time, print the times as a JSON object, with the best and mean time and standard deviation in nanoseconds, and the number of samples and runs.
+    #[arg(long, name = "json", requires = "time")]
+    json: bool,
+
+    /// Print the number of objects allocated for each type when the program exits.
+    #[arg(long, name = "stats", default_value_t = false)]
+    stats: bool,
+
+    /// Optimize the bytecode: inline calls to small functions.
+    #[arg(short = 'O', long, name = "optimize", default_value_t = false)]
+    optimize: bool,
+
+    /// Make runs reproducible: iterate over dicts in a fixed order and disable the optimizations that adapt to the running program, so that timings of different runs measure the same instructions.
+    #[arg(long, name = "deterministic", default_value_t = false)]
+    deterministic: bool,
+
+    /// Run the code once and print how many times each opcode was executed, and the total. Unlike the time, the counts are the same for every run.
+    #[arg(
+        long,
+        name = "count-instructions",
+        default_value_t = false,
+        conflicts_with = "time"
+    )]
+    count_instructions: bool,
+
+    /// Print the constant pool of the compiled program, with the types and reprs of the constants, instead of running it.
+    #[arg(long, name = "dump-consts", default_value_t = false)]
+    dump_consts: bool,
+
+    /// Print the syntax tree of the program as JSON, in the shape that src/parser/grammar.ebnf describes, instead of running it.
+    #[arg(long, name = "dump-ast", default_value_t = false)]
+    dump_ast: bool,
+
+    /// The minimum level of the messages that the log module prints: debug, info, warn or error. Defaults to the MERLIN_LOG environment variable, or info.
+    #[arg(long, name = "log-level", value_parser = ["debug", "info", "warn", "error"])]
+    log_level: Option<String>,
+
+    /// Cache compiled programs in this directory, so that running a program again skips compiling it if its source did not change. Defaults to the MERLIN_CACHE_DIR environment variable, and programs are not cached if neither is set.
+    #[arg(long, name = "cache-dir")]
+    cache_dir: Option<PathBuf>,
+
+    /// Whether errors are colored: always, never, or auto to color them only when they are written to a terminal.
+    #[arg(long, name = "color", default_value = "auto", value_parser = ["always", "never", "auto"])]
+    color: String,
+
+    /// Report mistakes that do not stop the program. `--warn unused` reports the variables that are assigned but never read, and the functions that are never used.
+    #[arg(long, name = "warn", value_parser = ["unused"])]
+    warn: Vec<String>,
+
+    /// Make binding the name of a function or class again in the same scope an error, instead of a warning.
+    #[arg(long, name = "strict", default_value_t = false)]
+    strict: bool,
+
+    /// What int arithmetic does when a result does not fit in an int: raise an OverflowExc, wrap around like a machine int, or promote the result to a bigint.
+    #[arg(long, name = "int-overflow", default_value = "raise", value_parser = ["raise", "wrap", "promote"])]
+    int_overflow: String,
+
+    /// Show only the first n items of a list or dict when printing it or its repr, followed by `...`, so that huge containers do not flood the output. 0 shows every item.
+    #[arg(long, name = "repr-limit", default_value_t = interpreter::REPR_LIMIT)]
+    repr_limit: usize,
+
+    /// Cache the ints from -5 to n instead of to 256, so that a program that counts to larger numbers allocates fewer ints. Every cached int is kept for as long as the program runs, so n is at most 1000000. --stats prints how many ints were found in the cache.
+    #[arg(long, name = "int-cache", value_parser = clap::value_parser!(i64).range(0..=interpreter::MAX_INT_CACHE_LIMIT as i64))]
+    int_cache: Option<i64>,
+
+    /// Print each instruction as it runs, with the values of the registers that it reads, below the source line that it runs for. `--trace=name` only traces the bodies of the functions with that name or qualified name, as in `--trace=Shape.area`.
+    #[arg(long, name = "trace", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    trace: Option<String>,
+
     /// Explain an error produced by the parser.
-    #[arg(long, short, name = "explain", default_value_t = -1)]
+    #[arg(long, name = "explain", default_value_t = -1)]
     explain: i32,
 }
 
-fn main() {
+//The commands that take the place of the file. The flags of merlin come before the command, and
+//apply to the programs that it runs.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a file, then run it again whenever it changes. The arguments after the file are passed to the program.
+    Watch {
+        file: String,
+
+        #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Time each .me file in a directory, and print a table of their times.
+    BenchSuite {
+        /// The directory of the programs.
+        #[arg(default_value = bench::BENCH_DIR)]
+        dir: PathBuf,
+    },
+}
+
+//Timed runs may nest interpreter::MAX_CALL_DEPTH calls, which takes more stack than the main thread
+//has in debug builds, so programs run on a thread with this much
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+//Call f on a thread with STACK_SIZE bytes of stack, and return its result
+fn on_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("Unable to start the thread")
+            .join()
+            .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+    })
+}
+
+fn main() -> std::process::ExitCode {
     let args = Args::parse();
+    on_large_stack(|| run(args))
+}
 
+fn run(args: Args) -> std::process::ExitCode {
     let time = match args.time {
         0 => None,
         v => Some(v),
@@ -181,37 +628,2900 @@ fn main() {
                         .bold()
                 );
                 println!("{}", errors::repr_err(tp).green());
-                return;
+                return ExitCode::Success.into();
             }
             None => {
                 println!(
                     "{}",
                     "Error number does not correspond to a valid error.".red()
                 );
-                return;
+                return ExitCode::Usage.into();
+            }
+        }
+    }
+
+    let log_level = match args.log_level.or_else(|| std::env::var("MERLIN_LOG").ok()) {
+        Some(name) => match LogLevel::from_name(&name) {
+            Some(level) => level,
+            None => {
+                println!(
+                    "{}",
+                    format!("Invalid log level '{name}', expected debug, info, warn or error")
+                        .red()
+                );
+                return ExitCode::Usage.into();
             }
+        },
+        None => LogLevel::Info,
+    };
+
+    let color = ColorMode::from_name(&args.color).expect("Invalid color mode");
+    //The other messages of the command line are colored the same way
+    match color {
+        ColorMode::Always => colored::control::set_override(true),
+        ColorMode::Never => colored::control::set_override(false),
+        ColorMode::Auto => (),
+    }
+
+    let int_overflow =
+        IntOverflow::from_name(&args.int_overflow).expect("Invalid int overflow mode");
+
+    let options = RunOptions {
+        time,
+        step_limit: args.step_limit,
+        json: args.json,
+        stats: args.stats,
+        optimize: args.optimize,
+        deterministic: args.deterministic,
+        count_instructions: args.count_instructions,
+        dump_consts: args.dump_consts,
+        dump_ast: args.dump_ast,
+        log_level,
+        int_overflow,
+        repr_limit: match args.repr_limit {
+            0 => None,
+            n => Some(n),
+        },
+        int_cache: args.int_cache.map(|max| max as isize),
+        color,
+        strict: args.strict,
+        warn_unused: args.warn.iter().any(|warning| warning == "unused"),
+        cache_dir: args
+            .cache_dir
+            .or_else(|| std::env::var_os("MERLIN_CACHE_DIR").map(PathBuf::from)),
+        trace: args.trace,
+    };
+    match args.command {
+        Some(Command::Watch { file, args }) => {
+            watch::watch(&file, &options, args);
+            return ExitCode::Success.into();
         }
+        Some(Command::BenchSuite { dir }) => {
+            return bench::bench_suite(&dir, &options, bench::time_in_child).into()
+        }
+        None => (),
     }
 
-    run_file(&args.file, time);
+    match (args.eval, args.file) {
+        //With -e, the file is the first argument of the program
+        (Some(source), file) => {
+            let program_args = file
+                .into_iter()
+                .chain(args.args)
+                .chain(args.program_args)
+                .collect();
+            run_source(source, String::from("<string>"), &options, program_args)
+        }
+        (None, Some(file)) if file == "-" => {
+            let mut source = String::new();
+            if std::io::Read::read_to_string(&mut std::io::stdin(), &mut source).is_err() {
+                println!("The program is unable to be read from stdin.");
+                return ExitCode::Usage.into();
+            }
+            let program_args = args.args.into_iter().chain(args.program_args).collect();
+            run_source(source, String::from("<stdin>"), &options, program_args)
+        }
+        (None, Some(file)) => {
+            let (files, program_args) = split_files(file, args.args, args.program_args);
+            if files.len() > 1 && options.time.is_some() {
+                println!("{}", "Only one file can be timed".red());
+                return ExitCode::Usage.into();
+            }
+            run_files(&files, &options, program_args)
+        }
+        (None, None) => unreachable!("clap requires a file without -e"),
+    }
+    .into()
 }
 
 #[cfg(test)]
 mod merlin_tests {
-    use crate::run_file;
+    use crate::rc::Trc;
+    use crate::{
+        compiler::{self, Bytecode},
+        diagnostics::{ColorMode, Diagnostics, Sink},
+        errors::ExitCode,
+        fileinfo::FileInfo,
+        interpreter::{self, Namespace, VM},
+        new_vm, parse, run_data, run_file, run_files, run_source, split_files,
+        value::{ConversionError, Value},
+        watch, RunOptions, TimeitHolder,
+    };
+
+    //The file of a test program. It is leaked, as the VM and the objects of the program borrow it
+    //for as long as they live.
+    fn program(source: &'static str) -> &'static FileInfo<'static> {
+        Box::leak(Box::new(FileInfo {
+            data: source.as_bytes(),
+            name: String::from("test"),
+        }))
+    }
+
+    //A VM for test programs, which tests may set up before running them. Its diagnostics are
+    //captured, so that tests can check what was reported.
+    fn test_vm() -> Trc<VM<'static>> {
+        let mut vm = new_vm(program("").clone(), Vec::new());
+        vm.diagnostics = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+        vm
+    }
+
+    //What a VM reported since it was last taken
+    fn diagnostics_of(vm: &Trc<VM<'static>>) -> String {
+        String::from_utf8(vm.diagnostics.take_buffer()).expect("Output is not utf8")
+    }
+
+    fn compile(vm: &Trc<VM<'static>>, source: &'static str) -> Trc<Bytecode<'static>> {
+        let info = program(source);
+        let ast = parse(info, &vm.diagnostics);
+        compiler::Compiler::new(info, vm.clone(), false).generate_bytecode(&ast)
+    }
+
+    //Run compiled top-level code, and return the namespace that it leaves
+    fn execute(vm: &Trc<VM<'static>>, bytecode: &Bytecode<'static>) -> Namespace<'static> {
+        VM::execute_with_namespace(vm.clone(), bytecode, Namespace::new()).1
+    }
+
+    //Compile and run a test program in a VM, which must not report anything
+    fn run(vm: &Trc<VM<'static>>, source: &'static str) -> Namespace<'static> {
+        let namespace = execute(vm, &compile(vm, source));
+        let output = diagnostics_of(vm);
+        assert!(output.is_empty(), "{source} reported:\n{output}");
+        namespace
+    }
+
+    //Compile and run a test program in a new VM
+    fn compile_and_run(source: &'static str) -> (Trc<VM<'static>>, Namespace<'static>) {
+        let vm = test_vm();
+        let namespace = run(&vm, source);
+        (vm, namespace)
+    }
+
+    //Run compiled code that raises, and return the error that it reports. It runs as a timed run,
+    //which stops at the error instead of exiting.
+    fn error_of(mut vm: Trc<VM<'static>>, bytecode: &Bytecode<'static>) -> String {
+        let interpreter = interpreter::Interpreter::new(vm.namespaces.clone(), vm.clone());
+        vm.interpreters.push(Trc::new(interpreter));
+        let mut holder = TimeitHolder {
+            baseline: 0,
+            time: 0.,
+            iterations: 0,
+            groups: 1,
+        };
+        assert!(VM::execute_timeit(vm.clone(), bytecode, &mut holder).is_none());
+        diagnostics_of(&vm)
+    }
+
+    //Run a test program that raises, and return the error that it reports
+    fn run_error(source: &'static str) -> String {
+        let vm = test_vm();
+        let bytecode = compile(&vm, source);
+        error_of(vm, &bytecode)
+    }
+
+    fn value(namespace: &Namespace<'_>, name: &str) -> Result<Value, ConversionError> {
+        Value::try_from(
+            namespace
+                .get(name)
+                .unwrap_or_else(|| panic!("{name} is not defined")),
+        )
+    }
 
     #[test]
     fn test_literals() {
-        run_file(&String::from("tests/literals.me"), None);
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/literals.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_decimals() {
+        let (_, namespace) = compile_and_run(
+            "million = 1_000_000\nfraction = 1_0.2_5\nexponent = 1_0.5E+1_0\nsmall = 2.5e-3\n",
+        );
+        assert_eq!(value(&namespace, "million"), Ok(Value::Int(1_000_000)));
+        assert_eq!(value(&namespace, "fraction"), Ok(Value::Float(10.25)));
+        assert_eq!(value(&namespace, "exponent"), Ok(Value::Float(10.5e10)));
+        assert_eq!(value(&namespace, "small"), Ok(Value::Float(2.5e-3)));
+
+        //A malformed number is a syntax error at the character that makes it malformed
+        let vm = test_vm();
+        let info = program("x = 1.2.3\n");
+        let status = crate::catch_exit(|| parse(info, &vm.diagnostics));
+        assert_eq!(status.err(), Some(ExitCode::Syntax));
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with(
+            "error[E019]: Malformed number '1.2.', a number may only have one '.', before its exponent.\ntest:1:8\n"
+        ), "{output}");
+    }
+
+    #[test]
+    fn test_run_source() {
+        //A program that is not read from a file is not cached
+        let dir = std::env::temp_dir().join(format!("merlin-run-source-{}", std::process::id()));
+        let options = RunOptions {
+            cache_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            run_source(
+                String::from("x = [1][1 - len(env.args())]\n"),
+                String::from("<string>"),
+                &options,
+                vec![String::from("a")],
+            ),
+            ExitCode::Success
+        );
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_cache_strict() {
+        //A redefinition is an error with --strict, even if the program was cached without it
+        let dir = std::env::temp_dir().join(format!("merlin-cache-strict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Directory not created");
+        let file = dir.join("redefine.me");
+        std::fs::write(
+            &file,
+            "fn f(a) {\n    return a\n}\nfn f(a) {\n    return a\n}\n",
+        )
+        .expect("File not written");
+        let file = file.to_string_lossy().into_owned();
+        let options = RunOptions {
+            cache_dir: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        assert_eq!(run_file(&file, &options, Vec::new()), ExitCode::Success);
+        assert!(dir.join("cache").exists());
+        let strict = RunOptions {
+            strict: true,
+            ..options
+        };
+        assert_eq!(run_file(&file, &strict, Vec::new()), ExitCode::Syntax);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cache_warnings() {
+        //A run that loads the program from the cache prints the warnings of compiling it
+        let dir =
+            std::env::temp_dir().join(format!("merlin-cache-warnings-{}", std::process::id()));
+        let info = FileInfo {
+            data: b"fn f(a) {\n    return a\n}\nfn f(a) {\n    return a\n}\nd = {1: 2, 1: 3}\n",
+            name: dir.join("warnings.me").to_string_lossy().into_owned(),
+        };
+        let options = RunOptions {
+            cache_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let compile = || {
+            let mut vm = new_vm(info.clone(), Vec::new());
+            vm.diagnostics = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+            crate::compile_program(vm.clone(), &info, &options);
+            String::from_utf8(vm.diagnostics.take_buffer()).expect("Output is not utf8")
+        };
+        let compiled = compile();
+        assert!(compiled.contains("warning[W003]"));
+        assert!(compiled.contains("warning[W005]"));
+        assert!(dir.exists());
+        assert_eq!(compile(), compiled);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cache_corrupted() {
+        use crate::cache;
+
+        //A cache file whose bytecode was damaged is compiled again instead of run
+        let dir =
+            std::env::temp_dir().join(format!("merlin-cache-corrupted-{}", std::process::id()));
+        let info = FileInfo {
+            data: b"x = 12346\n",
+            name: dir.join("corrupted.me").to_string_lossy().into_owned(),
+        };
+        let options = RunOptions {
+            cache_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let mut vm = new_vm(info.clone(), Vec::new());
+        vm.diagnostics = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+        crate::compile_program(vm.clone(), &info, &options);
+        assert!(vm.diagnostics.take_buffer().is_empty());
+        assert!(cache::load(&dir, &info, false, vm.clone()).is_some());
+
+        let path = std::fs::read_dir(&dir)
+            .expect("Cache directory not created")
+            .next()
+            .expect("Cache file not written")
+            .expect("Cache file not read")
+            .path();
+        let mut data = std::fs::read(&path).expect("Cache file not read");
+        let constant = 12346i64.to_le_bytes();
+        let idx = data
+            .windows(constant.len())
+            .position(|window| window == constant)
+            .expect("Constant not found in the cache file");
+        data[idx + 1] ^= 0x01;
+        std::fs::write(&path, data).expect("Cache file not written");
+        assert!(cache::load(&dir, &info, false, vm.clone()).is_none());
+
+        let bytecode = crate::compile_program(vm.clone(), &info, &options);
+        let namespace = interpreter::VM::execute_with_namespace(
+            vm.clone(),
+            &bytecode,
+            interpreter::Namespace::new(),
+        )
+        .1;
+        assert_eq!(value(&namespace, "x"), Ok(Value::Int(12346)));
+        //The damaged file was replaced
+        assert!(cache::load(&dir, &info, false, vm).is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_run_files() {
+        assert_eq!(
+            run_files(
+                &[
+                    String::from("src/tests/literals.me"),
+                    String::from("src/tests/operators.me"),
+                ],
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+
+        //Each program has its own namespace, and the first one that fails stops the others
+        let run = |sources: &[(&str, &str)]| {
+            let sources = sources
+                .iter()
+                .map(|(data, name)| (String::from(*data), String::from(*name)))
+                .collect::<Vec<_>>();
+            run_data(&sources, &RunOptions::default(), Vec::new())
+        };
+        assert_eq!(
+            run(&[("x = 1\n", "a"), ("x = 2\n", "b")]),
+            ExitCode::Success
+        );
+        assert_eq!(run(&[("x = 1\n", "a"), ("y = x\n", "b")]), ExitCode::Syntax);
+        //Only the first program is the main one
+        //Exits with the length of "true" or "false"
+        let exit_is_main = "env.exit(len(\"{}\".format(is_main())))\n";
+        assert_eq!(
+            run(&[(exit_is_main, "a"), ("x = 1\n", "b")]),
+            ExitCode::Exit(4)
+        );
+        assert_eq!(
+            run(&[("x = 1\n", "a"), (exit_is_main, "b")]),
+            ExitCode::Exit(5)
+        );
+        let main = "fn main() {\n    env.exit(7)\n}\n";
+        assert_eq!(run(&[(main, "a"), ("x = 1\n", "b")]), ExitCode::Exit(7));
+        assert_eq!(run(&[("x = 1\n", "a"), (main, "b")]), ExitCode::Success);
+        assert_eq!(
+            run(&[("x = 1 + \"a\"\n", "a"), ("env.exit(5)\n", "b")]),
+            ExitCode::Runtime
+        );
+    }
+
+    #[test]
+    fn test_exit_status() {
+        //A program that stops early returns its status instead of exiting the process
+        let run = |source: &str| {
+            run_source(
+                String::from(source),
+                String::from("<string>"),
+                &RunOptions::default(),
+                Vec::new(),
+            )
+        };
+        assert_eq!(run("x = 1 +\n"), ExitCode::Syntax);
+        assert_eq!(run("x = y\n"), ExitCode::Syntax);
+        assert_eq!(run("x = 1 + \"a\"\n"), ExitCode::Runtime);
+        assert_eq!(run("env.exit(5)\nx = 1 + \"a\"\n"), ExitCode::Exit(5));
+        assert_eq!(run("fn main() {\n    env.exit(0)\n}\n"), ExitCode::Exit(0));
+        assert_eq!(run("env.exit(255)\n"), ExitCode::Exit(255));
+        assert_eq!(run("env.exit(256)\n"), ExitCode::Runtime);
+        assert_eq!(run("env.exit(0 - 1)\n"), ExitCode::Runtime);
     }
 
     #[test]
     fn test_operators() {
-        run_file(&String::from("tests/operators.me"), None);
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/operators.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_consts() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/consts.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/scopes.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
     }
 
     #[test]
     fn test_functions() {
-        run_file(&String::from("tests/functions.me"), None);
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/functions.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_functions_optimized() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/functions.me"),
+                &RunOptions {
+                    optimize: true,
+                    ..Default::default()
+                },
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_nested_functions() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/nested_functions.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/builtins.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/classes.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_with() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/with.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_with_raises() {
+        use crate::value::MerlinError;
+
+        let (vm, _) = compile_and_run("class Resource {\n    fn init(self) {\n        self.exits = 0\n    }\n    fn enter(self) {\n        return 5\n    }\n    fn exit(self) {\n        self.exits = self.exits + 1\n    }\n}\nr = Resource()\nfn fail() {\n    with r as v {\n        x = v + \"a\"\n    }\n}\nfn exits() {\n    return r.exits\n}\n");
+
+        //exit runs when the body raises, and the exception is still the error
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert!(matches!(
+            call("fail"),
+            Err(MerlinError::Exception(exc)) if exc.starts_with("TypeMismatchExc")
+        ));
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with("TypeMismatchExc: \"Types do not match\"\ntest:15:13"));
+        assert_eq!(call("exits"), Ok(Value::Int(1)));
+        assert!(vm.contexts.is_empty());
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/comprehensions.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_comprehensions_deterministic() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/comprehensions.me"),
+                &RunOptions {
+                    deterministic: true,
+                    ..Default::default()
+                },
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            run_file(
+                &String::from("src/tests/strings.me"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    fn test_persistent_namespace() {
+        let first = FileInfo {
+            data: b"a = 1\nfn f(x) {\n    return x + a\n}\n",
+            name: String::from("first"),
+        };
+        let second = FileInfo {
+            data: b"b = f(2)\n",
+            name: String::from("second"),
+        };
+        let mut vm = new_vm(first.clone(), Vec::new());
+        vm.diagnostics = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+
+        let mut namespace = Namespace::new();
+        for info in [&first, &second] {
+            let ast = parse(info, &vm.diagnostics);
+            let bytecode =
+                compiler::Compiler::new_in_namespace(info, vm.clone(), false, &namespace)
+                    .generate_bytecode(&ast);
+            namespace = VM::execute_with_namespace(vm.clone(), &bytecode, namespace).1;
+        }
+
+        let b = namespace.get("b").expect("b is not defined");
+        assert_eq!(unsafe { b.internals.int() }, 3);
+        assert!(namespace.get("c").is_none());
+        assert!(vm.diagnostics.take_buffer().is_empty());
+    }
+
+    #[test]
+    fn test_error_in_second_file() {
+        let first = program("x = 1\n");
+        let second: &'static FileInfo<'static> = Box::leak(Box::new(FileInfo {
+            data: b"y = 2\nz = y + \"a\"\n",
+            name: String::from("second"),
+        }));
+        let mut vm = test_vm();
+
+        //Like merlin with several files, each one runs in the VM in turn
+        let res = crate::catch_exit(|| {
+            for info in [first, second] {
+                vm.info = info.clone();
+                assert_eq!(
+                    crate::run_program(vm.clone(), info, &RunOptions::default()),
+                    ExitCode::Success
+                );
+            }
+        });
+        assert_eq!(res.err(), Some(ExitCode::Runtime));
+        let output = diagnostics_of(&vm);
+        assert!(output.contains("\nsecond:2:5\n2 | z = y + \"a\"\n"));
+
+        //A function keeps the file that defined it when a later file calls it
+        let first = program("fn f(x) {\n    return x + \"a\"\n}\n");
+        let second: &'static FileInfo<'static> = Box::leak(Box::new(FileInfo {
+            data: b"y = 1\nz = f(y)\n",
+            name: String::from("second"),
+        }));
+        let res = crate::catch_exit(|| {
+            let mut namespace = Namespace::new();
+            for info in [first, second] {
+                let ast = parse(info, &vm.diagnostics);
+                let bytecode =
+                    compiler::Compiler::new_in_namespace(info, vm.clone(), false, &namespace)
+                        .generate_bytecode(&ast);
+                namespace = VM::execute_with_namespace(vm.clone(), &bytecode, namespace).1;
+            }
+        });
+        assert_eq!(res.err(), Some(ExitCode::Runtime));
+        let output = diagnostics_of(&vm);
+        assert!(output.contains("\ntest:2:12\n2 |     return x + \"a\"\n"));
+    }
+
+    #[test]
+    fn test_value_conversion() {
+        use crate::value::{ConversionError, Value};
+
+        let (vm, namespace) = compile_and_run(
+            "a = [1, 2.5, \"s\", 1 == 1, {\"k\": [3]}]\nfn f(x) {\n    return x\n}\n",
+        );
+
+        let a = value(&namespace, "a");
+        let expected = Value::List(vec![
+            Value::Int(1),
+            Value::Float(2.5),
+            Value::from("s"),
+            Value::Bool(true),
+            Value::Dict(vec![(Value::from("k"), Value::from(vec![3]))]),
+        ]);
+        assert_eq!(a, Ok(expected.clone()));
+        let object = expected.to_object(vm.clone()).expect("Value not converted");
+        assert_eq!(Value::try_from(object), Ok(expected));
+
+        assert_eq!(
+            value(&namespace, "f"),
+            Err(ConversionError::UnsupportedType(String::from("fn")))
+        );
+        let unhashable = Value::Dict(vec![(Value::from(vec![1]), Value::None)]);
+        assert_eq!(
+            unhashable.to_object(vm).map(|_| ()),
+            Err(ConversionError::UnhashableKey(String::from("list")))
+        );
+    }
+
+    #[test]
+    fn test_float_specials() {
+        let (_, namespace) = compile_and_run("big = 1e999\nnan = big - big\nneg = 0.0 - big\nsum = 0.1 + 0.2\ns = \"{} {} {} {}\".format(big, neg, nan, sum)\na = isnan(nan)\nb = isinf(nan)\nc = isinf(neg)\nd = isnan(1)\ne = isinf(2.5)\nflags = [a, b, c, d, e]\n");
+
+        assert_eq!(
+            value(&namespace, "s"),
+            Ok(Value::from("inf -inf nan 0.30000000000000004"))
+        );
+        assert_eq!(
+            value(&namespace, "flags"),
+            Ok(Value::List(
+                [true, false, true, false, false]
+                    .into_iter()
+                    .map(Value::Bool)
+                    .collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_len() {
+        let (_, namespace) = compile_and_run("class A {\n    fn len(self) {\n        return 7\n    }\n}\na = A()\nl = len([1, 2, 3])\nd = len({1: 2})\ns = len(\"héllo\")\ne = len(\"\")\nc = len(a)\nlens = [l, d, s, e, c]\n");
+        assert_eq!(
+            value(&namespace, "lens"),
+            Ok(Value::List(
+                [3, 1, 5, 0, 7].into_iter().map(Value::Int).collect()
+            ))
+        );
+
+        let output = run_error("x = len(1)\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Object of type 'int' has no len\""));
+    }
+
+    #[test]
+    fn test_string_order() {
+        let (_, namespace) = compile_and_run("ordered = [\"apple\" < \"banana\", \"b\" > \"abc\", \"app\" < \"apple\", \"\" < \"a\", \"Zebra\" < \"apple\", \"é\" > \"z\", \"pear\" <= \"pear\", \"pear\" >= \"pear\", \"a\" <= \"b\", \"b\" >= \"a\"]\nunordered = [\"banana\" < \"apple\", \"apple\" > \"apple\", \"apple\" < \"apple\", \"b\" <= \"a\", \"a\" >= \"b\", \"apple\" > \"app\" > \"b\"]\n");
+        let bools = |expected: bool, n: usize| Ok(Value::List(vec![Value::Bool(expected); n]));
+        //Strings are ordered by code point, so uppercase letters come before lowercase ones
+        assert_eq!(value(&namespace, "ordered"), bools(true, 10));
+        assert_eq!(value(&namespace, "unordered"), bools(false, 6));
+
+        for source in [
+            "x = \"a\" < 1\n",
+            "x = 1 < \"a\"\n",
+            "x = \"a\" >= 1.5\n",
+            "x = \"a\" > [\"a\"]\n",
+        ] {
+            let output = run_error(source);
+            assert!(
+                output.starts_with("TypeMismatchExc: \"Types do not match\""),
+                "{source}: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sorted() {
+        let (_, namespace) = compile_and_run("unordered = [5, 3, 9, 1, 3, 7, 0 - 2]\nascending = sorted(unordered)\nkept = [x for x in unordered]\nsort(unordered)\nwords = sorted([\"pear\", \"apple\", \"Zebra\", \"app\"])\nfn initial(w) {\n    return \"{:.1}\".format(w)\n}\nby_initial = sorted([\"bob\", \"apple\", \"banana\", \"avocado\", \"beet\"], initial)\ntagged = sorted([\"b1\", \"a1\", \"c1\", \"b2\", \"a2\", \"c2\", \"b3\", \"a3\", \"a4\"], initial)\nfn negated(x) {\n    return 0 - x\n}\ndescending = sorted([3, 1, 2], negated)\nno_items = sorted([])\n");
+        let ints = |values: &[i64]| {
+            Ok(Value::List(
+                values.iter().copied().map(Value::Int).collect(),
+            ))
+        };
+        let strs = |values: &[&str]| {
+            Ok(Value::List(
+                values.iter().map(|s| Value::from(*s)).collect(),
+            ))
+        };
+        assert_eq!(
+            value(&namespace, "ascending"),
+            ints(&[-2, 1, 3, 3, 5, 7, 9])
+        );
+        //sorted leaves the list as it was, and sort sorts it in place
+        assert_eq!(value(&namespace, "kept"), ints(&[5, 3, 9, 1, 3, 7, -2]));
+        assert_eq!(
+            value(&namespace, "unordered"),
+            ints(&[-2, 1, 3, 3, 5, 7, 9])
+        );
+        assert_eq!(
+            value(&namespace, "words"),
+            strs(&["Zebra", "app", "apple", "pear"])
+        );
+        assert_eq!(value(&namespace, "descending"), ints(&[3, 2, 1]));
+        assert_eq!(value(&namespace, "no_items"), ints(&[]));
+
+        //Items with equal keys keep their order, also across the merges of longer runs
+        assert_eq!(
+            value(&namespace, "by_initial"),
+            strs(&["apple", "avocado", "bob", "banana", "beet"])
+        );
+        assert_eq!(
+            value(&namespace, "tagged"),
+            strs(&["a1", "a2", "a3", "a4", "b1", "b2", "b3", "c1", "c2"])
+        );
+
+        let output = run_error("x = sorted([1, \"a\"])\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Cannot order \"a\" ('str') and 1 ('int')\""));
+        let output = run_error("class A {}\nx = sorted([A(), A()])\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Cannot order "));
+        let output = run_error("fn key(x) {\n    return x + \"a\"\n}\nx = sorted([1, 2], key)\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Types do not match\""));
+        let output = run_error("x = sorted()\n");
+        assert!(output.starts_with("ArgumentExc: \"Expected 1 or 2 argument(s), got 0\""));
+    }
+
+    #[test]
+    fn test_copy() {
+        let (_, namespace) = compile_and_run("class Box {\n    fn init(self, v) {\n        self.v = v\n    }\n}\ninner = [1, 2]\nb = Box(inner)\nb.w = inner\nouter = [inner, b, {\"k\": 3}]\nouter_ids = [id(x) for x in outer]\n\nshallow = copy(outer)\nshallow_shares = [id(x) in outer_ids for x in shallow]\nshallow_new = id(shallow) == id(outer)\nshallow_box = copy(b)\nbox_shares = [id(shallow_box) == id(b), id(shallow_box.v) == id(inner), id(shallow_box.w) == id(inner)]\n\ndeep = deepcopy(outer)\ndeep_shares = [id(x) in outer_ids for x in deep]\nnested = [inner, {\"k\": inner}, \"s\"]\ndeep_equal = deepcopy(nested) == nested\ndeep_box = deepcopy(b)\nbox_copies = [id(deep_box.v) == id(inner), id(deep_box.v) == id(deep_box.w), deep_box.v == [1, 2]]\n\nnode = Box(1)\nnode.next = node\nring = [node, node]\ncopied = deepcopy(ring)\ncopied_ids = [id(x) for x in copied]\ncopied_node = deepcopy(node)\ncycle = [id(copied_node) == id(node), id(copied_node.next) == id(copied_node), copied_node.v == 1]\nsame_node = copied_ids == [id(x.next) for x in copied]\nfresh_node = [id(x) == id(node) for x in copied]\n");
+        let bools = |expected: &[bool]| {
+            Ok(Value::List(
+                expected.iter().copied().map(Value::Bool).collect(),
+            ))
+        };
+        //copy makes a new container that shares the elements
+        assert_eq!(
+            value(&namespace, "shallow_shares"),
+            bools(&[true, true, true])
+        );
+        assert_eq!(value(&namespace, "shallow_new"), Ok(Value::Bool(false)));
+        assert_eq!(value(&namespace, "box_shares"), bools(&[false, true, true]));
+
+        //deepcopy copies the elements too, with the same values. Instances compare by identity, so
+        //the values are compared on a copy without them.
+        assert_eq!(
+            value(&namespace, "deep_shares"),
+            bools(&[false, false, false])
+        );
+        assert_eq!(value(&namespace, "deep_equal"), Ok(Value::Bool(true)));
+        //b holds inner twice, and its copy holds one copy of inner twice
+        assert_eq!(value(&namespace, "box_copies"), bools(&[false, true, true]));
+
+        //A cycle is copied into a cycle of the copies, and an object reached twice is copied once
+        assert_eq!(value(&namespace, "cycle"), bools(&[false, true, true]));
+        assert_eq!(value(&namespace, "same_node"), Ok(Value::Bool(true)));
+        assert_eq!(value(&namespace, "fresh_node"), bools(&[false, false]));
+
+        let output = run_error("x = copy(log)\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Cannot copy object of type 'module'\""));
+    }
+
+    #[test]
+    fn test_contains() {
+        let (_, namespace) = compile_and_run("class A {\n    fn contains(self, item) {\n        return item == 5\n    }\n}\na = A()\nl = [1, \"a\", [2]]\nd = {\"k\": 1}\nfound = [1 in l, \"b\" in l, [2] in l, \"k\" in d, 1 in d, \"ell\" in \"hello\", \"\" in \"\", 5 in a, 4 in a, 0 < 1 in l]\n");
+        assert_eq!(
+            value(&namespace, "found"),
+            Ok(Value::List(
+                [true, false, true, true, false, true, true, true, false, true]
+                    .into_iter()
+                    .map(Value::Bool)
+                    .collect()
+            ))
+        );
+
+        let output = run_error("x = 1 in \"abc\"\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Expected 'str' substring, got 'int'\""));
+    }
+
+    #[test]
+    fn test_filter() {
+        let (_, namespace) = compile_and_run("fn big(x) {\n    return x > 2\n}\nfn never(x) {\n    return 1 == 2\n}\nkept = filter(big, [1, 3, 2, 5])\nempty = filter(big, [])\nnone_kept = filter(never, [1, 2, 3])\n");
+        assert_eq!(
+            value(&namespace, "kept"),
+            Ok(Value::List(vec![Value::Int(3), Value::Int(5)]))
+        );
+        assert_eq!(value(&namespace, "empty"), Ok(Value::List(Vec::new())));
+        assert_eq!(value(&namespace, "none_kept"), Ok(Value::List(Vec::new())));
+
+        let output = run_error("fn one(x) {\n    return 1\n}\nx = filter(one, [1])\n");
+        assert!(output.starts_with(
+            "TypeMismatchExc: \"Function passed to 'filter' returned 'int', expected 'bool'\""
+        ));
+    }
+
+    #[test]
+    fn test_list_repetition() {
+        let (_, namespace) = compile_and_run("l = [1, 2] * 2\nempty = [1] * (0 - 3)\n");
+        assert_eq!(value(&namespace, "l"), Ok(Value::from(vec![1, 2, 1, 2])));
+        assert_eq!(value(&namespace, "empty"), Ok(Value::List(Vec::new())));
+
+        //A length that fits in usize may still be too large to allocate
+        let output = run_error("l = [1, 2] * 4611686018427387904\n");
+        assert!(output.starts_with("MemoryExc: \"list repetition is too large to allocate\""));
+        let output = run_error("l = [1, 2, 3] * 9223372036854775807\n");
+        assert!(output.starts_with("OverflowExc"));
+    }
+
+    #[test]
+    fn test_format() {
+        let (_, namespace) = compile_and_run("name = \"list\"\ns = \"{} has {} items\".format(name, 3)\nswapped = \"{1} {0} {1}\".format(\"a\", \"b\")\npadded = \"[{:>5}] [{:<5}] [{:^5}] [{:4}] [{:<3}]\".format(1, \"ab\", \"c\", \"x\", 7)\nwide = \"[{:2}] [{:>3}]\".format(\"abcdef\", \"é\")\ntruncated = \"{:.3}\".format(\"abcdef\")\nescaped = \"{{}} {} }}\".format([1, 2])\nunbound = str.format(\"{}\", 1)\nempty = \"plain\".format(1, 2)\n");
+        let expected = [
+            ("s", "list has 3 items"),
+            ("swapped", "b a b"),
+            ("padded", "[    1] [ab   ] [  c  ] [x   ] [7  ]"),
+            //The width does not truncate, and is counted in graphemes
+            ("wide", "[abcdef] [  é]"),
+            ("truncated", "abc"),
+            ("escaped", "{} [1, 2] }"),
+            ("unbound", "1"),
+            ("empty", "plain"),
+        ];
+        for (name, string) in expected {
+            assert_eq!(value(&namespace, name), Ok(Value::from(string)), "{name}");
+        }
+
+        let errors = [
+            (
+                "\"a } b\".format()",
+                "ValueExc: \"Single '}' encountered in format string\"",
+            ),
+            (
+                "\"a { b\".format()",
+                "ValueExc: \"Single '{' encountered in format string\"",
+            ),
+            (
+                "\"{} {0}\".format(1)",
+                "ValueExc: \"Cannot mix automatic and indexed placeholders in format string\"",
+            ),
+            (
+                "\"{a}\".format(1)",
+                "ValueExc: \"Invalid placeholder index 'a'\"",
+            ),
+            (
+                "\"{:x}\".format(1)",
+                "ValueExc: \"Invalid format specifier 'x'\"",
+            ),
+            (
+                "\"{} {}\".format(1)",
+                "IndexExc: \"Placeholder index '1' out of range for 1 argument(s)\"",
+            ),
+            (
+                "str.format(1)",
+                "TypeMismatchExc: \"Method 'format' must be called on a 'str'\"",
+            ),
+        ];
+        for (call, error) in errors {
+            let source: &'static str = Box::leak(format!("s = {call}\n").into_boxed_str());
+            let output = run_error(source);
+            assert!(output.starts_with(error), "{call}: {output}");
+        }
+    }
+
+    #[test]
+    fn test_format_precision() {
+        let (_, namespace) = compile_and_run(
+            "f = \"[{:5.2}]\".format(3.14159)\ni = \"{:.1}\".format(2)\ns = \"{:.2}\".format(\"abc\")\n",
+        );
+        assert_eq!(value(&namespace, "f"), Ok(Value::from("[ 3.14]")));
+        assert_eq!(value(&namespace, "i"), Ok(Value::from("2.0")));
+        assert_eq!(value(&namespace, "s"), Ok(Value::from("ab")));
+
+        let output = run_error("s = \"{:99999999}\".format(1)\n");
+        assert!(output.starts_with("ValueExc: \"Format width and precision may not exceed 65536\""));
+    }
+
+    #[test]
+    fn test_watch_rerun() {
+        let dir = std::env::temp_dir().join(format!("merlin-watch-rerun-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Unable to create the directory");
+        let file = dir.join("program.me");
+        let name = file.to_str().expect("The path is not utf8");
+        let options = RunOptions::default();
+        let vm = crate::new_run_vm(program("").clone(), &options, Vec::new());
+        let types = vm.types.clone();
+
+        //Each run sees the current version of the file, and a run that stops does not stop the next
+        for (source, code) in [
+            ("x = 1 + \"a\"\n", ExitCode::Runtime),
+            ("x = y\n", ExitCode::Syntax),
+            ("x = 1\ny = x + 1\n", ExitCode::Success),
+        ] {
+            std::fs::write(&file, source).expect("Unable to write the program");
+            assert_eq!(watch::run(vm.clone(), name, &options), code);
+        }
+        assert_eq!(value(&vm.globals, "y"), Ok(Value::Int(2)));
+        assert!(vm.interpreters.is_empty());
+
+        //Variables of an earlier run are gone, but the types are the same
+        std::fs::write(&file, "z = 3\n").expect("Unable to write the program");
+        assert_eq!(watch::run(vm.clone(), name, &options), ExitCode::Success);
+        assert!(vm.globals.get("y").is_none());
+        assert!(Trc::ptr_eq(&types, &vm.types));
+
+        std::fs::remove_dir_all(&dir).expect("Unable to remove the directory");
+        assert_eq!(watch::run(vm.clone(), name, &options), ExitCode::Usage);
+    }
+
+    #[test]
+    fn test_watch_debounce() {
+        use notify::{event::ModifyKind, Event, EventKind};
+
+        let dir =
+            std::env::temp_dir().join(format!("merlin-watch-debounce-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Unable to create the directory");
+        let file = dir.join("program.me");
+        let other = dir.join("other.me");
+        std::fs::write(&file, "").expect("Unable to write the program");
+        std::fs::write(&other, "").expect("Unable to write the program");
+        let file = file.canonicalize().expect("Unable to find the program");
+        let modify = |path: &std::path::Path| {
+            Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.to_path_buf()))
+        };
+
+        //The events of a change that is written in several steps, including those that come while
+        //waiting, run the program once
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(modify(&other)).expect("Unable to send the event");
+        tx.send(modify(&file)).expect("Unable to send the event");
+        let (sender, path) = (tx.clone(), other.clone());
+        let late = std::thread::spawn(move || {
+            std::thread::sleep(watch::DEBOUNCE / 2);
+            sender
+                .send(modify(&path))
+                .expect("Unable to send the event");
+        });
+        tx.send(modify(&file)).expect("Unable to send the event");
+        assert!(watch::next_change(&rx, &file));
+        late.join().expect("The sender panicked");
+        assert!(rx.try_recv().is_err());
+
+        //Changes to other files in the directory are ignored until the watcher stops
+        tx.send(modify(&other)).expect("Unable to send the event");
+        drop(tx);
+        assert!(!watch::next_change(&rx, &file));
+
+        std::fs::remove_dir_all(&dir).expect("Unable to remove the directory");
+    }
+
+    #[test]
+    fn test_subcommands() {
+        use crate::{Args, Command};
+        use clap::Parser;
+
+        let args = Args::try_parse_from(["merlin", "-t", "2", "bench-suite", "programs"])
+            .expect("Unable to parse the arguments");
+        assert_eq!(args.time, 2);
+        assert!(
+            matches!(args.command, Some(Command::BenchSuite { dir }) if dir.to_str() == Some("programs"))
+        );
+        let args =
+            Args::try_parse_from(["merlin", "bench-suite"]).expect("Unable to parse the arguments");
+        assert!(
+            matches!(args.command, Some(Command::BenchSuite { dir }) if dir.to_str() == Some(crate::bench::BENCH_DIR))
+        );
+
+        let args = Args::try_parse_from(["merlin", "-O", "watch", "program.me", "a", "-b"])
+            .expect("Unable to parse the arguments");
+        assert!(args.optimize);
+        assert!(
+            matches!(args.command, Some(Command::Watch { file, args }) if file == "program.me" && args == ["a", "-b"])
+        );
+        assert!(Args::try_parse_from(["merlin", "watch"]).is_err());
+
+        //An argument of the program with the name of a command goes after `--`
+        let args = Args::try_parse_from(["merlin", "program.me", "--", "watch"])
+            .expect("Unable to parse the arguments");
+        assert!(args.command.is_none());
+        assert_eq!(args.file.as_deref(), Some("program.me"));
+        assert_eq!(args.program_args, ["watch"]);
+    }
+
+    #[test]
+    fn test_split_files() {
+        use crate::Args;
+        use clap::Parser;
+
+        let split = |argv: &[&str]| {
+            let args = Args::try_parse_from(argv).expect("Unable to parse the arguments");
+            split_files(args.file.expect("No file"), args.args, args.program_args)
+        };
+        assert_eq!(
+            split(&["merlin", "a.me", "b.me", "x", "c.me"]),
+            (
+                vec!["a.me".into(), "b.me".into()],
+                vec!["x".into(), "c.me".into()]
+            )
+        );
+        assert_eq!(
+            split(&["merlin", "a.me", "--", "b.me"]),
+            (vec!["a.me".into()], vec!["b.me".into()])
+        );
+        //The first `--` is removed wherever it is, and only the first
+        assert_eq!(
+            split(&["merlin", "a.me", "x", "--", "y"]),
+            (vec!["a.me".into()], vec!["x".into(), "y".into()])
+        );
+        assert_eq!(
+            split(&["merlin", "a.me", "b.me", "x", "--", "--", "y.me"]),
+            (
+                vec!["a.me".into(), "b.me".into()],
+                vec!["x".into(), "--".into(), "y.me".into()]
+            )
+        );
+    }
+
+    #[test]
+    fn test_get_negative_index() {
+        use crate::objects::{intobject, listobject, stringobject};
+
+        let vm = test_vm();
+        let ints = [10, 20, 30].map(|raw| intobject::int_from(vm.clone(), raw));
+        let list = listobject::list_from(vm.clone(), ints.to_vec());
+        let string = stringobject::string_from(vm.clone(), String::from("abc"));
+        let get = |object: &crate::objects::Object<'static>, idx| {
+            object.tp.get.expect("No get slot")(
+                object.clone(),
+                intobject::int_from(vm.clone(), idx),
+            )
+        };
+
+        //-1 is the last item and -len the first, and -len-1 and len are out of range
+        for (idx, expected) in [(-1, Some(30)), (-3, Some(10)), (-4, None), (3, None)] {
+            let res = get(&list, idx);
+            match expected {
+                Some(expected) => assert_eq!(unsafe { res.unwrap().internals.int() }, expected),
+                None => assert_eq!(res.unwrap_err().tp.typename, "IndexExc"),
+            }
+        }
+        for (idx, expected) in [(-1, Some("c")), (-3, Some("a")), (-4, None), (3, None)] {
+            let res = get(&string, idx);
+            match expected {
+                Some(expected) => {
+                    assert_eq!(unsafe { res.unwrap().internals.str() }.as_str(), expected)
+                }
+                None => assert_eq!(res.unwrap_err().tp.typename, "IndexExc"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_recursive_repr() {
+        use crate::objects::{dictobject, intobject, listobject, mhash, stringobject, RawObject};
+
+        let vm = test_vm();
+        let one = intobject::int_from(vm.clone(), 1);
+        let list = listobject::list_from(vm.clone(), vec![one.clone(), one.clone()]);
+        let dict = dictobject::dict_from(vm.clone(), mhash::HashMap::new());
+        let key = stringobject::string_from(vm.clone(), String::from("d"));
+
+        //A list and a dict that hold themselves render without recursing, also inside each other
+        list.tp.set.expect("No set slot")(list.clone(), one.clone(), list.clone()).unwrap();
+        dict.tp.set.expect("No set slot")(dict.clone(), key, dict.clone()).unwrap();
+        assert_eq!(
+            RawObject::object_repr_safe(list.clone()).unwrap(),
+            "[1, [...]]"
+        );
+        assert_eq!(
+            RawObject::object_repr_safe(dict.clone()).unwrap(),
+            "{\"d\": {...}}"
+        );
+        list.tp.set.expect("No set slot")(list.clone(), one, dict).unwrap();
+        assert_eq!(
+            RawObject::object_repr_safe(list).unwrap(),
+            "[1, {\"d\": {...}}]"
+        );
+        assert!(vm.repr_in_progress.is_empty());
+    }
+
+    #[test]
+    fn test_too_many_registers() {
+        //Each item of the list is held in a register of its own until the list is built
+        let items = vec!["x + 1"; u16::MAX as usize + 1].join(", ");
+        let source = format!("x = 1\nl = [{items}]\n");
+        let vm = test_vm();
+        let res = crate::catch_exit(|| compile(&vm, Box::leak(source.into_boxed_str())));
+
+        assert_eq!(res.err(), Some(ExitCode::Syntax));
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with(
+            "error[E015]: Statement needs more than 65535 registers, split it into smaller ones\ntest:2:1\n"
+        ));
+    }
+
+    #[test]
+    fn test_count_instructions() {
+        let mut vm = test_vm();
+        vm.instruction_counts = Some(hashbrown::HashMap::new());
+        run(
+            &vm,
+            "x = 1\ny = x + 2\nfn f(a) {\n    return a * 2\n}\nz = f(y)\nw = f(z)\n",
+        );
+
+        let mut counts: Vec<(&str, usize)> = vm
+            .instruction_counts
+            .as_ref()
+            .expect("Instructions were not counted")
+            .iter()
+            .map(|(name, count)| (*name, *count))
+            .collect();
+        counts.sort();
+        assert_eq!(
+            counts,
+            [
+                ("BinaryAddInt", 1),
+                ("BinaryMulInt", 2),
+                ("Call", 2),
+                ("CopyRegister", 1),
+                ("MakeFunction", 1),
+                ("MoveRegister", 4),
+                ("Return", 2),
+            ]
+        );
+        //The counts are of the instructions that ran, so the body of f is counted once per call
+        assert_eq!(counts.iter().map(|(_, count)| count).sum::<usize>(), 13);
+    }
+
+    #[test]
+    fn test_frame_pool() {
+        //The top level and f each leave their registers, and f its variables, for the next calls
+        let (vm, _) = compile_and_run("fn f(a) {\n    return a + 1\n}\nx = f(1)\n");
+        assert_eq!(vm.pooled_frames(), (2, 1));
+
+        //Calls reuse the vectors of the calls before them, so the pool only grows with the depth
+        //of the calls, not with their number
+        let (vm, _) =
+            compile_and_run("fn f(a) {\n    return a + 1\n}\nx = f(f(f(1)))\ny = f(x) + f(x)\n");
+        assert_eq!(vm.pooled_frames(), (2, 1));
+        let (vm, _) =
+            compile_and_run("fn f(a) {\n    return a + 1\n}\nw = [f(i) for i in [1, 2, 3] * 30]\n");
+        assert_eq!(vm.pooled_frames(), (3, 2));
+
+        //Deterministic runs allocate every frame
+        let mut vm = test_vm();
+        vm.deterministic = true;
+        run(&vm, "fn f(a) {\n    return a + 1\n}\nx = f(1)\n");
+        assert_eq!(vm.pooled_frames(), (0, 0));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut vm = test_vm();
+        vm.trace = Some(interpreter::trace::Trace::new(Some(String::from("add"))));
+        let bytecode = compile(
+            &vm,
+            "fn add(a, b) {\n    return a + b\n}\nx = 1\ny = add(x, 2)\n",
+        );
+        execute(&vm, &bytecode);
+        let output = diagnostics_of(&vm);
+        assert_eq!(
+            output,
+            "[trace] test:2 | return a + b\n[trace]     BinaryAdd a = 1, b = 2\n[trace]     Return R(0) = 3\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "debugger"))]
+    fn test_breakpoint_disabled() {
+        //Without the debugger, a breakpoint does nothing
+        assert_eq!(
+            run_source(
+                String::from("fn f(x) {\n    breakpoint()\n    return x\n}\ny = f(1)\n"),
+                String::from("<string>"),
+                &RunOptions::default(),
+                Vec::new(),
+            ),
+            ExitCode::Success
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debugger")]
+    fn test_breakpoint() {
+        let mut vm = test_vm();
+        vm.debug_input = interpreter::debugger::Input::new(std::io::Cursor::new(
+            "locals\np x\np z\nwhere\nnext\nc\nlocals\n",
+        ));
+        let bytecode = compile(
+            &vm,
+            "x = 1\nfn f(a) {\n    b = [a, x]\n    breakpoint()\n    return b\n}\ny = f(2)\n",
+        );
+        let namespace = execute(&vm, &bytecode);
+        assert!(namespace.get("y").is_some());
+        let output = diagnostics_of(&vm);
+        assert_eq!(
+            output,
+            "[debug] Paused at test:4 | breakpoint()\n\
+            (merlin) a = 2\nb = [2, 1]\n\
+            (merlin) 1\n\
+            (merlin) 'z' is not a variable of this scope\n\
+            (merlin) test:4 | breakpoint()\n\
+            (merlin) Commands: where, locals, p <name>, c (continue), help\n\
+            (merlin) "
+        );
+    }
+
+    #[test]
+    fn test_bytecode_dump() {
+        let bytecode = compile(&test_vm(), "x = 1\ny = x + 2\n");
+        let dump = bytecode.dump();
+        assert!(dump.starts_with("registers: 2, variables: 2\nnames:\n    V(0) x\n    V(1) y\n"));
+        assert!(dump.contains("constants:\n    C(0) int 1\n    C(1) int 2\n"));
+        assert!(dump.contains(
+            "       1 line 2    BinaryAddInt { a: V(0), b: C(1), result: R(0), i: 1 }  ; x\n"
+        ));
+    }
+
+    #[test]
+    fn test_nested_arguments() {
+        let (_, namespace) = compile_and_run("class P {\n    fn init(self, y) {\n        self.y = y\n    }\n    fn get(self) {\n        return self\n    }\n}\nfn g(x) {\n    return P(x)\n}\nfn f(a) {\n    return a\n}\nresults = [f(g(1).y + 1), f(f(f(f(2)))), 1 + g(3).y * 2, f((1 + f(2)) * -f(3)), g(4).get().get().y, len([f(1), g(2)])]\n");
+        assert_eq!(
+            value(&namespace, "results"),
+            Ok(Value::List(
+                [2, 2, 7, -9, 4, 2].into_iter().map(Value::Int).collect()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_locals_globals() {
+        let (_, namespace) = compile_and_run("x = 1\nfn f(a) {\n    b = a + x\n    return locals()\n}\ninner = f(2) == {\"a\": 2, \"b\": 3}\ntop = sorted([k for k in locals()])\nfn g() {\n    return sorted([k for k in globals()])\n}\nnames = g()\n");
+        let value = |name: &str| Value::try_from(namespace.get(name).expect("name is not defined"));
+        let strs = |names: &[&str]| {
+            Ok(Value::List(
+                names
+                    .iter()
+                    .map(|name| Value::Str(name.to_string()))
+                    .collect(),
+            ))
+        };
+        assert_eq!(value("inner"), Ok(Value::Bool(true)));
+        //The variables that are not yet assigned are left out
+        assert_eq!(value("top"), strs(&["f", "inner", "x"]));
+        assert_eq!(value("names"), strs(&["f", "g", "inner", "top", "x"]));
+    }
+
+    #[test]
+    fn test_drop() {
+        let (_, namespace) = compile_and_run("class Counter {}\ncounter = Counter()\ncounter.n = 0\nclass R {\n    fn drop(self) {\n        counter.n = counter.n + 1\n        counter.last = self\n    }\n    fn f(self) {\n        return 1\n    }\n}\nclass S(R) {}\nfn make() {\n    r = R()\n    s = S()\n}\nmake()\nmade = counter.n\ncounter.last = 0\nafter = counter.n\nx = R()\nx = 1\nlast = counter.n\nfn call() {\n    y = R()\n    return y.f()\n}\nv = call()\ncalled = counter.n\n");
+        let value = |name: &str| Value::try_from(namespace.get(name).expect("name is not defined"));
+        //Subclasses inherit drop
+        assert_eq!(value("made"), Ok(Value::Int(2)));
+        //An instance that drop stored is not dropped again when it is freed
+        assert_eq!(value("after"), Ok(Value::Int(2)));
+        assert_eq!(value("last"), Ok(Value::Int(3)));
+        //Calling a method does not keep the instance alive
+        assert_eq!(value("called"), Ok(Value::Int(4)));
+    }
+
+    #[test]
+    fn test_dir() {
+        let (_, namespace) = compile_and_run("class A {\n    fn init(self) {\n        self.x = 1\n    }\n    fn area(self) {\n        return 1\n    }\n}\nclass B(A) {\n    fn perim(self) {\n        return 2\n    }\n}\nb = B()\ninstance = dir(b)\ncls = dir(B)\nnumber = dir(1)\nmodule = dir(log)\n");
+        let names = |name: &str, expected: &[&str]| {
+            assert_eq!(
+                Value::try_from(namespace.get(name).expect("name is not defined")),
+                Ok(Value::List(
+                    expected
+                        .iter()
+                        .map(|name| Value::Str(name.to_string()))
+                        .collect()
+                ))
+            );
+        };
+        names("instance", &["area", "init", "perim", "x"]);
+        names("cls", &["area", "init", "perim"]);
+        names("number", &[]);
+        names("module", &["debug", "error", "info", "warn"]);
+    }
+
+    #[test]
+    fn test_super() {
+        let (_, namespace) = compile_and_run("class A {\n    fn f(self) {\n        return 1\n    }\n    fn g(self) {\n        return 2\n    }\n}\nclass B(A) {\n    fn f(self) {\n        s = super(B, self)\n        return s.f() + 10\n    }\n}\nclass C(B) {\n    fn f(self) {\n        return super(C, self).f() + 100\n    }\n}\non_class = B.f(B)\ninherited = B.g(B)\non_instance = B().f()\nchained = C().f()\n");
+        //super(B, B) finds A.f when the method is called on the class itself
+        assert_eq!(value(&namespace, "on_class"), Ok(Value::Int(11)));
+        assert_eq!(value(&namespace, "inherited"), Ok(Value::Int(2)));
+        assert_eq!(value(&namespace, "on_instance"), Ok(Value::Int(11)));
+        assert_eq!(value(&namespace, "chained"), Ok(Value::Int(111)));
+
+        let output = run_error("class A {}\nclass B {}\nx = super(A, B())\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Expected an instance of 'A', got 'B'\""));
+        let output = run_error("class A {}\nx = super(1, A())\n");
+        assert!(output.starts_with("TypeMismatchExc: \"Expected 'type', got 'int'\""));
+    }
+
+    #[test]
+    fn test_int_overflow_modes() {
+        use crate::objects::intobject::IntOverflow;
+
+        let run_with = |mode: IntOverflow| {
+            let mut vm = test_vm();
+            vm.int_overflow = mode;
+            let namespace = run(&vm, "big = 9223372036854775807\nx = big + 1\nback = x - 1\nsquare = x * x\nsmall = square / x / x\ns = \"{} {}\".format(x, square)\n");
+            ["back", "small", "s"].map(|name| value(&namespace, name))
+        };
+        let literal = |mode: IntOverflow| {
+            let mut vm = test_vm();
+            vm.int_overflow = mode;
+            crate::catch_exit(|| {
+                let namespace = run(
+                    &vm,
+                    "x = 9223372036854775808\nsame = x == 9223372036854775807 + 1\ns = \"{}\".format(x - 1)\n",
+                );
+                ["same", "s"].map(|name| value(&namespace, name))
+            })
+        };
+
+        assert_eq!(
+            run_with(IntOverflow::Promote),
+            [
+                Ok(Value::Int(i64::MAX)),
+                Ok(Value::Int(1)),
+                Ok(Value::from(
+                    "9223372036854775808 85070591730234615865843651857942052864"
+                ))
+            ]
+        );
+        assert_eq!(
+            run_with(IntOverflow::Wrap),
+            [
+                Ok(Value::Int(i64::MAX)),
+                Ok(Value::Int(0)),
+                Ok(Value::from("-9223372036854775808 0"))
+            ]
+        );
+
+        //A literal that does not fit is a bigint when overflow promotes, and invalid otherwise
+        assert_eq!(
+            literal(IntOverflow::Promote),
+            Ok([
+                Ok(Value::Bool(true)),
+                Ok(Value::from("9223372036854775807"))
+            ])
+        );
+        assert_eq!(literal(IntOverflow::Raise), Err(ExitCode::Runtime));
+    }
+
+    #[test]
+    fn test_int_cache() {
+        use crate::objects::intobject::int_from;
+
+        let mut vm = test_vm();
+        vm.int_cache_stats = Some((0, 0));
+        assert!(Trc::ptr_eq(
+            &int_from(vm.clone(), -5),
+            &int_from(vm.clone(), -5)
+        ));
+        assert!(Trc::ptr_eq(
+            &int_from(vm.clone(), 256),
+            &int_from(vm.clone(), 256)
+        ));
+        assert!(!Trc::ptr_eq(
+            &int_from(vm.clone(), -6),
+            &int_from(vm.clone(), -6)
+        ));
+        assert!(!Trc::ptr_eq(
+            &int_from(vm.clone(), 1000),
+            &int_from(vm.clone(), 1000)
+        ));
+        assert_eq!(vm.int_cache_stats, Some((4, 4)));
+
+        interpreter::VM::resize_int_cache(vm.clone(), 1000);
+        let int = int_from(vm.clone(), 1000);
+        assert!(Trc::ptr_eq(&int, &int_from(vm.clone(), 1000)));
+        assert_eq!(int.as_int(), Some(1000));
+        assert!(!Trc::ptr_eq(
+            &int_from(vm.clone(), 1001),
+            &int_from(vm.clone(), 1001)
+        ));
+        assert_eq!(vm.int_cache_stats, Some((6, 6)));
+    }
+
+    #[test]
+    fn test_str_shares_buffer() {
+        use crate::objects::{stringobject, MethodValue, RawObject};
+
+        let vm = test_vm();
+        let text = stringobject::string_from(vm.clone(), String::from("héllo wörld"));
+        let chars = match RawObject::object_iter_safe(text.clone()) {
+            MethodValue::Some(chars) => chars,
+            MethodValue::Error(_) => panic!("Str is not iterable"),
+        };
+        let chars = chars
+            .iter()
+            .map(|chr| unsafe { chr.internals.str() })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chars.iter().map(|chr| chr.as_str()).collect::<String>(),
+            "héllo wörld"
+        );
+        assert_eq!(chars[1].as_str(), "é");
+        assert!(chars
+            .iter()
+            .all(|chr| chr.buffer_len() == "héllo wörld".len()));
+
+        let index = crate::objects::intobject::int_from(vm.clone(), 7);
+        let chr = match (text.tp.get.expect("Str has no get"))(text.clone(), index) {
+            MethodValue::Some(chr) => chr,
+            MethodValue::Error(_) => panic!("Str is not indexable"),
+        };
+        assert_eq!(chr.as_str(), Some("ö"));
+        assert_eq!(
+            unsafe { chr.internals.str() }.buffer_len(),
+            "héllo wörld".len()
+        );
+    }
+
+    #[test]
+    fn test_call_function() {
+        use crate::value::{MerlinError, Value};
+
+        let (vm, _) =
+            compile_and_run("base = 10\nfn add(a, b) {\n    return [a + b + base, a]\n}\n");
+
+        let call =
+            |name: &str, args: Vec<Value>| interpreter::VM::call_function(vm.clone(), name, args);
+        assert_eq!(
+            call("add", vec![Value::Int(1), Value::Int(2)]),
+            Ok(Value::from(vec![13, 1]))
+        );
+        assert_eq!(
+            call("missing", Vec::new()),
+            Err(MerlinError::NotDefined(String::from("missing")))
+        );
+        assert_eq!(
+            call("base", Vec::new()),
+            Err(MerlinError::NotAFunction(
+                String::from("base"),
+                String::from("int")
+            ))
+        );
+        assert!(matches!(
+            call("add", vec![Value::Int(1)]),
+            Err(MerlinError::Exception(exc)) if exc.contains("Function 'add' expected 2 argument(s), got 1")
+        ));
+    }
+
+    #[test]
+    fn test_call_function_raises() {
+        use crate::errors::ExitCode;
+        use crate::value::{MerlinError, Value};
+
+        let (vm, _) = compile_and_run("fn inner() {\n    return 1 + \"a\"\n}\nfn outer() {\n    return inner()\n}\nfn stop() {\n    env.exit(3)\n}\nfn ok() {\n    return 1\n}\n");
+
+        //An exception raised in the body of the function, or of a function that it calls, is
+        //returned instead of stopping the program
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert!(matches!(
+            call("outer"),
+            Err(MerlinError::Exception(exc)) if exc.starts_with("TypeMismatchExc")
+        ));
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with("TypeMismatchExc"));
+        assert!(vm.interpreters.is_empty());
+        assert_eq!(call("stop"), Err(MerlinError::Exited(ExitCode::Exit(3))));
+        assert_eq!(call("ok"), Ok(Value::Int(1)));
+        assert!(!vm.catch_errors);
+    }
+
+    #[test]
+    fn test_method_cache() {
+        let (vm, _) = compile_and_run("class A {\n    fn f(self) {\n        return 1\n    }\n}\nclass B {\n    fn f(self) {\n        return 2\n    }\n}\na = A()\nb = B()\nfn calls() {\n    return [a.f(), b.f(), a.f(), b.f()]\n}\n");
+
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert_eq!(
+            call("calls"),
+            Ok(Value::List(vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Int(1),
+                Value::Int(2)
+            ]))
+        );
+        //The cache holds the functions of A and B, and not the methods bound to a and b
+        let mut cached = vm
+            .method_cache
+            .iter()
+            .flatten()
+            .map(|(tp, _, fun)| (tp.typename.clone(), fun.tp.typename.clone()))
+            .collect::<Vec<_>>();
+        cached.sort();
+        assert_eq!(
+            cached,
+            [
+                (String::from("A"), String::from("fn")),
+                (String::from("B"), String::from("fn"))
+            ]
+        );
+
+        //Calls of the cached methods do not bind them to a and b
+        let mut stats_vm = vm.clone();
+        stats_vm.alloc_stats = Some(hashbrown::HashMap::new());
+        assert_eq!(
+            call("calls"),
+            Ok(Value::List(vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Int(1),
+                Value::Int(2)
+            ]))
+        );
+        let methodtp = vm.types.methodtp.as_ref().unwrap().typeid;
+        let stats = vm.alloc_stats.as_ref().unwrap();
+        assert_eq!(stats.get(&methodtp).map_or(0, |(_, n)| *n), 0);
+        assert!(stats.values().any(|(_, n)| *n > 0));
+    }
+
+    #[test]
+    fn test_method_cache_shadowed() {
+        let (vm, _) = compile_and_run("class A {\n    fn f(self) {\n        return 1\n    }\n}\na = A()\nb = A()\nfn g(self) {\n    return 2\n}\nb.f = g\nfn calls() {\n    return [a.f(), b.f(a), a.f()]\n}\n");
+
+        //The attribute of b hides the method that is cached for A, and is not bound to b
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert_eq!(
+            call("calls"),
+            Ok(Value::List(vec![
+                Value::Int(1),
+                Value::Int(2),
+                Value::Int(1)
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_instance_attrs() {
+        let (vm, _) = compile_and_run("class P {\n    fn init(self, x, y) {\n        self.x = x\n        self.y = y\n    }\n}\np = P(1, 2)\nq = P(3, 4)\np.x = 10\nfn shared() {\n    x = p.x\n    y = q.y\n    return x + y\n}\nfn diverged() {\n    a = P(0, 0)\n    a.a = 1\n    b = P(0, 0)\n    b.b = 2\n    c = P(0, 0)\n    c.c = 3\n    d = P(0, 0)\n    d.d = 4\n    e = P(0, 0)\n    e.e = 5\n    e.e = e.e + 1\n    f = deepcopy(e)\n    x = e.e\n    y = f.e\n    z = d.d\n    return x + y + z\n}\n");
+
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert_eq!(call("shared"), Ok(Value::Int(14)));
+        assert_eq!(call("diverged"), Ok(Value::Int(16)));
+        //The empty shape, x, x and y, and the four that extend it. The fifth extension moved the
+        //attributes of e to a dict instead.
+        assert_eq!(vm.shapes.len(), 7);
+    }
+
+    #[test]
+    fn test_main_not_run_when_embedded() {
+        let (vm, _) = compile_and_run("class State {\n    fn init(self) {\n        self.ran = 0\n    }\n}\nstate = State()\nfn main() {\n    state.ran = 1\n}\nfn ran() {\n    return state.ran\n}\nfn check() {\n    return is_main()\n}\n");
+
+        let call = |name: &str| interpreter::VM::call_function(vm.clone(), name, Vec::new());
+        assert_eq!(call("ran"), Ok(Value::Int(0)));
+        assert_eq!(call("check"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_const_table() {
+        let bytecode = compile(
+            &test_vm(),
+            "x = 1\nfn f(a) {\n    return a + 2.5\n}\ny = \"s\"\n",
+        );
+
+        let table = bytecode
+            .const_table()
+            .into_iter()
+            .filter(|entry| entry.typename != "code")
+            .map(|entry| (entry.depth, entry.typename, entry.repr))
+            .collect::<Vec<_>>();
+        let expected = [
+            (0, "int", "1"),
+            (0, "str", "\"f\""),
+            (0, "list", "[\"a\"]"),
+            (1, "float", "2.5"),
+            (0, "str", "\"s\""),
+        ];
+        assert_eq!(
+            table,
+            expected.map(|(depth, typename, repr)| (depth, typename.to_string(), repr.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_code_dedup() {
+        use crate::objects::codeobject;
+
+        let vm = test_vm();
+        let bytecode = compile(&vm, "fn f(a) {\n    return a + 1\n}\nfn g(a) {\n    return a + 1\n}\nfn h(a) {\n    return a + 2\n}\nx = f(1)\ny = h(1)\n");
+
+        //f and g run the same way, so they share one code object, and each keeps its own lines
+        let codes = bytecode
+            .consts
+            .iter()
+            .filter(|c| c.tp.typename == "code")
+            .collect::<Vec<_>>();
+        assert_eq!(codes.len(), 2);
+        let (f, g) = (&bytecode.bodies[0], &bytecode.bodies[1]);
+        assert!(Trc::ptr_eq(&f.code, &g.code));
+        assert!(Trc::ptr_eq(&f.code, unsafe { codes[0].internals.code() }));
+        assert!(f.positions != g.positions);
+        let data = compiler::serialize::serialize(&bytecode, &vm).expect("Bytecode not serialized");
+        let loaded = compiler::serialize::deserialize(&data, bytecode.info, vm.clone())
+            .expect("Bytecode not loaded");
+        assert!(Trc::ptr_eq(&loaded.bodies[0].code, &loaded.bodies[1].code));
+        assert!(loaded.bodies[0].positions == f.positions);
+        assert!(loaded.bodies[1].positions == g.positions);
+
+        let hash = |c: &crate::objects::Object| unsafe {
+            (c.tp.hash_fn.unwrap())(c.clone()).unwrap().internals.int()
+        };
+        let code_f = unsafe { codes[0].internals.code() };
+        let copy = codeobject::code_from(vm.clone(), Trc::new((**code_f).clone()));
+        assert_eq!(hash(codes[0]), hash(&copy));
+        assert!(**code_f == *unsafe { copy.internals.code() }.clone());
+        assert!(**code_f != **unsafe { codes[1].internals.code() });
+
+        let namespace = execute(&vm, &bytecode);
+        let x = value(&namespace, "x");
+        assert_eq!(x, Ok(Value::Int(2)));
+        let y = value(&namespace, "y");
+        assert_eq!(y, Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn test_code_dedup_positions() {
+        //An error in g is reported on its own line, and not on the line of f
+        let output = run_error("fn f(a) {\n    return a + 1\n}\nfn g(a) {\n    return a + 1\n}\nx = f(1)\ny = g(\"s\")\n");
+        assert!(output.contains("test:5:"));
+        assert!(!output.contains("test:2:"));
+    }
+
+    #[test]
+    fn test_fn_metadata() {
+        let (_, namespace) = compile_and_run("class Shape {\n    fn area(self, scale) {\n        return 0\n    }\n}\nfn outer() {\n    fn inner() {\n        return 1\n    }\n    return inner\n}\ninner = outer()\nname = Shape.area.name\nqualname = Shape.area.qualname\nargs = Shape.area.args\narity = Shape.area.arity\nnested = inner.qualname\nline = inner.line\n");
+
+        let get = |name: &str| value(&namespace, name).expect("not a value");
+        assert_eq!(get("name"), Value::from("area"));
+        assert_eq!(get("qualname"), Value::from("Shape.area"));
+        assert_eq!(
+            get("args"),
+            Value::List(vec![Value::from("self"), Value::from("scale")])
+        );
+        assert_eq!(get("arity"), Value::Int(2));
+        assert_eq!(get("nested"), Value::from("outer.inner"));
+        assert_eq!(get("line"), Value::Int(7));
+    }
+
+    #[test]
+    fn test_call_arities() {
+        //Calls with few arguments pass them on the stack, and ones with more in a vector
+        let (_, namespace) = compile_and_run("fn f(a, b, c, d, e) {\n    return a + b + c + d + e\n}\nclass C {\n    fn m(self, a, b, c, d) {\n        return a + b + c + d\n    }\n}\nfive = f(1, 2, 3, 4, 5)\nc = C()\nfour = c.m(1, 2, 3, 4)\n");
+
+        let five = value(&namespace, "five");
+        assert_eq!(five, Ok(Value::Int(15)));
+        let four = value(&namespace, "four");
+        assert_eq!(four, Ok(Value::Int(10)));
+    }
+
+    #[test]
+    fn test_move_registers() {
+        //The result of x * 2 is moved to y, but the one of y + 1 is read again by the chained store
+        let vm = test_vm();
+        let bytecode = compile(&vm, "x = 3\ny = x * 2\nz = w = y + 1\nq = w + z\n");
+        let names = bytecode
+            .instructions
+            .iter()
+            .map(|instruction| instruction.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names[1..3], ["BinaryMulInt", "MoveRegister"]);
+        assert!(bytecode.instructions.iter().any(|instruction| matches!(
+            instruction,
+            compiler::CompilerInstruction::CopyRegister {
+                from: compiler::CompilerRegister::R(_),
+                ..
+            }
+        )));
+
+        let namespace = execute(&vm, &bytecode);
+        let q = value(&namespace, "q");
+        assert_eq!(q, Ok(Value::Int(14)));
+    }
+
+    #[test]
+    fn test_superinstructions() {
+        let info = program("fn f(a) {\n    b = a + 1\n    return b\n}\nclass A {\n    fn get(self, k) {\n        return k\n    }\n}\na = A()\ny = f(1)\nx = a.get(y)\n");
+        let names = |bytecode: &compiler::Code| {
+            bytecode
+                .instructions
+                .iter()
+                .map(|instruction| instruction.name())
+                .collect::<Vec<_>>()
+        };
+        for optimize in [false, true] {
+            let vm = test_vm();
+            let ast = parse(info, &vm.diagnostics);
+            let bytecode =
+                compiler::Compiler::new(info, vm.clone(), optimize).generate_bytecode(&ast);
+            assert!(names(&bytecode).contains(&"CallAttr"));
+            //f's body stores b and returns it in one instruction
+            let body = unsafe { bytecode.consts[2].internals.code() };
+            assert_eq!(names(body), ["BinaryAddInt", "StoreReturn"]);
+
+            let namespace = execute(&vm, &bytecode);
+            let x = value(&namespace, "x");
+            assert_eq!(x, Ok(Value::Int(2)));
+        }
+    }
+
+    #[test]
+    fn test_quickening() {
+        //x * y runs as the float instruction after 16 runs, and x + x runs as the int one and then
+        //as the generic one again when it meets floats
+        let mut vm = test_vm();
+        vm.instruction_counts = Some(hashbrown::HashMap::new());
+        let namespace = run(&vm, "y = 0.5\nfloats = [x * y for x in [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0]]\nmixed = [x + x for x in [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 1.5, 2]]\n");
+
+        let counts = vm.instruction_counts.as_ref().unwrap();
+        assert_eq!(counts.get("BinaryMul"), Some(&16));
+        assert_eq!(counts.get("BinaryMulFloat"), Some(&2));
+        assert_eq!(counts.get("BinaryAdd"), Some(&17));
+        assert_eq!(counts.get("BinaryAddInt"), Some(&2));
+
+        let floats = value(&namespace, "floats");
+        assert_eq!(
+            floats,
+            Ok(Value::List(
+                (1..=18).map(|x| Value::Float(x as f64 * 0.5)).collect()
+            ))
+        );
+        let mixed = value(&namespace, "mixed");
+        let mut expected = (1..=17).map(|x| Value::Int(x * 2)).collect::<Vec<_>>();
+        expected.extend([Value::Float(3.0), Value::Int(4)]);
+        assert_eq!(mixed, Ok(Value::List(expected)));
+    }
+
+    #[test]
+    fn test_quickening_deterministic() {
+        //With --deterministic, the instructions run as the generic ones however often they see ints
+        let mut vm = test_vm();
+        vm.deterministic = true;
+        vm.instruction_counts = Some(hashbrown::HashMap::new());
+        run(
+            &vm,
+            "ys = [x * x for x in [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18]]\n",
+        );
+
+        let counts = vm.instruction_counts.as_ref().unwrap();
+        assert_eq!(counts.get("BinaryMul"), Some(&18));
+        assert_eq!(counts.get("BinaryMulInt"), None);
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn test_jit() {
+        use crate::interpreter::jit::{JitState, JIT_CALLS};
+
+        let mut vm = test_vm();
+        vm.int_overflow = crate::IntOverflow::Wrap;
+        let bytecode = compile(
+            &vm,
+            "fn f(a, b) {\n    c = a * b + 3\n    return c - a\n}\nfn g(a) {\n    return [a]\n}\n",
+        );
+        execute(&vm, &bytecode);
+
+        let call =
+            |name: &str, args: Vec<Value>| interpreter::VM::call_function(vm.clone(), name, args);
+        //The code objects of f and g, in the order they were defined
+        let codetp = vm.types.codetp.as_ref().unwrap().typeid;
+        let codes = bytecode
+            .consts
+            .iter()
+            .filter(|c| c.tp.typeid == codetp)
+            .collect::<Vec<_>>();
+        let state = |idx: usize| unsafe { codes[idx].internals.code() }.jit.get();
+        for n in 0..JIT_CALLS as i64 + 10 {
+            assert_eq!(
+                call("f", vec![Value::Int(n), Value::Int(2)]),
+                Ok(Value::Int(n + 3))
+            );
+            assert_eq!(call("g", vec![Value::Int(n)]), Ok(Value::from(vec![n])));
+        }
+        assert!(matches!(state(0), JitState::Compiled(_, 2)));
+        assert!(matches!(state(1), JitState::Unsupported));
+
+        //An overflow is left to the interpreter, which wraps it
+        let max = i64::MAX;
+        assert_eq!(
+            call("f", vec![Value::Int(max), Value::Int(2)]),
+            Ok(Value::Int(
+                max.wrapping_mul(2).wrapping_add(3).wrapping_sub(max)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_attr_names() {
+        let vm = test_vm();
+        let bytecode = compile(
+            &vm,
+            "a = env.args()\nb = env.get(\"HOME\")\nc = env.args()\n",
+        );
+
+        assert_eq!(bytecode.attr_names, ["args", "get"]);
+        assert!(bytecode
+            .const_table()
+            .iter()
+            .all(|entry| entry.repr != "\"args\"" && entry.repr != "\"get\""));
+        assert!(vm.interned.contains_key("args") && vm.interned.contains_key("get"));
+    }
+
+    #[test]
+    fn test_class_dict() {
+        let bytecode = compile(&test_vm(), "class A {\n    fn b(self) {\n        return 1\n    }\n    fn a(self) {\n        return 2\n    }\n}\n");
+
+        let body = bytecode
+            .instructions
+            .iter()
+            .find_map(|instruction| match instruction {
+                compiler::CompilerInstruction::MakeClass { bodyidx, .. } => {
+                    Some(&bytecode.bodies[*bodyidx])
+                }
+                _ => None,
+            })
+            .expect("No class");
+        match body.instructions.as_slice() {
+            [.., compiler::CompilerInstruction::BuildClassDict {
+                result,
+                names,
+                variables,
+                ..
+            }, compiler::CompilerInstruction::Return { register, .. }] => {
+                assert_eq!(result, register);
+                let names = names
+                    .iter()
+                    .map(|idx| body.attr_names[*idx].as_str())
+                    .collect::<Vec<_>>();
+                assert_eq!(names, ["b", "a"]);
+                assert_eq!(variables, &[0, 1]);
+            }
+            instructions => panic!("Class body does not return its dict: {:?}", instructions),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_checks() {
+        use crate::compiler::{CompilerInstruction, CompilerRegister};
+
+        //Bytecode that uses a register, name or constant that it does not have is not loaded
+        let vm = test_vm();
+        let bytecode = compile(&vm, "fn f(a) {\n    return a.x\n}\ny = 1\nz = y + 2\n");
+
+        //The bodies share their code, so each is changed in a copy of it
+        let copy = || {
+            let mut copy = (*bytecode).clone();
+            copy.code = Trc::new((*bytecode.code).clone());
+            copy
+        };
+        let mut register = copy();
+        let last = register.instructions.len() - 1;
+        *register.code.instructions[last].registers_mut()[0] =
+            CompilerRegister::R(register.n_registers as usize);
+        let mut name = copy();
+        for instruction in &mut name.code.instructions {
+            if let CompilerInstruction::MakeFunction { nameidx, .. } = instruction {
+                *nameidx = bytecode.consts.len();
+            }
+        }
+        let mut index = copy();
+        *index.code.instructions[last].index_mut().expect("No index") = last + 1;
+        //A function whose body is not made by its instruction
+        let mut unmade = copy();
+        unmade.bodies.push(bytecode.bodies[0].clone());
+
+        for (body, valid) in [
+            (&*bytecode, true),
+            (&register, false),
+            (&name, false),
+            (&index, false),
+            (&unmade, false),
+        ] {
+            let data = compiler::serialize::serialize(body, &vm).expect("Bytecode not serialized");
+            let loaded = compiler::serialize::deserialize(&data, bytecode.info, vm.clone());
+            assert_eq!(loaded.is_some(), valid);
+        }
+    }
+
+    #[test]
+    fn test_serialize_bytecode() {
+        let info = program("const c = 2\nfn f(a) {\n    return a * c + 2.5\n}\nclass A {\n    fn g(self) {\n        return \"s\"\n    }\n}\nx = f(1)\nz = env.args()\ny = [v + 1 for v in [1, 2]]\n");
+        let vm = test_vm();
+        let ast = parse(info, &vm.diagnostics);
+        let bytecode = compiler::Compiler::new(info, vm.clone(), true).generate_bytecode(&ast);
+
+        let data = compiler::serialize::serialize(&bytecode, &vm).expect("Bytecode not serialized");
+        let loaded =
+            compiler::serialize::deserialize(&data, info, vm.clone()).expect("Bytecode not loaded");
+        assert_eq!(
+            format!("{:?}", loaded.instructions),
+            format!("{:?}", bytecode.instructions)
+        );
+        let consts = |bytecode: &compiler::Bytecode| {
+            bytecode
+                .const_table()
+                .into_iter()
+                .filter(|entry| entry.typename != "code")
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(consts(&loaded), consts(&bytecode));
+        assert_eq!(loaded.attr_names, bytecode.attr_names);
+        assert_eq!(loaded.positions, bytecode.positions);
+        assert_eq!(loaded.scope_names, bytecode.scope_names);
+        assert_eq!(loaded.info.name, bytecode.info.name);
+
+        assert!(compiler::serialize::deserialize(&data[..data.len() - 1], info, vm).is_none());
+    }
+
+    #[test]
+    fn test_enclosing_names_shared() {
+        use crate::compiler::CompilerInstruction;
+
+        let info = program("k = 1\nfn f(a) {\n    fn g() {\n        return eval(\"a + k\")\n    }\n    return g()\n}\nclass A {\n    fn m(self) {\n        return eval(\"k\")\n    }\n}\nx = f(2)\ny = A().m()\n");
+        let vm = test_vm();
+        let ast = parse(info, &vm.diagnostics);
+        let bytecode = compiler::Compiler::new(info, vm.clone(), false).generate_bytecode(&ast);
+        let data = compiler::serialize::serialize(&bytecode, &vm).expect("Bytecode not serialized");
+        let loaded =
+            compiler::serialize::deserialize(&data, info, vm.clone()).expect("Bytecode not loaded");
+
+        //Nested bodies refer to the names of the bodies around them instead of copying them, and
+        //the names of a class body are not in the scopes of its methods
+        for top in [&bytecode, &loaded] {
+            let f = &top.bodies[0];
+            let g = &f.bodies[0];
+            let class = &top.bodies[1];
+            assert!(top.instructions.iter().any(|instruction| matches!(
+                instruction,
+                CompilerInstruction::MakeClass { bodyidx: 1, .. }
+            )));
+            let m = &class.bodies[0];
+            assert!(top.enclosing_names.is_empty());
+            assert_eq!(f.enclosing_names.len(), 1);
+            assert!(Trc::ptr_eq(&f.enclosing_names[0], &top.scope_names));
+            assert_eq!(g.enclosing_names.len(), 2);
+            assert!(Trc::ptr_eq(&g.enclosing_names[0], &top.scope_names));
+            assert!(Trc::ptr_eq(&g.enclosing_names[1], &f.scope_names));
+            assert!(Trc::ptr_eq(&class.enclosing_names[0], &top.scope_names));
+            assert_eq!(m.enclosing_names.len(), 1);
+            assert!(Trc::ptr_eq(&m.enclosing_names[0], &top.scope_names));
+        }
+
+        let namespace = execute(&vm, &loaded);
+        assert_eq!(value(&namespace, "x"), Ok(Value::Int(3)));
+        assert_eq!(value(&namespace, "y"), Ok(Value::Int(1)));
+
+        //The names of a scope are stored once, so the size grows with the number of functions
+        let size = |n: usize| {
+            let source = (0..n)
+                .map(|i| format!("fn f{i}(a) {{\n    return a + {i}\n}}\n"))
+                .collect::<String>();
+            let info: &'static FileInfo = Box::leak(Box::new(FileInfo {
+                data: Box::leak(source.into_boxed_str()).as_bytes(),
+                name: String::from("test"),
+            }));
+            let ast = parse(info, &vm.diagnostics);
+            let bytecode = compiler::Compiler::new(info, vm.clone(), false).generate_bytecode(&ast);
+            compiler::serialize::serialize(&bytecode, &vm)
+                .expect("Bytecode not serialized")
+                .len()
+        };
+        assert!(size(400) < size(200) * 21 / 10);
+    }
+
+    #[test]
+    fn test_deterministic_bytecode() {
+        let info = program("const k = 3\na = 1\nb = 2\nc = 3\nd = 4\ne = 5\nfn f(x, y, z) {\n    fn g() {\n        return x + y + z + k\n    }\n    w = g()\n    return w\n}\nclass A {\n    fn p(self) {\n        return a\n    }\n    fn q(self) {\n        return b\n    }\n    fn r(self) {\n        return c\n    }\n}\n");
+        let compile = || {
+            let vm = test_vm();
+            let ast = parse(info, &vm.diagnostics);
+            let bytecode = compiler::Compiler::new(info, vm.clone(), true).generate_bytecode(&ast);
+            let data =
+                compiler::serialize::serialize(&bytecode, &vm).expect("Bytecode not serialized");
+            (vm, bytecode, data)
+        };
+
+        let (vm, bytecode, data) = compile();
+        for _ in 0..8 {
+            assert_eq!(compile().2, data);
+        }
+        //Names are kept in the order of their variables, not in the order of their hashes
+        assert_eq!(
+            bytecode.scope_names.keys().collect::<Vec<_>>(),
+            ["a", "b", "c", "d", "e", "f", "A"]
+        );
+        let variables = bytecode.names.keys().copied().collect::<Vec<_>>();
+        assert!(variables.windows(2).all(|pair| pair[0] < pair[1]));
+        let loaded =
+            compiler::serialize::deserialize(&data, info, vm.clone()).expect("Bytecode not loaded");
+        assert_eq!(
+            compiler::serialize::serialize(&loaded, &vm).expect("Bytecode not serialized"),
+            data
+        );
+    }
+
+    #[test]
+    fn test_parse_safe() {
+        use crate::{errors::ErrorType, parser};
+
+        let parse = |data: &[u8]| {
+            let info = FileInfo {
+                data,
+                name: String::from("parse"),
+            };
+            parser::parse_safe(&info).map_err(|err| err.tp)
+        };
+        let deep = format!("x = {}1{}", "(".repeat(1000), ")".repeat(1000));
+
+        assert_eq!(
+            parse(b"x = 1\nfn f(a) {\n    return a\n}\n").map(|ast| ast.len()),
+            Ok(2)
+        );
+        assert!(matches!(parse(b"  # only a comment "), Ok(ast) if ast.is_empty()));
+        assert_eq!(parse(b"x = 1e").err(), Some(ErrorType::MalformedExponent));
+        assert_eq!(parse(b"x = 1e_5").err(), Some(ErrorType::MalformedExponent));
+        for number in [
+            "1__0", "1_", "1_.5", "1._5", "1.", "1.2.3", "1_e5", "1e5_", "1e5.3", "1.5e1__0",
+        ] {
+            let source = format!("x = {number}\n");
+            assert_eq!(
+                parse(source.as_bytes()).err(),
+                Some(ErrorType::MalformedNumber),
+                "{number}"
+            );
+        }
+        assert_eq!(parse(b"`").err(), Some(ErrorType::InvalidEscapedIdentifier));
+        assert_eq!(parse(b"fn").err(), Some(ErrorType::UnexpectedEOF));
+        assert_eq!(parse(b"class A {").err(), Some(ErrorType::UnexpectedToken));
+        assert_eq!(parse(b"x = \xff 1").err(), Some(ErrorType::TrailingAtomics));
+        assert_eq!(
+            parse(deep.as_bytes()).err(),
+            Some(ErrorType::NestingTooDeep)
+        );
+    }
+
+    #[test]
+    fn test_relex() {
+        use crate::lexer::{self, Edit, Token};
+
+        let lex = |data: &[u8]| -> Vec<Token> {
+            let info = FileInfo {
+                data,
+                name: String::from("relex"),
+            };
+            lexer::new(data, &info).map(|tok| tok.unwrap()).collect()
+        };
+        let before = "fn add(a, b) {\n    return a + b\n}\nx = \"text\"\ny = add(x, 1)\n";
+        //The start, removed length and inserted text of each edit
+        let edits: [(usize, usize, &str); 8] = [
+            (0, 0, "# comment\n"),
+            (5, 0, "itional"),
+            (22, 6, "a * b"),
+            (14, 15, ""),
+            (36, 0, " more"),
+            (32, 1, "\"\nz = 1\n"),
+            (before.len(), 0, "z = y"),
+            (0, before.len(), ""),
+        ];
+
+        for (start, removed, inserted) in edits {
+            let after = format!(
+                "{}{}{}",
+                &before[..start],
+                inserted,
+                &before[start + removed..]
+            );
+            let info = FileInfo {
+                data: after.as_bytes(),
+                name: String::from("relex"),
+            };
+            let edit = Edit {
+                start,
+                removed,
+                inserted: inserted.len(),
+            };
+            let tokens = lexer::relex(&info, &lex(before.as_bytes()), edit).unwrap();
+            assert_eq!(tokens, lex(after.as_bytes()), "{:?}", after);
+        }
+
+        let info = FileInfo {
+            data: b"x = 1\ny = x\n",
+            name: String::from("relex"),
+        };
+        let ast = crate::parser::from_tokens(lex(info.data)).generate_ast();
+        assert_eq!(ast.map(|ast| ast.len()).ok(), Some(2));
+    }
+
+    //Each program in src/tests/grammar must parse to the syntax tree in the JSON file beside it,
+    //as --dump-ast prints it. See src/parser/grammar.ebnf.
+    #[test]
+    fn test_grammar_conformance() {
+        use crate::parser;
+
+        let mut programs: Vec<std::path::PathBuf> = std::fs::read_dir("src/tests/grammar")
+            .expect("Grammar tests are missing")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "me"))
+            .collect();
+        programs.sort();
+        assert!(!programs.is_empty());
+
+        for program in programs {
+            let data = std::fs::read(&program).unwrap();
+            let info = FileInfo {
+                data: &data,
+                name: program.display().to_string(),
+            };
+            let ast = parser::parse_safe(&info)
+                .unwrap_or_else(|err| panic!("{}: {}", info.name, err.message));
+            let expected = std::fs::read_to_string(program.with_extension("json")).unwrap();
+            assert_eq!(
+                parser::nodes::json_array(&ast, 0),
+                expected.trim_end(),
+                "{} does not parse to its expected tree",
+                info.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_node_data() {
+        use crate::parser::{
+            nodes::{IdentifierNode, Node, NodeType},
+            Position,
+        };
+
+        let start = Position {
+            startcol: 4,
+            endcol: 5,
+            line: 2,
+        };
+        let node = Node::new(
+            start,
+            start,
+            NodeType::Identifier,
+            Box::new(IdentifierNode {
+                name: String::from("x"),
+            }),
+        );
+
+        assert_eq!(
+            node.raw("name").map_err(|err| err.message),
+            Ok(String::from("x"))
+        );
+        let err = node.child("expr").expect_err("Identifier has no child");
+        assert_eq!(err.message, "Identifier node has no node 'expr'");
+        assert_eq!(err.pos, start);
+        assert!(node.args().is_err());
+    }
+
+    #[test]
+    fn test_line_table() {
+        use crate::parser::Position;
+
+        let positions: Vec<(Position, Position)> = (0..100)
+            .map(|i| {
+                let start = Position {
+                    startcol: i % 7,
+                    endcol: i % 7 + 3,
+                    line: (i * 37) % 11 + i / 3,
+                };
+                let end = Position {
+                    startcol: 0,
+                    endcol: 200 + i,
+                    line: start.line + i % 2,
+                };
+                (start, end)
+            })
+            .collect();
+        let table = compiler::linetable::LineTable::new(&positions);
+        for (i, position) in positions.iter().enumerate() {
+            assert_eq!(table.get(i), Some(*position));
+        }
+        assert_eq!(table.get(positions.len()), None);
+    }
+
+    #[test]
+    fn test_diagnostics_buffer() {
+        use crate::{
+            errors::{print_error, ErrorType},
+            parser::Position,
+        };
+
+        let info = FileInfo {
+            data: b"x = 1\ny = x +* 2\n",
+            name: String::from("diagnostics"),
+        };
+        let pos = Position {
+            startcol: 6,
+            endcol: 7,
+            line: 1,
+        };
+        let out = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Auto);
+        print_error(
+            "Unexpected '*'",
+            ErrorType::UnexpectedToken,
+            &pos,
+            &info,
+            &out,
+        );
+        let text = String::from_utf8(out.take_buffer()).expect("Output is not utf8");
+        assert_eq!(
+            text,
+            "error[E001]: Unexpected '*'\ndiagnostics:2:7\n2 | y = x +* 2\n  |       ^   \n"
+        );
+        assert!(out.take_buffer().is_empty());
+
+        let colored = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Always);
+        print_error(
+            "Unexpected '*'",
+            ErrorType::UnexpectedToken,
+            &pos,
+            &info,
+            &colored,
+        );
+        assert!(colored.take_buffer().contains(&0x1b));
+    }
+
+    #[test]
+    fn test_eval_error_position() {
+        use crate::{errors::print_error, parser};
+
+        //Evaluated source does not end with a newline, so its last node ends at the end of input
+        let output = run_error("x = eval(\"env.args()\", 1 == 1)\n");
+        assert_eq!(
+            output,
+            "PermissionExc: \"Builtin 'args' is not allowed in restricted code\"\n<eval>:1:1\n1 | env.args()\n  | ^^^^^^^^^^\n"
+        );
+        let output = run_error("k = 1\nx = eval(\"k + y\")\n");
+        assert_eq!(
+            output,
+            "NameExc: \"Name 'y' not defined\"\n<eval>:1:5\n1 | k + y\n  |     ^\n"
+        );
+
+        //An error at the end of a line has an arrow after its last column
+        let info = program("x = (1 +");
+        let err = parser::parse_safe(info).expect_err("An incomplete program was parsed");
+        let out = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+        print_error(&err.message, err.tp, &err.pos, info, &out);
+        let text = String::from_utf8(out.take_buffer()).expect("Output is not utf8");
+        assert!(
+            text.ends_with("test:1:9\n1 | x = (1 +\n  |         ^\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_timeit_error() {
+        let mut vm = test_vm();
+        let bytecode = compile(&vm, "x = 1\ny = x / 0\n");
+        let interpreter = interpreter::Interpreter::new(vm.namespaces.clone(), vm.clone());
+        vm.interpreters.push(Trc::new(interpreter));
+
+        let mut holder = TimeitHolder {
+            baseline: 0,
+            time: 0.,
+            iterations: 0,
+            groups: 1,
+        };
+        assert!(interpreter::VM::execute_timeit(vm.clone(), &bytecode, &mut holder).is_none());
+        assert_eq!(holder.iterations, 0);
+        assert!(vm.interpreters.is_empty());
+        assert!(!vm.catch_errors);
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with("DivisionByZeroExc"));
+    }
+
+    #[cfg(feature = "checked")]
+    #[test]
+    fn test_checked_registers() {
+        use crate::compiler::CompilerRegister;
+
+        let vm = test_vm();
+        let mut bytecode = compile(&vm, "x = 1\ny = x + 2\n");
+        let last = bytecode.instructions.len() - 1;
+        *bytecode.code.instructions[last].registers_mut()[0] = CompilerRegister::R(100);
+        let output = error_of(vm, &bytecode);
+        assert!(output.starts_with(&format!(
+            "error[E016]: Malformed bytecode: instruction {} ({}): register R(100) does not exist",
+            last,
+            bytecode.instructions[last].name()
+        )));
+        assert!(output.contains("test:2:"));
+    }
+
+    #[test]
+    fn test_typed_internals() {
+        use crate::{
+            compiler::CompilerInstruction,
+            objects::{intobject, stringobject},
+        };
+
+        let vm = test_vm();
+        let int = intobject::int_from(vm.clone(), 3);
+        let string = stringobject::string_from(vm.clone(), String::from("3"));
+        assert_eq!(int.as_int(), Some(3));
+        assert_eq!(string.as_int(), None);
+        assert_eq!(string.as_str(), Some("3"));
+        assert!(int.as_list().is_none());
+
+        //The name of the function is read from a const that is not a string
+        let mut bytecode = compile(&vm, "fn f(a) {\n    return a\n}\n");
+        let idx = bytecode
+            .instructions
+            .iter()
+            .position(|i| matches!(i, CompilerInstruction::MakeFunction { .. }))
+            .expect("No MakeFunction");
+        if let CompilerInstruction::MakeFunction {
+            nameidx, codeidx, ..
+        } = &mut bytecode.code.instructions[idx]
+        {
+            *nameidx = *codeidx;
+        }
+        let output = error_of(vm, &bytecode);
+        //With the checked feature, the check of the instruction finds the const before it runs
+        assert!(output.starts_with(&format!(
+            "error[E016]: Malformed bytecode: instruction {} (MakeFunction): ",
+            idx
+        )));
+        assert!(output.lines().next().unwrap().ends_with("is not a string"));
+    }
+
+    #[cfg(feature = "threaded")]
+    #[test]
+    fn test_threaded_refcount() {
+        let rc = Trc::new(String::from("shared"));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let rc = rc.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10000 {
+                        let clone = rc.clone();
+                        assert_eq!(clone.as_str(), "shared");
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().expect("Thread panicked");
+        }
+        //The clones of every thread were counted on the one count, and dropped from it
+        assert_eq!(Trc::atomic_count(&rc), 1);
+    }
+
+    #[cfg(feature = "safe-internals")]
+    #[test]
+    #[should_panic(expected = "read the str of an object that holds int")]
+    fn test_safe_internals() {
+        use crate::objects::intobject;
+
+        let int = intobject::int_from(test_vm(), 1);
+        assert_eq!(unsafe { int.internals.int() }, 1);
+        unsafe { int.internals.str() };
+    }
+
+    //Resolve the names of a program, with print as the only builtin, and return the errors that
+    //were reported and whether resolving stopped the program
+    fn resolve_errors(source: &'static str) -> (Vec<String>, bool) {
+        let info = program(source);
+        let out = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Never);
+        let ast = parse(info, &out);
+        let builtins = hashbrown::HashSet::from([String::from("print")]);
+        let stopped = crate::catch_exit(|| {
+            compiler::resolver::Resolver::new(info, builtins, &out).resolve(&ast)
+        })
+        .is_err();
+
+        let output = String::from_utf8(out.take_buffer()).expect("Output is not utf8");
+        let errors = output
+            .lines()
+            .filter(|line| line.starts_with("error"))
+            .map(String::from)
+            .collect();
+        (errors, stopped)
+    }
+
+    #[test]
+    fn test_resolve_undefined_names() {
+        assert_eq!(
+            resolve_errors("x = 1\ny = x + 1\nprint(y)\n"),
+            (Vec::new(), false)
+        );
+
+        let (errors, stopped) = resolve_errors("x = y\n");
+        assert_eq!(errors, ["error[E012]: Name 'y' is not defined"]);
+        assert!(stopped);
+
+        //A name may only be read after the statement that assigns it, also in a function
+        let (errors, _) = resolve_errors("x = y\ny = 1\n");
+        assert_eq!(errors, ["error[E012]: Name 'y' is not defined"]);
+        let (errors, _) =
+            resolve_errors("fn f() {\n    return g()\n}\nfn g() {\n    return 1\n}\n");
+        assert_eq!(errors, ["error[E012]: Name 'g' is not defined"]);
+    }
+
+    #[test]
+    fn test_resolve_function_scopes() {
+        //Parameters are only bound in the body, which may also read the enclosing scopes and the
+        //function itself
+        assert_eq!(
+            resolve_errors("fn f(a, b) {\n    return a + b + f(a, b)\n}\nx = f(1, 2)\n"),
+            (Vec::new(), false)
+        );
+        assert_eq!(
+            resolve_errors("fn f(a) {\n    fn g() {\n        return a\n    }\n    return g\n}\n"),
+            (Vec::new(), false)
+        );
+        let (errors, _) = resolve_errors("fn f(a) {\n    b = a\n    return b\n}\nx = a + b\n");
+        assert_eq!(
+            errors,
+            [
+                "error[E012]: Name 'a' is not defined",
+                "error[E012]: Name 'b' is not defined"
+            ]
+        );
+
+        let (errors, _) = resolve_errors("fn f(a, a) {\n    return a\n}\n");
+        assert_eq!(
+            errors,
+            ["error[E013]: Duplicate parameter 'a' in function 'f'"]
+        );
+        let (errors, _) =
+            resolve_errors("x = 1\nfn f() {\n    y = x\n    x = 2\n    return y\n}\n");
+        assert_eq!(
+            errors,
+            ["error[E014]: Cannot bind 'x', as it was read at line 3 from the binding at line 1"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_class_scopes() {
+        //Methods may refer to their class, but not to the other names of the class body
+        assert_eq!(
+            resolve_errors("class A {\n    fn m(self) {\n        return A\n    }\n}\nx = A.m\n"),
+            (Vec::new(), false)
+        );
+        let (errors, _) = resolve_errors(
+            "class A {\n    fn m(self) {\n        return 1\n    }\n    fn n(self) {\n        return m(self)\n    }\n}\nx = m\n",
+        );
+        assert_eq!(
+            errors,
+            [
+                "error[E012]: Name 'm' is not defined",
+                "error[E012]: Name 'm' is not defined"
+            ]
+        );
+        let (errors, _) =
+            resolve_errors("class B(C) {\n    fn m(self) {\n        return 1\n    }\n}\n");
+        assert_eq!(errors, ["error[E012]: Name 'C' is not defined"]);
+    }
+
+    #[test]
+    fn test_resolve_several_errors() {
+        //Every error is reported before the program stops, in the order of the source
+        let (errors, stopped) =
+            resolve_errors("x = a\ny = b\nfn f(p, p) {\n    return q\n}\nz = x + y\n");
+        assert_eq!(
+            errors,
+            [
+                "error[E012]: Name 'a' is not defined",
+                "error[E012]: Name 'b' is not defined",
+                "error[E013]: Duplicate parameter 'p' in function 'f'",
+                "error[E012]: Name 'q' is not defined"
+            ]
+        );
+        assert!(stopped);
+    }
+
+    #[test]
+    fn test_warn_unused() {
+        let info = FileInfo {
+            data: b"x = 1\ny = 2\nfn f(a) {\n    b = a\n    return a\n}\nfn g() {\n    return x\n}\nz = f(1)\nfn main() {\n    return z\n}\n",
+            name: String::from("unused"),
+        };
+        let out = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Auto);
+        let ast = parse(&info, &out);
+        let mut resolver =
+            compiler::resolver::Resolver::new(&info, hashbrown::HashSet::new(), &out);
+        resolver.warn_unused = true;
+        resolver.resolve(&ast);
+
+        let output = String::from_utf8(out.take_buffer()).expect("Output is not utf8");
+        let warnings: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("warning"))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "warning[W001]: Variable 'y' is assigned but never read",
+                "warning[W001]: Variable 'b' is assigned but never read",
+                "warning[W002]: Function 'g' is defined but never used",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redefinition_warning() {
+        let info = FileInfo {
+            data: b"fn f() {\n    return 1\n}\nclass A {\n    fn m(self) {\n        return 0\n    }\n}\nx = 1\nx = 2\nfn f() {\n    return 2\n}\nA = f()\n",
+            name: String::from("redefinition"),
+        };
+        let out = Diagnostics::new(Sink::Buffer(Vec::new()), ColorMode::Auto);
+        let ast = parse(&info, &out);
+        compiler::resolver::Resolver::new(&info, hashbrown::HashSet::new(), &out).resolve(&ast);
+
+        let output = String::from_utf8(out.take_buffer()).expect("Output is not utf8");
+        let warnings: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("warning"))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "warning[W003]: Binding 'f' replaces the function defined at line 1, column 1",
+                "warning[W003]: Binding 'A' replaces the class defined at line 4, column 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_value_warning() {
+        let vm = test_vm();
+        compile(
+            &vm,
+            "x = 1\nx + 2\nx\ny = x * 2\nfn f() {\n    1 < 2\n    return f\n}\nf()\n",
+        );
+
+        let output = diagnostics_of(&vm);
+        let locations: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("test:"))
+            .collect();
+        assert_eq!(locations, ["test:2:1", "test:3:1", "test:6:5"]);
+    }
+
+    #[test]
+    fn test_container_repr() {
+        use crate::objects::{dictobject, intobject, listobject, mhash, stringobject, RawObject};
+
+        let mut vm = test_vm();
+        let string = stringobject::string_from(vm.clone(), String::from("a \"b\"\n"));
+        let mut map = mhash::HashMap::new();
+        map.insert(string.clone(), string.clone());
+        let dict = dictobject::dict_from(vm.clone(), map);
+        let list = listobject::list_from(
+            vm.clone(),
+            vec![intobject::int_from(vm.clone(), 1), string.clone(), dict],
+        );
+        assert_eq!(RawObject::object_str(&string), "a \"b\"\n");
+        let expected = r#"[1, "a \"b\"\n", {"a \"b\"\n": "a \"b\"\n"}]"#;
+        assert_eq!(RawObject::object_repr(&list), expected);
+        assert_eq!(RawObject::object_str(&list), expected);
+
+        let items = (0..5).map(|i| intobject::int_from(vm.clone(), i)).collect();
+        let long = listobject::list_from(vm.clone(), items);
+        vm.repr_limit = Some(3);
+        assert_eq!(RawObject::object_repr(&long), "[0, 1, 2, ...]");
+        vm.repr_limit = Some(5);
+        assert_eq!(RawObject::object_repr(&long), "[0, 1, 2, 3, 4]");
+    }
+
+    #[test]
+    fn test_duplicate_dict_keys() {
+        let vm = test_vm();
+        let bytecode = compile(
+            &vm,
+            "const K = 1\nd = {1: 2, 1.0: 3, K: 4, \"a\": 5, \"a\": 6}\n",
+        );
+
+        let output = diagnostics_of(&vm);
+        let lines: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("warning") || line.starts_with("test:"))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "warning[W005]: Key repeats the key at line 2, column 6, only the last value is kept",
+                "test:2:20",
+                "warning[W005]: Key repeats the key at line 2, column 26, only the last value is kept",
+                "test:2:34",
+            ]
+        );
+
+        let namespace = execute(&vm, &bytecode);
+        let d = value(&namespace, "d");
+        let Ok(Value::Dict(entries)) = d else {
+            panic!("d is not a dict");
+        };
+        assert_eq!(entries.len(), 3);
+        assert!(entries.contains(&(Value::Int(1), Value::Int(4))));
+        assert!(entries.contains(&(Value::Float(1.0), Value::Int(3))));
+        assert!(entries.contains(&(Value::Str(String::from("a")), Value::Int(6))));
+    }
+
+    #[test]
+    fn test_timeit_step_limit() {
+        let mut vm = test_vm();
+        let bytecode = compile(&vm, "x = 1\ny = x + 1\nz = y + 1\n");
+        let mut holder = TimeitHolder {
+            baseline: 0,
+            time: 0.,
+            iterations: 0,
+            groups: 1,
+        };
+
+        vm.step_limit = Some(bytecode.instructions.len());
+        let interpreter = interpreter::Interpreter::new(vm.namespaces.clone(), vm.clone());
+        vm.interpreters.push(Trc::new(interpreter));
+        assert!(interpreter::VM::execute_timeit(vm.clone(), &bytecode, &mut holder).is_some());
+        assert!(vm.diagnostics.take_buffer().is_empty());
+
+        vm.step_limit = Some(bytecode.instructions.len() - 1);
+        assert!(interpreter::VM::execute_timeit(vm.clone(), &bytecode, &mut holder).is_none());
+        assert!(vm.interpreters.is_empty());
+        let output = diagnostics_of(&vm);
+        assert!(output.starts_with("TimeoutExc"));
+    }
+
+    #[test]
+    fn test_timeit_call_depth() {
+        use crate::{interpreter::MAX_CALL_DEPTH, on_large_stack, time_source};
+
+        //Runaway recursion stops at the call depth limit before the step limit or the stack
+        let options = RunOptions {
+            time: Some(1),
+            step_limit: Some(MAX_CALL_DEPTH * 100),
+            ..Default::default()
+        };
+        let res = on_large_stack(|| {
+            time_source(
+                "fn f(n) {\n    return f(n)\n}\nx = f(1)\n",
+                "recursion",
+                3,
+                &options,
+            )
+            .err()
+        });
+        assert_eq!(res, Some(ExitCode::Runtime));
+    }
+
+    #[test]
+    fn test_timeit_report() {
+        use crate::{format_time, stats};
+
+        assert_eq!(format_time(512.0), "512.000 ns");
+        assert_eq!(format_time(5264.0), "5.264 µs");
+        assert_eq!(format_time(2.5e6), "2.500 ms");
+        assert_eq!(format_time(3e9), "3.000 s");
+
+        let times = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(stats::mean(&times), 5.0);
+        assert!((stats::std_dev(&times) - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(stats::std_dev(&[1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_bench_suite() {
+        use crate::{bench, time_source};
+
+        let options = RunOptions {
+            time: Some(1),
+            ..Default::default()
+        };
+        let timings = time_source("x = 1 + 2\n", "sum", 2, &options).expect("Sum was not timed");
+        assert_eq!(timings.times.len(), 2);
+        assert!(timings.iterations > 0);
+        assert_eq!(
+            time_source("x = 1 + \"a\"\n", "raises", 1, &options).err(),
+            Some(ExitCode::Runtime)
+        );
+        assert_eq!(
+            time_source("x = y\n", "undefined", 1, &options).err(),
+            Some(ExitCode::Syntax)
+        );
+
+        let dir = std::env::temp_dir().join(format!("merlin-bench-suite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Unable to create the directory");
+        std::fs::write(dir.join("sum.me"), "x = 1 + 2\n").expect("Unable to write the program");
+        std::fs::write(dir.join("notes.txt"), "x = y\n").expect("Unable to write the notes");
+        assert_eq!(
+            bench::bench_suite(&dir, &options, bench::time_in_process),
+            ExitCode::Success
+        );
+        std::fs::write(dir.join("raises.me"), "x = 1 + \"a\"\n")
+            .expect("Unable to write the program");
+        assert_eq!(
+            bench::bench_suite(&dir, &options, bench::time_in_process),
+            ExitCode::Runtime
+        );
+        std::fs::remove_dir_all(&dir).expect("Unable to remove the directory");
+
+        assert_eq!(
+            bench::bench_suite(&dir, &options, bench::time_in_process),
+            ExitCode::Usage
+        );
+    }
+
+    #[test]
+    fn test_bench_json_number() {
+        use crate::bench::json_number;
+
+        let json = "{\"best_ns\": 3869.296, \"mean_ns\": 4000.500, \"std_dev_ns\": 0.000, \"samples\": 1, \"iterations\": 251}";
+        assert_eq!(json_number(json, "best_ns"), Some(3869.296));
+        assert_eq!(json_number(json, "mean_ns"), Some(4000.5));
+        assert_eq!(json_number(json, "std_dev_ns"), Some(0.0));
+        assert_eq!(json_number(json, "iterations"), Some(251.0));
+        assert_eq!(json_number(json, "runs"), None);
+        assert_eq!(json_number("{\"best_ns\": 12", "best_ns"), None);
+        assert_eq!(json_number("{\"best_ns\": fast}", "best_ns"), None);
+    }
+
+    #[test]
+    fn test_bench_child_args() {
+        use crate::bench::child_args;
+        use crate::{IntOverflow, LogLevel, PathBuf};
+
+        assert_eq!(
+            child_args(3, &RunOptions::default()),
+            [
+                "-t",
+                "3",
+                "--json",
+                "--color=never",
+                "--log-level=info",
+                "--int-overflow=raise",
+                "--repr-limit=0"
+            ]
+        );
+        let options = RunOptions {
+            step_limit: Some(1000),
+            int_cache: Some(512),
+            cache_dir: Some(PathBuf::from("cache")),
+            log_level: LogLevel::Error,
+            int_overflow: IntOverflow::Wrap,
+            repr_limit: Some(20),
+            optimize: true,
+            strict: true,
+            warn_unused: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            child_args(1, &options),
+            [
+                "-t",
+                "1",
+                "--json",
+                "--color=never",
+                "--log-level=error",
+                "--int-overflow=wrap",
+                "--repr-limit=20",
+                "--step-limit=1000",
+                "--int-cache=512",
+                "--cache-dir=cache",
+                "--optimize",
+                "--strict",
+                "--warn=unused"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bench_time_in_child() {
+        use crate::bench::time_with;
+
+        //The test harness is in deps, next to the merlin executable of the same profile
+        let mut build = std::process::Command::new(env!("CARGO"));
+        build.args(["build", "--bin", "merlin", "--manifest-path"]);
+        build.arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        if !cfg!(debug_assertions) {
+            build.arg("--release");
+        }
+        let built = build.output().expect("Cargo did not run");
+        assert!(
+            built.status.success(),
+            "{}",
+            String::from_utf8_lossy(&built.stderr)
+        );
+        let exe = std::env::current_exe().expect("The test has no path");
+        let exe = exe
+            .parent()
+            .and_then(|deps| deps.parent())
+            .expect("The test is not in a target directory")
+            .join("merlin");
+
+        let dir = std::env::temp_dir().join(format!("merlin-bench-child-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Unable to create the directory");
+        //Its warnings are printed before its times
+        let warns = dir.join("warns.me");
+        std::fs::write(&warns, "x = 1\nfn f() {\n    y = 2\n}\n")
+            .expect("Unable to write the program");
+        let options = RunOptions {
+            warn_unused: true,
+            ..Default::default()
+        };
+        let (best, mean, std_dev, runs) =
+            time_with(&exe, &warns, 2, &options).expect("The program was not timed");
+        assert!(best > 0.0 && best <= mean);
+        assert!(std_dev >= 0.0);
+        assert!(runs > 0);
+
+        let exits = dir.join("exits.me");
+        std::fs::write(&exits, "env.exit(5)\n").expect("Unable to write the program");
+        assert_eq!(
+            time_with(&exe, &exits, 1, &RunOptions::default()),
+            Err(ExitCode::Exit(5))
+        );
+        let undefined = dir.join("undefined.me");
+        std::fs::write(&undefined, "x = y\n").expect("Unable to write the program");
+        assert_eq!(
+            time_with(&exe, &undefined, 1, &RunOptions::default()),
+            Err(ExitCode::Syntax)
+        );
+        std::fs::remove_dir_all(&dir).expect("Unable to remove the directory");
+        assert_eq!(
+            time_with(&dir.join("missing"), &exits, 1, &RunOptions::default()),
+            Err(ExitCode::Usage)
+        );
     }
 }
@@ -0,0 +1,278 @@
+//Ints that do not fit in a machine int. Int operations give them when they overflow and the VM
+//promotes overflowing ints, and they mix with ints in arithmetic. A bigint is never in the range of
+//an int: results that fit are ints again, so equal numbers are always the same type.
+
+use super::exceptionobject::{
+    overflowexc_from_str, typemismatchexc_from_str, valueexc_from_str, zerodivexc_from_str,
+};
+use super::{
+    boolobject, create_object_from_type, finalize_type, finalize_type_dict, intobject,
+    stringobject, MethodType, MethodValue, Object, ObjectInternals, TypeObject,
+};
+
+use crate::is_type_exact;
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use crate::{interpreter::VM, parser::Position};
+use num::{BigInt, ToPrimitive, Zero};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+//The most bits that a left shift may add, so that a shift does not exhaust memory
+const MAX_SHIFT: usize = 1 << 20;
+
+pub fn bigint_from(vm: Trc<VM<'_>>, raw: BigInt) -> Object<'_> {
+    if let Some(small) = raw.to_isize() {
+        return intobject::int_from(vm, small);
+    }
+    let mut tp = create_object_from_type(unwrap_fast!(vm.types.bigtp.as_ref()).clone(), vm, None);
+    tp.internals = ObjectInternals::new_big(raw);
+    tp
+}
+
+#[inline]
+pub fn is_bigint(object: &Object<'_>) -> bool {
+    is_type_exact!(object, unwrap_fast!(object.vm.types.bigtp.as_ref()))
+}
+
+//The value of an int or bigint
+fn to_big(object: &Object<'_>) -> Option<BigInt> {
+    if is_bigint(object) {
+        Some(BigInt::clone(unsafe { object.internals.big() }))
+    } else if is_type_exact!(object, unwrap_fast!(object.vm.types.inttp.as_ref())) {
+        Some(BigInt::from(unsafe { object.internals.int() }))
+    } else {
+        None
+    }
+}
+
+//The values of both operands, which may be ints or bigints
+fn operands<'a>(
+    selfv: &Object<'a>,
+    other: &Object<'a>,
+) -> MethodValue<(BigInt, BigInt), Object<'a>> {
+    match (to_big(selfv), to_big(other)) {
+        (Some(a), Some(b)) => MethodValue::Some((a, b)),
+        _ => MethodValue::Error(typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        )),
+    }
+}
+
+//A shift count or power, which must be a non-negative int
+fn count<'a>(selfv: &Object<'a>, other: &Object<'a>, what: &str) -> MethodValue<usize, Object<'a>> {
+    if !is_type_exact!(other, unwrap_fast!(other.vm.types.inttp.as_ref())) {
+        return MethodValue::Error(typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        ));
+    }
+    let value = unsafe { other.internals.int() };
+    if value < 0 {
+        return MethodValue::Error(valueexc_from_str(
+            selfv.vm.clone(),
+            &format!("Negative {}", what),
+            Position::default(),
+            Position::default(),
+        ));
+    }
+    MethodValue::Some(value as usize)
+}
+
+fn bigint_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
+    unimplemented!();
+}
+
+fn bigint_repr(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(stringobject::string_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.big() }.to_string(),
+    ))
+}
+fn bigint_abs(selfv: Object<'_>) -> MethodType<'_> {
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), num::Signed::abs(&value)))
+}
+fn bigint_neg(selfv: Object<'_>) -> MethodType<'_> {
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), -value))
+}
+fn bigint_invert(selfv: Object<'_>) -> MethodType<'_> {
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), !value))
+}
+fn bigint_hash(selfv: Object<'_>) -> MethodType<'_> {
+    let mut hasher = DefaultHasher::new();
+    unsafe { selfv.internals.big() }.hash(&mut hasher);
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        hasher.finish() as isize,
+    ))
+}
+fn bigint_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    //Bigints are never equal to ints, which hold the smaller values
+    if !is_type_exact!(&selfv, other.tp) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        *unsafe { selfv.internals.big() } == *unsafe { other.internals.big() },
+    ))
+}
+
+macro_rules! bigint_compare {
+    ($name:ident, $op:tt) => {
+        pub fn $name<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+            let res = operands(&selfv, &other);
+            if res.is_error() {
+                return MethodValue::Error(res.unwrap_err());
+            }
+            let (a, b) = res.unwrap();
+            MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), a $op b))
+        }
+    };
+}
+
+bigint_compare!(bigint_lt, <);
+bigint_compare!(bigint_gt, >);
+
+macro_rules! bigint_binop {
+    ($name:ident, $op:tt) => {
+        pub fn $name<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+            let res = operands(&selfv, &other);
+            if res.is_error() {
+                return MethodValue::Error(res.unwrap_err());
+            }
+            let (a, b) = res.unwrap();
+            MethodValue::Some(bigint_from(selfv.vm.clone(), a $op b))
+        }
+    };
+}
+
+bigint_binop!(bigint_add, +);
+bigint_binop!(bigint_sub, -);
+bigint_binop!(bigint_mul, *);
+bigint_binop!(bigint_bitand, &);
+bigint_binop!(bigint_bitor, |);
+bigint_binop!(bigint_bitxor, ^);
+
+//Rounds toward zero, like int division
+pub fn bigint_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let res = operands(&selfv, &other);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let (a, b) = res.unwrap();
+    if b.is_zero() {
+        let exc = zerodivexc_from_str(
+            selfv.vm.clone(),
+            "Division by 0",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(bigint_from(selfv.vm.clone(), a / b))
+}
+fn bigint_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let res = count(&selfv, &other, "power");
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let power = res.unwrap();
+    if power >= u32::MAX as usize {
+        let exc = overflowexc_from_str(
+            selfv.vm.clone(),
+            "Power is too large",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), value.pow(power as u32)))
+}
+pub fn bigint_lshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let res = count(&selfv, &other, "shift count");
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let shift = res.unwrap();
+    if shift > MAX_SHIFT {
+        let exc = overflowexc_from_str(
+            selfv.vm.clone(),
+            "Shift is too large",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), value << shift))
+}
+fn bigint_rshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let res = count(&selfv, &other, "shift count");
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let value = unwrap_fast!(to_big(&selfv));
+    MethodValue::Some(bigint_from(selfv.vm.clone(), value >> res.unwrap()))
+}
+
+pub fn init(mut vm: Trc<VM<'_>>) {
+    let tp = Trc::new(TypeObject {
+        typename: String::from("bigint"),
+        bases: vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )],
+        typeid: vm.types.n_types,
+        dict: None,
+
+        new: Some(bigint_new),
+
+        repr: Some(bigint_repr),
+        str: Some(bigint_repr),
+        abs: Some(bigint_abs),
+        neg: Some(bigint_neg),
+        invert: Some(bigint_invert),
+        hash_fn: Some(bigint_hash),
+
+        eq: Some(bigint_eq),
+        lt: Some(bigint_lt),
+        gt: Some(bigint_gt),
+        add: Some(bigint_add),
+        sub: Some(bigint_sub),
+        mul: Some(bigint_mul),
+        div: Some(bigint_div),
+        pow: Some(bigint_pow),
+        bitand: Some(bigint_bitand),
+        bitor: Some(bigint_bitor),
+        bitxor: Some(bigint_bitxor),
+        lshift: Some(bigint_lshift),
+        rshift: Some(bigint_rshift),
+
+        get: None,
+        set: None,
+        len: None,
+        contains: None,
+
+        call: None,
+
+        getattr: None,
+        setattr: None,
+        descrget: None,
+        descrset: None,
+    });
+
+    vm.types.bigtp = Some(tp.clone());
+    vm.types.n_types += 1;
+
+    finalize_type(tp.clone());
+    finalize_type_dict(tp);
+}
@@ -1,10 +1,10 @@
 use crate::is_type_exact;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
     interpreter::VM,
     objects::{boolobject, stringobject},
 };
-use trc::Trc;
 
 use super::finalize_type_dict;
 use super::{
@@ -25,7 +25,7 @@ fn bool_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> M
 fn bool_repr(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(stringobject::string_from(
         selfv.vm.clone(),
-        unsafe { selfv.internals.bool }.to_string(),
+        unsafe { selfv.internals.bool() }.to_string(),
     ))
 }
 fn bool_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
@@ -35,13 +35,13 @@ fn bool_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { selfv.internals.bool } == unsafe { other.internals.bool },
+        unsafe { selfv.internals.bool() } == unsafe { other.internals.bool() },
     ))
 }
 fn bool_hash(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(intobject::int_from(
         selfv.vm.clone(),
-        unsafe { selfv.internals.bool } as isize,
+        unsafe { selfv.internals.bool() } as isize,
     ))
 }
 
@@ -52,12 +52,12 @@ pub fn generate_cache<'a>(
 ) {
     unsafe {
         let mut tp = create_object_from_type(booltp.clone(), vm.clone(), None);
-        tp.internals = ObjectInternals { bool: false };
+        tp.internals = ObjectInternals::new_bool(false);
         let ptr = &(*tup).0 as *const Option<Object> as *mut Option<Object>;
         std::ptr::write(ptr, Some(tp));
 
         let mut tp = create_object_from_type(booltp.clone(), vm, None);
-        tp.internals = ObjectInternals { bool: true };
+        tp.internals = ObjectInternals::new_bool(true);
         let ptr = &(*tup).1 as *const Option<Object> as *mut Option<Object>;
         std::ptr::write(ptr, Some(tp));
     }
@@ -78,18 +78,27 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(bool_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: Some(bool_hash),
 
         eq: Some(bool_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: None,
 
@@ -0,0 +1,136 @@
+use super::{
+    create_object_from_type, finalize_type, finalize_type_dict, BuiltinFn, MethodType, MethodValue,
+    Object, TypeObject,
+};
+
+use crate::objects::exceptionobject::permissionexc_from_str;
+use crate::parser::Position;
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use crate::{
+    interpreter::VM,
+    objects::{boolobject, methodobject, stringobject, ObjectInternals},
+};
+
+pub fn builtin_from<'a>(vm: Trc<VM<'a>>, name: String, fun: BuiltinFn<'a>) -> Object<'a> {
+    new_builtin(vm, name, fun, false)
+}
+
+//A builtin that does IO or controls the process, which restricted code may not call
+pub fn io_builtin_from<'a>(vm: Trc<VM<'a>>, name: String, fun: BuiltinFn<'a>) -> Object<'a> {
+    new_builtin(vm, name, fun, true)
+}
+
+fn new_builtin<'a>(vm: Trc<VM<'a>>, name: String, fun: BuiltinFn<'a>, is_io: bool) -> Object<'a> {
+    let mut tp =
+        create_object_from_type(unwrap_fast!(vm.types.builtintp.as_ref()).clone(), vm, None);
+    tp.internals = ObjectInternals::new_builtin(super::BuiltinData { name, fun, is_io });
+    tp
+}
+
+fn builtin_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
+    unimplemented!();
+}
+fn builtin_repr(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(stringobject::string_from(
+        selfv.vm.clone(),
+        format!(
+            "<builtin '{}' @ 0x{:x}>",
+            unsafe { selfv.internals.builtin() }.name,
+            Trc::as_ptr(&selfv) as usize
+        ),
+    ))
+}
+fn builtin_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    //Builtins are only created once, so they are equal only to themselves
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        Trc::ptr_eq(&selfv, &other),
+    ))
+}
+
+fn builtin_call<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let builtin = unsafe { selfv.internals.builtin() };
+    if builtin.is_io && selfv.vm.restricted {
+        let exc = permissionexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Builtin '{}' is not allowed in restricted code",
+                builtin.name
+            ),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    (builtin.fun)(selfv.clone(), args)
+}
+
+//Builtins in a type dict are methods, which are bound to the instance they are loaded from
+fn builtin_descrget<'a>(
+    selfv: Object<'a>,
+    instance: Option<Object<'a>>,
+    _owner: Object<'a>,
+) -> MethodType<'a> {
+    if let Some(instance) = instance {
+        MethodValue::Some(methodobject::method_from(
+            selfv.vm.clone(),
+            selfv.clone(),
+            instance.clone(),
+        ))
+    } else {
+        MethodValue::Some(selfv.clone())
+    }
+}
+
+pub fn init(mut vm: Trc<VM<'_>>) {
+    let tp = Trc::new(TypeObject {
+        typename: String::from("builtin"),
+        bases: vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )],
+        typeid: vm.types.n_types,
+        dict: None,
+
+        new: Some(builtin_new),
+
+        repr: Some(builtin_repr),
+        str: Some(builtin_repr),
+        abs: None,
+        neg: None,
+        invert: None,
+        hash_fn: None,
+        eq: Some(builtin_eq),
+        lt: None,
+        gt: None,
+        add: None,
+        sub: None,
+        mul: None,
+        div: None,
+        pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
+
+        get: None,
+        set: None,
+        len: None,
+        contains: None,
+
+        call: Some(builtin_call),
+
+        getattr: None,
+        setattr: None,
+        descrget: Some(builtin_descrget),
+        descrset: None,
+    });
+
+    vm.types.builtintp = Some(tp.clone());
+    vm.types.n_types += 1;
+
+    finalize_type(tp.clone());
+    finalize_type_dict(tp);
+}
@@ -1,18 +1,85 @@
 #![allow(unused_unsafe)]
-use trc::Trc;
+use crate::rc::Trc;
 
-use crate::{interpreter::VM, parser::Position, unwrap_fast};
+use crate::{
+    interpreter::{Interpreter, VM},
+    parser::Position,
+    unwrap_fast,
+};
 
 use super::{
-    create_object_from_typeobject, exceptionobject::methodnotdefinedexc_from_str, finalize_type,
-    listobject, stringobject, MethodType, MethodValue, Object, RawObject, TypeObject,
+    boolobject, call_with_first, create_object_from_type, create_object_from_typeobject,
+    exceptionobject::{methodnotdefinedexc_from_str, unhashableexc_from_str},
+    finalize_type, intobject, object_id, shape, stringobject, MethodType, MethodValue, Object,
+    ObjectInternals, RawObject, TypeObject,
 };
 
+//Find a method on the class or on the classes it inherits from
+fn class_method<'a>(selfv: &Object<'a>, name: &str) -> MethodType<'a> {
+    let res = selfv.tp.lookup(
+        stringobject::string_from(selfv.vm.clone(), name.to_string()),
+        0,
+    );
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    match unwrap_fast!(res) {
+        Some(method) => MethodValue::Some(method),
+        None => MethodValue::Error(methodnotdefinedexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Method '{}' is not defined for '{}' type",
+                name, selfv.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        )),
+    }
+}
+
+//Call a method of a class instance by name, passing the instance first
+pub fn call_method<'a>(selfv: Object<'a>, name: &str, args: Vec<Object<'a>>) -> MethodType<'a> {
+    let method = class_method(&selfv, name);
+    if method.is_error() {
+        return method;
+    }
+    let method = unwrap_fast!(method);
+    let call_fn = method.tp.call;
+    if call_fn.is_none() {
+        return MethodValue::Error(methodnotdefinedexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Method 'call' is not defined for '{}' type",
+                method.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        ));
+    }
+    call_with_first(unwrap_fast!(call_fn), method, selfv.clone(), &args)
+}
+
+//Call the drop method of an instance that is being freed. The instance can not be passed to it, so
+//drop is called with a new instance of the class that takes its attributes. That instance does not
+//call drop again when it is freed, even if drop stored it somewhere so that it outlives the call.
+pub fn call_drop<'a>(instance: &mut RawObject<'a>) {
+    let mut vm = instance.vm.clone();
+    let mut selfv = create_object_from_type(instance.tp.clone(), vm.clone(), instance.dict.take());
+    selfv.internals = std::mem::replace(&mut instance.internals, ObjectInternals::new_none(()));
+    vm.dropped.insert(object_id(&selfv));
+    let res = call_method(selfv, "drop", Vec::new());
+    if res.is_error() {
+        Interpreter::new(vm.namespaces.clone(), vm.clone()).raise_exc(res.unwrap_err());
+    }
+}
+
 //unary
 fn class_repr(selfv: Object<'_>) -> MethodType<'_> {
-    let repr = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "repr".to_string()),
-    );
+    let placeholder = format!("<{} ...>", selfv.tp.typename);
+    RawObject::recursive_repr_guard(selfv, &placeholder, class_repr_method)
+}
+fn class_repr_method(selfv: Object<'_>) -> MethodType<'_> {
+    let repr = class_method(&selfv, "repr");
     if repr.is_some() {
         let call_fn = unwrap_fast!(repr).tp.call;
         if call_fn.is_none() {
@@ -26,8 +93,7 @@ fn class_repr(selfv: Object<'_>) -> MethodType<'_> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(repr), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(repr), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -41,9 +107,11 @@ fn class_repr(selfv: Object<'_>) -> MethodType<'_> {
 }
 
 fn class_str(selfv: Object<'_>) -> MethodType<'_> {
-    let str = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "str".to_string()),
-    );
+    let placeholder = format!("<{} ...>", selfv.tp.typename);
+    RawObject::recursive_repr_guard(selfv, &placeholder, class_str_method)
+}
+fn class_str_method(selfv: Object<'_>) -> MethodType<'_> {
+    let str = class_method(&selfv, "str");
     if str.is_some() {
         let call_fn = unwrap_fast!(str).tp.call;
         if call_fn.is_none() {
@@ -57,8 +125,7 @@ fn class_str(selfv: Object<'_>) -> MethodType<'_> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(str), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(str), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -72,9 +139,7 @@ fn class_str(selfv: Object<'_>) -> MethodType<'_> {
 }
 
 fn class_abs(selfv: Object<'_>) -> MethodType<'_> {
-    let abs = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "abs".to_string()),
-    );
+    let abs = class_method(&selfv, "abs");
     if abs.is_some() {
         let call_fn = unwrap_fast!(abs).tp.call;
         if call_fn.is_none() {
@@ -88,8 +153,7 @@ fn class_abs(selfv: Object<'_>) -> MethodType<'_> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(abs), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(abs), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -103,9 +167,7 @@ fn class_abs(selfv: Object<'_>) -> MethodType<'_> {
 }
 
 fn class_neg(selfv: Object<'_>) -> MethodType<'_> {
-    let neg = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "neg".to_string()),
-    );
+    let neg = class_method(&selfv, "neg");
     if neg.is_some() {
         let call_fn = unwrap_fast!(neg).tp.call;
         if call_fn.is_none() {
@@ -119,8 +181,7 @@ fn class_neg(selfv: Object<'_>) -> MethodType<'_> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(neg), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(neg), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -133,30 +194,27 @@ fn class_neg(selfv: Object<'_>) -> MethodType<'_> {
     ))
 }
 
-fn class_hash(selfv: Object<'_>) -> MethodType<'_> {
-    let hash = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "hash".to_string()),
-    );
-    if hash.is_some() {
-        let call_fn = unwrap_fast!(hash).tp.call;
+fn class_invert(selfv: Object<'_>) -> MethodType<'_> {
+    let invert = class_method(&selfv, "invert");
+    if invert.is_some() {
+        let call_fn = unwrap_fast!(invert).tp.call;
         if call_fn.is_none() {
             return MethodValue::Error(methodnotdefinedexc_from_str(
                 selfv.vm.clone(),
                 &format!(
                     "Method 'call' is not defined for '{}' type",
-                    unwrap_fast!(hash).tp.typename
+                    unwrap_fast!(invert).tp.typename
                 ),
                 Position::default(),
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(hash), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(invert), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
         &format!(
-            "Method 'hash' is not defined for '{}' type",
+            "Method 'invert' is not defined for '{}' type",
             selfv.tp.typename
         ),
         Position::default(),
@@ -164,11 +222,46 @@ fn class_hash(selfv: Object<'_>) -> MethodType<'_> {
     ))
 }
 
+fn class_hash(selfv: Object<'_>) -> MethodType<'_> {
+    let hash = class_method(&selfv, "hash");
+    if hash.is_some() {
+        let call_fn = unwrap_fast!(hash).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(hash).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(hash), &[selfv]);
+    }
+    //Instances that define their own equality must define their own hash to be hashable
+    let eq = class_method(&selfv, "eq");
+    if eq.is_some() {
+        return MethodValue::Error(unhashableexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Unhashable type '{}' (it defines 'eq' but not 'hash')",
+                selfv.tp.typename
+            ),
+            Position::default(),
+            Position::default(),
+        ));
+    }
+    //Otherwise they are only equal to themselves, so hash by identity
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        Trc::as_ptr(&selfv) as isize,
+    ))
+}
+
 //binary
 fn class_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let eq = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "eq".to_string()),
-    );
+    let eq = class_method(&selfv, "eq");
     if eq.is_some() {
         let call_fn = unwrap_fast!(eq).tp.call;
         if call_fn.is_none() {
@@ -182,13 +275,64 @@ fn class_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(eq), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(eq), &[selfv, other]);
+    }
+    //Without an 'eq' method, instances are only equal to themselves
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        Trc::ptr_eq(&selfv, &other),
+    ))
+}
+
+fn class_lt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let lt = class_method(&selfv, "lt");
+    if lt.is_some() {
+        let call_fn = unwrap_fast!(lt).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(lt).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(lt), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
         &format!(
-            "Method 'hash' is not defined for '{}' type",
+            "Method 'lt' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
+fn class_gt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let gt = class_method(&selfv, "gt");
+    if gt.is_some() {
+        let call_fn = unwrap_fast!(gt).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(gt).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(gt), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'gt' is not defined for '{}' type",
             selfv.tp.typename
         ),
         Position::default(),
@@ -197,9 +341,7 @@ fn class_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let add = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "add".to_string()),
-    );
+    let add = class_method(&selfv, "add");
     if add.is_some() {
         let call_fn = unwrap_fast!(add).tp.call;
         if call_fn.is_none() {
@@ -213,8 +355,7 @@ fn class_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(add), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(add), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -228,9 +369,7 @@ fn class_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_sub<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let sub = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "sub".to_string()),
-    );
+    let sub = class_method(&selfv, "sub");
     if sub.is_some() {
         let call_fn = unwrap_fast!(sub).tp.call;
         if call_fn.is_none() {
@@ -244,8 +383,7 @@ fn class_sub<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(sub), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(sub), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -259,9 +397,7 @@ fn class_sub<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let mul = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "mul".to_string()),
-    );
+    let mul = class_method(&selfv, "mul");
     if mul.is_some() {
         let call_fn = unwrap_fast!(mul).tp.call;
         if call_fn.is_none() {
@@ -275,8 +411,7 @@ fn class_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(mul), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(mul), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -290,9 +425,7 @@ fn class_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let div = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "div".to_string()),
-    );
+    let div = class_method(&selfv, "div");
     if div.is_some() {
         let call_fn = unwrap_fast!(div).tp.call;
         if call_fn.is_none() {
@@ -306,8 +439,7 @@ fn class_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(div), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(div), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -321,9 +453,7 @@ fn class_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    let pow = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "pow".to_string()),
-    );
+    let pow = class_method(&selfv, "pow");
     if pow.is_some() {
         let call_fn = unwrap_fast!(pow).tp.call;
         if call_fn.is_none() {
@@ -337,8 +467,7 @@ fn class_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, other]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(pow), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(pow), &[selfv, other]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -351,11 +480,149 @@ fn class_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
     ))
 }
 
+fn class_bitand<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let bitand = class_method(&selfv, "bitand");
+    if bitand.is_some() {
+        let call_fn = unwrap_fast!(bitand).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(bitand).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(bitand), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'bitand' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
+fn class_bitor<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let bitor = class_method(&selfv, "bitor");
+    if bitor.is_some() {
+        let call_fn = unwrap_fast!(bitor).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(bitor).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(bitor), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'bitor' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
+fn class_bitxor<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let bitxor = class_method(&selfv, "bitxor");
+    if bitxor.is_some() {
+        let call_fn = unwrap_fast!(bitxor).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(bitxor).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(bitxor), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'bitxor' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
+fn class_lshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let lshift = class_method(&selfv, "lshift");
+    if lshift.is_some() {
+        let call_fn = unwrap_fast!(lshift).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(lshift).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(lshift), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'lshift' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
+fn class_rshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    let rshift = class_method(&selfv, "rshift");
+    if rshift.is_some() {
+        let call_fn = unwrap_fast!(rshift).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(rshift).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(rshift), &[selfv, other]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'rshift' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
 //sequences
 fn class_get<'a>(selfv: Object<'a>, key: Object<'a>) -> MethodType<'a> {
-    let get = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "get".to_string()),
-    );
+    let get = class_method(&selfv, "get");
     if get.is_some() {
         let call_fn = unwrap_fast!(get).tp.call;
         if call_fn.is_none() {
@@ -369,8 +636,7 @@ fn class_get<'a>(selfv: Object<'a>, key: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, key]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(get), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(get), &[selfv, key]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -384,9 +650,7 @@ fn class_get<'a>(selfv: Object<'a>, key: Object<'a>) -> MethodType<'a> {
 }
 
 fn class_set<'a>(selfv: Object<'a>, key: Object<'a>, value: Object<'a>) -> MethodType<'a> {
-    let set = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "set".to_string()),
-    );
+    let set = class_method(&selfv, "set");
     if set.is_some() {
         let call_fn = unwrap_fast!(set).tp.call;
         if call_fn.is_none() {
@@ -400,8 +664,7 @@ fn class_set<'a>(selfv: Object<'a>, key: Object<'a>, value: Object<'a>) -> Metho
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv, key, value]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(set), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(set), &[selfv, key, value]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -415,9 +678,7 @@ fn class_set<'a>(selfv: Object<'a>, key: Object<'a>, value: Object<'a>) -> Metho
 }
 
 fn class_len(selfv: Object<'_>) -> MethodType<'_> {
-    let len = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "len".to_string()),
-    );
+    let len = class_method(&selfv, "len");
     if len.is_some() {
         let call_fn = unwrap_fast!(len).tp.call;
         if call_fn.is_none() {
@@ -431,8 +692,7 @@ fn class_len(selfv: Object<'_>) -> MethodType<'_> {
                 Position::default(),
             ));
         }
-        let args = listobject::list_from(selfv.vm.clone(), vec![selfv]);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(len), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(len), &[selfv]);
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -445,11 +705,37 @@ fn class_len(selfv: Object<'_>) -> MethodType<'_> {
     ))
 }
 
+fn class_contains<'a>(selfv: Object<'a>, item: Object<'a>) -> MethodType<'a> {
+    let contains = class_method(&selfv, "contains");
+    if contains.is_some() {
+        let call_fn = unwrap_fast!(contains).tp.call;
+        if call_fn.is_none() {
+            return MethodValue::Error(methodnotdefinedexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "Method 'call' is not defined for '{}' type",
+                    unwrap_fast!(contains).tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            ));
+        }
+        return (unwrap_fast!(call_fn))(unwrap_fast!(contains), &[selfv, item]);
+    }
+    MethodValue::Error(methodnotdefinedexc_from_str(
+        selfv.vm.clone(),
+        &format!(
+            "Method 'contains' is not defined for '{}' type",
+            selfv.tp.typename
+        ),
+        Position::default(),
+        Position::default(),
+    ))
+}
+
 //interaction
-fn class_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
-    let call = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "call".to_string()),
-    );
+fn class_call<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let call = class_method(&selfv, "call");
     if call.is_some() {
         let call_fn = unwrap_fast!(call).tp.call;
         if call_fn.is_none() {
@@ -463,10 +749,12 @@ fn class_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let mut selfv_vec = vec![selfv.clone()];
-        selfv_vec.extend(unsafe { &args.internals.arr }.iter().cloned());
-        let args = listobject::list_from(selfv.vm.clone(), selfv_vec);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(call), args);
+        return call_with_first(
+            unwrap_fast!(call_fn),
+            unwrap_fast!(call),
+            selfv.clone(),
+            args,
+        );
     }
     MethodValue::Error(methodnotdefinedexc_from_str(
         selfv.vm.clone(),
@@ -481,9 +769,7 @@ fn class_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
 
 //attribute
 fn class_getattr<'a>(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
-    let getattr = unsafe { &unwrap_fast!(selfv.tp.dict.as_ref()).internals.map }.get(
-        stringobject::string_from(selfv.vm.clone(), "getattr".to_string()),
-    );
+    let getattr = class_method(&selfv, "getattr");
     if getattr.is_some() {
         let call_fn = unwrap_fast!(getattr).tp.call;
         if call_fn.is_none() {
@@ -497,20 +783,28 @@ fn class_getattr<'a>(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
                 Position::default(),
             ));
         }
-        let selfv_vec = vec![selfv.clone(), attr];
-        let args = listobject::list_from(selfv.vm.clone(), selfv_vec);
-        return (unwrap_fast!(call_fn))(unwrap_fast!(getattr), args);
+        return (unwrap_fast!(call_fn))(unwrap_fast!(getattr), &[selfv.clone(), attr]);
     }
 
     RawObject::generic_getattr(selfv, attr)
 }
 
-pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) -> Object<'a> {
+pub fn create_class<'a>(
+    mut vm: Trc<VM<'a>>,
+    name: String,
+    dict: Object<'a>,
+    bases: Vec<Trc<TypeObject<'a>>>,
+) -> Object<'a> {
+    let bases = if bases.is_empty() {
+        vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )]
+    } else {
+        bases.into_iter().map(super::ObjectBase::Other).collect()
+    };
     let tp = Trc::new(TypeObject {
         typename: name,
-        bases: vec![super::ObjectBase::Other(
-            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
-        )],
+        bases,
         typeid: vm.types.n_types,
         dict: Some(dict.clone()),
 
@@ -556,6 +850,16 @@ pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) ->
         } else {
             None
         },
+        invert: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("invert")),
+        )
+        .is_some()
+        {
+            Some(class_invert)
+        } else {
+            None
+        },
         hash_fn: if dict.tp.get.unwrap()(
             dict.clone(),
             stringobject::string_from(vm.clone(), String::from("hash")),
@@ -577,6 +881,26 @@ pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) ->
         } else {
             None
         },
+        lt: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("lt")),
+        )
+        .is_some()
+        {
+            Some(class_lt)
+        } else {
+            None
+        },
+        gt: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("gt")),
+        )
+        .is_some()
+        {
+            Some(class_gt)
+        } else {
+            None
+        },
         add: if dict.tp.get.unwrap()(
             dict.clone(),
             stringobject::string_from(vm.clone(), String::from("add")),
@@ -627,6 +951,56 @@ pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) ->
         } else {
             None
         },
+        bitand: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("bitand")),
+        )
+        .is_some()
+        {
+            Some(class_bitand)
+        } else {
+            None
+        },
+        bitor: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("bitor")),
+        )
+        .is_some()
+        {
+            Some(class_bitor)
+        } else {
+            None
+        },
+        bitxor: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("bitxor")),
+        )
+        .is_some()
+        {
+            Some(class_bitxor)
+        } else {
+            None
+        },
+        lshift: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("lshift")),
+        )
+        .is_some()
+        {
+            Some(class_lshift)
+        } else {
+            None
+        },
+        rshift: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("rshift")),
+        )
+        .is_some()
+        {
+            Some(class_rshift)
+        } else {
+            None
+        },
 
         get: if dict.tp.get.unwrap()(
             dict.clone(),
@@ -658,6 +1032,16 @@ pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) ->
         } else {
             None
         },
+        contains: if dict.tp.get.unwrap()(
+            dict.clone(),
+            stringobject::string_from(vm.clone(), String::from("contains")),
+        )
+        .is_some()
+        {
+            Some(class_contains)
+        } else {
+            None
+        },
 
         call: if dict.tp.get.unwrap()(
             dict.clone(),
@@ -685,8 +1069,18 @@ pub fn create_class<'a>(mut vm: Trc<VM<'a>>, name: String, dict: Object<'a>) ->
         descrset: None,
     });
 
+    shape::register_class(vm.clone(), tp.typeid);
     vm.types.n_types += 1;
 
+    //Only the instances of classes that define or inherit drop look for it when they are freed
+    let drop = tp.lookup(
+        stringobject::string_from(vm.clone(), String::from("drop")),
+        0,
+    );
+    if !drop.is_error() && unwrap_fast!(drop).is_some() {
+        vm.drop_classes.insert(tp.typeid);
+    }
+
     finalize_type(tp.clone());
 
     create_object_from_typeobject(vm, tp)
@@ -1,24 +1,23 @@
-use std::mem::ManuallyDrop;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 use super::finalize_type_dict;
 use super::{
     create_object_from_type, finalize_type, MethodType, MethodValue, Object, RawObject, TypeObject,
 };
 use crate::is_type_exact;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
-    compiler::Bytecode,
+    compiler::Code,
     interpreter::VM,
-    objects::{boolobject, stringobject, ObjectInternals},
+    objects::{boolobject, intobject, stringobject, ObjectInternals},
 };
-use trc::Trc;
 
-pub fn code_from<'a>(vm: Trc<VM<'a>>, bytecode: Trc<Bytecode<'a>>) -> Object<'a> {
+pub fn code_from<'a>(vm: Trc<VM<'a>>, code: Trc<Code<'a>>) -> Object<'a> {
     let mut tp: Trc<RawObject> =
         create_object_from_type(unwrap_fast!(vm.types.codetp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        code: ManuallyDrop::new(bytecode),
-    };
+    tp.internals = ObjectInternals::new_code(code);
     tp
 }
 
@@ -38,7 +37,16 @@ fn code_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { &selfv.internals.code } == unsafe { &other.internals.code },
+        unsafe { selfv.internals.code() } == unsafe { other.internals.code() },
+    ))
+}
+
+fn code_hash(selfv: Object<'_>) -> MethodType<'_> {
+    let mut hasher = DefaultHasher::new();
+    unsafe { selfv.internals.code() }.hash(&mut hasher);
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        hasher.finish() as isize,
     ))
 }
 
@@ -57,17 +65,26 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(code_repr),
         abs: None,
         neg: None,
-        hash_fn: None,
+        invert: None,
+        hash_fn: Some(code_hash),
         eq: Some(code_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: None,
 
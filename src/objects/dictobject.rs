@@ -1,27 +1,21 @@
-use std::mem::ManuallyDrop;
-
 use super::mhash::HashMap;
 use super::{
-    create_object_from_type, finalize_type, finalize_type_dict, intobject, MethodType, MethodValue,
-    Object, RawObject, TypeObject,
+    create_object_from_type, finalize_type, finalize_type_dict, intobject, object_id, MethodType,
+    MethodValue, Object, RawObject, TypeObject,
 };
 
 use crate::is_type_exact;
-use crate::objects::exceptionobject::{methodnotdefinedexc_from_str, typemismatchexc_from_str};
-use crate::parser::Position;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
     interpreter::VM,
     objects::{boolobject, stringobject, ObjectInternals},
 };
-use trc::Trc;
 
 #[allow(dead_code)]
 pub fn dict_from<'a>(vm: Trc<VM<'a>>, raw: HashMap<'a>) -> Object<'a> {
     let mut tp = create_object_from_type(unwrap_fast!(vm.types.dicttp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        map: ManuallyDrop::new(raw),
-    };
+    tp.internals = ObjectInternals::new_map(raw);
     tp
 }
 
@@ -29,10 +23,16 @@ fn dict_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> M
     unimplemented!();
 }
 fn dict_repr(selfv: Object<'_>) -> MethodType<'_> {
+    RawObject::recursive_repr_guard(selfv, "{...}", dict_repr_items)
+}
+//Like the items of a list, see list_repr_items
+fn dict_repr_items(selfv: Object<'_>) -> MethodType<'_> {
     let mut res = String::from("{");
     let sf = selfv.clone();
-    let map = unsafe { &sf.internals.map }.clone();
-    for (key, value) in map.into_iter() {
+    let map = unsafe { sf.internals.map() }.clone();
+    let limit = selfv.vm.repr_limit.unwrap_or(usize::MAX);
+    let truncated = map.len() > limit;
+    for (key, value) in map.into_iter().take(limit) {
         let repr = RawObject::object_repr_safe(key);
         if repr.is_error() {
             return MethodValue::Error(repr.unwrap_err());
@@ -46,31 +46,8 @@ fn dict_repr(selfv: Object<'_>) -> MethodType<'_> {
         res += &unwrap_fast!(repr);
         res += ", ";
     }
-    if res.len() > 1 {
-        res.pop();
-        res.pop();
-    }
-    res += "}";
-    MethodValue::Some(stringobject::string_from(selfv.vm.clone(), res))
-}
-
-fn dict_str(selfv: Object<'_>) -> MethodType<'_> {
-    let mut res = String::from("{");
-    let sf = selfv.clone();
-    let map = unsafe { &sf.internals.map }.clone();
-    for (key, value) in map.into_iter() {
-        let repr = RawObject::object_str_safe(key);
-        if repr.is_error() {
-            return MethodValue::Error(repr.unwrap_err());
-        }
-        res += &unwrap_fast!(repr);
-        res += ": ";
-        let repr = RawObject::object_str_safe(value);
-        if !repr.is_some() {
-            return MethodValue::Error(repr.unwrap_err());
-        }
-        res += &unwrap_fast!(repr);
-        res += ", ";
+    if truncated {
+        res += "..., ";
     }
     if res.len() > 1 {
         res.pop();
@@ -82,7 +59,7 @@ fn dict_str(selfv: Object<'_>) -> MethodType<'_> {
 
 fn dict_get<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
     //NEGATIVE INDEX IS CONVERTED TO +
-    let out = unsafe { &selfv.internals.map }.get(other);
+    let out = unsafe { selfv.internals.map() }.get(other);
 
     if out.is_error() {
         return MethodValue::Error(out.unwrap_err());
@@ -92,100 +69,74 @@ fn dict_get<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 
 #[inline]
 fn dict_set<'a>(mut selfv: Object<'a>, other: Object<'a>, value: Object<'a>) -> MethodType<'a> {
-    //TODO check for hash here!
-    let mut map = unsafe { &selfv.internals.map }.clone();
+    let mut map = unsafe { selfv.internals.map() }.clone();
     let res = map.insert(other, value);
     if res.is_error() {
         return MethodValue::Error(res.unwrap_err());
     }
 
-    selfv.internals = ObjectInternals { map };
+    selfv.internals = ObjectInternals::new_map(map);
 
     MethodValue::Some(none_from!(selfv.vm))
 }
 fn dict_len(selfv: Object<'_>) -> MethodType<'_> {
-    let convert = unsafe { &selfv.internals.map }.len().try_into();
+    let convert = unsafe { selfv.internals.map() }.len().try_into();
 
     MethodValue::Some(intobject::int_from(selfv.vm.clone(), unwrap_fast!(convert)))
 }
 
-#[allow(unused_unsafe)]
+//Whether the dict has the key, like get without the KeyNotFoundExc
+fn dict_contains<'a>(selfv: Object<'a>, key: Object<'a>) -> MethodType<'a> {
+    let out = unsafe { selfv.internals.map() }.try_get(key);
+    if out.is_error() {
+        return MethodValue::Error(out.unwrap_err());
+    }
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unwrap_fast!(out).is_some(),
+    ))
+}
+
 fn dict_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
     if !is_type_exact!(&selfv, other.tp) {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
+    if Trc::ptr_eq(&selfv, &other) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), true));
+    }
 
-    if unsafe { &selfv.internals.map }.len() != unsafe { &other.internals.map }.len() {
+    if unsafe { selfv.internals.map() }.len() != unsafe { other.internals.map() }.len() {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
-    for ((key1, value1), (key2, value2)) in std::iter::zip(
-        unsafe { &selfv.internals.map }.into_iter(),
-        unsafe { &other.internals.map }.into_iter(),
-    ) {
-        if key1.tp.eq.is_none() {
-            let exc = methodnotdefinedexc_from_str(
-                selfv.vm.clone(),
-                &format!(
-                    "Method 'eq' is not defined for key 1 type '{}'",
-                    key1.tp.typename
-                ),
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
-        if value1.tp.eq.is_none() {
-            let exc = methodnotdefinedexc_from_str(
-                selfv.vm.clone(),
-                &format!(
-                    "Method 'eq' is not defined for value 1 type '{}'",
-                    value1.tp.typename
-                ),
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
 
-        let res = (key1.tp.eq.expect("Method is not defined"))(key1.clone(), key2.clone());
-        if res.is_error() {
-            return res;
-        }
-        if !is_type_exact!(
-            &unwrap_fast!(res),
-            unwrap_fast!(selfv.vm.types.booltp.as_ref()).clone()
-        ) {
-            let exc = typemismatchexc_from_str(
-                selfv.vm.clone(),
-                "Method 'eq' did not return 'bool'",
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
+    //A dict may contain itself, see list_eq
+    let pair = (object_id(&selfv), object_id(&other));
+    let mut vm = selfv.vm.clone();
+    if vm.eq_in_progress.contains(&pair) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), true));
+    }
+    vm.eq_in_progress.insert(pair);
+    let res = dict_eq_items(selfv, other);
+    vm.eq_in_progress.remove(&pair);
+    res
+}
 
-        if unsafe { unwrap_fast!(res).internals.bool } {
-            return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
+fn dict_eq_items<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    for (key, value) in unsafe { selfv.internals.map() }.into_iter() {
+        let otherv = unsafe { other.internals.map() }.try_get(key);
+        if otherv.is_error() {
+            return MethodValue::Error(otherv.unwrap_err());
         }
+        let otherv = match unwrap_fast!(otherv) {
+            Some(v) => v,
+            None => return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false)),
+        };
 
-        let res = (value1.tp.eq.expect("Method is not defined"))(value1.clone(), value2.clone());
+        let res = RawObject::object_eq_safe(value, otherv);
         if res.is_error() {
-            return res;
+            return MethodValue::Error(res.unwrap_err());
         }
-        if !is_type_exact!(
-            &unwrap_fast!(res),
-            unwrap_fast!(selfv.vm.types.booltp.as_ref()).clone()
-        ) {
-            let exc = typemismatchexc_from_str(
-                selfv.vm.clone(),
-                "Method 'eq' did not return 'bool'",
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
-
-        if unsafe { unwrap_fast!(res).internals.bool } {
+        if !unwrap_fast!(res) {
             return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
         }
     }
@@ -204,21 +155,30 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         new: Some(dict_new),
 
         repr: Some(dict_repr),
-        str: Some(dict_str),
+        str: Some(dict_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: None,
 
         eq: Some(dict_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: Some(dict_get),
         set: Some(dict_set),
         len: Some(dict_len),
+        contains: Some(dict_contains),
 
         call: None,
 
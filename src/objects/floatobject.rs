@@ -0,0 +1,241 @@
+use super::exceptionobject::{typemismatchexc_from_str, valueexc_from_str, zerodivexc_from_str};
+use super::{
+    boolobject, create_object_from_type, finalize_type, finalize_type_dict, intobject,
+    stringobject, MethodType, MethodValue, Object, ObjectInternals, TypeObject,
+};
+
+use crate::is_type_exact;
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use crate::{interpreter::VM, parser::Position};
+use std::collections::hash_map::DefaultHasher;
+
+use std::hash::{Hash, Hasher};
+
+pub fn float_from(vm: Trc<VM<'_>>, raw: f64) -> Object<'_> {
+    let mut tp = create_object_from_type(unwrap_fast!(vm.types.floattp.as_ref()).clone(), vm, None);
+    tp.internals = ObjectInternals::new_float(raw);
+    tp
+}
+pub fn float_from_str(vm: Trc<VM<'_>>, raw: String) -> MethodType<'_> {
+    let convert = raw.parse::<f64>();
+    if let Err(e) = convert {
+        let exc = valueexc_from_str(
+            vm.clone(),
+            &format!("float literal is invalid ({e})"),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(float_from(vm, unwrap_fast!(convert.as_ref()).to_owned()))
+}
+
+fn float_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
+    unimplemented!();
+}
+
+//The shortest text that parses back to the same float. Debug formatting always keeps a fractional
+//part or exponent, so 1e9 is not shown as an int.
+fn float_repr_str(value: f64) -> String {
+    if value.is_nan() {
+        String::from("nan")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn float_repr(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(stringobject::string_from(
+        selfv.vm.clone(),
+        float_repr_str(unsafe { selfv.internals.float() }),
+    ))
+}
+fn float_abs(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(float_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() }.abs(),
+    ))
+}
+fn float_neg(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(float_from(selfv.vm.clone(), -unsafe {
+        selfv.internals.float()
+    }))
+}
+fn float_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() } == unsafe { other.internals.float() },
+    ))
+}
+fn float_lt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() } < unsafe { other.internals.float() },
+    ))
+}
+fn float_gt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() } > unsafe { other.internals.float() },
+    ))
+}
+
+macro_rules! float_binop {
+    ($name:ident, $op:tt) => {
+        fn $name<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+            if !is_type_exact!(&selfv, other.tp) {
+                let exc = typemismatchexc_from_str(
+                    selfv.vm.clone(),
+                    "Types do not match",
+                    Position::default(),
+                    Position::default(),
+                );
+                return MethodValue::Error(exc);
+            }
+
+            MethodValue::Some(float_from(
+                selfv.vm.clone(),
+                unsafe { selfv.internals.float() } $op unsafe { other.internals.float() },
+            ))
+        }
+    };
+}
+
+float_binop!(float_add, +);
+float_binop!(float_sub, -);
+float_binop!(float_mul, *);
+
+fn float_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    //Like ints, dividing by zero raises instead of giving inf or nan
+    let otherv = unsafe { other.internals.float() };
+    if otherv == 0.0 {
+        let exc = zerodivexc_from_str(
+            selfv.vm.clone(),
+            "Division by 0",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(float_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() } / otherv,
+    ))
+}
+fn float_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(float_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.float() }.powf(unsafe { other.internals.float() }),
+    ))
+}
+fn float_hash(selfv: Object<'_>) -> MethodType<'_> {
+    //0.0 and -0.0 are equal, so they must hash the same
+    let value = unsafe { selfv.internals.float() };
+    let bits = if value == 0.0 { 0 } else { value.to_bits() };
+    let mut hasher = DefaultHasher::new();
+    bits.hash(&mut hasher);
+    MethodValue::Some(intobject::int_from(
+        selfv.vm.clone(),
+        hasher.finish() as isize,
+    ))
+}
+
+pub fn init(mut vm: Trc<VM<'_>>) {
+    let tp = Trc::new(TypeObject {
+        typename: String::from("float"),
+        bases: vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )],
+        typeid: vm.types.n_types,
+        dict: None,
+
+        new: Some(float_new),
+
+        repr: Some(float_repr),
+        str: Some(float_repr),
+        abs: Some(float_abs),
+        neg: Some(float_neg),
+        invert: None,
+        hash_fn: Some(float_hash),
+
+        eq: Some(float_eq),
+        lt: Some(float_lt),
+        gt: Some(float_gt),
+        add: Some(float_add),
+        sub: Some(float_sub),
+        mul: Some(float_mul),
+        div: Some(float_div),
+        pow: Some(float_pow),
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
+
+        get: None,
+        set: None,
+        len: None,
+        contains: None,
+
+        call: None,
+
+        getattr: None,
+        setattr: None,
+        descrget: None,
+        descrset: None,
+    });
+
+    vm.types.floattp = Some(tp.clone());
+    vm.types.n_types += 1;
+
+    finalize_type(tp.clone());
+    finalize_type_dict(tp);
+}
@@ -1,35 +1,54 @@
-use std::mem::ManuallyDrop;
-
-use super::exceptionobject::valueexc_from_str;
+use super::exceptionobject::{argumentexc_from_str, notimplementedexc_from_str};
 use super::methodobject::method_from;
 use super::{
-    create_object_from_type, finalize_type, finalize_type_dict, MethodType, MethodValue, Object,
-    TypeObject,
+    create_object_from_type, finalize_type, finalize_type_dict, intobject, listobject, MethodType,
+    MethodValue, Object, RawObject, TypeObject,
 };
 
 use crate::is_type_exact;
-use crate::objects::exceptionobject::typemismatchexc_from_str;
 use crate::parser::Position;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
-    interpreter::VM,
+    compiler::Bytecode,
+    interpreter::{Scope, VM},
     objects::{boolobject, stringobject, ObjectInternals},
 };
-use trc::Trc;
 
 pub fn fn_from<'a>(
     vm: Trc<VM<'a>>,
-    code: Object<'a>,
+    body: Trc<Bytecode<'a>>,
     args: Vec<Object<'a>>,
-    name: String,
+    qualname: String,
+    span: (Position, Position),
+    enclosing: Vec<Scope<'a>>,
+    is_abstract: bool,
 ) -> Object<'a> {
     let mut tp = create_object_from_type(unwrap_fast!(vm.types.fntp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        fun: ManuallyDrop::new(super::FnData { code, args, name }),
-    };
+    let name = unwrap_fast!(qualname.rsplit('.').next()).to_string();
+    tp.internals = ObjectInternals::new_fun(super::FnData {
+        body,
+        args,
+        name,
+        qualname,
+        span,
+        enclosing,
+        is_abstract,
+    });
     tp
 }
 
+//Whether the object is an abstract method, which a subclass must implement
+pub fn is_abstract(object: &Object<'_>) -> bool {
+    is_type_exact!(object, unwrap_fast!(object.vm.types.fntp.as_ref()))
+        && unsafe { object.internals.fun() }.is_abstract
+}
+
+//The number of parameters of a function
+pub fn n_args(object: &Object<'_>) -> usize {
+    unsafe { object.internals.fun() }.args.len()
+}
+
 fn fn_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
     unimplemented!();
 }
@@ -38,7 +57,7 @@ fn fn_repr(selfv: Object<'_>) -> MethodType<'_> {
         selfv.vm.clone(),
         format!(
             "<fn '{}' @ 0x{:x}>",
-            unsafe { &selfv.internals.fun }.name,
+            unsafe { selfv.internals.fun() }.qualname,
             Trc::as_ptr(&selfv) as usize
         ),
     ))
@@ -48,42 +67,72 @@ fn fn_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
 
+    //Functions are only equal to themselves
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { &selfv.internals.fun } == unsafe { &other.internals.fun },
+        Trc::ptr_eq(&selfv, &other),
     ))
 }
 
-fn fn_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
-    if !is_type_exact!(&args, unwrap_fast!(selfv.vm.types.listtp.as_ref()).clone()) {
-        let exc = typemismatchexc_from_str(
+fn fn_call<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    if args.len() != unsafe { selfv.internals.fun() }.args.len() {
+        let exc = argumentexc_from_str(
             selfv.vm.clone(),
-            "Expected args to be a 'list'",
+            &format!(
+                "Function '{}' expected {} argument(s), got {}",
+                unsafe { selfv.internals.fun() }.qualname,
+                unsafe { selfv.internals.fun() }.args.len(),
+                args.len()
+            ),
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
-    if unsafe { &args.internals.arr }.len() != unsafe { &selfv.internals.fun }.args.len() {
-        let exc = valueexc_from_str(
+    if unsafe { selfv.internals.fun() }.is_abstract {
+        let exc = notimplementedexc_from_str(
             selfv.vm.clone(),
             &format!(
-                "Expected {} argument(s), got {}",
-                unsafe { &selfv.internals.fun }.args.len(),
-                unsafe { &args.internals.arr }.len()
+                "Abstract method '{}' is not implemented",
+                unsafe { selfv.internals.fun() }.qualname
             ),
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
-    let mut map = hashbrown::HashMap::new();
-    for (value, index) in unsafe { &args.internals.arr }.iter().enumerate() {
-        map.insert(value as isize, index.clone());
+    let body = &unsafe { selfv.internals.fun() }.body;
+    if let Some(trace) = selfv.vm.clone().trace.as_mut() {
+        let fun = unsafe { selfv.internals.fun() };
+        trace.called(&fun.name, &fun.qualname, body);
     }
+    MethodValue::Some(VM::execute_call(
+        selfv.vm.clone(),
+        body,
+        args,
+        unsafe { selfv.internals.fun() }.enclosing.clone(),
+    ))
+}
 
-    let code = &unsafe { &selfv.internals.fun.code.internals.code };
-    MethodValue::Some(VM::execute_vars(selfv.vm.clone(), code, map))
+//The metadata of the function, or the attributes of its dict
+fn fn_getattr<'a>(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
+    let fun = unsafe { selfv.internals.fun() };
+    let vm = selfv.vm.clone();
+    if is_type_exact!(&attr, unwrap_fast!(vm.types.strtp.as_ref())) {
+        match &unsafe { attr.internals.str() }[..] {
+            "name" => return MethodValue::Some(stringobject::string_from(vm, fun.name.clone())),
+            "qualname" => {
+                return MethodValue::Some(stringobject::string_from(vm, fun.qualname.clone()))
+            }
+            "args" => return MethodValue::Some(listobject::list_from(vm, fun.args.clone())),
+            "arity" => return MethodValue::Some(intobject::int_from(vm, fun.args.len() as isize)),
+            "line" => {
+                return MethodValue::Some(intobject::int_from(vm, fun.span.0.line as isize + 1))
+            }
+            _ => {}
+        }
+    }
+    RawObject::generic_getattr(selfv, attr)
 }
 
 fn fn_descrget<'a>(
@@ -117,21 +166,30 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(fn_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: None,
         eq: Some(fn_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: Some(fn_call),
 
-        getattr: None,
+        getattr: Some(fn_getattr),
         setattr: None,
         descrget: Some(fn_descrget),
         descrset: None,
@@ -0,0 +1,149 @@
+//The data of an object, which depends on its type. By default it is an untagged union, and reading
+//a field that the object does not hold is undefined behavior. With the safe-internals feature it is
+//an enum that knows which field it holds, and reading another field panics. Both are used through
+//the same methods, so the tests can run against the enum to check the code that reads the union.
+
+use super::{mhash, shape, BuiltinData, ExcData, FnData, FnWrapper, SuperData, TypeObject};
+use crate::compiler::Code;
+use crate::rc::Trc;
+use num::BigInt;
+use std::mem::ManuallyDrop;
+
+macro_rules! internals {
+    (
+        copy { $($copy:ident, $copy_new:ident, $copy_variant:ident: $copy_tp:ty;)* }
+        heap { $($heap:ident, $heap_new:ident, $heap_variant:ident: $heap_tp:ty;)* }
+        mutable { $($mutable:ident, $mutable_mut:ident, $mutable_variant:ident: $mutable_tp:ty;)* }
+    ) => {
+        #[cfg(not(feature = "safe-internals"))]
+        pub union ObjectInternals<'a> {
+            $($copy: $copy_tp,)*
+            $($heap: ManuallyDrop<$heap_tp>,)*
+        }
+
+        #[cfg(feature = "safe-internals")]
+        pub enum ObjectInternals<'a> {
+            $($copy_variant($copy_tp),)*
+            $($heap_variant(ManuallyDrop<$heap_tp>),)*
+        }
+
+        #[cfg(feature = "safe-internals")]
+        impl ObjectInternals<'_> {
+            fn field(&self) -> &'static str {
+                match self {
+                    $(ObjectInternals::$copy_variant(_) => stringify!($copy),)*
+                    $(ObjectInternals::$heap_variant(_) => stringify!($heap),)*
+                }
+            }
+
+            #[cold]
+            fn mismatch(&self, field: &str) -> ! {
+                panic!(
+                    "Internal error: read the {} of an object that holds {}",
+                    field,
+                    self.field()
+                );
+            }
+        }
+
+        //The readers are unsafe: the caller must know from the type of the object that it holds
+        //the field. Only the union relies on that.
+        #[allow(clippy::missing_safety_doc)]
+        impl<'a> ObjectInternals<'a> {
+            $(
+                #[inline]
+                pub fn $copy_new(value: $copy_tp) -> Self {
+                    #[cfg(not(feature = "safe-internals"))]
+                    return ObjectInternals { $copy: value };
+                    #[cfg(feature = "safe-internals")]
+                    return ObjectInternals::$copy_variant(value);
+                }
+
+                #[inline]
+                pub unsafe fn $copy(&self) -> $copy_tp {
+                    #[cfg(not(feature = "safe-internals"))]
+                    return self.$copy;
+                    #[cfg(feature = "safe-internals")]
+                    match self {
+                        ObjectInternals::$copy_variant(value) => *value,
+                        _ => self.mismatch(stringify!($copy)),
+                    }
+                }
+            )*
+            $(
+                #[inline]
+                pub fn $heap_new(value: $heap_tp) -> Self {
+                    #[cfg(not(feature = "safe-internals"))]
+                    return ObjectInternals {
+                        $heap: ManuallyDrop::new(value),
+                    };
+                    #[cfg(feature = "safe-internals")]
+                    return ObjectInternals::$heap_variant(ManuallyDrop::new(value));
+                }
+
+                #[inline]
+                pub unsafe fn $heap(&self) -> &$heap_tp {
+                    #[cfg(not(feature = "safe-internals"))]
+                    return &self.$heap;
+                    #[cfg(feature = "safe-internals")]
+                    match self {
+                        ObjectInternals::$heap_variant(value) => value,
+                        _ => self.mismatch(stringify!($heap)),
+                    }
+                }
+            )*
+            $(
+                #[inline]
+                pub unsafe fn $mutable_mut(&mut self) -> &mut $mutable_tp {
+                    #[cfg(not(feature = "safe-internals"))]
+                    return &mut self.$mutable;
+                    #[cfg(feature = "safe-internals")]
+                    match self {
+                        ObjectInternals::$mutable_variant(value) => value,
+                        _ => self.mismatch(stringify!($mutable)),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+internals! {
+    copy {
+        none, new_none, None: ();
+        bool, new_bool, Bool: bool;
+        int, new_int, Int: isize;
+        float, new_float, Float: f64;
+    }
+    heap {
+        big, new_big, Big: BigInt;
+        str, new_str, Str: super::stringobject::StrData;
+        arr, new_arr, Arr: Vec<super::Object<'a>>;
+        map, new_map, Map: mhash::HashMap<'a>;
+        code, new_code, Code: Trc<Code<'a>>;
+        fun, new_fun, Fun: FnData<'a>;
+        exc, new_exc, Exc: ExcData<'a>;
+        typ, new_typ, Typ: TypeObject<'a>;
+        fn_wrapper, new_fn_wrapper, FnWrapper: FnWrapper<'a>;
+        builtin, new_builtin, Builtin: BuiltinData<'a>;
+        sup, new_sup, Sup: SuperData<'a>;
+        attrs, new_attrs, Attrs: shape::InstanceAttrs<'a>;
+    }
+    mutable {
+        attrs, attrs_mut, Attrs: shape::InstanceAttrs<'a>;
+    }
+}
+
+impl ObjectInternals<'_> {
+    //Drop the method of an object that holds one, which releases its function and instance. The
+    //caller must know from the type of the object that it holds a method, and not read it again.
+    pub unsafe fn drop_fn_wrapper(&mut self) {
+        #[cfg(not(feature = "safe-internals"))]
+        ManuallyDrop::drop(&mut self.fn_wrapper);
+        #[cfg(feature = "safe-internals")]
+        match self {
+            ObjectInternals::FnWrapper(value) => ManuallyDrop::drop(value),
+            _ => self.mismatch("fn_wrapper"),
+        }
+    }
+}
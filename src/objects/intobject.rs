@@ -1,34 +1,117 @@
-use super::exceptionobject::{typemismatchexc_from_str, zerodivexc_from_str};
+use super::exceptionobject::{typemismatchexc_from_str, valueexc_from_str, zerodivexc_from_str};
 use super::{
-    boolobject, create_object_from_type, finalize_type, finalize_type_dict, stringobject,
-    MethodType, MethodValue, Object, ObjectInternals, TypeObject,
+    bigintobject, boolobject, create_object_from_type, finalize_type, finalize_type_dict,
+    stringobject, MethodType, MethodValue, Object, ObjectInternals, TypeObject,
 };
 
 use crate::is_type_exact;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
-    interpreter::{INT_CACHE_OFFSET, INT_CACHE_SIZE, MAX_INT_CACHE, MIN_INT_CACHE, VM},
+    interpreter::{MIN_INT_CACHE, VM},
     objects::exceptionobject::overflowexc_from_str,
     parser::Position,
 };
+use num::BigInt;
 use std::collections::hash_map::DefaultHasher;
-use trc::Trc;
+use std::num::IntErrorKind;
 
 use std::hash::{Hash, Hasher};
 
+//What int arithmetic does when a result does not fit in an int. The VM only checks it once an
+//operation has overflowed, so it does not slow down the arithmetic that does not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntOverflow {
+    #[default]
+    Raise, //Raise an OverflowExc
+    Wrap,    //Wrap around like a machine int, which is fastest
+    Promote, //Give a bigint with the exact result
+}
+
+impl IntOverflow {
+    pub fn from_name(name: &str) -> Option<IntOverflow> {
+        match name {
+            "raise" => Some(IntOverflow::Raise),
+            "wrap" => Some(IntOverflow::Wrap),
+            "promote" => Some(IntOverflow::Promote),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntOverflow::Raise => "raise",
+            IntOverflow::Wrap => "wrap",
+            IntOverflow::Promote => "promote",
+        }
+    }
+}
+
+//The result of an operation that overflowed, by the overflow mode of the VM: an error with the
+//message, the wrapped around result, or the exact result as a bigint
+fn overflowed<'a>(
+    selfv: &Object<'a>,
+    message: &str,
+    wrapped: isize,
+    exact: impl FnOnce() -> BigInt,
+) -> MethodType<'a> {
+    match selfv.vm.int_overflow {
+        IntOverflow::Raise => MethodValue::Error(overflowexc_from_str(
+            selfv.vm.clone(),
+            message,
+            Position::default(),
+            Position::default(),
+        )),
+        IntOverflow::Wrap => MethodValue::Some(int_from(selfv.vm.clone(), wrapped)),
+        IntOverflow::Promote => {
+            MethodValue::Some(bigintobject::bigint_from(selfv.vm.clone(), exact()))
+        }
+    }
+}
+
+//An int mixed with a bigint is computed as a bigint
+macro_rules! bigint_operand {
+    ($selfv:expr, $other:expr, $slot:ident) => {
+        if bigintobject::is_bigint(&$other) {
+            return bigintobject::$slot($selfv, $other);
+        }
+    };
+}
+
 #[inline]
-pub fn int_from(vm: Trc<VM<'_>>, raw: isize) -> Object<'_> {
-    if (MIN_INT_CACHE..=MAX_INT_CACHE).contains(&raw) {
-        return unwrap_fast! {vm.cache.int_cache[(raw + INT_CACHE_OFFSET) as usize]
-        .as_ref()}
-        .clone();
+pub fn int_from(mut vm: Trc<VM<'_>>, raw: isize) -> Object<'_> {
+    //Ints below the cache wrap around to indices past its end
+    let cached = vm
+        .cache
+        .int_cache
+        .get(raw.wrapping_sub(MIN_INT_CACHE) as usize)
+        .cloned();
+    if let Some((hits, misses)) = vm.int_cache_stats.as_mut() {
+        match cached {
+            Some(_) => *hits += 1,
+            None => *misses += 1,
+        }
+    }
+    if let Some(int) = cached {
+        return int;
     }
     let mut tp = create_object_from_type(unwrap_fast!(vm.types.inttp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals { int: raw };
+    tp.internals = ObjectInternals::new_int(raw);
     tp
 }
 pub fn int_from_str(vm: Trc<VM<'_>>, raw: String) -> MethodType<'_> {
     let convert = raw.parse::<isize>();
+    //A literal that does not fit is a bigint when overflowing arithmetic would give one
+    if vm.int_overflow == IntOverflow::Promote {
+        if let Some(big) = convert
+            .as_ref()
+            .err()
+            .filter(|err| *err.kind() == IntErrorKind::PosOverflow)
+            .and_then(|_| raw.parse::<BigInt>().ok())
+        {
+            return MethodValue::Some(bigintobject::bigint_from(vm, big));
+        }
+    }
     if matches!(convert, Result::Err(_)) {
         let exc = overflowexc_from_str(
             vm.clone(),
@@ -48,19 +131,19 @@ fn int_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> Me
 fn int_repr(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(stringobject::string_from(
         selfv.vm.clone(),
-        unsafe { selfv.internals.int }.to_string(),
+        unsafe { selfv.internals.int() }.to_string(),
     ))
 }
 fn int_abs(selfv: Object<'_>) -> MethodType<'_> {
-    let res = unsafe { selfv.internals.int }.checked_abs();
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_abs();
     if res.is_none() {
-        let exc = overflowexc_from_str(
-            selfv.vm.clone(),
+        return overflowed(
+            &selfv,
             "int absolute value overflow (value is i128 minimum)",
-            Position::default(),
-            Position::default(),
+            value.wrapping_abs(),
+            || -BigInt::from(value),
         );
-        return MethodValue::Error(exc);
     }
 
     MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
@@ -72,25 +155,28 @@ fn int_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { selfv.internals.int } == unsafe { other.internals.int },
+        unsafe { selfv.internals.int() } == unsafe { other.internals.int() },
     ))
 }
-
-fn int_neg(selfv: Object<'_>) -> MethodType<'_> {
-    let res = unsafe { selfv.internals.int }.checked_neg();
-    if res.is_none() {
-        let exc = overflowexc_from_str(
+fn int_lt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_lt);
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            "int negation overflow (value is i128 minimum)",
+            "Types do not match",
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
 
-    MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } < unsafe { other.internals.int() },
+    ))
 }
-fn int_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+fn int_gt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_gt);
     if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
@@ -101,22 +187,55 @@ fn int_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let otherv = unsafe { other.internals.int };
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } > unsafe { other.internals.int() },
+    ))
+}
 
-    let res = unsafe { selfv.internals.int }.checked_add(otherv);
+fn int_neg(selfv: Object<'_>) -> MethodType<'_> {
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_neg();
     if res.is_none() {
-        let exc = overflowexc_from_str(
+        return overflowed(
+            &selfv,
+            "int negation overflow (value is i128 minimum)",
+            value.wrapping_neg(),
+            || -BigInt::from(value),
+        );
+    }
+
+    MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
+}
+fn int_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_add);
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            "int addition overflow",
+            "Types do not match",
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
 
+    let otherv = unsafe { other.internals.int() };
+
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_add(otherv);
+    if res.is_none() {
+        return overflowed(
+            &selfv,
+            "int addition overflow",
+            value.wrapping_add(otherv),
+            || BigInt::from(value) + otherv,
+        );
+    }
+
     MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
 }
 fn int_sub<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_sub);
     if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
@@ -127,22 +246,23 @@ fn int_sub<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let otherv = unsafe { other.internals.int };
+    let otherv = unsafe { other.internals.int() };
 
-    let res = unsafe { selfv.internals.int }.checked_sub(otherv);
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_sub(otherv);
     if res.is_none() {
-        let exc = overflowexc_from_str(
-            selfv.vm.clone(),
+        return overflowed(
+            &selfv,
             "int subtraction overflow",
-            Position::default(),
-            Position::default(),
+            value.wrapping_sub(otherv),
+            || BigInt::from(value) - otherv,
         );
-        return MethodValue::Error(exc);
     }
 
     MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
 }
 fn int_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_mul);
     if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
@@ -153,22 +273,23 @@ fn int_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let otherv = unsafe { other.internals.int };
+    let otherv = unsafe { other.internals.int() };
 
-    let res = unsafe { selfv.internals.int }.checked_mul(otherv);
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_mul(otherv);
     if res.is_none() {
-        let exc = overflowexc_from_str(
-            selfv.vm.clone(),
+        return overflowed(
+            &selfv,
             "int multiplication overflow",
-            Position::default(),
-            Position::default(),
+            value.wrapping_mul(otherv),
+            || BigInt::from(value) * otherv,
         );
-        return MethodValue::Error(exc);
     }
 
     MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
 }
 fn int_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_div);
     if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
@@ -179,7 +300,7 @@ fn int_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let otherv = unsafe { other.internals.int };
+    let otherv = unsafe { other.internals.int() };
     if otherv == 0 {
         let exc = zerodivexc_from_str(
             selfv.vm.clone(),
@@ -190,15 +311,15 @@ fn int_div<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let res = unsafe { selfv.internals.int }.checked_div(otherv);
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_div(otherv);
     if res.is_none() {
-        let exc = overflowexc_from_str(
-            selfv.vm.clone(),
+        return overflowed(
+            &selfv,
             "int division overflow",
-            Position::default(),
-            Position::default(),
+            value.wrapping_div(otherv),
+            || BigInt::from(value) / otherv,
         );
-        return MethodValue::Error(exc);
     }
 
     MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
@@ -214,7 +335,7 @@ fn int_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let otherv = unsafe { other.internals.int };
+    let otherv = unsafe { other.internals.int() };
 
     if otherv >= std::u32::MAX as isize {
         let exc = overflowexc_from_str(
@@ -226,52 +347,178 @@ fn int_pow<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    let res = unsafe { selfv.internals.int }.checked_pow(otherv as u32);
+    let value = unsafe { selfv.internals.int() };
+    let res = value.checked_pow(otherv as u32);
     if res.is_none() {
-        let exc = overflowexc_from_str(
-            selfv.vm.clone(),
+        //A negative power has no int result to promote
+        if otherv < 0 && selfv.vm.int_overflow == IntOverflow::Promote {
+            let exc = overflowexc_from_str(
+                selfv.vm.clone(),
+                "int power overflow",
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+        return overflowed(
+            &selfv,
             "int power overflow",
+            value.wrapping_pow(otherv as u32),
+            || BigInt::from(value).pow(otherv as u32),
+        );
+    }
+
+    MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
+}
+fn int_invert(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(int_from(selfv.vm.clone(), !unsafe {
+        selfv.internals.int()
+    }))
+}
+fn int_bitand<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_bitand);
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
 
-    MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
+    MethodValue::Some(int_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } & unsafe { other.internals.int() },
+    ))
 }
-fn int_hash(selfv: Object<'_>) -> MethodType<'_> {
-    let mut hasher = DefaultHasher::new();
-    unsafe { selfv.internals.int }.hash(&mut hasher);
-    return MethodValue::Some(int_from(selfv.vm.clone(), hasher.finish() as isize));
+fn int_bitor<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_bitor);
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(int_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } | unsafe { other.internals.int() },
+    ))
 }
+fn int_bitxor<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    bigint_operand!(selfv, other, bigint_bitxor);
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
 
-pub fn init_cache<'a>() -> [Option<Object<'a>>; INT_CACHE_SIZE as usize] {
-    unsafe {
-        let init = std::mem::MaybeUninit::uninit();
-        let mut arr: [Option<Object>; INT_CACHE_SIZE as usize] = init.assume_init();
+    MethodValue::Some(int_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } ^ unsafe { other.internals.int() },
+    ))
+}
+fn int_lshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
 
-        for item in &mut arr[..] {
-            std::ptr::write(item, None);
+    let otherv = unsafe { other.internals.int() };
+    if otherv < 0 {
+        let exc = valueexc_from_str(
+            selfv.vm.clone(),
+            "Negative shift count",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    //Shifting out set bits (or changing the sign) overflows, like multiplying by a power of 2
+    let value = unsafe { selfv.internals.int() };
+    let res = u32::try_from(otherv)
+        .ok()
+        .and_then(|n| Some((value.checked_shl(n)?, n)))
+        .filter(|(res, n)| res >> n == value)
+        .map(|(res, _)| res);
+    if res.is_none() {
+        //Bigints bound the bits that a shift adds
+        if selfv.vm.int_overflow == IntOverflow::Promote {
+            return bigintobject::bigint_lshift(selfv, other);
         }
+        let wrapped = u32::try_from(otherv)
+            .ok()
+            .and_then(|n| value.checked_shl(n))
+            .unwrap_or(0);
+        return overflowed(&selfv, "int left shift overflow", wrapped, || {
+            BigInt::from(value) << otherv as usize
+        });
+    }
 
-        arr
+    MethodValue::Some(int_from(selfv.vm.clone(), unwrap_fast!(res)))
+}
+fn int_rshift<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    let otherv = unsafe { other.internals.int() };
+    if otherv < 0 {
+        let exc = valueexc_from_str(
+            selfv.vm.clone(),
+            "Negative shift count",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
     }
+
+    //Shifting by the width or more leaves only the sign
+    let shift = otherv.min(isize::BITS as isize - 1) as u32;
+    MethodValue::Some(int_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.int() } >> shift,
+    ))
+}
+fn int_hash(selfv: Object<'_>) -> MethodType<'_> {
+    let mut hasher = DefaultHasher::new();
+    unsafe { selfv.internals.int() }.hash(&mut hasher);
+    return MethodValue::Some(int_from(selfv.vm.clone(), hasher.finish() as isize));
 }
 
+//The ints from MIN_INT_CACHE to max
 pub fn generate_cache<'a>(
     vm: Trc<VM<'a>>,
     int: Trc<TypeObject<'a>>,
-    arr: *mut [Option<Object<'a>>; INT_CACHE_SIZE as usize],
-) {
-    unsafe {
-        let mut i = MIN_INT_CACHE;
-        for item in &mut (*arr)[..] {
+    max: isize,
+) -> Vec<Object<'a>> {
+    (MIN_INT_CACHE..=max)
+        .map(|i| {
             let mut tp = create_object_from_type(int.clone(), vm.clone(), None);
-            tp.internals = ObjectInternals { int: i };
-            std::ptr::write(item, Some(tp));
-            i += 1;
-        }
-    }
+            tp.internals = ObjectInternals::new_int(i);
+            tp
+        })
+        .collect()
 }
 
 pub fn init(mut vm: Trc<VM<'_>>) {
@@ -289,18 +536,27 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(int_repr),
         abs: Some(int_abs),
         neg: Some(int_neg),
+        invert: Some(int_invert),
         hash_fn: Some(int_hash),
 
         eq: Some(int_eq),
+        lt: Some(int_lt),
+        gt: Some(int_gt),
         add: Some(int_add),
         sub: Some(int_sub),
         mul: Some(int_mul),
         div: Some(int_div),
         pow: Some(int_pow),
+        bitand: Some(int_bitand),
+        bitor: Some(int_bitor),
+        bitxor: Some(int_bitxor),
+        lshift: Some(int_lshift),
+        rshift: Some(int_rshift),
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: None,
 
@@ -1,25 +1,21 @@
-use std::mem::ManuallyDrop;
-
-use super::exceptionobject::valueexc_from_str;
+use super::exceptionobject::{memoryexc_from_str, overflowexc_from_str};
 use super::{
-    create_object_from_type, finalize_type, finalize_type_dict, intobject, MethodType, MethodValue,
-    Object, RawObject, TypeObject,
+    create_object_from_type, finalize_type, finalize_type_dict, intobject, object_id,
+    sequence_index, MethodType, MethodValue, Object, RawObject, TypeObject,
 };
 use crate::is_type_exact;
-use crate::objects::exceptionobject::{methodnotdefinedexc_from_str, typemismatchexc_from_str};
+use crate::objects::exceptionobject::typemismatchexc_from_str;
 use crate::parser::Position;
+use crate::rc::Trc;
 use crate::unwrap_fast;
 use crate::{
     interpreter::VM,
     objects::{boolobject, stringobject, ObjectInternals},
 };
-use trc::Trc;
 
 pub fn list_from<'a>(vm: Trc<VM<'a>>, raw: Vec<Object<'a>>) -> Object<'a> {
     let mut tp = create_object_from_type(unwrap_fast!(vm.types.listtp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        arr: ManuallyDrop::new(raw),
-    };
+    tp.internals = ObjectInternals::new_arr(raw);
     tp
 }
 
@@ -27,8 +23,14 @@ fn list_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> M
     unimplemented!();
 }
 fn list_repr(selfv: Object<'_>) -> MethodType<'_> {
+    RawObject::recursive_repr_guard(selfv, "[...]", list_repr_items)
+}
+//The items are shown by their reprs, so strings are quoted. Only the first VM::repr_limit are shown.
+fn list_repr_items(selfv: Object<'_>) -> MethodType<'_> {
+    let items = unsafe { selfv.internals.arr() };
+    let limit = selfv.vm.repr_limit.unwrap_or(usize::MAX);
     let mut res = String::from("[");
-    for item in unsafe { &selfv.internals.arr }.iter() {
+    for item in items.iter().take(limit) {
         let repr = RawObject::object_repr_safe(item.clone());
         if !repr.is_some() {
             return MethodValue::Error(repr.unwrap_err());
@@ -36,22 +38,8 @@ fn list_repr(selfv: Object<'_>) -> MethodType<'_> {
         res += &unwrap_fast!(repr);
         res += ", ";
     }
-    if res.len() > 1 {
-        res.pop();
-        res.pop();
-    }
-    res += "]";
-    MethodValue::Some(stringobject::string_from(selfv.vm.clone(), res))
-}
-fn list_str(selfv: Object<'_>) -> MethodType<'_> {
-    let mut res = String::from("[");
-    for item in unsafe { &selfv.internals.arr }.iter() {
-        let repr = RawObject::object_str_safe(item.clone());
-        if !repr.is_some() {
-            return MethodValue::Error(repr.unwrap_err());
-        }
-        res += &unwrap_fast!(repr);
-        res += ", ";
+    if items.len() > limit {
+        res += "..., ";
     }
     if res.len() > 1 {
         res.pop();
@@ -72,43 +60,83 @@ fn list_get<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Error(exc);
     }
 
-    //NEGATIVE INDEX IS CONVERTED TO +
-    let out = unsafe { &selfv.internals.arr }.get((unsafe { other.internals.int }).unsigned_abs());
-
-    if out.is_none() {
-        let exc = valueexc_from_str(
+    let pos = sequence_index(
+        selfv.vm.clone(),
+        unsafe { other.internals.int() },
+        unsafe { selfv.internals.arr() }.len(),
+    );
+    if pos.is_error() {
+        return MethodValue::Error(pos.unwrap_err());
+    }
+    MethodValue::Some(unsafe { selfv.internals.arr() }[unwrap_fast!(pos)].clone())
+}
+fn list_set<'a>(mut selfv: Object<'a>, other: Object<'a>, value: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&other, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            &format!(
-                "Index out of range: maximum index is '{}', but got '{}'",
-                unsafe { &selfv.internals.arr }.len(),
-                unsafe { &other.internals.int }.unsigned_abs()
-            ),
+            &format!("Expected 'int' index, got '{}'", other.tp.typename),
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
-    MethodValue::Some(unwrap_fast!(out).clone())
+
+    let pos = sequence_index(
+        selfv.vm.clone(),
+        unsafe { other.internals.int() },
+        unsafe { selfv.internals.arr() }.len(),
+    );
+    if pos.is_error() {
+        return MethodValue::Error(pos.unwrap_err());
+    }
+
+    let mut arr = unsafe { selfv.internals.arr() }.clone();
+    arr[unwrap_fast!(pos)] = value;
+
+    selfv.internals = ObjectInternals::new_arr(arr);
+
+    MethodValue::Some(none_from!(selfv.vm.clone()))
 }
-fn list_set<'a>(mut selfv: Object<'a>, other: Object<'a>, value: Object<'a>) -> MethodType<'a> {
-    if is_type_exact!(&other, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+fn list_len(selfv: Object<'_>) -> MethodType<'_> {
+    let convert = unsafe { selfv.internals.arr() }.len().try_into();
+    MethodValue::Some(intobject::int_from(selfv.vm.clone(), unwrap_fast!(convert)))
+}
+
+fn list_contains<'a>(selfv: Object<'a>, item: Object<'a>) -> MethodType<'a> {
+    for v in unsafe { selfv.internals.arr() }.iter() {
+        let res = RawObject::object_eq_safe(v.clone(), item.clone());
+        if res.is_error() {
+            return MethodValue::Error(res.unwrap_err());
+        }
+        if unwrap_fast!(res) {
+            return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), true));
+        }
+    }
+    MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false))
+}
+
+fn list_add<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            &format!("Expected 'int' index, got '{}'", other.tp.typename),
+            "Types do not match",
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
 
-    //NEGATIVE INDEX IS CONVERTED TO +
-    if unsafe { other.internals.int }.unsigned_abs() >= unsafe { &selfv.internals.arr }.len() {
-        let exc = valueexc_from_str(
+    let mut arr = unsafe { selfv.internals.arr() }.to_vec();
+    arr.extend(unsafe { other.internals.arr() }.iter().cloned());
+    MethodValue::Some(list_from(selfv.vm.clone(), arr))
+}
+fn list_mul<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&other, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
             &format!(
-                "Index out of range: maximum index is '{}', but got '{}'",
-                unsafe { &selfv.internals.arr }.len(),
-                unsafe { &other.internals.int }.unsigned_abs()
+                "Expected 'int' repetition count, got '{}'",
+                other.tp.typename
             ),
             Position::default(),
             Position::default(),
@@ -116,59 +144,71 @@ fn list_set<'a>(mut selfv: Object<'a>, other: Object<'a>, value: Object<'a>) ->
         return MethodValue::Error(exc);
     }
 
-    let mut arr = unsafe { &selfv.internals.arr }.clone();
-    arr[unsafe { other.internals.int }.unsigned_abs()] = value;
-
-    selfv.internals = ObjectInternals { arr };
+    //Negative counts produce an empty list
+    let n = unsafe { other.internals.int() }.max(0) as usize;
+    let len = unsafe { selfv.internals.arr() }.len().checked_mul(n);
+    if len.is_none() {
+        let exc = overflowexc_from_str(
+            selfv.vm.clone(),
+            "list repetition overflow",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
 
-    MethodValue::Some(none_from!(selfv.vm.clone()))
-}
-fn list_len(selfv: Object<'_>) -> MethodType<'_> {
-    let convert = unsafe { &selfv.internals.arr }.len().try_into();
-    MethodValue::Some(intobject::int_from(selfv.vm.clone(), unwrap_fast!(convert)))
+    //A count that fits in usize may still be far more than the memory there is
+    let mut arr = Vec::new();
+    if arr.try_reserve_exact(unwrap_fast!(len)).is_err() {
+        let exc = memoryexc_from_str(
+            selfv.vm.clone(),
+            "list repetition is too large to allocate",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    for _ in 0..n {
+        arr.extend(unsafe { selfv.internals.arr() }.iter().cloned());
+    }
+    MethodValue::Some(list_from(selfv.vm.clone(), arr))
 }
 
-#[allow(unused_unsafe)]
 fn list_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
     if !is_type_exact!(&selfv, other.tp) {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
+    if Trc::ptr_eq(&selfv, &other) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), true));
+    }
 
-    if unsafe { &selfv.internals.arr }.len() != unsafe { &other.internals.arr }.len() {
+    if unsafe { selfv.internals.arr() }.len() != unsafe { other.internals.arr() }.len() {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
-    for idx in 0..unsafe { &selfv.internals.arr }.len() {
-        if unwrap_fast!(&selfv.internals.arr.get(idx)).tp.eq.is_none() {
-            let exc = methodnotdefinedexc_from_str(
-                selfv.vm.clone(),
-                "Method 'eq' is not defined for value",
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
-        let v = unwrap_fast!(&selfv.internals.arr.get(idx));
-        let res = (v.tp.eq.expect("Method is not defined"))(
-            v.clone(),
-            unwrap_fast!(&other.internals.arr.get(idx)).clone(),
-        );
+
+    //A list may contain itself: if this pair is already being compared further up, it is
+    //equal so far, and the comparison in progress decides the result.
+    let pair = (object_id(&selfv), object_id(&other));
+    let mut vm = selfv.vm.clone();
+    if vm.eq_in_progress.contains(&pair) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), true));
+    }
+    vm.eq_in_progress.insert(pair);
+    let res = list_eq_items(selfv, other);
+    vm.eq_in_progress.remove(&pair);
+    res
+}
+
+fn list_eq_items<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    for (v, otherv) in std::iter::zip(
+        unsafe { selfv.internals.arr() }.iter(),
+        unsafe { other.internals.arr() }.iter(),
+    ) {
+        let res = RawObject::object_eq_safe(v.clone(), otherv.clone());
         if res.is_error() {
-            return res;
+            return MethodValue::Error(res.unwrap_err());
         }
-        if !is_type_exact!(
-            &unwrap_fast!(res),
-            unwrap_fast!(selfv.vm.types.booltp.as_ref()).clone()
-        ) {
-            let exc = typemismatchexc_from_str(
-                selfv.vm.clone(),
-                "Method 'eq' did not return 'bool'",
-                Position::default(),
-                Position::default(),
-            );
-            return MethodValue::Error(exc);
-        }
-
-        if unsafe { unwrap_fast!(res).internals.bool } {
+        if !unwrap_fast!(res) {
             return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
         }
     }
@@ -187,20 +227,29 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         new: Some(list_new),
 
         repr: Some(list_repr),
-        str: Some(list_str),
+        str: Some(list_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: None,
         eq: Some(list_eq),
-        add: None,
+        lt: None,
+        gt: None,
+        add: Some(list_add),
         sub: None,
-        mul: None,
+        mul: Some(list_mul),
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: Some(list_get),
         set: Some(list_set),
         len: Some(list_len),
+        contains: Some(list_contains),
 
         call: None,
 
@@ -1,21 +1,17 @@
-use std::mem::ManuallyDrop;
-
-use trc::Trc;
+use crate::rc::Trc;
 
 use crate::{interpreter::VM, is_type_exact, parser::Position, unwrap_fast};
 
 use super::{
-    boolobject, create_object_from_type, exceptionobject::methodnotdefinedexc_from_str,
-    finalize_type, finalize_type_dict, listobject, stringobject, MethodType, MethodValue, Object,
-    ObjectInternals, RawObject, TypeObject,
+    boolobject, call_with_first, create_object_from_type,
+    exceptionobject::methodnotdefinedexc_from_str, finalize_type, finalize_type_dict, stringobject,
+    MethodType, MethodValue, Object, ObjectInternals, RawObject, TypeObject,
 };
 
 pub fn method_from<'a>(vm: Trc<VM<'a>>, fun: Object<'a>, instance: Object<'a>) -> Object<'a> {
     let mut tp =
         create_object_from_type(unwrap_fast!(vm.types.methodtp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        fn_wrapper: ManuallyDrop::new(super::FnWrapper { fun, instance }),
-    };
+    tp.internals = ObjectInternals::new_fn_wrapper(super::FnWrapper { fun, instance });
     tp
 }
 
@@ -23,12 +19,17 @@ fn method_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) ->
     unimplemented!();
 }
 fn method_repr(selfv: Object<'_>) -> MethodType<'_> {
-    if unsafe { &selfv.internals.fn_wrapper }.fun.tp.repr.is_none() {
+    if unsafe { selfv.internals.fn_wrapper() }
+        .fun
+        .tp
+        .repr
+        .is_none()
+    {
         let exc = methodnotdefinedexc_from_str(
             selfv.vm.clone(),
             &format!(
                 "Method 'repr' is not defined for '{}' type",
-                unsafe { &selfv.internals.fn_wrapper }.fun.tp.typename
+                unsafe { selfv.internals.fn_wrapper() }.fun.tp.typename
             ),
             Position::default(),
             Position::default(),
@@ -37,13 +38,13 @@ fn method_repr(selfv: Object<'_>) -> MethodType<'_> {
     }
 
     let repr_result =
-        RawObject::object_repr_safe(unsafe { &selfv.internals.fn_wrapper }.fun.clone());
+        RawObject::object_repr_safe(unsafe { selfv.internals.fn_wrapper() }.fun.clone());
     if repr_result.is_error() {
         return MethodValue::Error(repr_result.unwrap_err());
     }
 
     let inst_tp_result =
-        RawObject::object_repr_safe(unsafe { &selfv.internals.fn_wrapper }.instance.clone());
+        RawObject::object_repr_safe(unsafe { selfv.internals.fn_wrapper() }.instance.clone());
     if inst_tp_result.is_error() {
         return MethodValue::Error(inst_tp_result.unwrap_err());
     }
@@ -63,14 +64,18 @@ fn method_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
         return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
     }
 
+    //Methods are equal if they bind the same function to the same instance
+    let selfdata = unsafe { selfv.internals.fn_wrapper() };
+    let otherdata = unsafe { other.internals.fn_wrapper() };
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { &selfv.internals.fun } == unsafe { &other.internals.fun },
+        Trc::ptr_eq(&selfdata.fun, &otherdata.fun)
+            && Trc::ptr_eq(&selfdata.instance, &otherdata.instance),
     ))
 }
 
-fn method_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
-    let mdata = unsafe { &selfv.internals.fn_wrapper };
+fn method_call<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let mdata = unsafe { selfv.internals.fn_wrapper() };
     if mdata.fun.tp.call.is_none() {
         let exc = methodnotdefinedexc_from_str(
             selfv.vm.clone(),
@@ -83,13 +88,11 @@ fn method_call<'a>(selfv: Object<'a>, args: Object<'a>) -> MethodType<'a> {
         );
         return MethodValue::Error(exc);
     }
-    let mut args = unsafe { &args.internals.arr }.clone();
-
-    args.insert(0, mdata.instance.clone());
-
-    mdata.fun.tp.call.unwrap()(
+    call_with_first(
+        mdata.fun.tp.call.unwrap(),
         mdata.fun.clone(),
-        listobject::list_from(selfv.vm.clone(), args.to_vec()),
+        mdata.instance.clone(),
+        args,
     )
 }
 
@@ -108,17 +111,26 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(method_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: None,
         eq: Some(method_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: Some(method_call),
 
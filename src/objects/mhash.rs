@@ -1,7 +1,7 @@
 use crate::{
     is_type_exact,
     objects::{
-        exceptionobject::{methodnotdefinedexc_from_str, typemismatchexc_from_str},
+        exceptionobject::{typemismatchexc_from_str, unhashableexc_from_str},
         MethodValue,
     },
     parser::Position,
@@ -9,10 +9,13 @@ use crate::{
 };
 
 use super::{exceptionobject::keynotfoundexc_from_str, MethodType, Object, RawObject};
+use crate::rc::Trc;
 
+//Keys with the same hash share a bucket, and are told apart with their eq slot
 #[derive(Clone, PartialEq, Eq)]
 pub struct HashMap<'a> {
-    values: hashbrown::HashMap<isize, (Object<'a>, Object<'a>)>,
+    values: hashbrown::HashMap<isize, Vec<(Object<'a>, Object<'a>)>>,
+    len: usize,
 }
 
 impl<'a> Default for HashMap<'a> {
@@ -25,6 +28,7 @@ impl<'a> HashMap<'a> {
     pub fn new() -> Self {
         HashMap {
             values: hashbrown::HashMap::new(),
+            len: 0,
         }
     }
 
@@ -32,16 +36,13 @@ impl<'a> HashMap<'a> {
     #[inline]
     fn hash(key: Object<'a>) -> MethodValue<isize, Object<'a>> {
         if key.tp.hash_fn.is_none() {
-            let exc = methodnotdefinedexc_from_str(
+            let exc = unhashableexc_from_str(
                 key.vm.clone(),
-                &format!(
-                    "Method 'hash' is not defined for '{}' type",
-                    key.tp.typename
-                ),
+                &format!("Unhashable type '{}'", key.tp.typename),
                 Position::default(),
                 Position::default(),
             );
-            unwrap_fast!(key.vm.interpreters.last()).raise_exc(exc);
+            return MethodValue::Error(exc);
         }
         let res = (key.tp.hash_fn.expect("Hash function not found"))(key.clone());
         if res.is_error() {
@@ -61,25 +62,75 @@ impl<'a> HashMap<'a> {
             return MethodValue::Error(exc);
         }
 
-        MethodValue::Some(unsafe { unwrap_fast!(res).internals.int })
+        MethodValue::Some(unsafe { unwrap_fast!(res).internals.int() })
+    }
+
+    //Find the index of a key in its bucket
+    #[inline]
+    fn find(
+        bucket: &[(Object<'a>, Object<'a>)],
+        key: &Object<'a>,
+    ) -> MethodValue<Option<usize>, Object<'a>> {
+        for (i, (k, _)) in bucket.iter().enumerate() {
+            if Trc::ptr_eq(k, key) {
+                return MethodValue::Some(Some(i));
+            }
+            let res = RawObject::object_eq_safe(key.clone(), k.clone());
+            if res.is_error() {
+                return MethodValue::Error(res.unwrap_err());
+            }
+            if unwrap_fast!(res) {
+                return MethodValue::Some(Some(i));
+            }
+        }
+        MethodValue::Some(None)
     }
 
+    //A key that is already present keeps its place, and its value is replaced
     #[inline]
     pub fn insert(&mut self, key: Object<'a>, value: Object<'a>) -> MethodValue<(), Object<'a>> {
         let keyv = Self::hash(key.clone());
         if keyv.is_error() {
             return MethodValue::Error(keyv.unwrap_err());
         }
-        self.values.insert(unwrap_fast!(keyv), (key, value));
+        let bucket = self.values.entry(unwrap_fast!(keyv)).or_default();
+        let idx = Self::find(bucket, &key);
+        if idx.is_error() {
+            return MethodValue::Error(idx.unwrap_err());
+        }
+        match unwrap_fast!(idx) {
+            Some(i) => bucket[i].1 = value,
+            None => {
+                bucket.push((key, value));
+                self.len += 1;
+            }
+        }
         MethodValue::Some(())
     }
 
-    pub fn get(&self, key: Object<'a>) -> MethodType<'a> {
+    //Get the value of a key, or None if it is not present
+    pub fn try_get(&self, key: Object<'a>) -> MethodValue<Option<Object<'a>>, Object<'a>> {
         let keyv = Self::hash(key.clone());
         if keyv.is_error() {
             return MethodValue::Error(keyv.unwrap_err());
         }
-        let res = self.values.get(&unwrap_fast!(keyv));
+        let bucket = match self.values.get(&unwrap_fast!(keyv)) {
+            Some(bucket) => bucket,
+            None => return MethodValue::Some(None),
+        };
+        let idx = Self::find(bucket, &key);
+        if idx.is_error() {
+            return MethodValue::Error(idx.unwrap_err());
+        }
+        MethodValue::Some(unwrap_fast!(idx).map(|i| bucket[i].1.clone()))
+    }
+
+    pub fn get(&self, key: Object<'a>) -> MethodType<'a> {
+        let res = self.try_get(key.clone());
+        if res.is_error() {
+            return MethodValue::Error(res.unwrap_err());
+        }
+        let res = unwrap_fast!(res);
         if res.is_none() {
             let str = RawObject::object_str_safe(key.clone());
             if str.is_error() {
@@ -93,17 +144,26 @@ impl<'a> HashMap<'a> {
             );
             return MethodValue::Error(exc);
         }
-        MethodValue::Some(unwrap_fast!(res).1.clone())
+        MethodValue::Some(unwrap_fast!(res))
     }
 
     pub fn len(&self) -> usize {
-        self.values.len()
+        self.len
+    }
+
+    //Bytes allocated for the buckets, not counting the keys and values themselves
+    pub fn heap_size(&self) -> usize {
+        self.values.capacity() * std::mem::size_of::<(isize, Vec<(Object<'a>, Object<'a>)>)>()
+            + self
+                .values
+                .values()
+                .map(|bucket| bucket.capacity() * std::mem::size_of::<(Object<'a>, Object<'a>)>())
+                .sum::<usize>()
     }
 }
 
 pub struct HMapIter<'a> {
-    keys: Vec<isize>,
-    values: hashbrown::HashMap<isize, (Object<'a>, Object<'a>)>,
+    items: Vec<(Object<'a>, Object<'a>)>,
     i: usize,
 }
 
@@ -111,10 +171,9 @@ impl<'a> Iterator for HMapIter<'a> {
     type Item = (Object<'a>, Object<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let key = self.keys.get(self.i)?;
-        let get = unwrap_fast!(self.values.get(key));
+        let item = self.items.get(self.i)?;
         self.i += 1;
-        Some((get.0.clone(), get.1.clone()))
+        Some(item.clone())
     }
 }
 
@@ -123,9 +182,22 @@ impl<'a> IntoIterator for &HashMap<'a> {
     type IntoIter = HMapIter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
+        let mut buckets: Vec<_> = self.values.iter().collect();
+        //The order of the buckets depends on the random state of the hasher, so deterministic runs
+        //visit them by hash instead
+        let deterministic = buckets
+            .iter()
+            .find_map(|(_, bucket)| bucket.first())
+            .is_some_and(|(key, _)| key.vm.deterministic);
+        if deterministic {
+            buckets.sort_unstable_by_key(|(hash, _)| **hash);
+        }
         return HMapIter {
-            keys: self.values.keys().copied().collect(),
-            values: self.values.clone(),
+            items: buckets
+                .into_iter()
+                .flat_map(|(_, bucket)| bucket)
+                .cloned()
+                .collect(),
             i: 0,
         };
     }
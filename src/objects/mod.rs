@@ -1,8 +1,12 @@
-use std::mem::ManuallyDrop;
 use std::ops::Deref;
 
-use crate::{compiler::Bytecode, interpreter::VM, parser::Position, unwrap_fast};
-use trc::Trc;
+use crate::rc::Trc;
+use crate::{
+    compiler::{Bytecode, Code},
+    interpreter::{Scope, METHOD_CACHE_SIZE, VM},
+    parser::Position,
+    unwrap_fast,
+};
 
 use self::exceptionobject::{
     attrexc_from_str, methodnotdefinedexc_from_str, typemismatchexc_from_str,
@@ -15,15 +19,24 @@ pub mod objectobject;
 pub mod typeobject;
 #[macro_use]
 pub mod noneobject;
+pub mod bigintobject;
 pub mod boolobject;
+pub mod builtinobject;
 pub mod classtype;
 pub mod codeobject;
 pub mod dictobject;
 pub mod exceptionobject;
+pub mod floatobject;
 pub mod fnobject;
+mod internals;
 pub mod listobject;
 pub mod methodobject;
+pub mod moduleobject;
+pub mod shape;
 pub mod stringobject;
+pub mod superobject;
+
+pub use internals::ObjectInternals;
 
 #[derive(Clone, PartialEq, Eq)]
 pub enum ObjectBase<'a> {
@@ -72,23 +85,32 @@ pub struct TypeObject<'a> {
     pub str: Option<fn(Object<'a>) -> MethodType<'a>>,  //self
     pub abs: Option<fn(Object<'a>) -> MethodType<'a>>,  //self
     pub neg: Option<fn(Object<'a>) -> MethodType<'a>>,  //self
+    pub invert: Option<fn(Object<'a>) -> MethodType<'a>>, //self
     pub hash_fn: Option<fn(Object<'a>) -> MethodType<'a>>, //self
 
     //binary
     pub eq: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub lt: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub gt: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub add: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub sub: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub mul: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub div: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub pow: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub bitand: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub bitor: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub bitxor: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub lshift: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
+    pub rshift: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
 
     //sequences
     pub get: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other
     pub set: Option<fn(Object<'a>, Object<'a>, Object<'a>) -> MethodType<'a>>, //self, other, value
     pub len: Option<fn(Object<'a>) -> MethodType<'a>>,             //self
+    pub contains: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, item
 
     //interaction
-    pub call: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, args
+    pub call: Option<CallFn<'a>>, //self, args
 
     //attributes
     pub getattr: Option<fn(Object<'a>, Object<'a>) -> MethodType<'a>>, //self, attr
@@ -105,6 +127,51 @@ impl<'a> PartialEq for RawObject<'a> {
     }
 }
 
+impl<'a> TypeObject<'a> {
+    //The method resolution order: this type, then its bases depth first, each type once
+    pub fn mro(&self) -> Vec<TypeObject<'a>> {
+        let mut mro: Vec<TypeObject<'a>> = vec![self.clone()];
+        for base in &self.bases {
+            if let ObjectBase::Object(_) = base {
+                continue;
+            }
+            for tp in base.mro() {
+                if !mro.iter().any(|seen| seen.typeid == tp.typeid) {
+                    mro.push(tp);
+                }
+            }
+        }
+        mro
+    }
+
+    //Find an attribute in the dicts of the types in the mro, skipping the first `skip` types
+    pub fn lookup(
+        &self,
+        attr: Object<'a>,
+        skip: usize,
+    ) -> MethodValue<Option<Object<'a>>, Object<'a>> {
+        for tp in self.mro().iter().skip(skip) {
+            if let Some(dict) = &tp.dict {
+                let res = unsafe { dict.internals.map() }.try_get(attr.clone());
+                if res.is_error() || unwrap_fast!(res).is_some() {
+                    return res;
+                }
+            }
+        }
+        MethodValue::Some(None)
+    }
+
+    //Whether this type is the type with the given id, or inherits from it
+    pub fn is_subtype(&self, typeid: u32) -> bool {
+        self.typeid == typeid
+            || self.bases.iter().any(|base| match base {
+                //The object type is its own base
+                ObjectBase::Object(vm) => unwrap_fast!(vm.types.objecttp.as_ref()).typeid == typeid,
+                ObjectBase::Other(tp) => tp.is_subtype(typeid),
+            })
+    }
+}
+
 impl<'a> PartialEq for TypeObject<'a> {
     fn eq(&self, other: &Self) -> bool {
         self.typename == other.typename && self.bases == other.bases
@@ -113,17 +180,77 @@ impl<'a> PartialEq for TypeObject<'a> {
 
 impl<'a> Drop for RawObject<'a> {
     fn drop(&mut self) {
+        //Nothing is called while the program is unwinding, as a panic in drop would abort
+        if !self.vm.drop_classes.is_empty()
+            && self.vm.drop_classes.contains(&self.tp.typeid)
+            && !self
+                .vm
+                .clone()
+                .dropped
+                .remove(&(self as *const RawObject<'a> as usize))
+            && !std::thread::panicking()
+        {
+            classtype::call_drop(self);
+        }
+        //The internals are not dropped, but a method must release its instance, which is often
+        //bound just to call it and would otherwise never be freed
+        if self.is_exact(&self.vm.types.methodtp) {
+            unsafe { self.internals.drop_fn_wrapper() };
+        }
         unsafe { std::ptr::drop_in_place(&mut self.internals) };
     }
 }
 
+//Reads of the internals that check the type of the object first, and are None for an object of
+//another type. Subclasses do not store the data of their builtin base, so the type must be exact.
+impl<'a> RawObject<'a> {
+    fn is_exact(&self, tp: &Option<Trc<TypeObject<'a>>>) -> bool {
+        is_type_exact!(self, unwrap_fast!(tp.as_ref()))
+    }
+
+    pub fn as_int(&self) -> Option<isize> {
+        self.is_exact(&self.vm.types.inttp)
+            .then(|| unsafe { self.internals.int() })
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        self.is_exact(&self.vm.types.floattp)
+            .then(|| unsafe { self.internals.float() })
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        self.is_exact(&self.vm.types.booltp)
+            .then(|| unsafe { self.internals.bool() })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.is_exact(&self.vm.types.strtp)
+            .then(|| unsafe { self.internals.str() }.as_str())
+    }
+
+    pub fn as_list(&self) -> Option<&[Object<'a>]> {
+        self.is_exact(&self.vm.types.listtp)
+            .then(|| unsafe { self.internals.arr() }.as_slice())
+    }
+
+    pub fn as_code(&self) -> Option<&Trc<Code<'a>>> {
+        self.is_exact(&self.vm.types.codetp)
+            .then(|| unsafe { self.internals.code() })
+    }
+
+    pub fn as_type(&self) -> Option<&TypeObject<'a>> {
+        self.is_exact(&self.vm.types.typetp)
+            .then(|| unsafe { self.internals.typ() })
+    }
+}
+
 impl<'a> RawObject<'a> {
     pub fn object_repr(object: &Object<'_>) -> String {
         unsafe {
-            &(object.clone().tp.repr.expect("Method is not defined"))(object.clone())
+            (object.clone().tp.repr.expect("Method is not defined"))(object.clone())
                 .unwrap()
                 .internals
-                .str
+                .str()
         }
         .to_string()
     }
@@ -166,16 +293,16 @@ impl<'a> RawObject<'a> {
             return MethodValue::Error(exc);
         }
 
-        MethodValue::Some(unsafe { &unwrap_fast!(reprv).internals.str }.to_string())
+        MethodValue::Some(unsafe { unwrap_fast!(reprv).internals.str() }.to_string())
     }
 
     #[allow(dead_code)]
     pub fn object_str(object: &Object<'_>) -> String {
         unsafe {
-            &(object.clone().tp.str.expect("Method is not defined"))(object.clone())
+            (object.clone().tp.str.expect("Method is not defined"))(object.clone())
                 .unwrap()
                 .internals
-                .str
+                .str()
         }
         .to_string()
     }
@@ -218,37 +345,419 @@ impl<'a> RawObject<'a> {
             return MethodValue::Error(exc);
         }
 
-        MethodValue::Some(unsafe { &unwrap_fast!(strv).internals.str }.to_string())
+        MethodValue::Some(unsafe { unwrap_fast!(strv).internals.str() }.to_string())
     }
 
-    #[inline]
-    fn generic_getattr(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
-        if selfv.dict.is_none() {
-            let repr = RawObject::object_str_safe(attr);
-            if repr.is_error() {
-                return MethodValue::Error(repr.unwrap_err());
+    //Run a repr or str slot, rendering the object as the placeholder if it is already being
+    //rendered further up, as a container that contains itself would otherwise recurse forever
+    pub fn recursive_repr_guard<'b>(
+        object: Object<'b>,
+        placeholder: &str,
+        repr: fn(Object<'b>) -> MethodType<'b>,
+    ) -> MethodType<'b> {
+        let id = object_id(&object);
+        let mut vm = object.vm.clone();
+        if vm.repr_in_progress.contains(&id) {
+            return MethodValue::Some(stringobject::string_from(
+                object.vm.clone(),
+                placeholder.to_string(),
+            ));
+        }
+        vm.repr_in_progress.insert(id);
+        let res = repr(object);
+        vm.repr_in_progress.remove(&id);
+        res
+    }
+
+    //Get the elements that iterating over an object yields: list elements, dict keys or characters
+    pub fn object_iter_safe(object: Object<'_>) -> MethodValue<Vec<Object<'_>>, Object<'_>> {
+        let vm = object.vm.clone();
+        if is_type_exact!(&object, unwrap_fast!(vm.types.listtp.as_ref())) {
+            return MethodValue::Some(unsafe { object.internals.arr() }.to_vec());
+        }
+        if is_type_exact!(&object, unwrap_fast!(vm.types.dicttp.as_ref())) {
+            return MethodValue::Some(
+                unsafe { object.internals.map() }
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect(),
+            );
+        }
+        if is_type_exact!(&object, unwrap_fast!(vm.types.strtp.as_ref())) {
+            return MethodValue::Some(stringobject::graphemes(&object));
+        }
+        MethodValue::Error(typemismatchexc_from_str(
+            vm.clone(),
+            &format!("'{}' object is not iterable", object.tp.typename),
+            Position::default(),
+            Position::default(),
+        ))
+    }
+
+    //Estimate the bytes used by an object and the buffers it owns, not counting the objects
+    //it refers to
+    pub fn object_sizeof(object: &Object<'_>) -> usize {
+        let types = &object.vm.types;
+        let heap = if is_type_exact!(object, unwrap_fast!(types.strtp.as_ref())) {
+            unsafe { object.internals.str() }.buffer_len()
+        } else if is_type_exact!(object, unwrap_fast!(types.listtp.as_ref())) {
+            unsafe { object.internals.arr() }.capacity() * std::mem::size_of::<Object<'_>>()
+        } else if is_type_exact!(object, unwrap_fast!(types.dicttp.as_ref())) {
+            unsafe { object.internals.map() }.heap_size()
+        } else if let Some(attrs) = shape::instance_attrs(object) {
+            attrs.values.capacity() * std::mem::size_of::<Object<'_>>()
+        } else {
+            0
+        };
+        std::mem::size_of::<RawObject<'_>>() + heap
+    }
+
+    //Whether an object can not be changed, so that copies of it may share it
+    fn is_immutable(object: &Object<'_>) -> bool {
+        let types = &object.vm.types;
+        [
+            &types.inttp,
+            &types.bigtp,
+            &types.floattp,
+            &types.strtp,
+            &types.booltp,
+            &types.nonetp,
+            &types.fntp,
+            &types.builtintp,
+            &types.typetp,
+        ]
+        .iter()
+        .any(|tp| is_type_exact!(object, unwrap_fast!(tp.as_ref())))
+    }
+
+    fn copy_error(object: &Object<'a>) -> MethodType<'a> {
+        MethodValue::Error(typemismatchexc_from_str(
+            object.vm.clone(),
+            &format!("Cannot copy object of type '{}'", object.tp.typename),
+            Position::default(),
+            Position::default(),
+        ))
+    }
+
+    //Copy the top level of a list, dict or class instance, sharing the elements. Immutable
+    //objects are returned as they are.
+    pub fn object_copy(object: Object<'a>) -> MethodType<'a> {
+        let vm = object.vm.clone();
+        if RawObject::is_immutable(&object) {
+            return MethodValue::Some(object);
+        }
+        if is_type_exact!(&object, unwrap_fast!(vm.types.listtp.as_ref())) {
+            return MethodValue::Some(listobject::list_from(
+                vm.clone(),
+                unsafe { object.internals.arr() }.to_vec(),
+            ));
+        }
+        if is_type_exact!(&object, unwrap_fast!(vm.types.dicttp.as_ref())) {
+            return MethodValue::Some(dictobject::dict_from(
+                vm.clone(),
+                (*unsafe { object.internals.map() }).clone(),
+            ));
+        }
+        //Class instances are the only other objects with a type dict
+        if object.tp.dict.is_some() {
+            let dict = match &object.dict {
+                Some(dict) => {
+                    let res = RawObject::object_copy(dict.clone());
+                    if res.is_error() {
+                        return res;
+                    }
+                    Some(unwrap_fast!(res))
+                }
+                None => None,
+            };
+            let mut copy = shape::instance_from(object.tp.clone(), vm);
+            copy.dict = dict;
+            if let Some(attrs) = shape::instance_attrs(&object) {
+                shape::set_instance_attrs(copy.clone(), attrs.clone());
+            }
+            return MethodValue::Some(copy);
+        }
+        RawObject::copy_error(&object)
+    }
+
+    //Copy a list, dict or class instance and everything it contains. Objects that are reached more
+    //than once, including through a cycle, are copied once: memo maps their ids to the copies.
+    pub fn object_deepcopy(object: Object<'a>, memo: &mut IdMap<Object<'a>>) -> MethodType<'a> {
+        let vm = object.vm.clone();
+        if RawObject::is_immutable(&object) {
+            return MethodValue::Some(object);
+        }
+        let id = object_id(&object);
+        if let Some(copy) = memo.get(&id) {
+            return MethodValue::Some(copy.clone());
+        }
+
+        //The copy is registered before its elements are copied, so that cycles refer back to it
+        if is_type_exact!(&object, unwrap_fast!(vm.types.listtp.as_ref())) {
+            let mut copy = listobject::list_from(vm.clone(), Vec::new());
+            memo.insert(id, copy.clone());
+            let mut arr = Vec::new();
+            for item in unsafe { object.internals.arr() }.iter() {
+                let res = RawObject::object_deepcopy(item.clone(), memo);
+                if res.is_error() {
+                    return res;
+                }
+                arr.push(unwrap_fast!(res));
+            }
+            copy.internals = ObjectInternals::new_arr(arr);
+            return MethodValue::Some(copy);
+        }
+        if is_type_exact!(&object, unwrap_fast!(vm.types.dicttp.as_ref())) {
+            let mut copy = dictobject::dict_from(vm.clone(), mhash::HashMap::new());
+            memo.insert(id, copy.clone());
+            let mut map = mhash::HashMap::new();
+            for (key, value) in unsafe { object.internals.map() }.into_iter() {
+                let key = RawObject::object_deepcopy(key, memo);
+                if key.is_error() {
+                    return key;
+                }
+                let value = RawObject::object_deepcopy(value, memo);
+                if value.is_error() {
+                    return value;
+                }
+                let res = map.insert(unwrap_fast!(key), unwrap_fast!(value));
+                if res.is_error() {
+                    return MethodValue::Error(res.unwrap_err());
+                }
+            }
+            copy.internals = ObjectInternals::new_map(map);
+            return MethodValue::Some(copy);
+        }
+        if object.tp.dict.is_some() {
+            let mut copy = shape::instance_from(object.tp.clone(), vm);
+            memo.insert(id, copy.clone());
+            if let Some(dict) = &object.dict {
+                let res = RawObject::object_deepcopy(dict.clone(), memo);
+                if res.is_error() {
+                    return res;
+                }
+                copy.dict = Some(unwrap_fast!(res));
             }
-            return MethodValue::Error(attrexc_from_str(
-                selfv.vm.clone(),
+            if let Some(attrs) = shape::instance_attrs(&object) {
+                let mut values = Vec::new();
+                for value in &attrs.values {
+                    let res = RawObject::object_deepcopy(value.clone(), memo);
+                    if res.is_error() {
+                        return res;
+                    }
+                    values.push(unwrap_fast!(res));
+                }
+                shape::set_instance_attrs(
+                    copy.clone(),
+                    shape::InstanceAttrs {
+                        shape: attrs.shape,
+                        values,
+                    },
+                );
+            }
+            return MethodValue::Some(copy);
+        }
+        RawObject::copy_error(&object)
+    }
+
+    //Compare two objects with the eq slot of the first
+    #[allow(unused_unsafe)]
+    pub fn object_eq_safe<'b>(
+        object: Object<'b>,
+        other: Object<'b>,
+    ) -> MethodValue<bool, Object<'b>> {
+        let eq = object.clone().tp.eq;
+        if eq.is_none() {
+            let exc = methodnotdefinedexc_from_str(
+                object.vm.clone(),
                 &format!(
-                    "Object of type '{}' has no attribute '{}'",
-                    selfv.tp.typename,
-                    repr.unwrap(),
+                    "Method 'eq' is not defined for '{}' type",
+                    object.tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+
+        let res = (unwrap_fast!(eq))(object.clone(), other);
+
+        if res.is_error() {
+            return MethodValue::Error(res.unwrap_err());
+        }
+
+        if !is_type_exact!(
+            &unwrap_fast!(res),
+            unwrap_fast!(object.vm.types.booltp.as_ref()).clone()
+        ) {
+            let exc = typemismatchexc_from_str(
+                object.vm.clone(),
+                &format!(
+                    "Method 'eq' of '{}' type returned non-bool",
+                    object.tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+
+        MethodValue::Some(unsafe { unwrap_fast!(res).internals.bool() })
+    }
+
+    //Order two objects with the lt slot of the first
+    pub fn object_lt_safe<'b>(
+        object: Object<'b>,
+        other: Object<'b>,
+    ) -> MethodValue<bool, Object<'b>> {
+        let lt = object.tp.lt;
+        Self::object_order_safe(object, other, lt, "lt")
+    }
+
+    //Order two objects with the gt slot of the first
+    pub fn object_gt_safe<'b>(
+        object: Object<'b>,
+        other: Object<'b>,
+    ) -> MethodValue<bool, Object<'b>> {
+        let gt = object.tp.gt;
+        Self::object_order_safe(object, other, gt, "gt")
+    }
+
+    //Whether an item is in an object, with the contains slot of the object
+    pub fn object_contains_safe<'b>(
+        object: Object<'b>,
+        item: Object<'b>,
+    ) -> MethodValue<bool, Object<'b>> {
+        let contains = object.tp.contains;
+        Self::object_order_safe(object, item, contains, "contains")
+    }
+
+    #[allow(unused_unsafe)]
+    fn object_order_safe<'b>(
+        object: Object<'b>,
+        other: Object<'b>,
+        slot: Option<fn(Object<'b>, Object<'b>) -> MethodType<'b>>,
+        name: &str,
+    ) -> MethodValue<bool, Object<'b>> {
+        if slot.is_none() {
+            let exc = methodnotdefinedexc_from_str(
+                object.vm.clone(),
+                &format!(
+                    "Method '{}' is not defined for '{}' type",
+                    name, object.tp.typename
                 ),
                 Position::default(),
                 Position::default(),
-            ));
-        } else {
-            let res = selfv.dict.as_ref().unwrap().clone().tp.get.unwrap()(
-                selfv.dict.as_ref().unwrap().clone(),
-                attr.clone(),
             );
-            if res.is_error()
-                && is_type_exact!(
-                    res.unwrap_err(),
-                    selfv.vm.types.keyntfndexctp.as_ref().unwrap()
-                )
-            {
+            return MethodValue::Error(exc);
+        }
+
+        let res = (unwrap_fast!(slot))(object.clone(), other);
+
+        if res.is_error() {
+            return MethodValue::Error(res.unwrap_err());
+        }
+
+        if !is_type_exact!(
+            &unwrap_fast!(res),
+            unwrap_fast!(object.vm.types.booltp.as_ref()).clone()
+        ) {
+            let exc = typemismatchexc_from_str(
+                object.vm.clone(),
+                &format!(
+                    "Method '{}' of '{}' type returned non-bool",
+                    name, object.tp.typename
+                ),
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+
+        MethodValue::Some(unsafe { unwrap_fast!(res).internals.bool() })
+    }
+
+    //Whether the object is a class, whose methods are looked up in the class itself
+    fn is_class(selfv: &Object<'a>) -> bool {
+        is_type_exact!(selfv, unwrap_fast!(selfv.vm.types.typetp.as_ref()))
+            && match (&selfv.dict, &unsafe { selfv.internals.typ() }.dict) {
+                (Some(dict), Some(typdict)) => Trc::ptr_eq(dict, typdict),
+                _ => false,
+            }
+    }
+
+    //An attribute of the object itself, from its dict or its shape
+    fn own_attr(
+        selfv: &Object<'a>,
+        attr: &Object<'a>,
+    ) -> MethodValue<Option<Object<'a>>, Object<'a>> {
+        match &selfv.dict {
+            Some(dict) => unsafe { dict.internals.map() }.try_get(attr.clone()),
+            None => MethodValue::Some(shape::get_attr(selfv, attr)),
+        }
+    }
+
+    //The fn that getting the attribute of the object would bind to it, if generic_getattr found it
+    //on the type of the object before and it is still in the method cache. CallAttr calls it with
+    //the object as the first argument instead of allocating a method for each call. Only the
+    //getattr slots that reach generic_getattr fill the cache, so a class with a getattr method is
+    //never skipped.
+    pub fn cached_method(selfv: &Object<'a>, attr: &Object<'a>) -> Option<Object<'a>> {
+        let slot = method_cache_slot(&selfv.tp, attr);
+        let (tp, name, fun) = selfv.vm.method_cache[slot].as_ref()?;
+        if !Trc::ptr_eq(tp, &selfv.tp)
+            || !Trc::ptr_eq(name, attr)
+            || !is_type_exact!(fun, unwrap_fast!(selfv.vm.types.fntp.as_ref()))
+            || Self::is_class(selfv)
+        {
+            return None;
+        }
+        //An attribute of the object itself hides the method
+        match Self::own_attr(selfv, attr) {
+            MethodValue::Some(None) => Some(fun.clone()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn generic_getattr(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
+        let is_class = Self::is_class(&selfv);
+
+        let get = Self::own_attr(&selfv, &attr);
+        if get.is_error() {
+            return MethodValue::Error(get.unwrap_err());
+        }
+        let mut res = unwrap_fast!(get);
+        let found_on_object = res.is_some();
+        //Not found in the object itself, so look through the types it inherits from. The methods
+        //of a class do not change after it is created, so the function that was found for a type
+        //and name is kept until another pair uses its slot. The cache holds the function and not
+        //the method bound to the instance, so that it does not keep the instance alive.
+        let mut cache_slot = None;
+        if res.is_none() && !is_class {
+            let slot = method_cache_slot(&selfv.tp, &attr);
+            match &selfv.vm.method_cache[slot] {
+                Some((tp, name, fun)) if Trc::ptr_eq(tp, &selfv.tp) && Trc::ptr_eq(name, &attr) => {
+                    res = Some(fun.clone());
+                }
+                _ => cache_slot = Some(slot),
+            }
+        }
+        if res.is_none() {
+            let get = if is_class {
+                unsafe { selfv.internals.typ() }.lookup(attr.clone(), 1)
+            } else {
+                selfv.tp.lookup(attr.clone(), 0)
+            };
+            if get.is_error() {
+                return MethodValue::Error(get.unwrap_err());
+            }
+            res = unwrap_fast!(get);
+        }
+
+        let res = match res {
+            Some(res) => res,
+            None => {
                 let repr = RawObject::object_str_safe(attr);
                 if repr.is_error() {
                     return MethodValue::Error(repr.unwrap_err());
@@ -264,40 +773,127 @@ impl<'a> RawObject<'a> {
                     Position::default(),
                 ));
             }
+        };
 
-            if unwrap_fast!(res).tp.descrget.is_some() {
-                if is_type_exact!(selfv, unwrap_fast!(selfv.vm.types.typetp.as_ref()))
-                    && Trc::ptr_eq(
-                        selfv.dict.as_ref().unwrap(),
-                        unsafe { &selfv.internals.typ }.dict.as_ref().unwrap(),
-                    )
-                {
-                    return unwrap_fast!(res).tp.descrget.unwrap()(
-                        unwrap_fast!(res).clone(),
-                        None,
-                        create_object_from_typeobject(selfv.vm.clone(), selfv.tp.clone()),
-                    );
-                }
-                return unwrap_fast!(res).tp.descrget.unwrap()(
-                    unwrap_fast!(res).clone(),
-                    Some(selfv.clone()),
+        //Attributes of the object itself (such as the members of a module) are not bound
+        if let Some(descrget) = res.tp.descrget.filter(|_| is_class || !found_on_object) {
+            if is_class {
+                return descrget(
+                    res.clone(),
+                    None,
                     create_object_from_typeobject(selfv.vm.clone(), selfv.tp.clone()),
                 );
             }
+            if let Some(slot) = cache_slot {
+                selfv.vm.clone().method_cache[slot] =
+                    Some((selfv.tp.clone(), attr.clone(), res.clone()));
+            }
+            return descrget(
+                res.clone(),
+                Some(selfv.clone()),
+                create_object_from_typeobject(selfv.vm.clone(), selfv.tp.clone()),
+            );
+        }
+
+        MethodValue::Some(res)
+    }
+
+    //Set an attribute of a class instance. Other objects have no attributes of their own to set.
+    pub fn generic_setattr(
+        selfv: Object<'a>,
+        attr: Object<'a>,
+        value: Object<'a>,
+    ) -> MethodType<'a> {
+        if shape::is_instance(&selfv)
+            && is_type_exact!(attr, unwrap_fast!(selfv.vm.types.strtp.as_ref()))
+        {
+            return shape::set_attr(selfv, attr, value);
+        }
 
-            res
+        let repr = RawObject::object_str_safe(attr);
+        if repr.is_error() {
+            return MethodValue::Error(repr.unwrap_err());
         }
+        MethodValue::Error(attrexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Cannot set attribute '{}' of object of type '{}'",
+                repr.unwrap(),
+                selfv.tp.typename,
+            ),
+            Position::default(),
+            Position::default(),
+        ))
     }
 }
 
+fn method_cache_slot(tp: &Trc<TypeObject<'_>>, name: &Object<'_>) -> usize {
+    //Objects are aligned, so the low bits of their addresses are the same
+    let hash = (Trc::as_ptr(tp) as usize >> 4) ^ (object_id(name) >> 4).wrapping_mul(31);
+    hash % METHOD_CACHE_SIZE
+}
+
 pub type Object<'a> = Trc<RawObject<'a>>;
 pub type MethodType<'a> = MethodValue<Object<'a>, Object<'a>>;
+//The call slot, which borrows the arguments so that a call does not allocate a list for them
+pub type CallFn<'a> = fn(Object<'a>, &[Object<'a>]) -> MethodType<'a>; //self, args
+
+//Call with an object before the arguments, as a method is called with its instance. Up to 3
+//arguments are passed with it in an array on the stack, so that most calls do not allocate.
+pub fn call_with_first<'a>(
+    call: CallFn<'a>,
+    callable: Object<'a>,
+    first: Object<'a>,
+    args: &[Object<'a>],
+) -> MethodType<'a> {
+    match args {
+        [] => call(callable, &[first]),
+        [a] => call(callable, &[first, a.clone()]),
+        [a, b] => call(callable, &[first, a.clone(), b.clone()]),
+        [a, b, c] => call(callable, &[first, a.clone(), b.clone(), c.clone()]),
+        _ => {
+            let mut all = Vec::with_capacity(args.len() + 1);
+            all.push(first);
+            all.extend_from_slice(args);
+            call(callable, &all)
+        }
+    }
+}
+
+//Sets and maps keyed by object identity (see object_id) rather than by the hash and eq slots
+pub type IdSet = hashbrown::HashSet<usize>;
+pub type IdMap<T> = hashbrown::HashMap<usize, T>;
+
+//The identity of an object: the address of its data, which is stable for as long as it is alive
+#[inline]
+pub fn object_id(object: &Object<'_>) -> usize {
+    Trc::as_ptr(object) as usize
+}
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct FnData<'a> {
-    code: Object<'a>,
+    body: Trc<Bytecode<'a>>, //Its code, and where it was defined
     args: Vec<Object<'a>>,
     name: String,
+    qualname: String, //With the classes and functions that it is in, as in Class.method
+    span: (Position, Position), //Of the definition
+    enclosing: Vec<Scope<'a>>,
+    is_abstract: bool,
+}
+
+pub type BuiltinFn<'a> = CallFn<'a>;
+
+#[derive(Clone)]
+pub struct BuiltinData<'a> {
+    name: String,
+    fun: BuiltinFn<'a>,
+    is_io: bool, //Does IO or controls the process, so restricted code may not call it
+}
+
+#[derive(Clone)]
+pub struct SuperData<'a> {
+    cls: Object<'a>,
+    instance: Object<'a>,
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -313,20 +909,6 @@ pub struct ExcData<'a> {
     pub end: Position,
 }
 
-pub union ObjectInternals<'a> {
-    pub none: (),
-    pub bool: bool,
-    pub int: isize,
-    pub str: ManuallyDrop<String>,
-    pub arr: ManuallyDrop<Vec<Object<'a>>>,
-    pub map: ManuallyDrop<mhash::HashMap<'a>>,
-    pub code: ManuallyDrop<Trc<Bytecode<'a>>>,
-    pub fun: ManuallyDrop<FnData<'a>>,
-    pub exc: ManuallyDrop<ExcData<'a>>,
-    pub typ: ManuallyDrop<TypeObject<'a>>,
-    pub fn_wrapper: ManuallyDrop<FnWrapper<'a>>,
-}
-
 pub enum MethodValue<T, E> {
     Some(T),
     Error(E),
@@ -381,24 +963,59 @@ fn create_object_from_type<'a>(
     vm: Trc<VM<'a>>,
     dict: Option<Object<'a>>,
 ) -> Object<'a> {
+    count_allocation(vm.clone(), &tp);
     let raw = RawObject {
         vm: vm.clone(),
         tp,
         dict,
-        internals: ObjectInternals { none: () },
+        internals: ObjectInternals::new_none(()),
     };
     Trc::new(raw)
 }
 
+//Convert an index into a sequence of length len to a position, where -1 is the last element
+pub fn sequence_index<'a>(
+    vm: Trc<VM<'a>>,
+    index: isize,
+    len: usize,
+) -> MethodValue<usize, Object<'a>> {
+    let pos = if index < 0 {
+        len.checked_sub(index.unsigned_abs())
+    } else {
+        Some(index.unsigned_abs()).filter(|pos| *pos < len)
+    };
+    match pos {
+        Some(pos) => MethodValue::Some(pos),
+        None => MethodValue::Error(exceptionobject::indexexc_from_str(
+            vm,
+            &format!(
+                "Index out of range: length is '{}', but got index '{}'",
+                len, index
+            ),
+            Position::default(),
+            Position::default(),
+        )),
+    }
+}
+
+#[inline]
+fn count_allocation<'a>(mut vm: Trc<VM<'a>>, tp: &TypeObject<'a>) {
+    if let Some(stats) = vm.alloc_stats.as_mut() {
+        stats
+            .entry(tp.typeid)
+            .or_insert_with(|| (tp.typename.clone(), 0))
+            .1 += 1;
+    }
+}
+
 #[inline]
 fn create_object_from_typeobject<'a>(vm: Trc<VM<'a>>, tp: Trc<TypeObject<'a>>) -> Object<'a> {
+    count_allocation(vm.clone(), unwrap_fast!(vm.types.typetp.as_ref()));
     let raw = RawObject {
         vm: vm.clone(),
         tp: unwrap_fast!(vm.types.typetp.as_ref()).clone(),
         dict: tp.dict.clone(),
-        internals: ObjectInternals {
-            typ: ManuallyDrop::new((*tp).clone()),
-        },
+        internals: ObjectInternals::new_typ((*tp).clone()),
     };
     Trc::new(raw)
 }
@@ -425,12 +1042,27 @@ fn inherit_slots<'a>(mut tp: Trc<TypeObject<'a>>, basetp: TypeObject<'a>) {
     } else {
         tp.neg
     };
+    tp.invert = if basetp.invert.is_some() {
+        basetp.invert
+    } else {
+        tp.invert
+    };
 
     tp.eq = if basetp.eq.is_some() {
         basetp.eq
     } else {
         tp.eq
     };
+    tp.lt = if basetp.lt.is_some() {
+        basetp.lt
+    } else {
+        tp.lt
+    };
+    tp.gt = if basetp.gt.is_some() {
+        basetp.gt
+    } else {
+        tp.gt
+    };
     tp.add = if basetp.add.is_some() {
         basetp.add
     } else {
@@ -456,6 +1088,31 @@ fn inherit_slots<'a>(mut tp: Trc<TypeObject<'a>>, basetp: TypeObject<'a>) {
     } else {
         tp.pow
     };
+    tp.bitand = if basetp.bitand.is_some() {
+        basetp.bitand
+    } else {
+        tp.bitand
+    };
+    tp.bitor = if basetp.bitor.is_some() {
+        basetp.bitor
+    } else {
+        tp.bitor
+    };
+    tp.bitxor = if basetp.bitxor.is_some() {
+        basetp.bitxor
+    } else {
+        tp.bitxor
+    };
+    tp.lshift = if basetp.lshift.is_some() {
+        basetp.lshift
+    } else {
+        tp.lshift
+    };
+    tp.rshift = if basetp.rshift.is_some() {
+        basetp.rshift
+    } else {
+        tp.rshift
+    };
 
     tp.get = if basetp.get.is_some() {
         basetp.get
@@ -519,6 +1176,8 @@ pub fn init_types(vm: Trc<VM<'_>>) {
     objectobject::init(vm.clone());
     typeobject::init(vm.clone());
     intobject::init(vm.clone());
+    bigintobject::init(vm.clone());
+    floatobject::init(vm.clone());
     boolobject::init(vm.clone());
     stringobject::init(vm.clone());
     listobject::init(vm.clone());
@@ -526,6 +1185,9 @@ pub fn init_types(vm: Trc<VM<'_>>) {
     dictobject::init(vm.clone());
     codeobject::init(vm.clone());
     fnobject::init(vm.clone());
+    builtinobject::init(vm.clone());
+    superobject::init(vm.clone());
+    moduleobject::init(vm.clone());
     exceptionobject::init_exc(vm.clone());
     exceptionobject::init_nameexc(vm.clone());
     exceptionobject::init_overflowexc(vm.clone());
@@ -535,9 +1197,22 @@ pub fn init_types(vm: Trc<VM<'_>>) {
     exceptionobject::init_valueexc(vm.clone());
     exceptionobject::init_zerodivexc(vm.clone());
     exceptionobject::init_attrexc(vm.clone());
+    exceptionobject::init_unhashableexc(vm.clone());
+    exceptionobject::init_indexexc(vm.clone());
+    exceptionobject::init_argumentexc(vm.clone());
+    exceptionobject::init_ioexc(vm.clone());
+    exceptionobject::init_notimplementedexc(vm.clone());
+    exceptionobject::init_permissionexc(vm.clone());
+    exceptionobject::init_timeoutexc(vm.clone());
+    exceptionobject::init_memoryexc(vm.clone());
     methodobject::init(vm.clone());
 }
 
+//Native methods live in the type dicts, so they can only be made once the types and the int cache exist
+pub fn init_methods(vm: Trc<VM<'_>>) {
+    stringobject::init_methods(vm.clone());
+}
+
 macro_rules! maybe_handle_exception {
     ($self:ident, $res:ident, $bytecode:expr, $i:expr) => {
         if $res.is_error() {
@@ -0,0 +1,87 @@
+use super::{
+    boolobject, create_object_from_type, finalize_type, finalize_type_dict, stringobject,
+    MethodType, MethodValue, Object, ObjectInternals, TypeObject,
+};
+
+use crate::interpreter::VM;
+use crate::rc::Trc;
+use crate::unwrap_fast;
+
+//A native module: its members are the attributes in its dict
+pub fn module_from<'a>(vm: Trc<VM<'a>>, name: String, dict: Object<'a>) -> Object<'a> {
+    let mut tp = create_object_from_type(
+        unwrap_fast!(vm.types.moduletp.as_ref()).clone(),
+        vm,
+        Some(dict),
+    );
+    tp.internals = ObjectInternals::new_str(stringobject::StrData::new(&name));
+    tp
+}
+
+fn module_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
+    unimplemented!();
+}
+fn module_repr(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(stringobject::string_from(
+        selfv.vm.clone(),
+        format!("<module '{}'>", unsafe { selfv.internals.str() }.as_str()),
+    ))
+}
+fn module_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    //Modules are only created once, so they are equal only to themselves
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        Trc::ptr_eq(&selfv, &other),
+    ))
+}
+
+pub fn init(mut vm: Trc<VM<'_>>) {
+    let tp = Trc::new(TypeObject {
+        typename: String::from("module"),
+        bases: vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )],
+        typeid: vm.types.n_types,
+        dict: None,
+
+        new: Some(module_new),
+
+        repr: Some(module_repr),
+        str: Some(module_repr),
+        abs: None,
+        neg: None,
+        invert: None,
+        hash_fn: None,
+        eq: Some(module_eq),
+        lt: None,
+        gt: None,
+        add: None,
+        sub: None,
+        mul: None,
+        div: None,
+        pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
+
+        get: None,
+        set: None,
+        len: None,
+        contains: None,
+
+        call: None,
+
+        getattr: None,
+        setattr: None,
+        descrget: None,
+        descrset: None,
+    });
+
+    vm.types.moduletp = Some(tp.clone());
+    vm.types.n_types += 1;
+
+    finalize_type(tp.clone());
+    finalize_type_dict(tp);
+}
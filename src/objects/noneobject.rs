@@ -2,9 +2,9 @@ use super::{
     boolobject, create_object_from_type, finalize_type, finalize_type_dict, intobject, MethodType,
     MethodValue, Object, ObjectInternals, TypeObject,
 };
+use crate::rc::Trc;
 use crate::{interpreter::VM, objects::stringobject};
 use crate::{is_type_exact, unwrap_fast};
-use trc::Trc;
 
 #[macro_export]
 macro_rules! none_from {
@@ -39,7 +39,7 @@ pub fn generate_cache<'a>(
 ) {
     unsafe {
         let mut tp = create_object_from_type(nonetp.clone(), vm, None);
-        tp.internals = ObjectInternals { none: () };
+        tp.internals = ObjectInternals::new_none(());
         std::ptr::write(ptr, Some(tp));
     }
 }
@@ -59,18 +59,27 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(none_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: Some(none_hash),
 
         eq: Some(none_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: None,
 
@@ -3,7 +3,7 @@ use super::{
     MethodValue, Object, RawObject, TypeObject,
 };
 use crate::interpreter::VM;
-use trc::Trc;
+use crate::rc::Trc;
 
 fn object_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
     unimplemented!();
@@ -41,23 +41,32 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(object_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: Some(object_hash),
 
         eq: Some(object_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
         call: None,
 
         getattr: Some(RawObject::generic_getattr),
-        setattr: None,
+        setattr: Some(RawObject::generic_setattr),
         descrget: None,
         descrset: None,
     });
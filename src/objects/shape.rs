@@ -0,0 +1,154 @@
+//Hidden classes for the attributes of class instances. Instances of a class that set the same
+//attributes in the same order, as init usually does, share a shape. The shape maps each name to an
+//index into the values of the instance, so that instances do not each need a dict. An instance whose
+//attributes diverge from the shapes of its class moves its attributes to a dict.
+
+use crate::rc::Trc;
+use crate::{interpreter::VM, is_type_exact, unwrap_fast};
+
+use super::{
+    create_object_from_type, dictobject, mhash, MethodType, MethodValue, Object, ObjectInternals,
+    TypeObject,
+};
+
+//The most attributes that a shape holds
+const MAX_SHAPE_ATTRS: usize = 32;
+//The most shapes that may follow a shape. Instances that set their attributes in more ways than
+//this are not alike enough to share shapes.
+const MAX_SHAPE_TRANSITIONS: usize = 4;
+
+#[derive(Clone, Default)]
+pub struct Shape {
+    names: Vec<String>,                             //The names of the values, in order
+    transitions: hashbrown::HashMap<String, usize>, //The shape after setting another name
+}
+
+#[derive(Clone)]
+pub struct InstanceAttrs<'a> {
+    pub shape: usize, //Index into VM::shapes
+    pub values: Vec<Object<'a>>,
+}
+
+//Give a new class the empty shape that its instances start with
+pub fn register_class(mut vm: Trc<VM<'_>>, typeid: u32) {
+    vm.shapes.push(Shape::default());
+    let root = vm.shapes.len() - 1;
+    vm.class_shapes.insert(typeid, root);
+}
+
+//Whether an object is an instance of a class, and so keeps its attributes in a shape
+#[inline]
+pub fn is_instance(object: &Object<'_>) -> bool {
+    object.vm.class_shapes.contains_key(&object.tp.typeid)
+}
+
+//Create an instance of a class with no attributes
+pub fn instance_from<'a>(tp: Trc<TypeObject<'a>>, vm: Trc<VM<'a>>) -> Object<'a> {
+    let root = vm.class_shapes.get(&tp.typeid).copied();
+    let mut instance = create_object_from_type(tp, vm, None);
+    if let Some(shape) = root {
+        instance.internals = ObjectInternals::new_attrs(InstanceAttrs {
+            shape,
+            values: Vec::new(),
+        });
+    }
+    instance
+}
+
+//The attributes of an instance, which are empty once it uses a dict
+pub fn instance_attrs<'a, 'b>(object: &'b Object<'a>) -> Option<&'b InstanceAttrs<'a>> {
+    if is_instance(object) {
+        Some(unsafe { object.internals.attrs() })
+    } else {
+        None
+    }
+}
+
+//The names of the attributes that an instance keeps in its shape, in the order they were set
+pub fn attr_names(object: &Object<'_>) -> Vec<String> {
+    match instance_attrs(object) {
+        Some(attrs) => object.vm.shapes[attrs.shape].names.clone(),
+        None => Vec::new(),
+    }
+}
+
+pub fn set_instance_attrs<'a>(mut object: Object<'a>, attrs: InstanceAttrs<'a>) {
+    object.internals = ObjectInternals::new_attrs(attrs);
+}
+
+//Find an attribute that an instance keeps in its shape
+pub fn get_attr<'a>(object: &Object<'a>, attr: &Object<'a>) -> Option<Object<'a>> {
+    if !is_type_exact!(attr, unwrap_fast!(object.vm.types.strtp.as_ref())) {
+        return None;
+    }
+    let attrs = instance_attrs(object)?;
+    let name = unsafe { attr.internals.str() };
+    let idx = object.vm.shapes[attrs.shape]
+        .names
+        .iter()
+        .position(|other| **other == **name)?;
+    Some(attrs.values[idx].clone())
+}
+
+//The shape after setting a name that a shape does not have, if the class may have another shape
+fn transition(mut vm: Trc<VM<'_>>, shape: usize, name: &str) -> Option<usize> {
+    if let Some(next) = vm.shapes[shape].transitions.get(name) {
+        return Some(*next);
+    }
+    let current = &vm.shapes[shape];
+    if current.names.len() >= MAX_SHAPE_ATTRS || current.transitions.len() >= MAX_SHAPE_TRANSITIONS
+    {
+        return None;
+    }
+    let mut names = current.names.clone();
+    names.push(name.to_string());
+    vm.shapes.push(Shape {
+        names,
+        transitions: hashbrown::HashMap::new(),
+    });
+    let next = vm.shapes.len() - 1;
+    vm.shapes[shape].transitions.insert(name.to_string(), next);
+    Some(next)
+}
+
+//Set an attribute of an instance, whose name must be a string
+pub fn set_attr<'a>(mut object: Object<'a>, attr: Object<'a>, value: Object<'a>) -> MethodType<'a> {
+    let vm = object.vm.clone();
+    if let Some(dict) = object.dict.clone() {
+        let res = unwrap_fast!(dict.tp.set)(dict, attr, value);
+        if res.is_error() {
+            return res;
+        }
+        return MethodValue::Some(none_from!(vm));
+    }
+
+    let name = unsafe { attr.internals.str() }.to_string();
+    let attrs = unsafe { object.internals.attrs_mut() };
+    let current = &vm.shapes[attrs.shape];
+    if let Some(idx) = current.names.iter().position(|other| *other == name) {
+        attrs.values[idx] = value;
+        return MethodValue::Some(none_from!(vm));
+    }
+    if let Some(next) = transition(vm.clone(), attrs.shape, &name) {
+        attrs.shape = next;
+        attrs.values.push(value);
+        return MethodValue::Some(none_from!(vm));
+    }
+
+    //The instance diverged from the other instances of its class
+    let names = vm.shapes[attrs.shape].names.clone();
+    let values = std::mem::take(&mut attrs.values);
+    let mut map = mhash::HashMap::new();
+    for (name, value) in names.iter().zip(values) {
+        let res = map.insert(VM::intern(vm.clone(), name), value);
+        if res.is_error() {
+            return MethodValue::Error(res.unwrap_err());
+        }
+    }
+    let res = map.insert(attr, value);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    object.dict = Some(dictobject::dict_from(vm.clone(), map));
+    MethodValue::Some(none_from!(vm))
+}
@@ -1,45 +1,118 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::mem::ManuallyDrop;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::interpreter::VM;
 use crate::is_type_exact;
-use crate::objects::exceptionobject::valueexc_from_str;
-use crate::objects::{boolobject, intobject};
+use crate::objects::{boolobject, builtinobject, dictobject, intobject, mhash};
 use crate::parser::Position;
+use crate::rc::Trc;
 use crate::unwrap_fast;
-use trc::Trc;
 
-use super::exceptionobject::typemismatchexc_from_str;
+use super::exceptionobject::{
+    argumentexc_from_str, indexexc_from_str, typemismatchexc_from_str, valueexc_from_str,
+};
 use super::{
-    create_object_from_type, finalize_type, finalize_type_dict, MethodType, MethodValue, Object,
-    ObjectInternals, TypeObject,
+    create_object_from_type, finalize_type, finalize_type_dict, sequence_index, MethodType,
+    MethodValue, Object, ObjectInternals, RawObject, TypeObject,
 };
 
 const MFBH_MAX_LEN: usize = 256;
+//The largest width or precision a format placeholder may ask for
+const MAX_FORMAT_WIDTH: usize = 1 << 16;
+
+//The buffer of a str. It is counted with atomics when objects may be shared between threads, like
+//Trc, which can not hold a str itself.
+#[cfg(not(feature = "threaded"))]
+type Buffer = std::rc::Rc<str>;
+#[cfg(feature = "threaded")]
+type Buffer = std::sync::Arc<str>;
+
+//The text of a str: a range of a buffer that is shared by the strs that were sliced from each other.
+//Indexing a str, iterating over it and str() do not copy its text, but a str that shares a buffer
+//keeps all of it alive.
+#[derive(Clone)]
+pub struct StrData {
+    buf: Buffer,
+    start: usize,
+    end: usize,
+}
+
+impl StrData {
+    pub fn new(text: &str) -> Self {
+        StrData {
+            buf: Buffer::from(text),
+            start: 0,
+            end: text.len(),
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        //The range is checked to be on character boundaries when the str is sliced
+        unsafe { self.buf.get_unchecked(self.start..self.end) }
+    }
+
+    //The text between two byte offsets, sharing the buffer
+    pub fn slice(&self, start: usize, end: usize) -> Self {
+        assert!(
+            self.as_str().is_char_boundary(start) && self.as_str().is_char_boundary(end),
+            "Str is sliced inside a character"
+        );
+        StrData {
+            buf: self.buf.clone(),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+
+    //The size of the whole buffer, which is shared with the strs that were sliced from this one
+    pub fn buffer_len(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl std::ops::Deref for StrData {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
 
 pub fn string_from(vm: Trc<VM<'_>>, raw: String) -> Object<'_> {
+    string_from_data(vm, StrData::new(&raw))
+}
+
+//A str that shares the text of another
+pub fn string_from_data(vm: Trc<VM<'_>>, data: StrData) -> Object<'_> {
     let mut tp = create_object_from_type(unwrap_fast!(vm.types.strtp.as_ref()).clone(), vm, None);
-    tp.internals = ObjectInternals {
-        str: ManuallyDrop::new(raw),
-    };
+    tp.internals = ObjectInternals::new_str(data);
     tp
 }
 
+//The strs of the characters of a str, each sharing its text
+pub fn graphemes<'a>(selfv: &Object<'a>) -> Vec<Object<'a>> {
+    let data = unsafe { selfv.internals.str() };
+    UnicodeSegmentation::grapheme_indices(data.as_str(), true)
+        .map(|(idx, chr)| string_from_data(selfv.vm.clone(), data.slice(idx, idx + chr.len())))
+        .collect()
+}
+
 fn string_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
     unimplemented!();
 }
+//Quoted, with quotes, backslashes and control characters escaped
 fn string_repr(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(string_from(
         selfv.vm.clone(),
-        "\"".to_owned() + unsafe { &selfv.internals.str } + "\"",
+        format!("{:?}", unsafe { selfv.internals.str() }.as_str()),
     ))
 }
 fn string_str(selfv: Object<'_>) -> MethodType<'_> {
-    MethodValue::Some(string_from(
+    MethodValue::Some(string_from_data(
         selfv.vm.clone(),
-        unsafe { &selfv.internals.str }.to_string(),
+        unsafe { selfv.internals.str() }.clone(),
     ))
 }
 fn string_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
@@ -49,45 +122,96 @@ fn string_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
 
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        unsafe { &selfv.internals.str } == unsafe { &other.internals.str },
+        unsafe { selfv.internals.str() }.as_str() == unsafe { other.internals.str() }.as_str(),
     ))
 }
 
-fn string_get<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
-    if !is_type_exact!(&other, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+//Strings are ordered lexicographically by code point
+fn string_lt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
         let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            &format!("Expected 'int' index, got '{}'", other.tp.typename),
+            "Types do not match",
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.str() }.as_str() < unsafe { other.internals.str() }.as_str(),
+    ))
+}
+fn string_gt<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            "Types do not match",
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
 
-    //NEGATIVE INDEX IS CONVERTED TO +
-    let out = UnicodeSegmentation::graphemes(unsafe { &selfv.internals.str }.as_str(), true)
-        .nth(unsafe { other.internals.int }.unsigned_abs());
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.str() }.as_str() > unsafe { other.internals.str() }.as_str(),
+    ))
+}
 
-    if out.is_none() {
-        let exc = valueexc_from_str(
+fn string_get<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&other, unwrap_fast!(selfv.vm.types.inttp.as_ref()).clone()) {
+        let exc = typemismatchexc_from_str(
             selfv.vm.clone(),
-            &format!(
-                "Index out of range: maximum index is '{}', but got '{}'",
-                unsafe { &selfv.internals.str }.len(),
-                unsafe { &other.internals.int }.unsigned_abs()
-            ),
+            &format!("Expected 'int' index, got '{}'", other.tp.typename),
             Position::default(),
             Position::default(),
         );
         return MethodValue::Error(exc);
     }
-    MethodValue::Some(string_from(selfv.vm.clone(), unwrap_fast!(out).to_string()))
+
+    let data = unsafe { selfv.internals.str() };
+    let graphemes: Vec<(usize, &str)> =
+        UnicodeSegmentation::grapheme_indices(data.as_str(), true).collect();
+    let pos = sequence_index(
+        selfv.vm.clone(),
+        unsafe { other.internals.int() },
+        graphemes.len(),
+    );
+    if pos.is_error() {
+        return MethodValue::Error(pos.unwrap_err());
+    }
+    let (idx, chr) = graphemes[unwrap_fast!(pos)];
+    MethodValue::Some(string_from_data(
+        selfv.vm.clone(),
+        data.slice(idx, idx + chr.len()),
+    ))
 }
+//The number of code points, not of bytes
 fn string_len(selfv: Object<'_>) -> MethodType<'_> {
-    let convert = unsafe { &selfv.internals.str }.len().try_into();
+    let convert = unsafe { selfv.internals.str() }.chars().count().try_into();
     MethodValue::Some(intobject::int_from(selfv.vm.clone(), unwrap_fast!(convert)))
 }
 
+//Whether a string is a substring, so the empty string is in every string
+fn string_contains<'a>(selfv: Object<'a>, item: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, item.tp) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 'str' substring, got '{}'", item.tp.typename),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+
+    MethodValue::Some(boolobject::bool_from(
+        selfv.vm.clone(),
+        unsafe { selfv.internals.str() }.contains(unsafe { item.internals.str().as_str() }),
+    ))
+}
+
 #[inline]
 fn string_hash(selfv: Object<'_>) -> MethodType<'_> {
     //Use DefaultHasher for long data:
@@ -95,11 +219,11 @@ fn string_hash(selfv: Object<'_>) -> MethodType<'_> {
     //jschievink: ...DefaultHasher is an implementation of SipHash...   ...pretty fast on long data, for short data this hash tends to be very slow ...
     //Use bytes[0] + bytes[len-1] + len for len > 1, bytes[0] for len==1, 0 for len==0
 
-    let bytes = unsafe { &selfv.internals.str }[..].as_bytes();
+    let bytes = unsafe { selfv.internals.str() }[..].as_bytes();
 
     if bytes.len() > MFBH_MAX_LEN {
         let mut hasher = DefaultHasher::new();
-        unsafe { &selfv.internals.str }.hash(&mut hasher);
+        unsafe { selfv.internals.str() }.hash(&mut hasher);
         return MethodValue::Some(intobject::int_from(
             selfv.vm.clone(),
             hasher.finish() as isize,
@@ -118,6 +242,258 @@ fn string_hash(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(intobject::int_from(selfv.vm.clone(), res + len))
 }
 
+//A replacement field of a format string: {index:<width.precision}
+struct FormatSpec {
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+fn parse_format_spec(spec: &str) -> Option<FormatSpec> {
+    let mut chars = spec.chars().peekable();
+    let align = chars.next_if(|c| matches!(c, '<' | '>' | '^'));
+
+    let mut width = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        width.push(c);
+    }
+
+    let mut precision = None;
+    if chars.next_if_eq(&'.').is_some() {
+        let mut digits = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        precision = Some(digits.parse().ok()?);
+    }
+
+    if chars.next().is_some() {
+        return None;
+    }
+    Some(FormatSpec {
+        align,
+        width: if width.is_empty() {
+            0
+        } else {
+            width.parse().ok()?
+        },
+        precision,
+    })
+}
+
+//Render one argument, then apply the precision and width. For ints and floats the precision is
+//the number of digits after the point, for everything else it is the maximum length of the str.
+fn format_field<'a>(arg: Object<'a>, spec: &FormatSpec) -> MethodValue<String, Object<'a>> {
+    let is_int = is_type_exact!(&arg, unwrap_fast!(arg.vm.types.inttp.as_ref()));
+    let is_float = is_type_exact!(&arg, unwrap_fast!(arg.vm.types.floattp.as_ref()));
+    let repr = match spec.precision {
+        Some(precision) if is_float => {
+            format!("{:.*}", precision, unsafe { arg.internals.float() })
+        }
+        Some(precision) if is_int && precision > 0 => format!(
+            "{}.{}",
+            unsafe { arg.internals.int() },
+            "0".repeat(precision)
+        ),
+        _ => {
+            let repr = RawObject::object_str_safe(arg.clone());
+            if repr.is_error() {
+                return repr;
+            }
+            unwrap_fast!(repr)
+        }
+    };
+    let mut graphemes: Vec<&str> = Vec::new();
+    graphemes.extend(UnicodeSegmentation::graphemes(repr.as_str(), true));
+    if let (Some(precision), false) = (spec.precision, is_int || is_float) {
+        graphemes.truncate(precision);
+    }
+
+    let pad = spec.width.saturating_sub(graphemes.len());
+    //Numbers are right aligned by default, everything else is left aligned
+    let align = spec
+        .align
+        .unwrap_or(if is_int || is_float { '>' } else { '<' });
+    let (left, right) = match align {
+        '>' => (pad, 0),
+        '^' => (pad / 2, pad - pad / 2),
+        _ => (0, pad),
+    };
+    MethodValue::Some(" ".repeat(left) + &graphemes.concat() + &" ".repeat(right))
+}
+
+//Check that a method was called on a str, which is passed as the first argument
+fn check_str_self<'a>(
+    selfv: &Object<'a>,
+    args: &[Object<'a>],
+    name: &str,
+) -> MethodValue<(), Object<'a>> {
+    if args.is_empty() || !is_type_exact!(&args[0], unwrap_fast!(selfv.vm.types.strtp.as_ref())) {
+        let exc = typemismatchexc_from_str(
+            selfv.vm.clone(),
+            &format!("Method '{}' must be called on a 'str'", name),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    MethodValue::Some(())
+}
+
+//str.format(*args)
+fn string_format<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_str_self(&selfv, args, "format");
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    let fmt = unsafe { args[0].internals.str() };
+    let values = &args[1..];
+
+    let value_error = |msg: &str| {
+        MethodValue::Error(valueexc_from_str(
+            selfv.vm.clone(),
+            msg,
+            Position::default(),
+            Position::default(),
+        ))
+    };
+
+    let mut res = String::new();
+    let mut auto_index = 0;
+    let mut numbering = None;
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '}' {
+            if chars.next_if_eq(&'}').is_none() {
+                return value_error("Single '}' encountered in format string");
+            }
+            res.push('}');
+            continue;
+        }
+        if c != '{' {
+            res.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'{').is_some() {
+            res.push('{');
+            continue;
+        }
+
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => field.push(c),
+                None => return value_error("Single '{' encountered in format string"),
+            }
+        }
+        let (index, spec) = match field.split_once(':') {
+            Some((index, spec)) => (index, spec),
+            None => (field.as_str(), ""),
+        };
+
+        //Placeholders are either all numbered automatically or all indexed
+        let is_auto = index.is_empty();
+        if *numbering.get_or_insert(is_auto) != is_auto {
+            return value_error("Cannot mix automatic and indexed placeholders in format string");
+        }
+        let index = if is_auto {
+            auto_index += 1;
+            auto_index - 1
+        } else {
+            match index.parse::<usize>() {
+                Ok(index) => index,
+                Err(_) => {
+                    return value_error(&format!("Invalid placeholder index '{}'", index));
+                }
+            }
+        };
+
+        let spec = match parse_format_spec(spec) {
+            Some(spec) => spec,
+            None => return value_error(&format!("Invalid format specifier '{}'", spec)),
+        };
+        if spec.width.max(spec.precision.unwrap_or(0)) > MAX_FORMAT_WIDTH {
+            return value_error(&format!(
+                "Format width and precision may not exceed {}",
+                MAX_FORMAT_WIDTH
+            ));
+        }
+        let value = match values.get(index) {
+            Some(value) => value,
+            None => {
+                let exc = indexexc_from_str(
+                    selfv.vm.clone(),
+                    &format!(
+                        "Placeholder index '{}' out of range for {} argument(s)",
+                        index,
+                        values.len()
+                    ),
+                    Position::default(),
+                    Position::default(),
+                );
+                return MethodValue::Error(exc);
+            }
+        };
+
+        let field = format_field(value.clone(), &spec);
+        if field.is_error() {
+            return MethodValue::Error(field.unwrap_err());
+        }
+        res += &unwrap_fast!(field);
+    }
+
+    MethodValue::Some(string_from(selfv.vm.clone(), res))
+}
+
+//str.join(iterable), which allocates the result once
+fn string_join<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let res = check_str_self(&selfv, args, "join");
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    if args.len() != 2 {
+        let exc = argumentexc_from_str(
+            selfv.vm.clone(),
+            &format!("Expected 1 argument(s), got {}", args.len() - 1),
+            Position::default(),
+            Position::default(),
+        );
+        return MethodValue::Error(exc);
+    }
+    let sep = unsafe { args[0].internals.str() };
+
+    let items = RawObject::object_iter_safe(args[1].clone());
+    if items.is_error() {
+        return MethodValue::Error(items.unwrap_err());
+    }
+    let items = unwrap_fast!(items);
+
+    let mut len = sep.len() * items.len().saturating_sub(1);
+    for item in &items {
+        if !is_type_exact!(item, unwrap_fast!(selfv.vm.types.strtp.as_ref())) {
+            let exc = typemismatchexc_from_str(
+                selfv.vm.clone(),
+                &format!("Expected 'str' item, got '{}'", item.tp.typename),
+                Position::default(),
+                Position::default(),
+            );
+            return MethodValue::Error(exc);
+        }
+        len += unsafe { item.internals.str() }.len();
+    }
+
+    let mut res = String::with_capacity(len);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            res += sep;
+        }
+        res += unsafe { item.internals.str() };
+    }
+
+    MethodValue::Some(string_from(selfv.vm.clone(), res))
+}
+
 pub fn init(mut vm: Trc<VM<'_>>) {
     let tp = Trc::new(TypeObject {
         typename: String::from("str"),
@@ -133,18 +509,27 @@ pub fn init(mut vm: Trc<VM<'_>>) {
         str: Some(string_str),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: Some(string_hash),
 
         eq: Some(string_eq),
+        lt: Some(string_lt),
+        gt: Some(string_gt),
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: Some(string_get),
         set: None,
         len: Some(string_len),
+        contains: Some(string_contains),
 
         call: None,
 
@@ -160,3 +545,20 @@ pub fn init(mut vm: Trc<VM<'_>>) {
     finalize_type(tp.clone());
     finalize_type_dict(tp);
 }
+
+//The methods are builtins in the type dict
+pub fn init_methods(vm: Trc<VM<'_>>) {
+    let mut map = mhash::HashMap::new();
+    map.insert(
+        string_from(vm.clone(), String::from("format")),
+        builtinobject::builtin_from(vm.clone(), String::from("format"), string_format),
+    );
+
+    map.insert(
+        string_from(vm.clone(), String::from("join")),
+        builtinobject::builtin_from(vm.clone(), String::from("join"), string_join),
+    );
+
+    let mut tp = unwrap_fast!(vm.types.strtp.as_ref()).clone();
+    tp.dict = Some(dictobject::dict_from(vm.clone(), map));
+}
@@ -0,0 +1,117 @@
+use super::{
+    create_object_from_type, exceptionobject::attrexc_from_str, finalize_type, finalize_type_dict,
+    MethodType, MethodValue, Object, RawObject, TypeObject,
+};
+
+use crate::parser::Position;
+use crate::rc::Trc;
+use crate::unwrap_fast;
+use crate::{
+    interpreter::VM,
+    objects::{stringobject, ObjectInternals},
+};
+
+pub fn super_from<'a>(vm: Trc<VM<'a>>, cls: Object<'a>, instance: Object<'a>) -> Object<'a> {
+    let mut tp = create_object_from_type(unwrap_fast!(vm.types.supertp.as_ref()).clone(), vm, None);
+    tp.internals = ObjectInternals::new_sup(super::SuperData { cls, instance });
+    tp
+}
+
+fn super_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
+    unimplemented!();
+}
+fn super_repr(selfv: Object<'_>) -> MethodType<'_> {
+    MethodValue::Some(stringobject::string_from(
+        selfv.vm.clone(),
+        format!(
+            "<super '{}' @ 0x{:x}>",
+            unsafe { selfv.internals.sup().cls.internals.typ() }.typename,
+            Trc::as_ptr(&selfv) as usize
+        ),
+    ))
+}
+
+//Look the attribute up in the classes after cls, and bind it to the instance
+fn super_getattr<'a>(selfv: Object<'a>, attr: Object<'a>) -> MethodType<'a> {
+    let sup = unsafe { selfv.internals.sup() };
+    let cls = unsafe { sup.cls.internals.typ() };
+    let res = cls.lookup(attr.clone(), 1);
+    if res.is_error() {
+        return MethodValue::Error(res.unwrap_err());
+    }
+    match unwrap_fast!(res) {
+        Some(res) => {
+            if let Some(descrget) = res.tp.descrget {
+                return descrget(res.clone(), Some(sup.instance.clone()), sup.cls.clone());
+            }
+            MethodValue::Some(res)
+        }
+        None => {
+            let repr = RawObject::object_str_safe(attr);
+            if repr.is_error() {
+                return MethodValue::Error(repr.unwrap_err());
+            }
+            MethodValue::Error(attrexc_from_str(
+                selfv.vm.clone(),
+                &format!(
+                    "No base of '{}' has attribute '{}'",
+                    cls.typename,
+                    repr.unwrap(),
+                ),
+                Position::default(),
+                Position::default(),
+            ))
+        }
+    }
+}
+
+pub fn init(mut vm: Trc<VM<'_>>) {
+    let tp = Trc::new(TypeObject {
+        typename: String::from("super"),
+        bases: vec![super::ObjectBase::Other(
+            unwrap_fast!(vm.types.objecttp.as_ref()).clone(),
+        )],
+        typeid: vm.types.n_types,
+        dict: None,
+
+        new: Some(super_new),
+
+        repr: Some(super_repr),
+        str: Some(super_repr),
+        abs: None,
+        neg: None,
+        invert: None,
+        hash_fn: None,
+        eq: None,
+        lt: None,
+        gt: None,
+        add: None,
+        sub: None,
+        mul: None,
+        div: None,
+        pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
+
+        get: None,
+        set: None,
+        len: None,
+        contains: None,
+
+        call: None,
+
+        getattr: Some(super_getattr),
+        setattr: None,
+        descrget: None,
+        descrset: None,
+    });
+
+    vm.types.supertp = Some(tp.clone());
+    vm.types.n_types += 1;
+
+    finalize_type(tp.clone());
+    finalize_type_dict(tp);
+}
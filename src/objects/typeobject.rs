@@ -1,11 +1,19 @@
 use crate::interpreter::VM;
-use trc::Trc;
+use crate::is_type_exact;
+use crate::parser::Position;
+use crate::rc::Trc;
 
 use super::{
-    boolobject, finalize_type, finalize_type_dict, intobject, stringobject, unwrap_fast,
-    MethodType, MethodValue, Object, TypeObject,
+    boolobject, call_with_first, create_object_from_typeobject,
+    exceptionobject::{argumentexc_from_str, methodnotdefinedexc_from_str},
+    finalize_type, finalize_type_dict, fnobject, intobject, shape, stringobject, unwrap_fast,
+    MethodType, MethodValue, Object, RawObject, TypeObject,
 };
 
+pub fn type_from<'a>(vm: Trc<VM<'a>>, tp: Trc<TypeObject<'a>>) -> Object<'a> {
+    create_object_from_typeobject(vm, tp)
+}
+
 fn type_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> MethodType<'a> {
     unimplemented!();
 }
@@ -13,16 +21,115 @@ fn type_new<'a>(_selfv: Object<'a>, _args: Object<'a>, _kwargs: Object<'a>) -> M
 fn type_repr(selfv: Object<'_>) -> MethodType<'_> {
     MethodValue::Some(stringobject::string_from(
         selfv.vm.clone(),
-        format!("<class '{}'>", unsafe { &selfv.internals.typ }.typename),
+        format!("<class '{}'>", unsafe { selfv.internals.typ() }.typename),
     ))
 }
 fn type_eq<'a>(selfv: Object<'a>, other: Object<'a>) -> MethodType<'a> {
+    if !is_type_exact!(&selfv, other.tp) {
+        return MethodValue::Some(boolobject::bool_from(selfv.vm.clone(), false));
+    }
+
     MethodValue::Some(boolobject::bool_from(
         selfv.vm.clone(),
-        selfv.tp == other.tp,
+        unsafe { selfv.internals.typ() }.typeid == unsafe { other.internals.typ() }.typeid,
     ))
 }
 
+//Calling a class creates an instance of it, which is passed to its 'init' method if there is one
+fn type_call<'a>(selfv: Object<'a>, args: &[Object<'a>]) -> MethodType<'a> {
+    let tp = unsafe { selfv.internals.typ() };
+    if tp.dict.is_none() {
+        return MethodValue::Error(methodnotdefinedexc_from_str(
+            selfv.vm.clone(),
+            &format!("Cannot instantiate '{}' type", tp.typename),
+            Position::default(),
+            Position::default(),
+        ));
+    }
+
+    //Every abstract method must be overridden by a class in the mro
+    let mut missing = Vec::new();
+    for base in tp.mro() {
+        let dict = match &base.dict {
+            Some(dict) => dict,
+            None => continue,
+        };
+        for (name, _) in unsafe { dict.internals.map() }.into_iter() {
+            let method = tp.lookup(name.clone(), 0);
+            if method.is_error() {
+                return MethodValue::Error(method.unwrap_err());
+            }
+            if let Some(method) = unwrap_fast!(method) {
+                let name = RawObject::object_str_safe(name);
+                if name.is_error() {
+                    return MethodValue::Error(name.unwrap_err());
+                }
+                let name = name.unwrap();
+                if fnobject::is_abstract(&method) && !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+        }
+    }
+    if !missing.is_empty() {
+        missing.sort();
+        return MethodValue::Error(methodnotdefinedexc_from_str(
+            selfv.vm.clone(),
+            &format!(
+                "Cannot instantiate '{}' type with unimplemented abstract method(s) {}",
+                tp.typename,
+                missing
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Position::default(),
+            Position::default(),
+        ));
+    }
+
+    let instance = shape::instance_from(Trc::new((*tp).clone()), selfv.vm.clone());
+    let init = tp.lookup(
+        stringobject::string_from(selfv.vm.clone(), String::from("init")),
+        0,
+    );
+    if init.is_error() {
+        return MethodValue::Error(init.unwrap_err());
+    }
+    match unwrap_fast!(init) {
+        Some(init) => {
+            let call_fn = init.tp.call;
+            if call_fn.is_none() {
+                return MethodValue::Error(methodnotdefinedexc_from_str(
+                    selfv.vm.clone(),
+                    &format!(
+                        "Method 'call' is not defined for '{}' type",
+                        init.tp.typename
+                    ),
+                    Position::default(),
+                    Position::default(),
+                ));
+            }
+            let res = call_with_first(unwrap_fast!(call_fn), init, instance.clone(), args);
+            if res.is_error() {
+                return res;
+            }
+        }
+        None => {
+            if !args.is_empty() {
+                return MethodValue::Error(argumentexc_from_str(
+                    selfv.vm.clone(),
+                    &format!("Expected 0 argument(s), got {}", args.len()),
+                    Position::default(),
+                    Position::default(),
+                ));
+            }
+        }
+    }
+    MethodValue::Some(instance)
+}
+
 pub fn init<'a>(mut vm: Trc<VM<'a>>) {
     let tp = Trc::new(TypeObject {
         typename: String::from("type"),
@@ -38,22 +145,31 @@ pub fn init<'a>(mut vm: Trc<VM<'a>>) {
         str: Some(type_repr),
         abs: None,
         neg: None,
+        invert: None,
         hash_fn: Some(|selfv: Object<'a>| {
             MethodValue::Some(intobject::int_from(selfv.vm.clone(), -3))
         }),
 
         eq: Some(type_eq),
+        lt: None,
+        gt: None,
         add: None,
         sub: None,
         mul: None,
         div: None,
         pow: None,
+        bitand: None,
+        bitor: None,
+        bitxor: None,
+        lshift: None,
+        rshift: None,
 
         get: None,
         set: None,
         len: None,
+        contains: None,
 
-        call: None,
+        call: Some(type_call),
 
         getattr: None,
         setattr: None,
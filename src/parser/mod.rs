@@ -5,7 +5,7 @@ pub mod nodes;
 use crate::fileinfo::FileInfo;
 use crate::lexer::{Lexer, Token, TokenType};
 
-use crate::errors::{raise_error, ErrorType};
+use crate::errors::{ErrorType, ParseError};
 
 use crate::parser::nodes::Node;
 
@@ -14,14 +14,19 @@ use precedence::Precedence;
 
 use self::nodes::NodeType;
 
-pub struct Parser<'a> {
+//How deeply expressions and blocks may nest, so that parsing deeply nested input stops with an
+//error instead of overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+pub struct Parser {
     tokens: Vec<Token>,
     current: Token,
     idx: usize,
-    info: &'a FileInfo<'a>,
+    in_class_body: bool,
+    depth: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Position {
     pub startcol: usize,
     pub endcol: usize,
@@ -41,14 +46,76 @@ impl Position {
 //Atom: In-place (not left off after seq). If uses expr, then do not .reverse
 //Expr, Statements, etc: Next (leave off on next)
 
-pub fn new<'a>(lexer: Lexer, info: &'a FileInfo) -> Parser<'a> {
-    let tokens: Vec<_> = lexer.collect();
-    return Parser {
-        tokens: tokens.to_owned(),
-        current: tokens.first().expect("No tokens").to_owned(),
+//Whether a '{' after this token opens a block (fn, class or with body) rather than a dict
+fn opens_block(prev: Option<&Token>) -> bool {
+    matches!(
+        prev.map(|tok| &tok.tp),
+        Some(
+            TokenType::Identifier
+                | TokenType::RParen
+                | TokenType::RSquare
+                | TokenType::RCurly
+                | TokenType::Decimal
+                | TokenType::String
+        )
+    )
+}
+
+//Drop the newlines inside unclosed (), [] or {} literals, so that expressions may span lines.
+//Newlines are significant again inside a nested block.
+fn join_bracketed_lines(tokens: Vec<Token>) -> Vec<Token> {
+    let mut res: Vec<Token> = Vec::with_capacity(tokens.len());
+    //For each open bracket, whether newlines are ignored inside it
+    let mut brackets: Vec<bool> = Vec::new();
+    for token in tokens {
+        match token.tp {
+            TokenType::LParen | TokenType::LSquare => brackets.push(true),
+            TokenType::LCurly => brackets.push(!opens_block(res.last())),
+            TokenType::RParen | TokenType::RSquare | TokenType::RCurly => {
+                brackets.pop();
+            }
+            TokenType::Newline if brackets.last() == Some(&true) => continue,
+            _ => {}
+        }
+        res.push(token);
+    }
+    res
+}
+
+//The token after the last one, also for a file with only whitespace and comments. It is placed
+//right after the last token, so that a node that ends the source without a newline still ends
+//where its last token does.
+fn eof(last: Option<&Token>) -> Token {
+    let (line, col, offset) = last.map_or((0, 0, 0), |last| (last.line, last.endcol, last.offset));
+    Token {
+        data: String::from("\0"),
+        tp: TokenType::Eof,
+        line,
+        startcol: col,
+        endcol: col + 1,
+        offset,
+    }
+}
+
+pub fn new(lexer: Lexer) -> Result<Parser, ParseError> {
+    Ok(from_tokens(lexer.collect::<Result<Vec<_>, _>>()?))
+}
+
+//A parser for tokens that were already lexed, such as by lexer::relex
+pub fn from_tokens(tokens: Vec<Token>) -> Parser {
+    let tokens = join_bracketed_lines(tokens);
+    Parser {
+        current: tokens.first().cloned().unwrap_or_else(|| eof(None)),
+        tokens,
         idx: 1,
-        info,
-    };
+        in_class_body: false,
+        depth: 0,
+    }
+}
+
+//Parse a program without exiting or panicking on any input, returning the first error instead
+pub fn parse_safe(info: &FileInfo) -> Result<Vec<Node>, ParseError> {
+    new(crate::lexer::new(info.data, info))?.generate_ast()
 }
 
 macro_rules! allowed_to_vec {
@@ -61,47 +128,27 @@ macro_rules! allowed_to_vec {
     };
 }
 
-impl<'a> Parser<'a> {
-    fn advance(&mut self) -> Token {
-        self.idx += 1;
-        if self.tokens.get(self.idx - 1).is_none() {
-            self.current = Token {
-                data: String::from("\0"),
-                tp: TokenType::Eof,
-                line: 0,
-                startcol: 0,
-                endcol: 0,
-            };
-            return self.current.to_owned();
-        }
+impl Parser {
+    //Make the token at idx the current one, or EOF if there is none
+    fn load_current(&mut self) -> Token {
         self.current = self
-            .tokens
-            .get(self.idx - 1)
-            .expect("Tokens index out of range")
-            .to_owned();
+            .idx
+            .checked_sub(1)
+            .and_then(|idx| self.tokens.get(idx))
+            .cloned()
+            .unwrap_or_else(|| eof(self.tokens.last()));
 
         self.current.to_owned()
     }
 
-    fn reverse(&mut self) -> Token {
-        self.idx -= 1;
-        if self.tokens.get(self.idx - 1).is_none() {
-            self.current = Token {
-                data: String::from("\0"),
-                tp: TokenType::Eof,
-                line: 0,
-                startcol: 0,
-                endcol: 0,
-            };
-            return self.current.to_owned();
-        }
-        self.current = self
-            .tokens
-            .get(self.idx - 1)
-            .expect("Tokens index out of range")
-            .to_owned();
+    fn advance(&mut self) -> Token {
+        self.idx += 1;
+        self.load_current()
+    }
 
-        self.current.to_owned()
+    fn reverse(&mut self) -> Token {
+        self.idx = self.idx.saturating_sub(1);
+        self.load_current()
     }
 
     fn skip_newlines(&mut self) {
@@ -114,6 +161,10 @@ impl<'a> Parser<'a> {
         self.current.tp == tp
     }
 
+    fn current_is_keyword(&self, keyword: &str) -> bool {
+        self.current_is_type(TokenType::Keyword) && self.current.data == keyword
+    }
+
     fn next_is_type(&mut self, tp: TokenType) -> bool {
         self.advance();
         if self.current.tp == tp {
@@ -124,99 +175,166 @@ impl<'a> Parser<'a> {
         false
     }
 
-    fn raise_error(&mut self, error: &str, errtp: ErrorType) -> ! {
-        raise_error(
-            error,
-            errtp,
-            &Position::create_from_parts(
+    //An error at the current token
+    fn error(&self, error: &str, errtp: ErrorType) -> ParseError {
+        ParseError {
+            message: error.to_string(),
+            tp: errtp,
+            pos: Position::create_from_parts(
                 self.current.startcol,
                 self.current.endcol,
                 self.current.line,
             ),
-            self.info,
-        );
+        }
+    }
+
+    //Enter a nested expression or block, which must be left with leave
+    fn enter(&mut self) -> Result<(), ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(
+                &format!("Nesting is deeper than {} levels.", MAX_DEPTH),
+                ErrorType::NestingTooDeep,
+            ));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
     }
 
     fn get_precedence(&self) -> Precedence {
         match self.current.tp {
             TokenType::Plus | TokenType::Hyphen => Precedence::Sum,
             TokenType::Asterisk | TokenType::Slash => Precedence::Product,
-            TokenType::Period => Precedence::Attr,
-            TokenType::LParen => Precedence::Call,
+            TokenType::DoubleEquals
+            | TokenType::NotEquals
+            | TokenType::Less
+            | TokenType::Greater
+            | TokenType::LessEquals
+            | TokenType::GreaterEquals => Precedence::Equals,
+            TokenType::Keyword if self.current.data == "in" => Precedence::Equals,
+            TokenType::Pipe => Precedence::BitwiseOr,
+            TokenType::Caret => Precedence::BitwiseXor,
+            TokenType::Ampersand => Precedence::BitwiseAnd,
+            TokenType::LeftShift | TokenType::RightShift => Precedence::BitwiseShift,
+            //Attributes and calls bind tighter than any operator, so a + q.f(x) is a + (q.f(x))
+            TokenType::Period | TokenType::LParen => Precedence::Call,
             _ => Precedence::Lowest,
         }
     }
 
-    fn ensure_not_eof(&mut self, allowed: Vec<&str>) {
+    fn ensure_not_eof(&mut self, allowed: Vec<&str>) -> Result<(), ParseError> {
         if self.current_is_type(TokenType::Eof) {
-            self.raise_error(
+            return Err(self.error(
                 &format!(
                     "Unexpected EOF (expected one of {}).",
                     allowed_to_vec!(allowed)
                 ),
                 ErrorType::UnexpectedEOF,
-            )
+            ));
         }
+        Ok(())
     }
 
-    fn expect(&mut self, typ: TokenType) {
+    fn expect(&mut self, typ: TokenType) -> Result<(), ParseError> {
+        if typ == TokenType::Identifier && self.current_is_type(TokenType::Keyword) {
+            return Err(self.reserved_keyword());
+        }
         if !self.current_is_type(typ.clone()) {
-            self.raise_error(
+            return Err(self.error(
                 format!(
                     "Invalid or unexpected token (expected '{}', got '{}').",
                     typ, self.current.tp
                 )
                 .as_str(),
                 ErrorType::UnexpectedToken,
-            )
+            ));
         }
+        Ok(())
     }
 
-    fn expect_and<F>(&mut self, typ: TokenType, fun: F)
+    fn reserved_keyword(&self) -> ParseError {
+        self.error(
+            &format!(
+                "'{0}' is a reserved keyword, and may not be used as a name (escape it as `{0}`).",
+                self.current.data
+            ),
+            ErrorType::ReservedKeyword,
+        )
+    }
+
+    fn expect_and<F>(&mut self, typ: TokenType, fun: F) -> Result<(), ParseError>
     where
         F: FnOnce(&Token) -> bool,
     {
         if !self.current_is_type(typ.clone()) && !fun(&self.current) {
-            self.raise_error(
+            return Err(self.error(
                 format!(
                     "Invalid or unexpected token (expected '{}', got '{}').",
                     typ, self.current.tp
                 )
                 .as_str(),
                 ErrorType::UnexpectedToken,
-            )
+            ));
         }
+        Ok(())
     }
 
     // ===========================================
     // ===========================================
 
-    pub fn generate_ast(&mut self) -> Vec<Node> {
+    pub fn generate_ast(&mut self) -> Result<Vec<Node>, ParseError> {
+        //A program may start with blank lines or comments
+        self.skip_newlines();
         self.block(None)
     }
 
     #[allow(clippy::type_complexity)]
-    fn block(&mut self, allowed: Option<(&dyn Fn(&Token) -> bool, Vec<&str>)>) -> Vec<Node> {
+    fn block(
+        &mut self,
+        allowed: Option<(&dyn Fn(&Token) -> bool, Vec<&str>)>,
+    ) -> Result<Vec<Node>, ParseError> {
+        self.enter()?;
         let mut nodes = Vec::new();
 
         while !self.current_is_type(TokenType::Eof) && !self.current_is_type(TokenType::RCurly) {
             if allowed.is_some() && !allowed.as_ref().unwrap().0(&self.current) {
-                self.raise_error(
+                return Err(self.error(
                     &format!(
                         "Invalid or unexpected token (expected one of {}).",
                         allowed_to_vec!(allowed.unwrap().1)
                     ),
                     ErrorType::UnexpectedToken,
-                );
+                ));
             }
-            nodes.push(self.parse_statement());
+            nodes.push(self.parse_statement()?);
             self.skip_newlines();
         }
 
-        nodes
+        self.leave();
+        Ok(nodes)
     }
 
-    fn parse_statement(&mut self) -> Node {
+    //A block in braces, as the body of a function, class or with statement. It may be empty, with
+    //the braces on the same line or on different lines.
+    #[allow(clippy::type_complexity)]
+    fn braced_block(
+        &mut self,
+        allowed: Option<(&dyn Fn(&Token) -> bool, Vec<&str>)>,
+    ) -> Result<Vec<Node>, ParseError> {
+        self.expect(TokenType::LCurly)?;
+        self.advance();
+        self.skip_newlines();
+        let code = self.block(allowed)?;
+        self.skip_newlines();
+        self.expect(TokenType::RCurly)?;
+        self.advance();
+        Ok(code)
+    }
+
+    fn parse_statement(&mut self) -> Result<Node, ParseError> {
         match self.current.tp {
             TokenType::Keyword => self.keyword(),
             _ => self.expr(Precedence::Lowest),
@@ -227,54 +345,79 @@ impl<'a> Parser<'a> {
         matches!(self.current.tp, TokenType::Decimal)
             || matches!(self.current.tp, TokenType::Identifier)
             || matches!(self.current.tp, TokenType::Hyphen)
+            || matches!(self.current.tp, TokenType::Tilde)
             || matches!(self.current.tp, TokenType::LParen)
             || matches!(self.current.tp, TokenType::String)
             || matches!(self.current.tp, TokenType::LCurly)
     }
 
-    fn atom(&mut self) -> Option<Node> {
-        match self.current.tp {
+    fn atom(&mut self) -> Result<Option<Node>, ParseError> {
+        self.enter()?;
+        let res = match self.current.tp {
             TokenType::Decimal => Some(self.generate_decimal()),
-            TokenType::Identifier => Some(self.generate_identifier()),
-            TokenType::Hyphen => Some(self.generate_negate()),
-            TokenType::LParen => Some(self.generate_grouped()),
+            TokenType::Identifier => Some(self.generate_identifier()?),
+            TokenType::Hyphen => Some(self.generate_negate()?),
+            TokenType::Tilde => Some(self.generate_invert()?),
+            TokenType::LParen => Some(self.generate_grouped()?),
             TokenType::String => Some(self.generate_string()),
-            TokenType::LSquare => Some(self.generate_list()),
-            TokenType::LCurly => Some(self.generate_dict()),
+            TokenType::LSquare => Some(self.generate_list()?),
+            TokenType::LCurly => Some(self.generate_dict()?),
             _ => None,
-        }
+        };
+        self.leave();
+        Ok(res)
     }
 
-    fn keyword(&mut self) -> Node {
+    fn keyword(&mut self) -> Result<Node, ParseError> {
+        if self.next_is_type(TokenType::Equals) {
+            return Err(self.reserved_keyword());
+        }
         if self.current.data == "fn" {
             self.parse_fn()
         } else if self.current.data == "return" {
             self.parse_return()
         } else if self.current.data == "class" {
             self.parse_class()
+        } else if self.current.data == "abstract" {
+            self.parse_abstract_fn()
+        } else if self.current.data == "with" {
+            self.parse_with()
+        } else if self.current.data == "const" {
+            self.parse_const()
+        } else if self.current.data == "let" {
+            self.parse_let()
         } else {
-            self.raise_error("Unknown keyword.", ErrorType::UnknownKeyword);
+            Err(self.error(
+                &format!(
+                    "Keyword '{}' is reserved, but not supported yet.",
+                    self.current.data
+                ),
+                ErrorType::UnknownKeyword,
+            ))
         }
     }
 
-    fn expr(&mut self, precedence: Precedence) -> Node {
+    fn expr(&mut self, precedence: Precedence) -> Result<Node, ParseError> {
         let mut left;
 
-        let atomics = vec!["decimal", "identifier", "-", "(", "string", "["];
+        let atomics = vec!["decimal", "identifier", "-", "~", "(", "string", "["];
 
-        match self.atom() {
-            None => self.raise_error(
-                &format!(
-                    "Invalid or unexpected token (expected one of {}).",
-                    allowed_to_vec!(atomics)
-                ),
-                ErrorType::UnexpectedToken,
-            ),
+        match self.atom()? {
+            None if self.current_is_type(TokenType::Keyword) => return Err(self.reserved_keyword()),
+            None => {
+                return Err(self.error(
+                    &format!(
+                        "Invalid or unexpected token (expected one of {}).",
+                        allowed_to_vec!(atomics)
+                    ),
+                    ErrorType::UnexpectedToken,
+                ))
+            }
             Some(val) => left = val,
         }
 
         if left.tp == NodeType::StoreNode {
-            return left;
+            return Ok(left);
         }
 
         let prev = self.current.clone();
@@ -284,35 +427,57 @@ impl<'a> Parser<'a> {
             && (precedence as u32) < (self.get_precedence() as u32)
         {
             match self.current.tp {
-                TokenType::Plus | TokenType::Hyphen | TokenType::Asterisk | TokenType::Slash => {
-                    left = self.generate_binary(left, self.get_precedence());
+                TokenType::Plus
+                | TokenType::Hyphen
+                | TokenType::Asterisk
+                | TokenType::Slash
+                | TokenType::DoubleEquals
+                | TokenType::NotEquals
+                | TokenType::Less
+                | TokenType::Greater
+                | TokenType::LessEquals
+                | TokenType::GreaterEquals
+                | TokenType::Ampersand
+                | TokenType::Pipe
+                | TokenType::Caret
+                | TokenType::LeftShift
+                | TokenType::RightShift => {
+                    left = self.generate_binary(left, self.get_precedence())?;
+                }
+                TokenType::Keyword if self.current.data == "in" => {
+                    left = self.generate_binary(left, self.get_precedence())?;
                 }
                 TokenType::LParen => {
-                    left = self.generate_call(left);
+                    left = self.generate_call(left)?;
                 }
                 TokenType::Period => {
-                    left = self.generate_attr(left);
+                    left = self.generate_attr(left)?;
+                    if left.tp == NodeType::AttrStore {
+                        return Ok(left);
+                    }
                 }
                 _ => {
-                    return left;
+                    return Ok(left);
                 }
             }
             i += 1;
         }
 
+        //A '-' after an operand is a subtraction that binds more loosely, as in 1 * 2 - 3
         if self.is_atomic()
+            && !self.current_is_type(TokenType::Hyphen)
             && i == 0
             && !self.current_is_type(TokenType::Eof)
             && !self.current_is_type(TokenType::Newline)
             && prev.tp != TokenType::Newline
         {
-            self.raise_error(
+            return Err(self.error(
                 "Trailing atomic tokens are not allowed.",
                 ErrorType::TrailingAtomics,
-            );
+            ));
         }
 
-        left
+        Ok(left)
     }
 
     // ============ Atomic ==============
@@ -336,14 +501,14 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn generate_identifier(&mut self) -> Node {
+    fn generate_identifier(&mut self) -> Result<Node, ParseError> {
         let starttok = self.current.clone();
         let name: String = self.current.data.clone();
         if self.next_is_type(TokenType::Equals) {
             self.advance();
             self.advance();
-            let expr = self.expr(Precedence::Lowest);
-            return nodes::Node::new(
+            let expr = self.expr(Precedence::Lowest)?;
+            return Ok(nodes::Node::new(
                 Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
                 Position::create_from_parts(
                     self.current.startcol,
@@ -352,7 +517,7 @@ impl<'a> Parser<'a> {
                 ),
                 nodes::NodeType::StoreNode,
                 Box::new(nodes::StoreNode { name, expr }),
-            );
+            ));
         }
 
         let res = nodes::Node::new(
@@ -369,21 +534,17 @@ impl<'a> Parser<'a> {
             nodes::NodeType::Identifier,
             Box::new(nodes::IdentifierNode { name }),
         );
-        if self.next_is_type(TokenType::LParen) {
-            self.advance();
-            return self.generate_call(res);
-        }
-        res
+        Ok(res)
     }
 
-    fn generate_negate(&mut self) -> Node {
+    fn generate_negate(&mut self) -> Result<Node, ParseError> {
         self.advance();
 
-        let expr = self.expr(Precedence::Lowest);
+        let expr = self.expr(Precedence::Lowest)?;
 
         self.reverse();
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             expr.start,
             Position::create_from_parts(
                 self.current.startcol,
@@ -395,12 +556,42 @@ impl<'a> Parser<'a> {
                 expr,
                 op: nodes::OpType::Neg,
             }),
-        )
+        ))
     }
 
-    fn generate_grouped(&mut self) -> Node {
+    fn generate_invert(&mut self) -> Result<Node, ParseError> {
+        let start = Position::create_from_parts(
+            self.current.startcol,
+            self.current.endcol,
+            self.current.line,
+        );
         self.advance();
-        self.expr(Precedence::Lowest)
+
+        //Binds tighter than the binary operators, so ~a & b is (~a) & b
+        let expr = self.expr(Precedence::BitwiseNot)?;
+
+        self.reverse();
+
+        Ok(nodes::Node::new(
+            start,
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::Unary,
+            Box::new(nodes::UnaryNode {
+                expr,
+                op: nodes::OpType::Invert,
+            }),
+        ))
+    }
+
+    fn generate_grouped(&mut self) -> Result<Node, ParseError> {
+        self.advance();
+        let expr = self.expr(Precedence::Lowest)?;
+        self.expect(TokenType::RParen)?;
+        Ok(expr)
     }
 
     fn generate_string(&mut self) -> Node {
@@ -422,7 +613,7 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn generate_list(&mut self) -> Node {
+    fn generate_list(&mut self) -> Result<Node, ParseError> {
         let start = Position::create_from_parts(
             self.current.startcol,
             self.current.endcol,
@@ -431,11 +622,30 @@ impl<'a> Parser<'a> {
         self.advance();
         let mut values = Vec::new();
         while !self.current_is_type(TokenType::RSquare) && !self.current_is_type(TokenType::Eof) {
-            values.push(self.expr(Precedence::Lowest));
+            values.push(self.expr(Precedence::Lowest)?);
+            if values.len() == 1 && self.current_is_keyword("for") {
+                let (name, iter) = self.parse_comprehension()?;
+                self.expect(TokenType::RSquare)?;
+                return Ok(nodes::Node::new(
+                    start,
+                    Position::create_from_parts(
+                        self.current.startcol,
+                        self.current.endcol,
+                        self.current.line,
+                    ),
+                    nodes::NodeType::ListComp,
+                    Box::new(nodes::ComprehensionNode {
+                        key: None,
+                        value: values.pop().unwrap(),
+                        name,
+                        iter,
+                    }),
+                ));
+            }
             if self.current_is_type(TokenType::RSquare) {
                 break;
             }
-            self.expect(TokenType::Comma);
+            self.expect(TokenType::Comma)?;
             self.advance();
         }
 
@@ -445,15 +655,15 @@ impl<'a> Parser<'a> {
             self.current.line,
         );
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             start,
             end,
             nodes::NodeType::List,
             Box::new(nodes::ListNode { values }),
-        )
+        ))
     }
 
-    fn generate_dict(&mut self) -> Node {
+    fn generate_dict(&mut self) -> Result<Node, ParseError> {
         let start = Position::create_from_parts(
             self.current.startcol,
             self.current.endcol,
@@ -462,16 +672,35 @@ impl<'a> Parser<'a> {
         self.advance();
         let mut values = Vec::new();
         while !self.current_is_type(TokenType::RCurly) && !self.current_is_type(TokenType::Eof) {
-            let key = self.expr(Precedence::Lowest);
-            self.expect(TokenType::Colon);
+            let key = self.expr(Precedence::Lowest)?;
+            self.expect(TokenType::Colon)?;
             self.advance();
-            let value = self.expr(Precedence::Lowest);
+            let value = self.expr(Precedence::Lowest)?;
+            if values.is_empty() && self.current_is_keyword("for") {
+                let (name, iter) = self.parse_comprehension()?;
+                self.expect(TokenType::RCurly)?;
+                return Ok(nodes::Node::new(
+                    start,
+                    Position::create_from_parts(
+                        self.current.startcol,
+                        self.current.endcol,
+                        self.current.line,
+                    ),
+                    nodes::NodeType::DictComp,
+                    Box::new(nodes::ComprehensionNode {
+                        key: Some(key),
+                        value,
+                        name,
+                        iter,
+                    }),
+                ));
+            }
             values.push((key, value));
 
             if self.current_is_type(TokenType::RCurly) {
                 break;
             }
-            self.expect(TokenType::Comma);
+            self.expect(TokenType::Comma)?;
             self.advance();
         }
         let end = Position::create_from_parts(
@@ -480,22 +709,54 @@ impl<'a> Parser<'a> {
             self.current.line,
         );
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             start,
             end,
             nodes::NodeType::Dict,
             Box::new(nodes::DictNode { values }),
-        )
+        ))
+    }
+
+    //Parse the `for name in iter` part of a comprehension
+    fn parse_comprehension(&mut self) -> Result<(String, Node), ParseError> {
+        self.advance();
+        self.expect(TokenType::Identifier)?;
+        let name = self.current.data.clone();
+        self.advance();
+        if !self.current_is_keyword("in") {
+            return Err(self.error(
+                &format!(
+                    "Invalid or unexpected token (expected 'in', got '{}').",
+                    self.current.tp
+                ),
+                ErrorType::UnexpectedToken,
+            ));
+        }
+        self.advance();
+        let iter = self.expr(Precedence::Lowest)?;
+        Ok((name, iter))
     }
 
     // ============ Expr ==============
 
-    fn generate_binary(&mut self, left: Node, precedence: Precedence) -> Node {
+    fn generate_binary(&mut self, left: Node, precedence: Precedence) -> Result<Node, ParseError> {
         let tp = match self.current.tp {
             TokenType::Plus => nodes::OpType::Add,
             TokenType::Hyphen => nodes::OpType::Sub,
             TokenType::Asterisk => nodes::OpType::Mul,
             TokenType::Slash => nodes::OpType::Div,
+            TokenType::DoubleEquals => nodes::OpType::Eq,
+            TokenType::NotEquals => nodes::OpType::Ne,
+            TokenType::Less => nodes::OpType::Lt,
+            TokenType::Greater => nodes::OpType::Gt,
+            TokenType::LessEquals => nodes::OpType::Le,
+            TokenType::GreaterEquals => nodes::OpType::Ge,
+            TokenType::Ampersand => nodes::OpType::BitAnd,
+            TokenType::Pipe => nodes::OpType::BitOr,
+            TokenType::Caret => nodes::OpType::BitXor,
+            TokenType::LeftShift => nodes::OpType::LShift,
+            TokenType::RightShift => nodes::OpType::RShift,
+            TokenType::Keyword if self.current.data == "in" => nodes::OpType::In,
             _ => {
                 unreachable!()
             }
@@ -503,7 +764,12 @@ impl<'a> Parser<'a> {
 
         self.advance();
 
-        nodes::Node::new(
+        let right = self.expr(precedence)?;
+        if Self::is_comparison(tp) && self.is_comparison_token() {
+            return self.generate_compare(left, tp, right);
+        }
+
+        Ok(nodes::Node::new(
             left.start,
             Position::create_from_parts(
                 self.current.startcol,
@@ -513,30 +779,92 @@ impl<'a> Parser<'a> {
             nodes::NodeType::Binary,
             Box::new(nodes::BinaryNode {
                 left,
-                right: self.expr(precedence),
+                right,
                 op: tp,
             }),
+        ))
+    }
+
+    fn is_comparison(op: nodes::OpType) -> bool {
+        matches!(
+            op,
+            nodes::OpType::Eq
+                | nodes::OpType::Ne
+                | nodes::OpType::Lt
+                | nodes::OpType::Gt
+                | nodes::OpType::Le
+                | nodes::OpType::Ge
+                | nodes::OpType::In
         )
     }
 
-    fn generate_call(&mut self, left: Node) -> Node {
+    fn is_comparison_token(&self) -> bool {
+        matches!(
+            self.current.tp,
+            TokenType::DoubleEquals
+                | TokenType::NotEquals
+                | TokenType::Less
+                | TokenType::Greater
+                | TokenType::LessEquals
+                | TokenType::GreaterEquals
+        ) || self.current_is_keyword("in")
+    }
+
+    //Comparisons chain like in Python: `a < b < c` means `a < b and b < c`, with b evaluated once
+    fn generate_compare(
+        &mut self,
+        first: Node,
+        op: nodes::OpType,
+        second: Node,
+    ) -> Result<Node, ParseError> {
+        let start = first.start;
+        let mut operands = vec![first, second];
+        let mut ops = vec![op];
+        while self.is_comparison_token() {
+            ops.push(match self.current.tp {
+                TokenType::DoubleEquals => nodes::OpType::Eq,
+                TokenType::NotEquals => nodes::OpType::Ne,
+                TokenType::Less => nodes::OpType::Lt,
+                TokenType::Greater => nodes::OpType::Gt,
+                TokenType::LessEquals => nodes::OpType::Le,
+                TokenType::GreaterEquals => nodes::OpType::Ge,
+                TokenType::Keyword => nodes::OpType::In,
+                _ => unreachable!(),
+            });
+            self.advance();
+            operands.push(self.expr(Precedence::Equals)?);
+        }
+
+        Ok(nodes::Node::new(
+            start,
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::Compare,
+            Box::new(nodes::CompareNode { operands, ops }),
+        ))
+    }
+
+    fn generate_call(&mut self, left: Node) -> Result<Node, ParseError> {
         self.advance();
 
+        //Each argument is parsed up to the ',' or ')' after it, so it may hold calls, attributes
+        //and operators nested to any depth
         let mut args = Vec::new();
         while !self.current_is_type(TokenType::RParen) && !self.current_is_type(TokenType::Eof) {
-            args.push(self.expr(Precedence::Lowest));
+            args.push(self.expr(Precedence::Lowest)?);
             if self.current_is_type(TokenType::RParen) {
-                self.advance();
                 break;
             }
-            self.expect(TokenType::Comma);
-            self.advance();
-        }
-        if self.current_is_type(TokenType::RParen) {
+            self.expect(TokenType::Comma)?;
             self.advance();
         }
+        self.expect(TokenType::RParen)?;
+        self.advance();
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             left.start,
             Position::create_from_parts(
                 self.current.startcol,
@@ -545,17 +873,32 @@ impl<'a> Parser<'a> {
             ),
             nodes::NodeType::Call,
             Box::new(nodes::CallNode { ident: left, args }),
-        )
+        ))
     }
 
-    fn generate_attr(&mut self, left: Node) -> Node {
+    fn generate_attr(&mut self, left: Node) -> Result<Node, ParseError> {
         self.advance();
 
-        self.expect(TokenType::Identifier);
+        self.expect(TokenType::Identifier)?;
         let attr = self.current.data.clone();
         self.advance();
 
-        nodes::Node::new(
+        if self.current_is_type(TokenType::Equals) {
+            self.advance();
+            let expr = self.expr(Precedence::Lowest)?;
+            return Ok(nodes::Node::new(
+                left.start,
+                Position::create_from_parts(
+                    self.current.startcol,
+                    self.current.endcol,
+                    self.current.line,
+                ),
+                nodes::NodeType::AttrStore,
+                Box::new(nodes::AttrStoreNode { left, attr, expr }),
+            ));
+        }
+
+        Ok(nodes::Node::new(
             left.start,
             Position::create_from_parts(
                 self.current.startcol,
@@ -564,41 +907,76 @@ impl<'a> Parser<'a> {
             ),
             nodes::NodeType::AttrLoad,
             Box::new(nodes::AttrLoadNode { left, attr }),
-        )
+        ))
     }
 
     // ============ Keyword ==============
 
-    fn parse_fn(&mut self) -> Node {
-        let starttok = self.current.clone();
+    //Parse the name and parameters of a function
+    fn parse_fn_header(&mut self) -> Result<(String, Vec<String>), ParseError> {
         self.advance();
-        self.ensure_not_eof(vec!["identifier"]);
+        self.ensure_not_eof(vec!["identifier"])?;
+        self.expect(TokenType::Identifier)?;
         let name = self.current.data.clone();
         let mut args = Vec::new();
 
         self.advance();
-        self.expect(TokenType::LParen);
+        self.expect(TokenType::LParen)?;
         self.advance();
         while !self.current_is_type(TokenType::RParen) && !self.current_is_type(TokenType::Eof) {
-            self.expect(TokenType::Identifier);
+            self.expect(TokenType::Identifier)?;
             args.push(self.current.data.clone());
             self.advance();
             if self.current_is_type(TokenType::RParen) {
-                self.advance();
                 break;
             }
-            self.expect(TokenType::Comma);
+            self.expect(TokenType::Comma)?;
             self.advance();
         }
-        self.expect(TokenType::LCurly);
+        self.expect(TokenType::RParen)?;
         self.advance();
-        self.skip_newlines();
-        let code = self.block(None);
-        self.skip_newlines();
-        self.expect(TokenType::RCurly);
+        Ok((name, args))
+    }
+
+    fn parse_fn(&mut self) -> Result<Node, ParseError> {
+        let starttok = self.current.clone();
+        let (name, args) = self.parse_fn_header()?;
+        let in_class_body = self.in_class_body;
+        self.in_class_body = false;
+        let code = self.braced_block(None)?;
+        self.in_class_body = in_class_body;
+
+        Ok(nodes::Node::new(
+            Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::Function,
+            Box::new(nodes::FunctionNode {
+                name,
+                args,
+                code,
+                is_abstract: false,
+            }),
+        ))
+    }
+
+    //An abstract method has no body, and must be implemented by a subclass
+    fn parse_abstract_fn(&mut self) -> Result<Node, ParseError> {
+        let starttok = self.current.clone();
+        if !self.in_class_body {
+            return Err(self.error(
+                "Abstract methods may only be declared in a class body.",
+                ErrorType::UnexpectedToken,
+            ));
+        }
         self.advance();
+        self.expect_and(TokenType::Keyword, |tok| tok.data == "fn")?;
+        let (name, args) = self.parse_fn_header()?;
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
             Position::create_from_parts(
                 self.current.startcol,
@@ -606,16 +984,21 @@ impl<'a> Parser<'a> {
                 self.current.line,
             ),
             nodes::NodeType::Function,
-            Box::new(nodes::FunctionNode { name, args, code }),
-        )
+            Box::new(nodes::FunctionNode {
+                name,
+                args,
+                code: Vec::new(),
+                is_abstract: true,
+            }),
+        ))
     }
 
-    fn parse_return(&mut self) -> Node {
+    fn parse_return(&mut self) -> Result<Node, ParseError> {
         self.advance();
 
-        let expr = self.expr(Precedence::Lowest);
+        let expr = self.expr(Precedence::Lowest)?;
 
-        nodes::Node::new(
+        Ok(nodes::Node::new(
             expr.start,
             Position::create_from_parts(
                 self.current.startcol,
@@ -624,30 +1007,90 @@ impl<'a> Parser<'a> {
             ),
             nodes::NodeType::Return,
             Box::new(nodes::ReturnNode { expr }),
-        )
+        ))
     }
 
-    fn parse_class(&mut self) -> Node {
+    fn parse_const(&mut self) -> Result<Node, ParseError> {
         let starttok = self.current.clone();
         self.advance();
-        self.ensure_not_eof(vec!["identifier"]);
+        self.expect(TokenType::Identifier)?;
         let name = self.current.data.clone();
         self.advance();
+        self.expect(TokenType::Equals)?;
+        self.advance();
+
+        let expr = self.expr(Precedence::Lowest)?;
 
-        self.expect(TokenType::LCurly);
+        Ok(nodes::Node::new(
+            Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::Const,
+            Box::new(nodes::ConstNode { name, expr }),
+        ))
+    }
+
+    fn parse_let(&mut self) -> Result<Node, ParseError> {
+        let starttok = self.current.clone();
+        self.advance();
+        self.expect(TokenType::Identifier)?;
+        let name = self.current.data.clone();
+        self.advance();
+        self.expect(TokenType::Equals)?;
         self.advance();
-        self.skip_newlines();
 
-        self.expect_and(TokenType::Keyword, |tok| tok.data == "fn");
-        let code = self.block(Some((
-            &|tok| tok.tp == TokenType::Keyword && tok.data == "fn",
-            vec!["fn"],
-        )));
-        self.skip_newlines();
-        self.expect(TokenType::RCurly);
+        let expr = self.expr(Precedence::Lowest)?;
+
+        Ok(nodes::Node::new(
+            Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::Let,
+            Box::new(nodes::LetNode { name, expr }),
+        ))
+    }
+
+    fn parse_class(&mut self) -> Result<Node, ParseError> {
+        let starttok = self.current.clone();
+        self.advance();
+        self.ensure_not_eof(vec!["identifier"])?;
+        self.expect(TokenType::Identifier)?;
+        let name = self.current.data.clone();
         self.advance();
 
-        nodes::Node::new(
+        let mut bases = Vec::new();
+        if self.current_is_type(TokenType::LParen) {
+            self.advance();
+            while !self.current_is_type(TokenType::RParen) && !self.current_is_type(TokenType::Eof)
+            {
+                self.expect(TokenType::Identifier)?;
+                bases.push(self.current.data.clone());
+                self.advance();
+                if self.current_is_type(TokenType::RParen) {
+                    break;
+                }
+                self.expect(TokenType::Comma)?;
+                self.advance();
+            }
+            self.expect(TokenType::RParen)?;
+            self.advance();
+        }
+
+        let in_class_body = self.in_class_body;
+        self.in_class_body = true;
+        let code = self.braced_block(Some((
+            &|tok| tok.tp == TokenType::Keyword && (tok.data == "fn" || tok.data == "abstract"),
+            vec!["fn", "abstract"],
+        )))?;
+        self.in_class_body = in_class_body;
+
+        Ok(nodes::Node::new(
             Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
             Position::create_from_parts(
                 self.current.startcol,
@@ -657,8 +1100,34 @@ impl<'a> Parser<'a> {
             nodes::NodeType::Class,
             Box::new(nodes::ClassNode {
                 name,
+                bases,
                 methods: code,
             }),
-        )
+        ))
+    }
+
+    fn parse_with(&mut self) -> Result<Node, ParseError> {
+        let starttok = self.current.clone();
+        self.advance();
+        let expr = self.expr(Precedence::Lowest)?;
+
+        self.expect_and(TokenType::Keyword, |tok| tok.data == "as")?;
+        self.advance();
+        self.expect(TokenType::Identifier)?;
+        let name = self.current.data.clone();
+        self.advance();
+
+        let code = self.braced_block(None)?;
+
+        Ok(nodes::Node::new(
+            Position::create_from_parts(starttok.startcol, starttok.endcol, starttok.line),
+            Position::create_from_parts(
+                self.current.startcol,
+                self.current.endcol,
+                self.current.line,
+            ),
+            nodes::NodeType::With,
+            Box::new(nodes::WithNode { expr, name, code }),
+        ))
     }
 }
@@ -1,4 +1,4 @@
-use crate::parser::Position;
+use crate::{errors::InternalError, parser::Position};
 use std::fmt::Debug;
 
 #[derive(Debug)]
@@ -18,6 +18,164 @@ impl Node {
             data,
         }
     }
+
+    fn missing(&self, what: &str) -> InternalError {
+        InternalError {
+            message: format!("{:?} node has no {}", self.tp, what),
+            pos: self.start,
+        }
+    }
+
+    //The data of a node, or an error at the node if it does not have the data that its type
+    //should have
+    pub fn raw(&self, key: &str) -> Result<String, InternalError> {
+        let data = self.data.get_data();
+        data.raw
+            .get(key)
+            .cloned()
+            .ok_or_else(|| self.missing(&format!("raw value '{}'", key)))
+    }
+
+    pub fn child(&self, key: &str) -> Result<&Node, InternalError> {
+        let data = self.data.get_data();
+        data.nodes
+            .get(key)
+            .copied()
+            .ok_or_else(|| self.missing(&format!("node '{}'", key)))
+    }
+
+    pub fn nodearr(&self) -> Result<&Vec<Node>, InternalError> {
+        self.data
+            .get_data()
+            .nodearr
+            .ok_or_else(|| self.missing("node list"))
+    }
+
+    pub fn args(&self) -> Result<Vec<String>, InternalError> {
+        self.data
+            .get_data()
+            .args
+            .ok_or_else(|| self.missing("arguments"))
+    }
+
+    pub fn mapping(&self) -> Result<&Vec<(Node, Node)>, InternalError> {
+        self.data
+            .get_data()
+            .mapping
+            .ok_or_else(|| self.missing("mapping"))
+    }
+
+    pub fn op(&self) -> Result<OpType, InternalError> {
+        self.data
+            .get_data()
+            .op
+            .ok_or_else(|| self.missing("operator"))
+    }
+
+    pub fn ops(&self) -> Result<&Vec<OpType>, InternalError> {
+        self.data
+            .get_data()
+            .ops
+            .ok_or_else(|| self.missing("operators"))
+    }
+
+    //The node as indented JSON, without positions, so that the shape of a parse can be compared
+    //with an expected one. Raw values and child nodes are sorted by key.
+    pub fn to_json(&self, indent: usize) -> String {
+        let data = self.data.get_data();
+        let inner = "  ".repeat(indent + 1);
+        let mut fields = vec![format!(
+            "\"type\": {}",
+            json_string(&format!("{:?}", self.tp))
+        )];
+
+        let mut raw: Vec<_> = data.raw.iter().collect();
+        raw.sort();
+        for (key, value) in raw {
+            fields.push(format!("{}: {}", json_string(key), json_string(value)));
+        }
+        if let Some(op) = data.op {
+            fields.push(format!("\"op\": {}", json_string(&format!("{:?}", op))));
+        }
+        if let Some(ops) = data.ops {
+            let ops: Vec<String> = ops
+                .iter()
+                .map(|op| json_string(&format!("{:?}", op)))
+                .collect();
+            fields.push(format!("\"ops\": [{}]", ops.join(", ")));
+        }
+        let mut nodes: Vec<_> = data.nodes.iter().collect();
+        nodes.sort_by_key(|(key, _)| *key);
+        for (key, node) in nodes {
+            fields.push(format!(
+                "{}: {}",
+                json_string(key),
+                node.to_json(indent + 1)
+            ));
+        }
+        if let Some(args) = data.args {
+            let args: Vec<String> = args.iter().map(|arg| json_string(arg)).collect();
+            fields.push(format!("\"args\": [{}]", args.join(", ")));
+        }
+        if let Some(nodearr) = data.nodearr {
+            fields.push(format!("\"body\": {}", json_array(nodearr, indent + 1)));
+        }
+        if let Some(mapping) = data.mapping {
+            let pairs: Vec<String> = mapping
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}  [{}, {}]",
+                        inner,
+                        key.to_json(indent + 2),
+                        value.to_json(indent + 2)
+                    )
+                })
+                .collect();
+            let mapping = if pairs.is_empty() {
+                String::from("[]")
+            } else {
+                format!("[\n{}\n{}]", pairs.join(",\n"), inner)
+            };
+            fields.push(format!("\"mapping\": {}", mapping));
+        }
+
+        let fields: Vec<String> = fields
+            .into_iter()
+            .map(|field| format!("{}{}", inner, field))
+            .collect();
+        format!("{{\n{}\n{}}}", fields.join(",\n"), "  ".repeat(indent))
+    }
+}
+
+//A list of nodes as indented JSON
+pub fn json_array(nodes: &[Node], indent: usize) -> String {
+    if nodes.is_empty() {
+        return String::from("[]");
+    }
+    let inner = "  ".repeat(indent + 1);
+    let items: Vec<String> = nodes
+        .iter()
+        .map(|node| format!("{}{}", inner, node.to_json(indent + 1)))
+        .collect();
+    format!("[\n{}\n{}]", items.join(",\n"), "  ".repeat(indent))
+}
+
+fn json_string(value: &str) -> String {
+    let mut res = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
 }
 
 #[derive(Debug, PartialEq)]
@@ -35,6 +193,13 @@ pub enum NodeType {
     Dict,
     Class,
     AttrLoad,
+    AttrStore,
+    With,
+    ListComp,
+    DictComp,
+    Compare,
+    Const,
+    Let,
 }
 
 #[derive(Debug)]
@@ -42,6 +207,7 @@ pub struct NodeValue<'a> {
     pub raw: hashbrown::HashMap<String, String>,
     pub nodes: hashbrown::HashMap<String, &'a Node>,
     pub op: Option<OpType>,
+    pub ops: Option<&'a Vec<OpType>>,
     pub nodearr: Option<&'a Vec<Node>>,
     pub args: Option<Vec<String>>,
     pub mapping: Option<&'a Vec<(Node, Node)>>,
@@ -63,6 +229,7 @@ impl<'a> NodeValue<'a> {
             raw: hashbrown::HashMap::new(),
             nodes: hashbrown::HashMap::new(),
             op: None,
+            ops: None,
             nodearr: None,
             args: None,
             mapping: None,
@@ -97,6 +264,19 @@ pub enum OpType {
     Mul,
     Div,
     Neg,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    In,
+    BitAnd,
+    BitOr,
+    BitXor,
+    LShift,
+    RShift,
+    Invert,
 }
 
 pub struct BinaryNode {
@@ -118,6 +298,24 @@ impl NodeData for BinaryNode {
 
 // ========================
 
+//A chain of comparisons like `a < b < c`, where each operand is compared with the next
+pub struct CompareNode {
+    pub operands: Vec<Node>,
+    pub ops: Vec<OpType>,
+}
+
+impl NodeData for CompareNode {
+    fn get_data(&self) -> NodeValue<'_> {
+        let mut value = NodeValue::new();
+        value.nodearr = Some(&self.operands);
+        value.ops = Some(&self.ops);
+
+        value
+    }
+}
+
+// ========================
+
 pub struct StoreNode {
     pub name: String,
     pub expr: Node,
@@ -135,6 +333,41 @@ impl NodeData for StoreNode {
 
 // ========================
 
+pub struct ConstNode {
+    pub name: String,
+    pub expr: Node,
+}
+
+impl NodeData for ConstNode {
+    fn get_data(&self) -> NodeValue<'_> {
+        let mut value = NodeValue::new();
+        value.nodes.insert(String::from("expr"), &self.expr);
+        value.raw.insert(String::from("name"), self.name.clone());
+
+        value
+    }
+}
+
+// ========================
+
+//A variable that is only bound until the end of the block that declares it
+pub struct LetNode {
+    pub name: String,
+    pub expr: Node,
+}
+
+impl NodeData for LetNode {
+    fn get_data(&self) -> NodeValue<'_> {
+        let mut value = NodeValue::new();
+        value.nodes.insert(String::from("expr"), &self.expr);
+        value.raw.insert(String::from("name"), self.name.clone());
+
+        value
+    }
+}
+
+// ========================
+
 pub struct IdentifierNode {
     pub name: String,
 }
@@ -154,12 +387,16 @@ pub struct FunctionNode {
     pub name: String,
     pub args: Vec<String>,
     pub code: Vec<Node>,
+    pub is_abstract: bool,
 }
 
 impl NodeData for FunctionNode {
     fn get_data(&self) -> NodeValue {
         let mut value = NodeValue::new();
         value.raw.insert(String::from("name"), self.name.clone());
+        value
+            .raw
+            .insert(String::from("abstract"), self.is_abstract.to_string());
         value.nodearr = Some(&self.code);
         value.args = Some(self.args.clone());
 
@@ -265,6 +502,7 @@ impl NodeData for DictNode {
 
 pub struct ClassNode {
     pub name: String,
+    pub bases: Vec<String>,
     pub methods: Vec<Node>,
 }
 
@@ -272,6 +510,7 @@ impl NodeData for ClassNode {
     fn get_data(&self) -> NodeValue {
         let mut value = NodeValue::new();
         value.raw.insert(String::from("name"), self.name.clone());
+        value.args = Some(self.bases.clone());
         value.nodearr = Some(&self.methods);
 
         value
@@ -294,3 +533,65 @@ impl NodeData for AttrLoadNode {
         value
     }
 }
+
+// ========================
+
+pub struct AttrStoreNode {
+    pub left: Node,
+    pub attr: String,
+    pub expr: Node,
+}
+
+impl NodeData for AttrStoreNode {
+    fn get_data(&self) -> NodeValue {
+        let mut value = NodeValue::new();
+        value.raw.insert(String::from("attr"), self.attr.clone());
+        value.nodes.insert(String::from("left"), &self.left);
+        value.nodes.insert(String::from("expr"), &self.expr);
+
+        value
+    }
+}
+
+// ========================
+
+pub struct WithNode {
+    pub expr: Node,
+    pub name: String,
+    pub code: Vec<Node>,
+}
+
+impl NodeData for WithNode {
+    fn get_data(&self) -> NodeValue {
+        let mut value = NodeValue::new();
+        value.nodes.insert(String::from("expr"), &self.expr);
+        value.raw.insert(String::from("name"), self.name.clone());
+        value.nodearr = Some(&self.code);
+
+        value
+    }
+}
+
+// ========================
+
+//Shared by list and dict comprehensions, dict comprehensions also have a key
+pub struct ComprehensionNode {
+    pub key: Option<Node>,
+    pub value: Node,
+    pub name: String,
+    pub iter: Node,
+}
+
+impl NodeData for ComprehensionNode {
+    fn get_data(&self) -> NodeValue {
+        let mut value = NodeValue::new();
+        if let Some(key) = &self.key {
+            value.nodes.insert(String::from("key"), key);
+        }
+        value.nodes.insert(String::from("value"), &self.value);
+        value.nodes.insert(String::from("iter"), &self.iter);
+        value.raw.insert(String::from("name"), self.name.clone());
+
+        value
+    }
+}
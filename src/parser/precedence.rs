@@ -2,7 +2,6 @@
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Precedence {
     Lowest,
-    Attr,
     To,
     Assign,
     LogicalOr,
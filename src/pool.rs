@@ -0,0 +1,192 @@
+//Small-object allocator for the VM, enabled with the "pool" feature.
+//Every object is a Trc, which makes two small heap allocations with the global allocator, so the
+//pool has to be installed as the global allocator to see them. It only pools on a thread while a VM
+//exists there, which holds a Scope: small blocks are then carved out of large chunks, and freed
+//blocks are kept on a free list per size class so that the next allocation of that size reuses
+//them. Everything else, like the allocations of the command line before the VM is created, goes to
+//the system allocator as if there was no pool. The lists are per thread, and a VM runs on one
+//thread, so each VM reuses the objects it drops without locking.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const ALIGN: usize = 16;
+const MAX_SMALL: usize = 512;
+const N_CLASSES: usize = MAX_SMALL / ALIGN;
+const CHUNK_SIZE: usize = 64 * 1024;
+//The most chunks that the pool makes, 256 MiB, after which small blocks come from the system
+const MAX_CHUNKS: usize = 4096;
+
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+thread_local! {
+    static FREE_LISTS: [Cell<*mut FreeBlock>; N_CLASSES] =
+        const { [const { Cell::new(ptr::null_mut()) }; N_CLASSES] };
+    //The unused part of the current chunk: (start, length)
+    static CHUNK: Cell<(*mut u8, usize)> = const { Cell::new((ptr::null_mut(), 0)) };
+    //The VMs on this thread, which pool while there are any
+    static SCOPES: Cell<usize> = const { Cell::new(0) };
+}
+
+//The start of each chunk, in an open addressed table with twice as many slots as chunks, so that
+//freeing a block can tell whether the pool made it. Chunks are aligned to their size, so the chunk
+//of a block is found by masking its address. A block may be freed on another thread than the one
+//that made it, so the table is shared.
+static CHUNKS: [AtomicUsize; 2 * MAX_CHUNKS] = [const { AtomicUsize::new(0) }; 2 * MAX_CHUNKS];
+static N_CHUNKS: AtomicUsize = AtomicUsize::new(0);
+
+fn chunk_slot(base: usize) -> usize {
+    (base / CHUNK_SIZE).wrapping_mul(0x9e3779b97f4a7c15) % CHUNKS.len()
+}
+
+fn register_chunk(base: usize) {
+    let mut slot = chunk_slot(base);
+    while CHUNKS[slot]
+        .compare_exchange(0, base, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        slot = (slot + 1) % CHUNKS.len();
+    }
+}
+
+fn in_pool(ptr: *mut u8) -> bool {
+    let base = ptr as usize & !(CHUNK_SIZE - 1);
+    let mut slot = chunk_slot(base);
+    loop {
+        match CHUNKS[slot].load(Ordering::Acquire) {
+            0 => return false,
+            start if start == base => return true,
+            _ => slot = (slot + 1) % CHUNKS.len(),
+        }
+    }
+}
+
+//Held by each VM, so that the pool serves the allocations of the thread that it runs on
+pub struct Scope;
+
+impl Scope {
+    pub fn new() -> Scope {
+        SCOPES.with(|scopes| scopes.set(scopes.get() + 1));
+        Scope
+    }
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope::new()
+    }
+}
+
+impl Clone for Scope {
+    fn clone(&self) -> Self {
+        Scope::new()
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let _ = SCOPES.try_with(|scopes| scopes.set(scopes.get().saturating_sub(1)));
+    }
+}
+
+pub struct PoolAllocator;
+
+#[global_allocator]
+static ALLOCATOR: PoolAllocator = PoolAllocator;
+
+//Index of the size class of a small allocation, whose blocks are (class + 1) * ALIGN bytes
+#[inline]
+fn size_class(layout: &Layout) -> Option<usize> {
+    if layout.size() <= MAX_SMALL && layout.align() <= ALIGN {
+        Some(layout.size().max(1).div_ceil(ALIGN) - 1)
+    } else {
+        None
+    }
+}
+
+//Take a new block from the current chunk, starting a new chunk if it is used up. Null if the pool
+//has made all of its chunks, or the system is out of memory.
+unsafe fn carve(class: usize) -> *mut u8 {
+    let size = (class + 1) * ALIGN;
+    CHUNK
+        .try_with(|chunk| {
+            let (mut start, mut len) = chunk.get();
+            if len < size {
+                if N_CHUNKS.fetch_add(1, Ordering::AcqRel) >= MAX_CHUNKS {
+                    return ptr::null_mut();
+                }
+                start = System.alloc(Layout::from_size_align_unchecked(CHUNK_SIZE, CHUNK_SIZE));
+                if start.is_null() {
+                    return start;
+                }
+                register_chunk(start as usize);
+                len = CHUNK_SIZE;
+            }
+            chunk.set((start.add(size), len - size));
+            start
+        })
+        .unwrap_or(ptr::null_mut())
+}
+
+unsafe impl GlobalAlloc for PoolAllocator {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let class = match size_class(&layout) {
+            Some(class) if SCOPES.try_with(Cell::get).unwrap_or(0) > 0 => class,
+            _ => return System.alloc(layout),
+        };
+        let block = FREE_LISTS
+            .try_with(|lists| {
+                let head = lists[class].get();
+                if head.is_null() {
+                    carve(class)
+                } else {
+                    lists[class].set((*head).next);
+                    head as *mut u8
+                }
+            })
+            .unwrap_or(ptr::null_mut());
+        if block.is_null() {
+            System.alloc(layout)
+        } else {
+            block
+        }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let class = match size_class(&layout) {
+            Some(class) if in_pool(ptr) => class,
+            _ => return System.dealloc(ptr, layout),
+        };
+        //Blocks of the pool are never given back to the system. If the thread is exiting, the
+        //block is leaked.
+        let _ = FREE_LISTS.try_with(|lists| {
+            let block = ptr as *mut FreeBlock;
+            (*block).next = lists[class].get();
+            lists[class].set(block);
+        });
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        if !in_pool(ptr) {
+            if size_class(&new_layout).is_none() || SCOPES.try_with(Cell::get).unwrap_or(0) == 0 {
+                return System.realloc(ptr, layout, new_size);
+            }
+        } else if size_class(&layout) == size_class(&new_layout) {
+            return ptr;
+        }
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,142 @@
+//The reference-counted pointer behind every object, the VM and its scopes.
+//trc keeps a count for each thread beside a shared one. On targets without atomics it falls back
+//to locks that copy the counts out before changing them, so a scheduler that runs VMs on several
+//threads can not rely on it. With the "threaded" feature, Trc is AtomicRc, which keeps a single
+//atomic count like Arc, while still handing out mutable access like trc does.
+
+#[cfg(not(feature = "threaded"))]
+pub use trc::Trc;
+
+#[cfg(feature = "threaded")]
+pub use atomic::AtomicRc as Trc;
+
+#[cfg(feature = "threaded")]
+#[cfg(not(target_has_atomic = "ptr"))]
+compile_error!("The 'threaded' feature requires a target with pointer-sized atomics");
+
+#[cfg(feature = "threaded")]
+mod atomic {
+    use std::fmt::{self, Debug, Display};
+    use std::hash::{Hash, Hasher};
+    use std::ops::{Deref, DerefMut};
+    use std::ptr::NonNull;
+    use std::sync::atomic::{fence, AtomicUsize, Ordering};
+
+    //Counts above this are a leak of clones, which would overflow the count like for Arc
+    const MAX_COUNT: usize = isize::MAX as usize;
+
+    struct Inner<T> {
+        count: AtomicUsize,
+        data: T,
+    }
+
+    pub struct AtomicRc<T> {
+        ptr: NonNull<Inner<T>>,
+    }
+
+    unsafe impl<T: Send + Sync> Send for AtomicRc<T> {}
+    unsafe impl<T: Send + Sync> Sync for AtomicRc<T> {}
+
+    impl<T> AtomicRc<T> {
+        pub fn new(data: T) -> Self {
+            let inner = Box::new(Inner {
+                count: AtomicUsize::new(1),
+                data,
+            });
+            AtomicRc {
+                ptr: NonNull::from(Box::leak(inner)),
+            }
+        }
+
+        fn inner(&self) -> &Inner<T> {
+            unsafe { self.ptr.as_ref() }
+        }
+
+        pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+            this.ptr == other.ptr
+        }
+
+        pub fn as_ptr(this: &Self) -> *const T {
+            &this.inner().data
+        }
+
+        //There is one count, which every thread shares. These match the counts of trc, so that
+        //the VM can tell whether it holds the only reference with either.
+        pub fn local_refcount(this: &Self) -> usize {
+            this.inner().count.load(Ordering::Acquire)
+        }
+
+        pub fn atomic_count(this: &Self) -> usize {
+            this.inner().count.load(Ordering::Acquire)
+        }
+    }
+
+    impl<T> Clone for AtomicRc<T> {
+        fn clone(&self) -> Self {
+            //A new reference is made from an existing one, so nothing needs to be ordered
+            let old = self.inner().count.fetch_add(1, Ordering::Relaxed);
+            if old > MAX_COUNT {
+                std::process::abort();
+            }
+            AtomicRc { ptr: self.ptr }
+        }
+    }
+
+    impl<T> Drop for AtomicRc<T> {
+        fn drop(&mut self) {
+            if self.inner().count.fetch_sub(1, Ordering::Release) != 1 {
+                return;
+            }
+            //Every use of the data on other threads happens before it is dropped here
+            fence(Ordering::Acquire);
+            drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+        }
+    }
+
+    impl<T> Deref for AtomicRc<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.inner().data
+        }
+    }
+
+    //Like trc, the VM mutates objects through any of their references
+    impl<T> DerefMut for AtomicRc<T> {
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { &mut (*self.ptr.as_ptr()).data }
+        }
+    }
+
+    impl<T: Default> Default for AtomicRc<T> {
+        fn default() -> Self {
+            AtomicRc::new(T::default())
+        }
+    }
+
+    impl<T: PartialEq> PartialEq for AtomicRc<T> {
+        fn eq(&self, other: &Self) -> bool {
+            **self == **other
+        }
+    }
+
+    impl<T: Eq> Eq for AtomicRc<T> {}
+
+    impl<T: Hash> Hash for AtomicRc<T> {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (**self).hash(state)
+        }
+    }
+
+    impl<T: Debug> Debug for AtomicRc<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            Debug::fmt(&**self, f)
+        }
+    }
+
+    impl<T: Display> Display for AtomicRc<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            Display::fmt(&**self, f)
+        }
+    }
+}
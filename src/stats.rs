@@ -1,5 +1,6 @@
 //Taken from rust's stats.rs
-//I have not included their LICENSE as I only took small portions. The code is unchanged.
+//I have not included their LICENSE as I only took small portions. The code is unchanged, except that
+//the functions of their Stats trait take slices.
 
 fn local_sort(v: &mut [f64]) {
     v.sort_by(|x: &f64, y: &f64| x.total_cmp(y));
@@ -50,3 +51,34 @@ pub fn winsorize(samples: &mut [f64], pct: f64) {
         }
     }
 }
+
+pub fn sum(samples: &[f64]) -> f64 {
+    samples.iter().sum()
+}
+
+pub fn mean(samples: &[f64]) -> f64 {
+    assert!(!samples.is_empty());
+    sum(samples) / (samples.len() as f64)
+}
+
+/// Variance of the samples: the mean of the squared deviations from the mean, using Bessel's
+/// correction, as the samples are a part of all possible runs.
+pub fn var(samples: &[f64]) -> f64 {
+    if samples.len() < 2 {
+        0.0
+    } else {
+        let mean = mean(samples);
+        let mut v: f64 = 0.0;
+        for s in samples {
+            let x = *s - mean;
+            v += x * x;
+        }
+        let denom = (samples.len() - 1) as f64;
+        v / denom
+    }
+}
+
+/// Standard deviation: the square root of the variance.
+pub fn std_dev(samples: &[f64]) -> f64 {
+    var(samples).sqrt()
+}
@@ -0,0 +1,210 @@
+//Plain Rust values for programs that embed merlin, so that they can pass data to Merlin code and
+//read its results without reading the internals of objects
+
+use crate::rc::Trc;
+use crate::{
+    errors::ExitCode,
+    interpreter::VM,
+    objects::{
+        boolobject, dictobject, floatobject, intobject, listobject, mhash, object_id, stringobject,
+        IdSet, Object,
+    },
+    unwrap_fast,
+};
+use std::fmt::Display;
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>), //In the order that the dict iterates over its items
+    None,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversionError {
+    UnsupportedType(String), //An object of a type that has no Value, like a function
+    Cycle,                   //A list or dict that contains itself
+    UnhashableKey(String),   //A dict key of a type that can not be hashed
+}
+
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnsupportedType(typename) => {
+                write!(f, "Objects of type '{}' can not be converted", typename)
+            }
+            ConversionError::Cycle => write!(f, "A container that contains itself"),
+            ConversionError::UnhashableKey(typename) => {
+                write!(f, "Dict key of type '{}' is unhashable", typename)
+            }
+        }
+    }
+}
+
+//An error from calling Merlin code from Rust
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MerlinError {
+    NotDefined(String),           //No top-level variable has the name
+    NotAFunction(String, String), //The name and type of a variable that is not a function
+    Conversion(ConversionError),  //An argument or the result has no object or Value
+    Exception(String),            //The repr of an exception that the call raised
+    Exited(ExitCode),             //The program stopped, with env.exit or an error of eval
+}
+
+impl Display for MerlinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MerlinError::NotDefined(name) => write!(f, "Name '{}' is not defined", name),
+            MerlinError::NotAFunction(name, typename) => {
+                write!(f, "'{}' is a '{}', not a function", name, typename)
+            }
+            MerlinError::Conversion(err) => err.fmt(f),
+            MerlinError::Exception(exc) => write!(f, "{}", exc),
+            MerlinError::Exited(code) => write!(f, "Exited with status {}", code.status()),
+        }
+    }
+}
+
+impl From<ConversionError> for MerlinError {
+    fn from(err: ConversionError) -> Self {
+        MerlinError::Conversion(err)
+    }
+}
+
+impl<'a> TryFrom<Object<'a>> for Value {
+    type Error = ConversionError;
+
+    fn try_from(object: Object<'a>) -> Result<Self, Self::Error> {
+        Value::from_object(&object, &mut IdSet::new())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Value::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::None, Into::into)
+    }
+}
+
+impl Value {
+    //Convert an object, with the ids of the containers that are being converted, to stop cycles
+    fn from_object(object: &Object<'_>, in_progress: &mut IdSet) -> Result<Value, ConversionError> {
+        let types = &object.vm.types;
+        if is_type_exact!(object, unwrap_fast!(types.inttp.as_ref()).clone()) {
+            return Ok(Value::Int(unsafe { object.internals.int() } as i64));
+        }
+        if is_type_exact!(object, unwrap_fast!(types.floattp.as_ref()).clone()) {
+            return Ok(Value::Float(unsafe { object.internals.float() }));
+        }
+        if is_type_exact!(object, unwrap_fast!(types.strtp.as_ref()).clone()) {
+            return Ok(Value::Str(unsafe { object.internals.str() }.to_string()));
+        }
+        if is_type_exact!(object, unwrap_fast!(types.booltp.as_ref()).clone()) {
+            return Ok(Value::Bool(unsafe { object.internals.bool() }));
+        }
+        if is_type_exact!(object, unwrap_fast!(types.nonetp.as_ref()).clone()) {
+            return Ok(Value::None);
+        }
+
+        let is_list = is_type_exact!(object, unwrap_fast!(types.listtp.as_ref()).clone());
+        let is_dict = is_type_exact!(object, unwrap_fast!(types.dicttp.as_ref()).clone());
+        if !is_list && !is_dict {
+            return Err(ConversionError::UnsupportedType(object.tp.typename.clone()));
+        }
+        if !in_progress.insert(object_id(object)) {
+            return Err(ConversionError::Cycle);
+        }
+        let res = if is_list {
+            unsafe { object.internals.arr() }
+                .iter()
+                .map(|item| Value::from_object(item, in_progress))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::List)
+        } else {
+            unsafe { object.internals.map() }
+                .into_iter()
+                .map(|(key, value)| {
+                    Ok((
+                        Value::from_object(&key, in_progress)?,
+                        Value::from_object(&value, in_progress)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Dict)
+        };
+        in_progress.remove(&object_id(object));
+        res
+    }
+
+    //Create the object of a value. Fails if a dict has a key that can not be hashed, like a list.
+    #[allow(dead_code)]
+    pub fn to_object<'a>(&self, vm: Trc<VM<'a>>) -> Result<Object<'a>, ConversionError> {
+        Ok(match self {
+            Value::Int(value) => intobject::int_from(vm, *value as isize),
+            Value::Float(value) => floatobject::float_from(vm, *value),
+            Value::Str(value) => stringobject::string_from(vm, value.clone()),
+            Value::Bool(value) => boolobject::bool_from(vm, *value),
+            Value::List(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.to_object(vm.clone()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                listobject::list_from(vm, items)
+            }
+            Value::Dict(items) => {
+                let mut map = mhash::HashMap::new();
+                for (key, value) in items {
+                    let key = key.to_object(vm.clone())?;
+                    let typename = key.tp.typename.clone();
+                    if map.insert(key, value.to_object(vm.clone())?).is_error() {
+                        return Err(ConversionError::UnhashableKey(typename));
+                    }
+                }
+                dictobject::dict_from(vm, map)
+            }
+            Value::None => none_from!(vm),
+        })
+    }
+}
@@ -0,0 +1,118 @@
+//Run a program again whenever its file changes, for `merlin watch file.me`
+
+use crate::rc::Trc;
+use crate::{
+    catch_exit, errors::ExitCode, fileinfo::FileInfo, interpreter::VM, new_run_vm, run_program,
+    RunOptions,
+};
+use colored::Colorize;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+//How long to wait for more changes after one, as editors often write a file in several steps
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+//Run the current version of the program in the VM of the watch, which keeps its types, builtins
+//and caches between runs, and starts each run from a clean state. A program that stops, with an
+//error or env.exit, unwinds to here, so watching goes on.
+pub fn run<'a>(mut vm: Trc<VM<'a>>, file: &str, options: &RunOptions) -> ExitCode {
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(_) => {
+            println!("File '{}' is unable to be opened or read.", file);
+            return ExitCode::Usage;
+        }
+    };
+    VM::reset(vm.clone());
+    let info = VM::own_source(vm.clone(), &source, file);
+    vm.info = info.clone();
+    let code = catch_exit(|| run_program(vm.clone(), info, options)).unwrap_or_else(|code| code);
+    if code == ExitCode::Success && !options.dump_ast && !options.dump_consts {
+        vm.print_stats();
+    }
+    code
+}
+
+fn is_change_to(event: &Event, file: &Path) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any
+    ) && event
+        .paths
+        .iter()
+        .any(|path| path.canonicalize().ok().as_deref() == Some(file))
+}
+
+//Wait for a change to the file, then for DEBOUNCE more, and drop the events that came in that
+//time, so that a change that is written in several steps runs the program once. Returns false
+//when the watcher stopped.
+pub fn next_change(rx: &mpsc::Receiver<notify::Result<Event>>, file: &Path) -> bool {
+    loop {
+        match rx.recv() {
+            Ok(Ok(event)) if is_change_to(&event, file) => break,
+            Ok(_) => continue,
+            Err(_) => return false,
+        }
+    }
+    std::thread::sleep(DEBOUNCE);
+    while rx.try_recv().is_ok() {}
+    true
+}
+
+//Watch a file and run it, then run it again after each change, with the options and the arguments
+//of the program that merlin was given
+pub fn watch(file: &str, options: &RunOptions, args: Vec<String>) {
+    let name = file;
+    let file = match PathBuf::from(file).canonicalize() {
+        Ok(file) => file,
+        Err(_) => {
+            println!("File '{}' is unable to be opened or read.", file);
+            return;
+        }
+    };
+    //Editors may replace the file instead of writing to it, so its directory is watched
+    let dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            println!(
+                "{}",
+                format!("Unable to watch '{}': {err}", file.display()).red()
+            );
+            return;
+        }
+    };
+    if let Err(err) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+        println!(
+            "{}",
+            format!("Unable to watch '{}': {err}", file.display()).red()
+        );
+        return;
+    }
+
+    let vm = new_run_vm(
+        FileInfo {
+            data: b"",
+            name: name.to_string(),
+        },
+        options,
+        args,
+    );
+    loop {
+        let code = run(vm.clone(), name, options);
+        if code != ExitCode::Success {
+            println!("{}", format!("Exited with {}", code.status()).red());
+        }
+        if !next_change(&rx, &file) {
+            return;
+        }
+        println!(
+            "{}",
+            format!("===== '{}' changed, running it again =====", file.display()).blue()
+        );
+    }
+}